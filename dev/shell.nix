@@ -38,8 +38,8 @@
         echo "CAST store: $CAST_STORE"
         echo ""
         echo "Available commands:"
-        echo "  cargo build --manifest-path packages/cast-cli/Cargo.toml"
-        echo "  cargo test --manifest-path packages/cast-cli/Cargo.toml"
+        echo "  cargo build --workspace"
+        echo "  cargo test --workspace"
         echo "  cargo watch -x check"
         echo "  nix flake check"
         echo "  nix fmt"
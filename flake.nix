@@ -27,12 +27,15 @@
             pname = "cast-cli";
             version = "0.1.0";
 
-            src = inputs.gitignore.lib.gitignoreSource ./packages/cast-cli;
+            src = inputs.gitignore.lib.gitignoreSource ./.;
 
             cargoLock = {
-              lockFile = ./packages/cast-cli/Cargo.lock;
+              lockFile = ./Cargo.lock;
             };
 
+            cargoBuildFlags = ["-p" "cast-cli"];
+            cargoTestFlags = ["--workspace"];
+
             nativeBuildInputs = with pkgs; [pkg-config];
 
             buildInputs = with pkgs;
@@ -0,0 +1,224 @@
+// Published checksum files for `cast fetch --checksums`
+use anyhow::{Context, Result};
+use cast_core::hash::{ContentHash, HashAlgorithm};
+use sha2::Digest as _;
+use std::io::Read;
+use std::path::Path;
+
+/// Algorithm of unprefixed entries in a checksum file, if its name says
+///
+/// `B3SUMS`, `BLAKE3SUMS`, and `*.b3`/`*.blake3` hold BLAKE3 digests;
+/// `SHA256SUMS` and `*.sha256` hold SHA-256 ones. Any other name is `None`:
+/// both algorithms produce 64 hex digits, so such entries are checked
+/// against whichever of the two matches the file.
+pub fn algorithm_for_file_name(source: &str) -> Option<HashAlgorithm> {
+    let name = source.rsplit('/').next().unwrap_or(source).to_ascii_lowercase();
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext);
+    match (name.as_str(), extension) {
+        ("b3sums" | "blake3sums", _) | (_, Some("b3" | "blake3")) => Some(HashAlgorithm::Blake3),
+        ("sha256sums", _) | (_, Some("sha256")) => Some(HashAlgorithm::Sha256),
+        _ => None,
+    }
+}
+
+/// Incremental digest for one `HashAlgorithm`
+trait Hasher: Send {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self: Box<Self>) -> ContentHash;
+}
+
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> ContentHash {
+        ContentHash::Blake3(blake3::Hasher::finalize(&self).into())
+    }
+}
+
+impl Hasher for sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> ContentHash {
+        ContentHash::Sha256(sha2::Digest::finalize(*self).into())
+    }
+}
+
+/// Start an incremental digest
+fn hasher(algorithm: HashAlgorithm) -> Box<dyn Hasher> {
+    match algorithm {
+        HashAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+        HashAlgorithm::Sha256 => Box::new(sha2::Sha256::new()),
+    }
+}
+
+/// One `<hash>  <filename>` line of a checksum file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The digest, or both readings of it when its algorithm is undetermined
+    pub hashes: Vec<ContentHash>,
+    pub filename: String,
+}
+
+/// Parse `sha256sum`/`b3sum`-style lines
+///
+/// A `blake3:` or `sha256:` prefix on the hash selects its algorithm;
+/// unprefixed hashes use `default`, or stay undetermined without one. Blank
+/// lines and `#` comments are skipped, and the `*` binary-mode marker before
+/// a filename is ignored.
+pub fn parse(text: &str, default: Option<HashAlgorithm>) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (hash, filename) = line
+            .split_once(char::is_whitespace)
+            .with_context(|| format!("Invalid checksum line {}: {}", number + 1, line))?;
+        let filename = filename.trim_start();
+        let filename = filename.strip_prefix('*').unwrap_or(filename);
+        let prefixed = match default {
+            _ if hash.contains(':') => vec![hash.to_string()],
+            Some(algorithm) => vec![format!("{}:{}", algorithm.prefix(), hash)],
+            None => [HashAlgorithm::Blake3, HashAlgorithm::Sha256]
+                .iter()
+                .map(|algorithm| format!("{}:{}", algorithm.prefix(), hash))
+                .collect(),
+        };
+        let hashes = prefixed
+            .iter()
+            .map(|hash| hash.parse::<ContentHash>())
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("Invalid checksum on line {}: {}", number + 1, hash))?;
+
+        entries.push(Entry {
+            hashes,
+            filename: filename.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Entry whose file name (ignoring any directory) is `name`
+pub fn find<'a>(entries: &'a [Entry], name: &str) -> Option<&'a Entry> {
+    entries
+        .iter()
+        .find(|entry| entry.filename.rsplit('/').next() == Some(name))
+}
+
+/// Read a checksum file from an `http(s)://` or `file://` URL, or a local path
+pub async fn load(source: &str) -> Result<String> {
+    match reqwest::Url::parse(source) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => reqwest::get(url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to download checksums: {}", source))?
+            .text()
+            .await
+            .with_context(|| format!("Failed to read checksums: {}", source)),
+        Ok(url) if url.scheme() == "file" => {
+            let path = url
+                .to_file_path()
+                .map_err(|_| anyhow::anyhow!("Invalid file URL: {}", source))?;
+            read_file(&path).await
+        }
+        _ => read_file(Path::new(source)).await,
+    }
+}
+
+async fn read_file(path: &Path) -> Result<String> {
+    tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read checksums: {}", path.display()))
+}
+
+/// Check a file against a checksum entry
+///
+/// An entry of undetermined algorithm passes if either digest matches.
+pub async fn verify_file(path: &Path, entry: &Entry) -> Result<()> {
+    let owned = path.to_path_buf();
+    let algorithms: Vec<_> = entry.hashes.iter().map(ContentHash::algorithm).collect();
+    let actual = tokio::task::spawn_blocking(move || -> Result<Vec<ContentHash>> {
+        let mut file = std::fs::File::open(&owned)?;
+        let mut hashers: Vec<_> = algorithms.into_iter().map(hasher).collect();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            for hasher in &mut hashers {
+                hasher.update(&buf[..n]);
+            }
+        }
+        Ok(hashers.into_iter().map(|hasher| hasher.finalize()).collect())
+    })
+    .await
+    .context("Checksum task panicked")?
+    .with_context(|| format!("Failed to checksum file: {}", path.display()))?;
+
+    if !actual.iter().any(|hash| entry.hashes.contains(hash)) {
+        let join = |hashes: &[ContentHash]| hashes.iter().map(ContentHash::to_string).collect::<Vec<_>>().join(" or ");
+        anyhow::bail!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            entry.filename,
+            join(&entry.hashes),
+            join(&actual)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn test_parse_checksum_lines() {
+        let b3 = blake3::hash(b"abc").to_hex().to_string();
+        let text = format!("# release checksums\n{}  data.tar.gz\n\nblake3:{} *sub/other.bin\n", ABC_SHA256.to_uppercase(), b3);
+        let sha256: ContentHash = format!("sha256:{}", ABC_SHA256).parse().unwrap();
+        let blake3: ContentHash = format!("blake3:{}", b3).parse().unwrap();
+
+        let entries = parse(&text, Some(HashAlgorithm::Sha256)).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].hashes, vec![sha256]);
+        assert_eq!(find(&entries, "other.bin").unwrap().hashes, vec![blake3]);
+        assert!(find(&entries, "missing.bin").is_none());
+        assert_eq!(parse(&text, None).unwrap()[0].hashes.len(), 2);
+
+        assert!(parse("not-a-digest  file", Some(HashAlgorithm::Sha256)).is_err());
+        assert!(parse(&format!("md5:{}  file", ABC_SHA256), None).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_for_file_name() {
+        assert_eq!(algorithm_for_file_name("https://example.org/B3SUMS"), Some(HashAlgorithm::Blake3));
+        assert_eq!(algorithm_for_file_name("release.tar.gz.b3"), Some(HashAlgorithm::Blake3));
+        assert_eq!(algorithm_for_file_name("SHA256SUMS"), Some(HashAlgorithm::Sha256));
+        assert_eq!(algorithm_for_file_name("data.sha256"), Some(HashAlgorithm::Sha256));
+        // Names that merely contain "b3" say nothing about the algorithm
+        assert_eq!(algorithm_for_file_name("https://example.org/db3/checksums.txt"), None);
+        assert_eq!(algorithm_for_file_name("mb3-release-SUMS"), None);
+    }
+
+    #[tokio::test]
+    async fn test_verify_file_with_undetermined_algorithm() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("abc");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let entry = |digest: &str| parse(&format!("{}  abc", digest), None).unwrap().remove(0);
+        verify_file(&path, &entry(ABC_SHA256)).await.unwrap();
+        verify_file(&path, &entry(&blake3::hash(b"abc").to_hex())).await.unwrap();
+        let err = verify_file(&path, &entry(&"0".repeat(64))).await.unwrap_err();
+        assert!(err.to_string().contains(" or sha256:"), "{}", err);
+    }
+}
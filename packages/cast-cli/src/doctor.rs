@@ -0,0 +1,203 @@
+// Environment health checks for `cast doctor`
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+use cast_core::db::MetadataDb;
+use cast_core::storage::StorageConfig;
+
+/// Free space below which the store's file system is reported as low (1 GiB)
+pub const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "PASS"),
+            Status::Warn => write!(f, "WARN"),
+            Status::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// Result of one health check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Check that the store root exists and is a directory
+///
+/// A missing root is only a warning: it is created on first use.
+pub fn check_store_exists(root: &Path) -> Check {
+    const NAME: &str = "store";
+    match std::fs::metadata(root) {
+        Ok(metadata) if metadata.is_dir() => Check::new(NAME, Status::Pass, root.display().to_string()),
+        Ok(_) => Check::new(NAME, Status::Fail, format!("{} is not a directory", root.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Check::new(
+            NAME,
+            Status::Warn,
+            format!("{} does not exist yet; it is created on first use", root.display()),
+        ),
+        Err(e) => Check::new(NAME, Status::Fail, format!("{}: {}", root.display(), e)),
+    }
+}
+
+/// Check that files can be created in the store root
+pub fn check_writable(root: &Path) -> Check {
+    const NAME: &str = "writable";
+    let metadata = match std::fs::metadata(root) {
+        Ok(metadata) => metadata,
+        Err(_) => return Check::new(NAME, Status::Warn, "store does not exist yet; skipped"),
+    };
+
+    // Checked up front as well as by probing, since a privileged user can
+    // write to a directory whose permissions say it is read-only
+    if metadata.permissions().readonly() {
+        return Check::new(NAME, Status::Fail, format!("{} is read-only", root.display()));
+    }
+
+    let probe = root.join(format!(".cast-doctor-{}", std::process::id()));
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            Check::new(NAME, Status::Pass, format!("{} is writable", root.display()))
+        }
+        Err(e) => Check::new(NAME, Status::Fail, format!("Cannot write to {}: {}", root.display(), e)),
+    }
+}
+
+/// Check that the file system holding `path` has at least `min` bytes free
+pub fn check_free_space(path: &Path, min: u64) -> Check {
+    const NAME: &str = "disk space";
+    // Measure the nearest existing ancestor when the store is not created yet
+    let existing = path.ancestors().find(|p| p.exists()).unwrap_or(path);
+    match fs4::available_space(existing) {
+        Ok(free) if free >= min => Check::new(NAME, Status::Pass, format!("{} bytes free", free)),
+        Ok(free) => Check::new(
+            NAME,
+            Status::Warn,
+            format!("only {} bytes free (recommended: at least {})", free, min),
+        ),
+        Err(e) => Check::new(NAME, Status::Warn, format!("Cannot determine free space: {}", e)),
+    }
+}
+
+/// Check that the metadata database opens and passes `integrity_check`
+pub async fn check_database(config: &StorageConfig) -> Check {
+    const NAME: &str = "database";
+    let path = config.db_path();
+    let db = match MetadataDb::with_options(&path, &config.db_options()).await {
+        Ok(db) => db,
+        Err(e) => return Check::new(NAME, Status::Fail, format!("{:#}", e)),
+    };
+
+    match db.integrity_check().await {
+        Ok(problems) if problems.is_empty() => Check::new(NAME, Status::Pass, path.display().to_string()),
+        Ok(problems) => Check::new(NAME, Status::Fail, problems.join("; ")),
+        Err(e) => Check::new(NAME, Status::Fail, format!("{:#}", e)),
+    }
+}
+
+/// Run every check against a loaded configuration
+///
+/// The database check is skipped when the store is missing or unusable,
+/// since opening the database would create it.
+pub async fn run(config: &StorageConfig) -> Vec<Check> {
+    let mut checks = vec![
+        Check::new("config", Status::Pass, "configuration loaded"),
+        check_store_exists(&config.root),
+        check_writable(&config.root),
+        check_free_space(&config.root, MIN_FREE_SPACE),
+    ];
+
+    if !config.root.is_dir() || checks.iter().any(|c| c.status == Status::Fail) {
+        checks.push(Check::new("database", Status::Warn, "skipped: store is not usable"));
+    } else {
+        checks.push(check_database(config).await);
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_only_store_fails_writability() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("store");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::set_permissions(&root, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let check = check_writable(&root);
+        std::fs::set_permissions(&root, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(check.status, Status::Fail, "{}", check.detail);
+        assert_eq!(check_store_exists(&root).status, Status::Pass);
+    }
+
+    #[tokio::test]
+    async fn test_healthy_store_passes() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        std::fs::create_dir(&config.root).unwrap();
+
+        let checks = run(&config).await;
+        for check in &checks {
+            // Free space depends on the machine running the tests
+            if check.name != "disk space" {
+                assert_eq!(check.status, Status::Pass, "{}: {}", check.name, check.detail);
+            }
+        }
+        assert!(checks.iter().any(|c| c.name == "database"));
+    }
+
+    #[test]
+    fn test_missing_store_warns() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("missing");
+        assert_eq!(check_store_exists(&root).status, Status::Warn);
+        assert_eq!(check_free_space(&root, 0).status, Status::Pass);
+    }
+
+    #[tokio::test]
+    async fn test_doctor_does_not_create_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("missing"),
+            ..Default::default()
+        };
+
+        let checks = run(&config).await;
+        assert!(checks.iter().all(|c| c.status != Status::Fail));
+        assert!(!config.root.exists());
+    }
+}
@@ -0,0 +1,154 @@
+// Mirror racing for `cast fetch --mirror`
+use anyhow::{Context, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
+use reqwest::Url;
+use tokio::io::AsyncWriteExt;
+
+use cast_core::hash::Blake3Hash;
+use cast_core::io::{Throttle, ThrottledWriter};
+use cast_core::storage::local::LocalStorage;
+use cast_core::storage::PutResult;
+
+use crate::checksums;
+
+/// A download stored from one of several mirrors
+pub struct Mirrored {
+    /// URL the object was downloaded from
+    pub url: Url,
+    pub result: PutResult,
+    /// The serving mirror's `Last-Modified` header
+    pub server_mtime: Option<String>,
+}
+
+/// Store the object behind `urls` from whichever mirror answers first
+///
+/// Every remaining URL is requested at once and the first successful
+/// response is downloaded; the others are dropped. If that download fails or
+/// does not verify against `expected_hash` (and `checksum`, when given), the
+/// mirrors not yet tried are raced again. Fails only when every mirror has
+/// failed. Downloads are not resumed across mirrors.
+pub async fn race_mirrors(
+    storage: &LocalStorage,
+    urls: &[Url],
+    expected_hash: Option<&Blake3Hash>,
+    checksum: Option<&checksums::Entry>,
+    throttle: Option<&Throttle>,
+    quiet: bool,
+) -> Result<Mirrored> {
+    let client = reqwest::Client::new();
+    let mut remaining = urls.to_vec();
+    let mut errors = Vec::new();
+
+    while !remaining.is_empty() {
+        let mut pending: FuturesUnordered<_> = remaining
+            .iter()
+            .cloned()
+            .map(|url| {
+                let request = client.get(url.clone()).send();
+                async move {
+                    let response = request.await.and_then(|r| r.error_for_status());
+                    (url, response)
+                }
+            })
+            .collect();
+
+        let mut winner = None;
+        while let Some((url, response)) = pending.next().await {
+            match response {
+                Ok(response) => {
+                    winner = Some((url, response));
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!("Mirror {} failed: {}", url, e);
+                    errors.push(format!("{}: {}", url, e));
+                    remaining.retain(|u| *u != url);
+                }
+            }
+        }
+        drop(pending);
+
+        let Some((url, response)) = winner else {
+            break;
+        };
+        remaining.retain(|u| *u != url);
+        tracing::info!("Downloading from mirror {}", url);
+        match store_response(storage, &url, response, expected_hash, checksum, throttle, quiet).await {
+            Ok((result, server_mtime)) => {
+                return Ok(Mirrored {
+                    url,
+                    result,
+                    server_mtime,
+                })
+            }
+            Err(e) => {
+                tracing::warn!("Mirror {} failed: {:#}", url, e);
+                errors.push(format!("{}: {:#}", url, e));
+            }
+        }
+    }
+
+    anyhow::bail!("Every mirror failed:\n  {}", errors.join("\n  "))
+}
+
+/// Download a response to the store's `tmp/` directory, then verify and store it
+async fn store_response(
+    storage: &LocalStorage,
+    url: &Url,
+    mut response: reqwest::Response,
+    expected_hash: Option<&Blake3Hash>,
+    checksum: Option<&checksums::Entry>,
+    throttle: Option<&Throttle>,
+    quiet: bool,
+) -> Result<(PutResult, Option<String>)> {
+    let server_mtime = response
+        .headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let part = storage.partial_download_path(url.as_str());
+    let result = async {
+        if let Some(parent) = part.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let file = tokio::fs::File::create(&part)
+            .await
+            .with_context(|| format!("Failed to create download: {}", part.display()))?;
+        let mut file: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = match throttle {
+            Some(throttle) => Box::new(ThrottledWriter::new(file, throttle.clone())),
+            None => Box::new(file),
+        };
+
+        let bar = crate::progress_bar(response.content_length(), quiet);
+        let copied: Result<()> = async {
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .with_context(|| format!("Download interrupted: {}", url))?
+            {
+                file.write_all(&chunk)
+                    .await
+                    .with_context(|| format!("Failed to write download: {}", part.display()))?;
+                bar.inc(chunk.len() as u64);
+            }
+            file.flush().await.context("Failed to flush download")
+        }
+        .await;
+        bar.finish_and_clear();
+        copied?;
+
+        if let Some(entry) = checksum {
+            checksums::verify_file(&part, entry).await?;
+        }
+        let file = tokio::fs::File::open(&part)
+            .await
+            .with_context(|| format!("Failed to open download: {}", part.display()))?;
+        storage.put_reader_verified(file, expected_hash).await
+    }
+    .await;
+    crate::remove_partial_download(&part).await;
+    Ok((result?, server_mtime))
+}
@@ -0,0 +1,194 @@
+// Git LFS pointers and the batch API for `cast import-lfs` / `cast export-lfs`
+//
+// A pointer file stands in for a large file in a Git repository:
+//
+//     version https://git-lfs.github.com/spec/v1
+//     oid sha256:<64 hex digits>
+//     size <bytes>
+//
+// Objects are downloaded by POSTing to `<endpoint>/objects/batch`, which
+// answers with a URL (and headers) to GET each object from.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+use cast_core::hash::ContentHash;
+
+pub const SPEC_VERSION: &str = "https://git-lfs.github.com/spec/v1";
+
+const MEDIA_TYPE: &str = "application/vnd.git-lfs+json";
+
+/// Parsed LFS pointer file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pointer {
+    /// SHA-256 of the object
+    pub oid: [u8; 32],
+    pub size: u64,
+}
+
+impl Pointer {
+    /// Parse a pointer file
+    ///
+    /// The `version` line must come first; other keys are ignored, as the
+    /// spec allows extensions.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().filter(|line| !line.is_empty());
+        match lines.next().and_then(|line| line.strip_prefix("version ")) {
+            Some(SPEC_VERSION) => {}
+            Some(version) => anyhow::bail!("Unsupported LFS pointer version: {}", version),
+            None => anyhow::bail!("Not an LFS pointer: missing version line"),
+        }
+
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            let (key, value) = line
+                .split_once(' ')
+                .with_context(|| format!("Invalid LFS pointer line: {}", line))?;
+            match key {
+                "oid" => {
+                    let hex = value
+                        .strip_prefix("sha256:")
+                        .with_context(|| format!("Unsupported LFS oid: {}", value))?;
+                    match format!("sha256:{}", hex).parse::<ContentHash>() {
+                        Ok(ContentHash::Sha256(bytes)) => oid = Some(bytes),
+                        _ => anyhow::bail!("Invalid LFS oid: {}", value),
+                    }
+                }
+                "size" => size = Some(value.parse().with_context(|| format!("Invalid LFS size: {}", value))?),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            oid: oid.context("LFS pointer has no oid")?,
+            size: size.context("LFS pointer has no size")?,
+        })
+    }
+
+    /// Lowercase hex of the oid, as used by the batch API
+    pub fn oid_hex(&self) -> String {
+        ContentHash::Sha256(self.oid).to_hex()
+    }
+}
+
+impl fmt::Display for Pointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "version {}\noid sha256:{}\nsize {}\n", SPEC_VERSION, self.oid_hex(), self.size)
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    objects: Vec<BatchObject>,
+}
+
+#[derive(Deserialize)]
+struct BatchObject {
+    #[serde(default)]
+    actions: Option<Actions>,
+    #[serde(default)]
+    error: Option<ObjectError>,
+}
+
+#[derive(Deserialize)]
+struct Actions {
+    download: Option<Action>,
+}
+
+#[derive(Deserialize)]
+struct Action {
+    href: String,
+    #[serde(default)]
+    header: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ObjectError {
+    code: u16,
+    message: String,
+}
+
+/// Start downloading the object behind `pointer` from an LFS server
+///
+/// Returns the response and the URL it came from. `token`, if given, is sent
+/// as a bearer token to the batch endpoint.
+pub async fn download(endpoint: &str, pointer: &Pointer, token: Option<&str>) -> Result<(String, reqwest::Response)> {
+    let client = reqwest::Client::new();
+    let batch_url = format!("{}/objects/batch", endpoint.trim_end_matches('/'));
+    let body = serde_json::json!({
+        "operation": "download",
+        "transfers": ["basic"],
+        "objects": [{ "oid": pointer.oid_hex(), "size": pointer.size }],
+    });
+
+    let mut request = client
+        .post(&batch_url)
+        .header(reqwest::header::ACCEPT, MEDIA_TYPE)
+        .header(reqwest::header::CONTENT_TYPE, MEDIA_TYPE)
+        .body(body.to_string());
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("LFS batch request failed: {}", batch_url))?
+        .bytes()
+        .await
+        .with_context(|| format!("Failed to read LFS batch response from {}", batch_url))?;
+    let response: BatchResponse = serde_json::from_slice(&response).context("Invalid LFS batch response")?;
+
+    let object = response
+        .objects
+        .into_iter()
+        .next()
+        .context("LFS batch response lists no objects")?;
+    if let Some(error) = object.error {
+        anyhow::bail!("LFS server refused object {}: {} ({})", pointer.oid_hex(), error.message, error.code);
+    }
+    let action = object
+        .actions
+        .and_then(|actions| actions.download)
+        .with_context(|| format!("LFS server gave no download for object {}", pointer.oid_hex()))?;
+
+    let mut request = client.get(&action.href);
+    for (name, value) in &action.header {
+        request = request.header(name, value);
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to download LFS object: {}", action.href))?;
+    Ok((action.href, response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "version https://git-lfs.github.com/spec/v1\n\
+        oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n\
+        size 12345\n";
+
+    #[test]
+    fn test_parse_pointer() {
+        let pointer = Pointer::parse(SAMPLE).unwrap();
+        assert_eq!(pointer.size, 12345);
+        assert_eq!(pointer.oid_hex(), "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393");
+
+        assert!(Pointer::parse("oid sha256:00\nsize 1\n").is_err());
+        assert!(Pointer::parse(&SAMPLE.replace("spec/v1", "spec/v2")).is_err());
+        assert!(Pointer::parse(&SAMPLE.replace("sha256:4d", "md5:4d")).is_err());
+        assert!(Pointer::parse(&SAMPLE.replace("size 12345\n", "")).is_err());
+    }
+
+    #[test]
+    fn test_pointer_round_trip() {
+        let pointer = Pointer::parse(SAMPLE).unwrap();
+        assert_eq!(pointer.to_string(), SAMPLE);
+        assert_eq!(Pointer::parse(&pointer.to_string()).unwrap(), pointer);
+    }
+}
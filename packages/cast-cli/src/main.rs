@@ -1,49 +1,206 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use anyhow::{Context, Result};
 use std::path::Path;
+use std::str::FromStr;
+use tokio::io::AsyncWriteExt;
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
-mod db;
-mod hash;
-mod manifest;
-mod storage;
+mod checksums;
+mod doctor;
+mod fetch;
+mod lfs;
+mod nix;
+mod oci;
+mod output;
+mod transform;
+mod tree;
 
+use cast_core::io::{parse_rate, Throttle, ThrottledReader, ThrottledWriter};
+use cast_core::{db, hash, manifest, storage};
+use db::{CheckpointMode, DatabaseStats, ImportMode, MetadataDb, ObjectRecord};
 use hash::Blake3Hash;
-use manifest::{Content, Manifest, Transformation};
+use manifest::{Content, ContentKind, Manifest, Transformation};
+use output::Format;
+use storage::local::LocalStorage;
+use storage::{FileMetadata, PutResult, StorageBackend, StorageConfig};
 
 #[derive(Parser)]
 #[command(name = "cast")]
 #[command(about = "Content-Addressed Storage Tool", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Named storage profile from the config file
+    #[arg(long, global = true, env = "CAST_PROFILE")]
+    profile: Option<String>,
+
+    /// Use the store rooted at this path, ignoring the config file, profiles,
+    /// and CAST_STORE
+    #[arg(long, global = true, value_name = "PATH")]
+    store: Option<String>,
+
+    /// Print machine-readable JSON instead of human-readable text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Don't show progress bars
+    #[arg(long, short, global = true)]
+    quiet: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Create a store and its metadata database, and write a starter config
+    Init {
+        /// Store root to create (default: the configured root)
+        path: Option<String>,
+
+        /// Initialize even if a store already exists there (an existing
+        /// config file is still left untouched)
+        #[arg(long)]
+        force: bool,
+    },
+
     /// Store a file in CAS and return its hash
     Put {
-        /// Path to the file to store
+        /// Path to the file to store, or `-` to read from stdin
         file: String,
+
+        /// Store every file under a directory as a dataset manifest
+        #[arg(short, long, requires_all = ["name", "version"])]
+        recursive: bool,
+
+        /// Dataset name for `--recursive`
+        #[arg(long, requires = "recursive")]
+        name: Option<String>,
+
+        /// Dataset version for `--recursive`
+        #[arg(long, requires = "recursive")]
+        version: Option<String>,
+
+        /// With `--recursive`, report what would be stored without writing anything
+        #[arg(long, requires = "recursive")]
+        dry_run: bool,
+
+        /// Record the file's name, permissions, and mtime for `get --restore`
+        #[arg(long, conflicts_with = "recursive")]
+        preserve: bool,
+
+        /// Let `gc --expired` delete the object after this long (e.g. `12h`, `7d`)
+        #[arg(long, value_parser = parse_duration, conflicts_with = "recursive")]
+        ttl: Option<chrono::Duration>,
+
+        /// Store the object as content-defined chunks shared with similar objects
+        #[arg(long, conflicts_with = "recursive")]
+        chunked: bool,
     },
 
     /// Retrieve file path by hash
     Get {
-        /// BLAKE3 hash of the file
+        /// BLAKE3 hash of the file (full, `blake3:`-prefixed, or a unique prefix),
+        /// or `<name>@<version>` (`<name>@latest` for the newest) for a dataset's
+        /// manifest; `-` reads newline-separated hashes from stdin
+        #[arg(required_unless_present = "stdin")]
+        hash: Option<String>,
+
+        /// Read newline-separated hashes from stdin
+        #[arg(long, conflicts_with_all = ["hash", "output", "stdout", "restore", "materialize"])]
+        stdin: bool,
+
+        /// Copy the object to this path instead of printing its store path
+        #[arg(long, conflicts_with = "stdout")]
+        output: Option<String>,
+
+        /// Write the object's bytes to stdout
+        #[arg(long)]
+        stdout: bool,
+
+        /// Recreate the file under its recorded name and permissions in DIR
+        /// (default: the current directory)
+        #[arg(
+            long,
+            value_name = "DIR",
+            num_args = 0..=1,
+            default_missing_value = ".",
+            conflicts_with_all = ["output", "stdout"]
+        )]
+        restore: Option<String>,
+
+        /// Recreate the file tree of a dataset (or manifest object) in DIR
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "stdout", "restore"])]
+        materialize: Option<String>,
+    },
+
+    /// Copy the files of a dataset whose paths match a glob into a directory
+    Cp {
+        /// Dataset name
+        name: String,
+
+        /// Dataset version
+        version: String,
+
+        /// Glob over content paths, e.g. `a/**` or `**/*.fasta`
+        pattern: String,
+
+        /// Directory to copy into, keeping each file's relative path
+        dest: String,
+    },
+
+    /// Describe a stored object and the datasets that reference it
+    Info {
+        /// BLAKE3 hash of the object (full, `blake3:`-prefixed, or a unique prefix);
+        /// `-` reads newline-separated hashes from stdin
+        #[arg(required_unless_present = "stdin")]
+        hash: Option<String>,
+
+        /// Read newline-separated hashes from stdin
+        #[arg(long, conflicts_with = "hash")]
+        stdin: bool,
+
+        /// Only list the datasets that reference the object
+        #[arg(long)]
+        referenced_by: bool,
+    },
+
+    /// Print the transformation lineage of an output as a Graphviz DOT graph
+    Lineage {
+        /// BLAKE3 hash of the output (full or `blake3:`-prefixed)
+        hash: String,
+    },
+
+    /// Write an object's raw bytes to stdout
+    Cat {
+        /// BLAKE3 hash of the object (full, `blake3:`-prefixed, or a unique prefix)
         hash: String,
     },
 
     /// Download and register a database
     Fetch {
-        /// URL to download from
+        /// URL to download from (http, https, or file)
         url: String,
 
+        /// Another URL serving the same file; repeat for several. The primary
+        /// URL and mirrors are raced and the first to respond is used, so pass
+        /// --hash or --checksums to verify what it serves
+        #[arg(long = "mirror", value_name = "URL")]
+        mirrors: Vec<String>,
+
         /// Expected BLAKE3 hash (optional)
         #[arg(long)]
         hash: Option<String>,
+
+        /// Checksum file (URL or path) listing the download's expected digest,
+        /// as `<hash>  <filename>` lines (SHA-256 or BLAKE3)
+        #[arg(long, conflicts_with = "hash")]
+        checksums: Option<String>,
+
+        /// Cap the download at this many bytes per second (e.g. `500K`, `10MB`)
+        #[arg(long, value_parser = parse_rate)]
+        rate: Option<u64>,
     },
 
     /// Transform a dataset
@@ -52,229 +209,5772 @@ enum Commands {
         #[arg(long)]
         input_manifest: String,
 
-        /// Output directory
+        /// Output directory (with --pipeline, each step works in its `step-<n>` subdirectory)
         #[arg(long)]
         output_dir: String,
 
         /// Transformation type
+        #[arg(long, required_unless_present = "pipeline", conflicts_with = "pipeline")]
+        transform_type: Option<String>,
+
+        /// Transformation parameter as `key=value` (repeatable)
+        #[arg(long = "param", value_name = "KEY=VALUE", conflicts_with = "pipeline")]
+        params: Vec<String>,
+
+        /// Run the ordered steps of a pipeline spec instead of a single transform
+        #[arg(long)]
+        pipeline: Option<String>,
+
+        /// Ignore cached results and always rescan the output directory
+        #[arg(long, conflicts_with = "pipeline")]
+        no_cache: bool,
+
+        /// Maximum number of files hashed concurrently
+        #[arg(long, default_value_t = DEFAULT_SCAN_JOBS)]
+        jobs: usize,
+
+        /// Write the output manifest to this file instead of stdout (`-` for stdout)
+        #[arg(long, value_name = "PATH")]
+        output_manifest: Option<String>,
+
+        /// JSON file mapping output paths to the input paths they were made
+        /// from, recorded as each output file's `derived_from`
+        #[arg(long, value_name = "PATH", conflicts_with = "pipeline")]
+        derived_from: Option<String>,
+
+        /// Format of the output manifest
+        #[arg(long, default_value = "json", value_parser = ["json", "yaml"])]
+        output_format: String,
+    },
+
+    /// List registered datasets or stored objects
+    List {
+        /// List datasets (the default)
+        #[arg(long, conflicts_with = "objects")]
+        datasets: bool,
+
+        /// List objects with their sizes and refcounts
+        #[arg(long)]
+        objects: bool,
+
+        /// Only show datasets whose name matches this glob pattern
+        #[arg(long, conflicts_with = "objects")]
+        name: Option<String>,
+
+        /// Maximum number of rows to show
         #[arg(long)]
-        transform_type: String,
+        limit: Option<i64>,
+
+        /// Number of rows to skip
+        #[arg(long, default_value_t = 0)]
+        offset: i64,
+    },
+
+    /// Summarize the store and metadata database
+    Stats {
+        /// Number of largest objects to show
+        #[arg(long, default_value_t = 5)]
+        top: i64,
+
+        /// Also count recorded transformations by type
+        #[arg(long)]
+        transforms: bool,
     },
 
+    /// Show how much space deduplication saves
+    DedupReport,
+
     /// Garbage collect unreferenced objects
     Gc {
         /// Dry run - don't actually delete anything
         #[arg(long)]
         dry_run: bool,
+
+        /// Delete objects past their `put --ttl` instead, even if referenced
+        #[arg(long)]
+        expired: bool,
+
+        /// Stop after deleting this many objects
+        #[arg(long, value_name = "N")]
+        max_deletes: Option<usize>,
+
+        /// Stop deleting once GC has run this long (e.g. `30m`, `2h`)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        max_time: Option<chrono::Duration>,
+    },
+
+    /// Delete all but the most recent versions of a dataset
+    Prune {
+        /// Dataset name
+        name: String,
+
+        /// Number of most recent versions to keep
+        #[arg(long)]
+        keep: usize,
+
+        /// List the versions that would be removed without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Copy objects missing from one store into another
+    Sync {
+        /// Root of the store to copy from
+        src: String,
+
+        /// Root of the store to copy into
+        dst: String,
+
+        /// Cap the transfer at this many bytes per second (e.g. `500K`, `10MB`)
+        #[arg(long, value_parser = parse_rate)]
+        rate: Option<u64>,
+    },
+
+    /// Move small loose objects into a packfile
+    Pack {
+        /// Only pack objects of at most this many bytes
+        #[arg(long, default_value_t = DEFAULT_PACK_MAX_SIZE)]
+        max_size: u64,
+    },
+
+    /// Remove an object, or drop one reference to it
+    Rm {
+        /// Object hash (full, `blake3:`-prefixed, or a unique hex prefix);
+        /// `-` reads newline-separated hashes from stdin
+        #[arg(required_unless_present = "stdin")]
+        hash: Option<String>,
+
+        /// Read newline-separated hashes from stdin
+        #[arg(long, conflicts_with = "hash")]
+        stdin: bool,
+
+        /// Delete the object regardless of refcount or referencing datasets
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Re-hash objects to check their contents still match their hash
+    Verify {
+        /// Object hash (full, `blake3:`-prefixed, or a unique hex prefix);
+        /// `-` reads newline-separated hashes from stdin
+        #[arg(required_unless_present = "stdin")]
+        hash: Option<String>,
+
+        /// Read newline-separated hashes from stdin
+        #[arg(long, conflicts_with = "hash")]
+        stdin: bool,
+    },
+
+    /// Verify a manifest's ed25519 signature
+    VerifyManifest {
+        /// Path to the signed manifest
+        file: String,
+
+        /// Trusted public key: hex string or path to a file containing it
+        #[arg(long)]
+        key: String,
+    },
+
+    /// Check that every object a dataset references is in the store
+    VerifyDataset {
+        /// Dataset name
+        name: String,
+
+        /// Dataset version
+        version: String,
+
+        /// Also re-hash each object to detect corruption
+        #[arg(long)]
+        deep: bool,
+    },
+
+    /// Re-hash every object in the store to detect corruption
+    VerifyStore {
+        /// Maximum number of objects hashed concurrently
+        #[arg(long, default_value_t = DEFAULT_SCAN_JOBS)]
+        jobs: usize,
+    },
+
+    /// Show a dataset's files as a tree, without materializing it
+    #[command(visible_alias = "ls")]
+    Tree {
+        /// Dataset name
+        name: String,
+
+        /// Dataset version
+        version: String,
+    },
+
+    /// Show content differences between two manifests
+    Diff {
+        /// Path to the old manifest
+        old: String,
+
+        /// Path to the new manifest
+        new: String,
+    },
+
+    /// Export a dataset and its objects as a self-contained tar bundle
+    Export {
+        /// Dataset name
+        name: String,
+
+        /// Dataset version
+        version: String,
+
+        /// Path of the bundle to write
+        bundle: String,
+    },
+
+    /// Compute the Nix store path an object would have, optionally writing its NAR
+    ExportNix {
+        /// BLAKE3 hash of the object (full, `blake3:`-prefixed, or a unique prefix)
+        hash: String,
+
+        /// Store path name, e.g. `uniprot.fasta`
+        #[arg(long)]
+        name: String,
+
+        /// Mark the file executable in the NAR
+        #[arg(long)]
+        executable: bool,
+
+        /// Also write the NAR archive to this path
+        #[arg(long, value_name = "PATH")]
+        nar: Option<String>,
+    },
+
+    /// Import an OCI image's layers from a registry as a dataset
+    ImportOci {
+        /// Image reference, e.g. `ghcr.io/org/data:2024.01` or `repo@sha256:<digest>`
+        image: String,
+
+        /// Bearer token for the registry; without one, an anonymous token is
+        /// requested when the registry asks for authentication
+        #[arg(long, env = "CAST_OCI_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Talk to the registry over plain HTTP
+        #[arg(long)]
+        insecure: bool,
+    },
+
+    /// Import the object behind a Git LFS pointer file
+    ImportLfs {
+        /// Path to the pointer file
+        pointer: String,
+
+        /// LFS server URL, e.g. `https://github.com/org/repo.git/info/lfs`
+        #[arg(long)]
+        endpoint: String,
+
+        /// Bearer token for the LFS server
+        #[arg(long, env = "CAST_LFS_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+
+    /// Write a Git LFS pointer file for a stored object
+    ExportLfs {
+        /// BLAKE3 hash of the object (full, `blake3:`-prefixed, or a unique prefix)
+        hash: String,
+
+        /// Write the pointer to this path instead of stdout
+        #[arg(long, short, value_name = "PATH")]
+        output: Option<String>,
+    },
+
+    /// Import a dataset bundle written by `export`
+    Import {
+        /// Path to the bundle
+        bundle: String,
+    },
+
+    /// Metadata database maintenance
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+
+    /// Read or change settings in the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Check that the store, database, and config are usable
+    Doctor,
+
+    /// Print a shell completion script (e.g. `cast completions zsh > _cast`)
+    #[command(hide = true)]
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
     },
 }
 
-/// Transform command implementation
-async fn transform_command(
-    input_manifest: &str,
-    output_dir: &str,
-    transform_type: &str,
-) -> Result<()> {
-    tracing::info!("Processing transformation: {}", transform_type);
-    tracing::info!("Input manifest: {}", input_manifest);
-    tracing::info!("Output directory: {}", output_dir);
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the effective value of a setting
+    Get {
+        /// Setting name, e.g. `root` or `compression`
+        key: String,
+    },
 
-    // Read and parse input manifest
-    let input_content = tokio::fs::read_to_string(input_manifest)
-        .await
-        .with_context(|| format!("Failed to read input manifest: {}", input_manifest))?;
+    /// Change a setting in the config file
+    Set {
+        /// Setting name, e.g. `root` or `compression`
+        key: String,
 
-    let input_manifest_data: Manifest = serde_json::from_str(&input_content)
-        .with_context(|| format!("Failed to parse input manifest: {}", input_manifest))?;
+        /// New value
+        value: String,
+    },
+}
 
-    // Scan output directory for files
-    let output_path = Path::new(output_dir);
-    if !output_path.exists() {
-        anyhow::bail!("Output directory does not exist: {}", output_dir);
-    }
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Export all metadata as a JSON document
+    Export {
+        /// Write to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<String>,
+    },
 
-    let mut contents = Vec::new();
-    let mut entries = tokio::fs::read_dir(output_path).await?;
+    /// Import metadata from a JSON document produced by `db export`
+    Import {
+        /// Path to the JSON dump
+        file: String,
 
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-        if path.is_file() {
-            // Hash the file
-            let hash = Blake3Hash::from_file(&path)
-                .with_context(|| format!("Failed to hash file: {}", path.display()))?;
+        /// Clear existing metadata instead of merging
+        #[arg(long)]
+        replace: bool,
+    },
 
-            // Get file metadata
-            let metadata = tokio::fs::metadata(&path).await?;
-            let size = metadata.len();
+    /// Write a consistent snapshot of the metadata database
+    Backup {
+        /// Destination path for the backup (must not exist)
+        path: String,
+    },
 
-            #[cfg(unix)]
-            let executable = metadata.permissions().mode() & 0o111 != 0;
-            #[cfg(not(unix))]
-            let executable = false;
+    /// Rebuild object refcounts from the datasets that reference them
+    RepairRefs,
 
-            // Get relative path
-            let rel_path = path
-                .strip_prefix(output_path)
-                .unwrap()
-                .to_string_lossy()
-                .to_string();
+    /// Checkpoint the write-ahead log into the database file
+    Checkpoint {
+        /// Checkpoint mode; `truncate` also shrinks the `-wal` file to zero
+        #[arg(long, default_value = "truncate", value_parser = ["passive", "full", "truncate"])]
+        mode: String,
+    },
+}
 
-            contents.push(Content {
-                path: rel_path,
-                hash: hash.to_hex(),
-                size,
-                executable,
-            });
+/// Async reader adapter that reports the running byte count
+struct ProgressReader<R, F> {
+    inner: R,
+    total: u64,
+    progress: F,
+}
 
-            tracing::debug!("Processed file: {} (hash: {})", path.display(), hash);
+impl<R, F: FnMut(u64)> ProgressReader<R, F> {
+    fn new(inner: R, progress: F) -> Self {
+        Self {
+            inner,
+            total: 0,
+            progress,
         }
     }
+}
 
-    if contents.is_empty() {
-        anyhow::bail!("No files found in output directory: {}", output_dir);
+impl<R: tokio::io::AsyncRead + Unpin, F: FnMut(u64) + Unpin> tokio::io::AsyncRead
+    for ProgressReader<R, F>
+{
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = std::pin::Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        let read = (buf.filled().len() - before) as u64;
+        if read > 0 {
+            self.total += read;
+            let total = self.total;
+            (self.progress)(total);
+        }
+        poll
     }
+}
 
-    tracing::info!("Processed {} output files", contents.len());
+/// Byte progress bar on stderr, hidden when quiet or stderr isn't a terminal
+fn progress_bar(len: Option<u64>, quiet: bool) -> indicatif::ProgressBar {
+    use std::io::IsTerminal;
 
-    // Get source hash for provenance
-    let source_hash = input_manifest_data
-        .source
-        .archive_hash
-        .clone()
-        .unwrap_or_else(|| "blake3:unknown".to_string());
+    if quiet || !std::io::stderr().is_terminal() {
+        return indicatif::ProgressBar::hidden();
+    }
 
-    // Create transformation record
-    let new_transformation = Transformation {
-        transform_type: transform_type.to_string(),
-        from: source_hash.clone(),
-        params: None,
-    };
+    match len {
+        Some(len) => indicatif::ProgressBar::new(len).with_style(
+            indicatif::ProgressStyle::with_template(
+                "{bar:40} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            )
+            .expect("valid progress template"),
+        ),
+        None => indicatif::ProgressBar::new_spinner().with_style(
+            indicatif::ProgressStyle::with_template("{spinner} {bytes} ({bytes_per_sec})")
+                .expect("valid progress template"),
+        ),
+    }
+}
 
-    // Build transformations array (preserve existing + add new)
-    let mut transformations = input_manifest_data.transformations.clone();
-    transformations.push(new_transformation);
+/// Result of `cast init`
+#[derive(Debug, serde::Serialize)]
+struct InitOutput {
+    root: std::path::PathBuf,
+    database: std::path::PathBuf,
+    /// Config file written, if any
+    config_file: Option<std::path::PathBuf>,
+}
 
-    // Generate output manifest
-    let output_manifest = Manifest {
-        schema_version: "1.0".to_string(),
-        dataset: input_manifest_data.dataset.clone(),
-        source: input_manifest_data.source.clone(),
-        contents,
-        transformations,
+/// Create the store root, object directory, and metadata database
+///
+/// An existing store is refused unless `force` is set; its contents are
+/// never removed either way. A starter config pointing at the store is
+/// written to `config_file` only if none exists there; `force` does not
+/// overwrite it.
+async fn init_store(root: &Path, force: bool, config_file: Option<&Path>) -> Result<InitOutput> {
+    let config = StorageConfig {
+        root: root.to_path_buf(),
+        ..Default::default()
     };
 
-    // Output manifest as JSON to stdout
-    let manifest_json = serde_json::to_string_pretty(&output_manifest)
-        .context("Failed to serialize output manifest")?;
+    if !force && (config.store_path().exists() || config.db_path().exists()) {
+        anyhow::bail!(
+            "A store is already initialized at {}; pass --force to initialize it anyway",
+            root.display()
+        );
+    }
 
-    println!("{}", manifest_json);
+    LocalStorage::new(config.clone()).initialize().await?;
+    open_db(&config).await?;
 
-    Ok(())
+    let config_file = match config_file {
+        Some(path) if !path.exists() => {
+            config.save_to(path).await?;
+            Some(path.to_path_buf())
+        }
+        Some(path) => {
+            tracing::warn!("Leaving existing config file untouched: {}", path.display());
+            None
+        }
+        None => None,
+    };
+
+    Ok(InitOutput {
+        root: root.to_path_buf(),
+        database: config.db_path(),
+        config_file,
+    })
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing subscriber for logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+/// Init command implementation
+async fn init_command(
+    path: Option<&str>,
+    force: bool,
+    profile: Option<&str>,
+    store: Option<&str>,
+    format: Format,
+) -> Result<()> {
+    let root = match path {
+        Some(path) => {
+            let expanded = storage::config::expand_path(path)?;
+            std::path::absolute(&expanded)
+                .with_context(|| format!("Failed to resolve store path: {}", expanded.display()))?
+        }
+        None => load_config(profile, store).await?.root,
+    };
 
-    let cli = Cli::parse();
+    let config_file = StorageConfig::config_file_path();
+    let output = init_store(&root, force, config_file.as_deref()).await?;
 
-    match cli.command {
-        Commands::Put { file } => {
-            tracing::info!("Storing file: {}", file);
-            println!("Stub: Would store file {}", file);
-            println!("This will be implemented in task 5 (BLAKE3 hashing)");
-            Ok(())
-        }
-        Commands::Get { hash } => {
-            tracing::info!("Retrieving file with hash: {}", hash);
-            println!("Stub: Would retrieve file with hash {}", hash);
-            println!("This will be implemented in task 6 (Local storage backend)");
-            Ok(())
-        }
-        Commands::Fetch { url, hash } => {
-            tracing::info!("Fetching from URL: {}", url);
-            if let Some(h) = hash {
-                tracing::info!("Expected hash: {}", h);
-            }
-            println!("Stub: Would fetch from {}", url);
-            println!("This will be implemented in Phase 2");
-            Ok(())
-        }
-        Commands::Transform {
-            input_manifest,
-            output_dir,
-            transform_type,
-        } => {
-            transform_command(&input_manifest, &output_dir, &transform_type).await
-        }
-        Commands::Gc { dry_run } => {
-            tracing::info!("Running garbage collection (dry_run: {})", dry_run);
-            println!("Stub: Would run garbage collection");
-            println!("This will be implemented in Phase 4");
-            Ok(())
+    output::emit(format, &output, |output| {
+        println!("Initialized store at {}", output.root.display());
+        if let Some(config_file) = &output.config_file {
+            println!("Wrote config to {}", config_file.display());
         }
-    }
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+/// Put command implementation
+///
+/// Stores `file` (or stdin when `file` is `-`) and registers the object in
+/// the metadata database. With `preserve`, the file's name, permissions, and
+/// mtime are recorded in the object's metadata for `get --restore`. With
+/// `chunked`, it is stored as content-defined chunks (see
+/// `LocalStorage::put_chunked`).
+async fn put_command(
+    config: &StorageConfig,
+    file: &str,
+    preserve: bool,
+    chunked: bool,
+    ttl: Option<chrono::Duration>,
+    format: Format,
+    quiet: bool,
+) -> Result<PutResult> {
+    if preserve && file == "-" {
+        anyhow::bail!("--preserve needs a file path, not stdin");
+    }
 
-    #[test]
-    fn test_cli_parsing() {
-        // Test that CLI parsing works
-        use clap::CommandFactory;
-        Cli::command().debug_assert();
+    if file != "-" && !Path::new(file).is_file() {
+        anyhow::bail!("File not found: {}", file);
     }
 
-    #[tokio::test]
-    async fn test_transform_command() {
-        // Create temp directory for output
-        let temp_dir = TempDir::new().unwrap();
-        let output_dir = temp_dir.path();
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
 
-        // Create a test file in output directory
-        let test_file = output_dir.join("test.txt");
-        tokio::fs::write(&test_file, b"transformed data").await.unwrap();
+    let result = if chunked {
+        // Chunking reads synchronously on a blocking thread, so no progress bar
+        if file == "-" {
+            storage.put_chunked(std::io::stdin()).await?
+        } else {
+            let handle = std::fs::File::open(file).with_context(|| format!("Failed to open file: {}", file))?;
+            storage
+                .put_chunked(handle)
+                .await
+                .with_context(|| format!("Failed to store file: {}", file))?
+        }
+    } else if file == "-" {
+        let bar = progress_bar(None, quiet);
+        let reader = ProgressReader::new(tokio::io::stdin(), |total| bar.set_position(total));
+        let result = storage.put_reader(reader).await;
+        bar.finish_and_clear();
+        result?
+    } else {
+        let handle = tokio::fs::File::open(file)
+            .await
+            .with_context(|| format!("Failed to open file: {}", file))?;
+        let bar = progress_bar(Some(handle.metadata().await?.len()), quiet);
+        let reader = ProgressReader::new(handle, |total| bar.set_position(total));
+        let result = storage.put_reader(reader).await;
+        bar.finish_and_clear();
+        result.with_context(|| format!("Failed to store file: {}", file))?
+    };
 
-        // Create input manifest
-        let manifest_dir = TempDir::new().unwrap();
-        let input_manifest_path = manifest_dir.path().join("input-manifest.json");
+    let db = open_db(config).await?;
+    let key = result.hash.to_string_prefixed();
+    let existed = db.get_object(&key).await?.is_some();
+    db.register_object(&key, result.size as i64, None).await?;
+
+    if preserve {
+        let file_metadata = FileMetadata::from_path(Path::new(file))?;
+        let existing = db.get_object(&key).await?.and_then(|record| record.metadata);
+        db.set_object_metadata(&key, &file_metadata.merge_into(existing.as_deref())?)
+            .await?;
+    }
+
+    // An object that was already stored keeps its expiry (or lack of one)
+    match ttl {
+        Some(_) if existed => tracing::warn!("{} was already stored; --ttl leaves its expiry unchanged", result.hash),
+        Some(ttl) => db.set_object_expiry(&key, chrono::Utc::now() + ttl).await?,
+        None => {}
+    }
+    mirror_objects(config, [result.hash]).await?;
+
+    output::emit(format, &result, |result| {
+        println!("{}", result.hash);
+        if result.deduplicated {
+            eprintln!("Already stored (deduplicated)");
+        }
+    })?;
+
+    Ok(result)
+}
+
+/// Result of `cast put --recursive`
+#[derive(Debug, serde::Serialize)]
+struct PutTreeOutput {
+    manifest_hash: Blake3Hash,
+    name: String,
+    version: String,
+    files: usize,
+    size: u64,
+    /// Files whose content was already stored, in the store or earlier in the tree
+    deduplicated: usize,
+}
+
+/// Result of `cast put --recursive --dry-run`
+#[derive(Debug, serde::Serialize)]
+struct PutTreePreview {
+    files: usize,
+    new_files: usize,
+    deduplicated: usize,
+    new_bytes: u64,
+}
+
+/// Collect every file and symlink under `root`, depth first in path order
+///
+/// Returns `(relative path, absolute path, is_symlink)` triples; relative
+/// paths always use `/` separators. Symlinks are not followed.
+async fn walk_tree(root: &Path) -> Result<Vec<(String, std::path::PathBuf, bool)>> {
+    let mut entries = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut children = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            children.push((entry.path(), entry.file_type().await?));
+        }
+        children.sort_by(|a, b| b.0.cmp(&a.0));
+
+        for (path, file_type) in children {
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !file_type.is_file() && !file_type.is_symlink() {
+                tracing::warn!("Skipping special file: {}", path.display());
+                continue;
+            }
+
+            let rel_path = relative_path(root, &path)?;
+            entries.push((rel_path, path, file_type.is_symlink()));
+        }
+    }
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+/// Path of `path` relative to `root`, with `/` separators
+///
+/// Fails if `path` is not under `root` or the remainder would step outside
+/// it through `..` components.
+fn relative_path(root: &Path, path: &Path) -> Result<String> {
+    let rel = path
+        .strip_prefix(root)
+        .with_context(|| format!("Path {} is not under {}", path.display(), root.display()))?;
+
+    let mut parts = Vec::new();
+    for component in rel.components() {
+        match component {
+            std::path::Component::Normal(part) => parts.push(part.to_string_lossy()),
+            std::path::Component::CurDir => {}
+            _ => anyhow::bail!("Path {} escapes {}", path.display(), root.display()),
+        }
+    }
+    if parts.is_empty() {
+        anyhow::bail!("Path {} is the directory itself, not an entry in it", path.display());
+    }
+    Ok(parts.join("/"))
+}
+
+/// Summarize what a directory without regular files or symlinks contains
+///
+/// Used to explain why a scan found nothing, e.g. "it contains only 2 empty
+/// subdirectories".
+async fn describe_empty_dir(root: &Path) -> Result<String> {
+    let (mut dirs, mut special) = (0, 0);
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut read_dir = tokio::fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                dirs += 1;
+                pending.push(entry.path());
+            } else {
+                special += 1;
+            }
+        }
+    }
+
+    let plural = |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    Ok(match (dirs, special) {
+        (0, 0) => "it is empty".to_string(),
+        (dirs, 0) => format!("it contains only {}", plural(dirs, "empty subdirectory", "empty subdirectories")),
+        (0, special) => format!("it contains only {}", plural(special, "special file", "special files")),
+        (dirs, special) => format!(
+            "it contains only {} and {}",
+            plural(dirs, "subdirectory", "subdirectories"),
+            plural(special, "special file", "special files")
+        ),
+    })
+}
+
+/// Put --recursive implementation
+///
+/// Stores every file under `dir`, builds a manifest listing them (relative
+/// paths, sizes, executable bits, symlink targets), stores the manifest, and
+/// registers it as dataset `name`/`version`. A file the same size as one
+/// already stored from the tree is hashed before storing, and a copy of
+/// stored content only gets a manifest entry, not another write to `tmp/`.
+/// `name` must be a valid new dataset name (see `Dataset::split_name`).
+async fn put_tree_command(
+    config: &StorageConfig,
+    dir: &str,
+    name: &str,
+    version: &str,
+    format: Format,
+    quiet: bool,
+) -> Result<(Manifest, PutTreeOutput)> {
+    manifest::Dataset::split_name(name)?;
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        anyhow::bail!("Directory not found: {}", dir);
+    }
+
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
+
+    let entries = walk_tree(root).await?;
+    let mut total = 0;
+    for (_, path, is_symlink) in &entries {
+        if !is_symlink {
+            total += tokio::fs::metadata(path).await?.len();
+        }
+    }
+
+    let buf_size = config.hash_buffer_size();
+    let bar = progress_bar(Some(total), quiet);
+    let mut contents = Vec::with_capacity(entries.len());
+    let mut stored = std::collections::HashSet::new();
+    let mut stored_sizes = std::collections::HashSet::new();
+    let mut deduplicated = 0;
+    for (rel_path, path, is_symlink) in entries {
+        if is_symlink {
+            let target = tokio::fs::read_link(&path)
+                .await
+                .with_context(|| format!("Failed to read symlink: {}", path.display()))?
+                .to_string_lossy()
+                .to_string();
+            contents.push(Content {
+                path: rel_path,
+                hash: Blake3Hash::from_bytes(target.as_bytes()).to_string_prefixed(),
+                size: target.len() as u64,
+                executable: false,
+                mime_type: None,
+                kind: ContentKind::Symlink { target },
+                derived_from: Vec::new(),
+            });
+            continue;
+        }
+
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .with_context(|| format!("Failed to stat file: {}", path.display()))?;
+        let size = metadata.len();
+
+        let mut copy_of = None;
+        if stored_sizes.contains(&size) {
+            let hash_path = path.clone();
+            let hash = tokio::task::spawn_blocking(move || Blake3Hash::from_file_with_capacity(&hash_path, buf_size))
+                .await
+                .context("Hashing task panicked")?
+                .with_context(|| format!("Failed to hash file: {}", path.display()))?;
+            copy_of = stored.contains(&hash).then_some(hash);
+        }
+        let hash = match copy_of {
+            Some(hash) => {
+                deduplicated += 1;
+                hash
+            }
+            None => {
+                let result = storage
+                    .put_file(&path)
+                    .await
+                    .with_context(|| format!("Failed to store file: {}", path.display()))?;
+                if result.deduplicated {
+                    deduplicated += 1;
+                }
+                stored.insert(result.hash);
+                stored_sizes.insert(result.size);
+                result.hash
+            }
+        };
+        bar.inc(size);
+
+        #[cfg(unix)]
+        let executable = metadata.permissions().mode() & 0o111 != 0;
+        #[cfg(not(unix))]
+        let executable = false;
+
+        contents.push(Content {
+            path: rel_path,
+            hash: hash.to_string_prefixed(),
+            size,
+            executable,
+            mime_type: guess_mime_type(&path),
+            kind: ContentKind::File,
+            derived_from: Vec::new(),
+        });
+    }
+    bar.finish_and_clear();
+
+    let manifest = Manifest {
+        schema_version: manifest::CURRENT_SCHEMA_VERSION.to_string(),
+        dataset: manifest::Dataset {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: None,
+        },
+        source: manifest::Source {
+            url: None,
+            download_date: None,
+            server_mtime: None,
+            archive_hash: None,
+            archive_type: None,
+        },
+        contents,
+        transformations: Vec::new(),
+        signature: None,
+    };
+    manifest.validate()?;
+
+    let manifest_hash = manifest.content_hash();
+    storage.register_dataset(&manifest).await?;
+    let db = open_db(config).await?;
+    db.register_manifest(&manifest, &manifest_hash.to_string_prefixed())
+        .await?;
+
+    let stored: std::collections::HashSet<Blake3Hash> = manifest
+        .contents
+        .iter()
+        .filter(|c| c.kind.is_file())
+        .map(|c| Ok(Blake3Hash::from_str(&c.hash)?))
+        .collect::<Result<_>>()?;
+    mirror_objects(config, stored.into_iter().chain([manifest_hash])).await?;
+
+    let summary = PutTreeOutput {
+        manifest_hash,
+        name: name.to_string(),
+        version: version.to_string(),
+        files: manifest.contents.len(),
+        size: manifest.contents.iter().map(|c| c.size).sum(),
+        deduplicated,
+    };
+    output::emit(format, &summary, |summary| {
+        println!("{}", summary.manifest_hash);
+        eprintln!(
+            "Stored {} file(s), {} bytes, as {}/{} ({} deduplicated)",
+            summary.files, summary.size, summary.name, summary.version, summary.deduplicated
+        );
+    })?;
+
+    Ok((manifest, summary))
+}
+
+/// Put --recursive --dry-run implementation
+///
+/// Hashes every file under `dir` as `scan_entry` does for transform outputs,
+/// and checks it against the store without storing anything. A file is
+/// counted as deduplicated if its content is already stored or appears
+/// elsewhere in the tree; symlinks are not counted.
+async fn put_tree_preview_command(
+    config: &StorageConfig,
+    dir: &str,
+    format: Format,
+    quiet: bool,
+) -> Result<PutTreePreview> {
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        anyhow::bail!("Directory not found: {}", dir);
+    }
+
+    let storage = LocalStorage::new(config.clone());
+    let buf_size = config.hash_buffer_size();
+    let files: Vec<_> = walk_tree(root)
+        .await?
+        .into_iter()
+        .filter(|(_, _, is_symlink)| !is_symlink)
+        .collect();
+    let mut total = 0;
+    for (_, path, _) in &files {
+        total += tokio::fs::metadata(path).await?.len();
+    }
+
+    let bar = progress_bar(Some(total), quiet);
+    let bar = &bar;
+    let contents = map_bounded(files, DEFAULT_SCAN_JOBS, |(rel_path, path, is_symlink)| async move {
+        let content = scan_entry(rel_path, path, is_symlink, buf_size).await?;
+        bar.inc(content.size);
+        Ok(content)
+    })
+    .await?;
+
+    let mut seen = std::collections::HashSet::new();
+    let mut preview = PutTreePreview {
+        files: contents.len(),
+        new_files: 0,
+        deduplicated: 0,
+        new_bytes: 0,
+    };
+    for content in &contents {
+        let hash = Blake3Hash::from_str(&content.hash)?;
+        if seen.insert(hash) && !storage.exists(&hash).await {
+            preview.new_files += 1;
+            preview.new_bytes += content.size;
+        } else {
+            preview.deduplicated += 1;
+        }
+    }
+    bar.finish_and_clear();
+
+    output::emit(format, &preview, |preview| {
+        println!(
+            "Would store {} new file(s), {} bytes; {} already present",
+            preview.new_files, preview.new_bytes, preview.deduplicated
+        );
+    })?;
+    Ok(preview)
+}
+
+/// Parse a full hash, falling back to resolving it as a unique prefix
+async fn resolve_hash(storage: &LocalStorage, hash: &str) -> Result<Blake3Hash> {
+    match Blake3Hash::from_str(hash) {
+        Ok(hash) => Ok(hash),
+        Err(_) => storage.resolve_prefix(hash).await,
+    }
+}
+
+/// Hashes a command should act on
+///
+/// A single hash argument is returned as is; `-` or `--stdin` reads one hash
+/// per line from stdin, ignoring blank lines.
+fn hash_args(hash: Option<String>, stdin: bool) -> Result<Vec<String>> {
+    match hash {
+        Some(hash) if hash != "-" => Ok(vec![hash]),
+        _ if stdin || hash.is_some() => read_hash_list(std::io::stdin().lock()),
+        _ => anyhow::bail!("No hash given"),
+    }
+}
+
+/// Read one hash per line, skipping blank lines
+fn read_hash_list(reader: impl std::io::BufRead) -> Result<Vec<String>> {
+    let mut hashes = Vec::new();
+    for line in reader.lines() {
+        let line = line.context("Failed to read hashes from stdin")?;
+        let line = line.trim();
+        if !line.is_empty() {
+            hashes.push(line.to_string());
+        }
+    }
+    Ok(hashes)
+}
+
+/// Run `op` for each hash
+///
+/// A single hash behaves exactly like calling `op` directly. With several,
+/// each failure is reported on stderr and the rest still run; the batch
+/// fails if any hash did.
+async fn for_each_hash<F, Fut>(hashes: &[String], mut op: F) -> Result<()>
+where
+    F: FnMut(String) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    if let [hash] = hashes {
+        return op(hash.clone()).await;
+    }
+
+    let mut failed = 0;
+    for hash in hashes {
+        if let Err(e) = op(hash.clone()).await {
+            eprintln!("Error: {}: {:#}", hash, e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} of {} hashes failed", failed, hashes.len());
+    }
+    Ok(())
+}
+
+/// What a `get` argument refers to
+#[derive(Debug, PartialEq, Eq)]
+enum ObjectRef<'a> {
+    /// A full hash or unique hash prefix
+    Hash(&'a str),
+    /// The manifest of a dataset version (`latest` for the newest)
+    Dataset { name: &'a str, version: &'a str },
+}
+
+/// Parse `<name>@<version>` as a dataset reference, and anything else as a hash
+fn parse_object_ref(reference: &str) -> Result<ObjectRef<'_>> {
+    match reference.split_once('@') {
+        None => Ok(ObjectRef::Hash(reference)),
+        Some((name, version)) if !name.is_empty() && !version.is_empty() && !version.contains('@') => {
+            Ok(ObjectRef::Dataset { name, version })
+        }
+        Some(_) => anyhow::bail!("Invalid dataset reference '{}' (expected <name>@<version>)", reference),
+    }
+}
+
+/// Resolve a `get` argument to an object hash
+async fn resolve_object_ref(config: &StorageConfig, storage: &LocalStorage, reference: &str) -> Result<Blake3Hash> {
+    match parse_object_ref(reference)? {
+        ObjectRef::Hash(hash) => resolve_hash(storage, hash).await,
+        ObjectRef::Dataset { name, version } => resolve_dataset_ref(config, name, version).await,
+    }
+}
+
+/// Resolve `<name>@<version>` to the hash of that dataset version's manifest
+///
+/// The version `latest` means the most recently registered one.
+async fn resolve_dataset_ref(config: &StorageConfig, name: &str, version: &str) -> Result<Blake3Hash> {
+    let db = open_db(config).await?;
+    let record = if version == "latest" {
+        db.latest_version(name).await?
+    } else {
+        db.get_dataset(name, version).await?
+    };
+    let record = record.with_context(|| format!("Dataset not found: {}@{}", name, version))?;
+    Ok(Blake3Hash::from_str(&record.manifest_hash)?)
+}
+
+/// Where `cast get` put an object
+#[derive(Debug, serde::Serialize)]
+struct GetOutput {
+    hash: Blake3Hash,
+    path: std::path::PathBuf,
+}
+
+/// Get command implementation
+///
+/// `--stdout` always writes raw bytes, regardless of the output format.
+async fn get_command(
+    config: &StorageConfig,
+    hash: &str,
+    output: Option<&str>,
+    stdout: bool,
+    format: Format,
+) -> Result<()> {
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_object_ref(config, &storage, hash).await?;
+    let _lock = storage.lock_shared_for_read().await;
+
+    if !storage.exists(&hash).await && !repair_from_mirror(config, &hash).await {
+        anyhow::bail!("Object {} is not present in the store at {}", hash, config.root.display());
+    }
+    record_access(config, &hash).await;
+
+    if stdout {
+        let mut reader = storage.get_reader(&hash)?;
+        return tokio::task::spawn_blocking(move || {
+            use std::io::Write;
+
+            let mut out = std::io::stdout().lock();
+            std::io::copy(&mut reader, &mut out)
+                .and_then(|_| out.flush())
+                .context("Failed to write object to stdout")
+        })
+        .await
+        .context("Get task panicked")?;
+    }
+
+    let path = match output {
+        Some(output) => {
+            let mut reader = storage.get_reader(&hash)?;
+            let target = std::path::PathBuf::from(output);
+            let dest = target.clone();
+            tokio::task::spawn_blocking(move || -> Result<()> {
+                let mut file = std::fs::File::create(&dest)
+                    .with_context(|| format!("Failed to create {}", dest.display()))?;
+                std::io::copy(&mut reader, &mut file)?;
+                Ok(())
+            })
+            .await
+            .context("Get task panicked")?
+            .with_context(|| format!("Failed to copy {} to {}", hash, output))?;
+            tracing::info!("Copied {} to {}", hash, output);
+            target
+        }
+        None => {
+            let path = storage.get(&hash).await?;
+            std::fs::canonicalize(&path)
+                .with_context(|| format!("Failed to resolve path: {}", path.display()))?
+        }
+    };
+
+    let result = GetOutput { hash, path };
+    output::emit(format, &result, |result| {
+        if output.is_none() {
+            println!("{}", result.path.display());
+        }
+    })
+}
+
+/// Get --materialize implementation
+///
+/// Reads the referenced manifest object and recreates its file tree in `dir`.
+async fn materialize_command(config: &StorageConfig, reference: &str, dir: &Path, format: Format) -> Result<()> {
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_object_ref(config, &storage, reference).await?;
+    let _lock = storage.lock_shared_for_read().await;
+
+    let mut json = String::new();
+    std::io::Read::read_to_string(&mut storage.get_reader(&hash)?, &mut json)
+        .with_context(|| format!("Failed to read manifest object: {}", hash))?;
+    let manifest = Manifest::from_json(&json).with_context(|| format!("Object {} is not a manifest", hash))?;
+    storage.materialize(&manifest, dir).await?;
+
+    let result = GetOutput {
+        hash,
+        path: dir.to_path_buf(),
+    };
+    output::emit(format, &result, |result| {
+        println!(
+            "Materialized {}/{} into {}",
+            manifest.dataset.name,
+            manifest.dataset.version,
+            result.path.display()
+        )
+    })
+}
+
+/// Result of `cast cp`
+#[derive(Debug, serde::Serialize)]
+struct CpOutput {
+    name: String,
+    version: String,
+    pattern: String,
+    dest: std::path::PathBuf,
+    files: usize,
+    size: u64,
+}
+
+/// Cp command implementation
+///
+/// `*` and `?` stop at `/`, while `**` spans directories. Fails without
+/// writing anything if the pattern matches no content entry.
+async fn cp_command(
+    config: &StorageConfig,
+    name: &str,
+    version: &str,
+    pattern: &str,
+    dest: &Path,
+    format: Format,
+) -> Result<CpOutput> {
+    let matcher = globset::GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid glob pattern: {}", pattern))?
+        .compile_matcher();
+
+    let db = open_db(config).await?;
+    let storage = LocalStorage::new(config.clone());
+    let mut manifest = load_dataset_manifest(&db, &storage, name, version).await?;
+    manifest.contents.retain(|content| matcher.is_match(&content.path));
+    if manifest.contents.is_empty() {
+        anyhow::bail!("No paths in {}/{} match {}", name, version, pattern);
+    }
+
+    let _lock = storage.lock_shared_for_read().await;
+    storage.copy_out(&manifest, dest).await?;
+
+    let result = CpOutput {
+        name: name.to_string(),
+        version: version.to_string(),
+        pattern: pattern.to_string(),
+        dest: dest.to_path_buf(),
+        files: manifest.contents.len(),
+        size: manifest.contents.iter().filter(|c| c.kind.is_file()).map(|c| c.size).sum(),
+    };
+    output::emit(format, &result, |result| {
+        println!(
+            "Copied {} entries ({} bytes) of {}/{} into {}",
+            result.files,
+            result.size,
+            result.name,
+            result.version,
+            result.dest.display()
+        )
+    })?;
+    Ok(result)
+}
+
+/// Get --restore implementation
+///
+/// Writes the object into `dir` under the name recorded by `put --preserve`,
+/// then applies the recorded permissions and mtime.
+async fn restore_command(config: &StorageConfig, hash: &str, dir: &Path, format: Format) -> Result<std::path::PathBuf> {
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_hash(&storage, hash).await?;
+    let _lock = storage.lock_shared_for_read().await;
+    if !storage.exists(&hash).await && !repair_from_mirror(config, &hash).await {
+        anyhow::bail!("Object {} is not present in the store at {}", hash, config.root.display());
+    }
+
+    let db = open_db(config).await?;
+    let record = db.get_object(&hash.to_string_prefixed()).await?;
+    record_access(config, &hash).await;
+    let file_metadata = FileMetadata::from_object_metadata(record.and_then(|r| r.metadata).as_deref())
+        .with_context(|| format!("No file metadata recorded for {} (store it with `put --preserve`)", hash))?;
+
+    // Never let a recorded name escape the target directory
+    let name = Path::new(&file_metadata.name);
+    if name.components().count() != 1 || name.file_name().is_none() {
+        anyhow::bail!("Refusing to restore to unsafe file name: {}", file_metadata.name);
+    }
+    let path = dir.join(name);
+
+    let mut reader = storage.get_reader(&hash)?;
+    let dest = path.clone();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut file = std::fs::File::create(&dest)
+            .with_context(|| format!("Failed to create {}", dest.display()))?;
+        std::io::copy(&mut reader, &mut file)
+            .with_context(|| format!("Failed to write {}", dest.display()))?;
+
+        if let Some(mtime) = file_metadata.mtime {
+            let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime.max(0) as u64);
+            file.set_modified(mtime)
+                .with_context(|| format!("Failed to set mtime of {}", dest.display()))?;
+        }
+
+        // Setuid, setgid, and sticky bits are never restored
+        #[cfg(unix)]
+        std::fs::set_permissions(&dest, std::fs::Permissions::from_mode(file_metadata.mode & 0o777))
+            .with_context(|| format!("Failed to set permissions of {}", dest.display()))?;
+        Ok(())
+    })
+    .await
+    .context("Restore task panicked")??;
+
+    tracing::info!("Restored {} to {}", hash, path.display());
+    let result = GetOutput { hash, path };
+    output::emit(format, &result, |result| println!("{}", result.path.display()))?;
+    Ok(result.path)
+}
+
+/// Download attempts `fetch` makes before giving up on a flaky connection
+const FETCH_ATTEMPTS: usize = 3;
+
+/// Validators saved next to a partial download
+///
+/// A later attempt only resumes with a `Range` request when the server
+/// advertised byte ranges, and sends the ETag as `If-Range` so a changed
+/// resource is downloaded from scratch.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct PartialDownload {
+    accept_ranges: bool,
+    etag: Option<String>,
+}
+
+/// Download `url` into `part`, resuming an earlier partial download if possible
+///
+/// Returns the server's `Last-Modified` header. When the transfer is
+/// interrupted the partial file is kept for the next attempt.
+async fn download_http(
+    client: &reqwest::Client,
+    url: &reqwest::Url,
+    part: &Path,
+    throttle: Option<&Throttle>,
+    quiet: bool,
+) -> Result<Option<String>> {
+    use reqwest::header::{ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+    use reqwest::StatusCode;
+
+    let header = |response: &reqwest::Response, name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+            .map(str::to_string)
+    };
+
+    if let Some(parent) = part.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    let meta_path = part.with_extension("json");
+    let previous: Option<PartialDownload> = match tokio::fs::read(&meta_path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).ok(),
+        Err(_) => None,
+    };
+    let mut offset = match (&previous, tokio::fs::metadata(part).await) {
+        (Some(previous), Ok(metadata)) if previous.accept_ranges => metadata.len(),
+        _ => 0,
+    };
+    let previous_etag = previous.and_then(|p| p.etag);
+
+    let mut response = loop {
+        let mut request = client.get(url.clone());
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+            if let Some(etag) = &previous_etag {
+                request = request.header(IF_RANGE, etag);
+            }
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to download: {}", url))?;
+
+        if offset > 0 {
+            let expected_range = format!("bytes {}-", offset);
+            let resumed = response.status() == StatusCode::PARTIAL_CONTENT
+                && header(&response, CONTENT_RANGE).is_some_and(|r| r.starts_with(&expected_range))
+                && match (&previous_etag, header(&response, ETAG)) {
+                    (Some(old), Some(new)) => *old == new,
+                    _ => true,
+                };
+
+            if !resumed {
+                tracing::info!("Cannot resume download of {}; restarting", url);
+                offset = 0;
+                // A full response can be used as is; anything else is re-requested
+                if response.status() != StatusCode::OK {
+                    continue;
+                }
+            }
+        }
+
+        break response
+            .error_for_status()
+            .with_context(|| format!("Failed to download: {}", url))?;
+    };
+
+    let server_mtime = header(&response, LAST_MODIFIED);
+    let validators = PartialDownload {
+        accept_ranges: response.status() == StatusCode::PARTIAL_CONTENT
+            || header(&response, ACCEPT_RANGES).is_some_and(|v| v.eq_ignore_ascii_case("bytes")),
+        etag: header(&response, ETAG),
+    };
+    let validators = serde_json::to_vec(&validators).context("Failed to serialize download state")?;
+    tokio::fs::write(&meta_path, validators)
+        .await
+        .with_context(|| format!("Failed to write download state: {}", meta_path.display()))?;
+
+    let file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(offset > 0)
+        .truncate(offset == 0)
+        .open(part)
+        .await
+        .with_context(|| format!("Failed to open download: {}", part.display()))?;
+    let mut file: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = match throttle {
+        Some(throttle) => Box::new(ThrottledWriter::new(file, throttle.clone())),
+        None => Box::new(file),
+    };
+
+    let bar = progress_bar(response.content_length().map(|len| offset + len), quiet);
+    bar.set_position(offset);
+    let result: Result<()> = async {
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .with_context(|| format!("Download interrupted: {}", url))?
+        {
+            file.write_all(&chunk)
+                .await
+                .with_context(|| format!("Failed to write download: {}", part.display()))?;
+            offset += chunk.len() as u64;
+            bar.set_position(offset);
+        }
+        file.flush().await.context("Failed to flush download")
+    }
+    .await;
+    bar.finish_and_clear();
+    result?;
+
+    Ok(server_mtime)
+}
+
+/// Whether a download error is worth retrying (the connection, not the server)
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| {
+                e.is_body() || e.is_decode() || e.is_timeout() || e.is_connect() || e.is_request()
+            })
+    })
+}
+
+/// Discard a partial download and its saved validators
+async fn remove_partial_download(part: &Path) {
+    let _ = tokio::fs::remove_file(part).await;
+    let _ = tokio::fs::remove_file(part.with_extension("json")).await;
+}
+
+/// Fetch command implementation
+///
+/// Streams `url` (http, https, or file) into the store, verifying it against
+/// `expected_hash` when given, and registers the object with its `Source`
+/// as metadata. Nothing is stored if verification fails. `rate` caps the
+/// transfer in bytes per second, across any resumed attempts. With
+/// `mirrors`, the download comes from whichever http(s) URL answers first
+/// (see `fetch::race_mirrors`), and the source records that URL.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_command(
+    config: &StorageConfig,
+    url: &str,
+    mirrors: &[String],
+    expected_hash: Option<&str>,
+    checksums: Option<&str>,
+    rate: Option<u64>,
+    format: Format,
+    quiet: bool,
+) -> Result<PutResult> {
+    let mut expected = expected_hash
+        .map(Blake3Hash::from_str)
+        .transpose()
+        .context("Invalid --hash")?;
+
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("Invalid URL: {}", url))?;
+
+    // BLAKE3 entries are checked as the object is stored; other digests are
+    // checked against the downloaded file first
+    let checksum = match checksums {
+        Some(source) => Some(checksum_entry(source, &parsed).await?),
+        None => None,
+    };
+    let checksum = match checksum {
+        Some(entry) => match entry.hashes[..] {
+            [hash::ContentHash::Blake3(hash)] => {
+                expected = Some(hash);
+                None
+            }
+            _ => Some(entry),
+        },
+        None => None,
+    };
+
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
+    let throttle = rate.map(Throttle::new);
+
+    let download_date = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let mut source_url = url.to_string();
+    let (result, server_mtime) = match parsed.scheme() {
+        _ if !mirrors.is_empty() => {
+            let mut urls = vec![parsed.clone()];
+            for mirror in mirrors {
+                urls.push(reqwest::Url::parse(mirror).with_context(|| format!("Invalid mirror URL: {}", mirror))?);
+            }
+            if let Some(url) = urls.iter().find(|u| !matches!(u.scheme(), "http" | "https")) {
+                anyhow::bail!("Mirrored fetches need http(s) URLs: {}", url);
+            }
+            if expected.is_none() && checksum.is_none() {
+                tracing::warn!(
+                    "Fetching from {} mirrors without --hash or --checksums; whichever answers first is stored unverified",
+                    urls.len()
+                );
+            }
+            let mirrored =
+                fetch::race_mirrors(&storage, &urls, expected.as_ref(), checksum.as_ref(), throttle.as_ref(), quiet)
+                    .await?;
+            source_url = mirrored.url.to_string();
+            (mirrored.result, mirrored.server_mtime)
+        }
+        "http" | "https" => {
+            let client = reqwest::Client::new();
+            let part = storage.partial_download_path(url);
+
+            let mut attempt = 1;
+            let server_mtime = loop {
+                match download_http(&client, &parsed, &part, throttle.as_ref(), quiet).await {
+                    Ok(server_mtime) => break server_mtime,
+                    Err(e) if attempt < FETCH_ATTEMPTS && is_transient(&e) => {
+                        attempt += 1;
+                        tracing::warn!("{:#}; resuming (attempt {}/{})", e, attempt, FETCH_ATTEMPTS);
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+
+            // The download is complete, so the partial file is discarded
+            // whether or not it hashes correctly
+            if let Some(entry) = &checksum {
+                if let Err(e) = checksums::verify_file(&part, entry).await {
+                    remove_partial_download(&part).await;
+                    return Err(e);
+                }
+            }
+            let file = tokio::fs::File::open(&part)
+                .await
+                .with_context(|| format!("Failed to open download: {}", part.display()))?;
+            let result = storage.put_reader_verified(file, expected.as_ref()).await;
+            remove_partial_download(&part).await;
+            (result?, server_mtime)
+        }
+        "file" => {
+            let path = parsed
+                .to_file_path()
+                .map_err(|_| anyhow::anyhow!("Invalid file URL: {}", url))?;
+            if let Some(entry) = &checksum {
+                checksums::verify_file(&path, entry).await?;
+            }
+            let file = tokio::fs::File::open(&path)
+                .await
+                .with_context(|| format!("Failed to open file: {}", path.display()))?;
+            let bar = progress_bar(Some(file.metadata().await?.len()), quiet);
+            let file: Box<dyn tokio::io::AsyncRead + Unpin + Send> = match throttle {
+                Some(throttle) => Box::new(ThrottledReader::new(file, throttle)),
+                None => Box::new(file),
+            };
+            let reader = ProgressReader::new(file, |total| bar.set_position(total));
+            let result = storage.put_reader_verified(reader, expected.as_ref()).await;
+            bar.finish_and_clear();
+            (result?, None)
+        }
+        other => anyhow::bail!("Unsupported URL scheme: {}", other),
+    };
+
+    let source = manifest::Source {
+        url: Some(source_url),
+        download_date: Some(download_date),
+        server_mtime,
+        archive_hash: Some(result.hash.to_string_prefixed()),
+        archive_type: manifest::ArchiveType::detect_reader(storage.get_reader(&result.hash)?)
+            .with_context(|| format!("Failed to read fetched object {}", result.hash))?,
+    };
+    let metadata = serde_json::to_string(&source).context("Failed to serialize source")?;
+
+    let db = open_db(config).await?;
+    db.register_object(&result.hash.to_string_prefixed(), result.size as i64, Some(metadata))
+        .await?;
+
+    output::emit(format, &result, |result| println!("{}", result.hash))?;
+    Ok(result)
+}
+
+/// Find the entry for `url`'s file name in the checksum file at `source`
+async fn checksum_entry(source: &str, url: &reqwest::Url) -> Result<checksums::Entry> {
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .with_context(|| format!("URL has no file name to look up in checksums: {}", url))?;
+
+    let text = checksums::load(source).await?;
+    let entries = checksums::parse(&text, checksums::algorithm_for_file_name(source))
+        .with_context(|| format!("Failed to parse checksums: {}", source))?;
+    checksums::find(&entries, name)
+        .cloned()
+        .with_context(|| format!("No checksum for {} in {}", name, source))
+}
+
+/// List command implementation
+async fn list_command(
+    config: &StorageConfig,
+    objects: bool,
+    name: Option<&str>,
+    limit: Option<i64>,
+    offset: i64,
+    format: Format,
+) -> Result<()> {
+    let db = open_db(config).await?;
+
+    if objects {
+        let records = db.list_objects(limit, offset).await?;
+        output::emit(format, &records, |records| {
+            for record in records {
+                println!("{}  {:>12}  refs={}", record.hash, record.size, record.refs);
+            }
+        })
+    } else {
+        let records = db.list_datasets(name, limit, offset).await?;
+        output::emit(format, &records, |records| {
+            for record in records {
+                println!(
+                    "{}/{}  {}  {}",
+                    record.name, record.version, record.manifest_hash, record.created_at
+                );
+            }
+        })
+    }
+}
+
+/// Details about one object, reported by `cast info`
+#[derive(Debug, serde::Serialize)]
+struct ObjectInfo {
+    hash: Blake3Hash,
+    /// Whether the object is in the store, loose, chunked, or packed
+    present: bool,
+    /// Store path of a loose object; chunked and packed objects have none
+    path: Option<std::path::PathBuf>,
+    /// Content length in bytes
+    size: Option<i64>,
+    /// Bytes the object occupies in the store, if present
+    disk_size: Option<u64>,
+    refs: Option<i32>,
+    created_at: Option<String>,
+    /// When `get`, `cat`, or `restore` last read the object
+    last_accessed: Option<String>,
+    /// Whether the metadata database knows about the object
+    registered: bool,
+    /// Datasets referencing the object, as `name/version`
+    datasets: Vec<String>,
+}
+
+/// Look up an object on disk and in the metadata database
+async fn object_info(storage: &LocalStorage, db: &MetadataDb, hash: Blake3Hash) -> Result<ObjectInfo> {
+    let key = hash.to_string_prefixed();
+    let record = db.get_object(&key).await?;
+
+    let present = storage.try_exists(&hash)?;
+    if record.is_none() && !present {
+        anyhow::bail!("Object not found: {}", hash);
+    }
+
+    // Only a loose object has a single file to point at
+    let loose = present && !storage.is_chunked(&hash) && !storage.is_packed(&hash)?;
+    let path = if loose { Some(storage.get(&hash).await?) } else { None };
+    let path = path.map(|path| std::fs::canonicalize(&path).unwrap_or(path));
+    let (size, disk_size) = if present {
+        (Some(storage.object_len(&hash)?), Some(storage.disk_size(&hash).await?))
+    } else {
+        (None, None)
+    };
+    let datasets = db
+        .datasets_referencing(&key)
+        .await?
+        .into_iter()
+        .map(|d| format!("{}/{}", d.name, d.version))
+        .collect();
+
+    Ok(ObjectInfo {
+        hash,
+        present,
+        path,
+        size: record.as_ref().map(|r| r.size).or(size.map(|s| s as i64)),
+        disk_size,
+        refs: record.as_ref().map(|r| r.refs),
+        created_at: record.as_ref().map(|r| r.created_at.clone()),
+        last_accessed: record.as_ref().and_then(|r| r.last_accessed.clone()),
+        registered: record.is_some(),
+        datasets,
+    })
+}
+
+/// Info command implementation
+async fn info_command(config: &StorageConfig, hash: &str, referenced_by: bool, format: Format) -> Result<()> {
+    let storage = LocalStorage::new(config.clone());
+    let db = open_db(config).await?;
+    let hash = resolve_hash(&storage, hash).await?;
+
+    if referenced_by {
+        let datasets = db.datasets_referencing(&hash.to_string_prefixed()).await?;
+        return output::emit(format, &datasets, |datasets| {
+            for dataset in datasets {
+                println!("{}/{}", dataset.name, dataset.version);
+            }
+        });
+    }
+
+    let info = object_info(&storage, &db, hash).await?;
+    if !info.registered {
+        tracing::warn!("{} is in the store but not registered in the metadata database", hash);
+    }
+    if !info.present {
+        tracing::warn!("{} is registered but missing from the store", hash);
+    }
+
+    output::emit(format, &info, |info| {
+        let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        println!("Hash:          {}", info.hash);
+        println!("Path:          {}", show(info.path.as_ref().map(|p| p.display().to_string())));
+        println!("Size:          {}", show(info.size.map(|s| format!("{} bytes", s))));
+        println!("Disk size:     {}", show(info.disk_size.map(|s| format!("{} bytes", s))));
+        println!("Refs:          {}", show(info.refs.map(|r| r.to_string())));
+        println!("Created:       {}", show(info.created_at.clone()));
+        println!("Last accessed: {}", show(info.last_accessed.clone()));
+        if info.datasets.is_empty() {
+            println!("Datasets:      -");
+        } else {
+            println!("Datasets:      {}", info.datasets.join(", "));
+        }
+    })
+}
+
+/// Cat command implementation
+///
+/// Writes the object's raw bytes to stdout. A reader closing the pipe early
+/// (e.g. `cast cat <hash> | head`) is not an error.
+async fn cat_command(config: &StorageConfig, hash: &str) -> Result<()> {
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_hash(&storage, hash).await?;
+    let _lock = storage.lock_shared_for_read().await;
+    if !storage.exists(&hash).await {
+        repair_from_mirror(config, &hash).await;
+    }
+    let mut reader = storage.get_reader(&hash)?;
+    record_access(config, &hash).await;
+
+    tokio::task::spawn_blocking(move || {
+        use std::io::Write;
+
+        let mut stdout = std::io::stdout().lock();
+        match std::io::copy(&mut reader, &mut stdout).and_then(|_| stdout.flush()) {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+            result => result.context("Failed to write object to stdout"),
+        }
+    })
+    .await
+    .context("Cat task panicked")?
+}
+
+/// Load a registered dataset's manifest
+///
+/// Uses the copy in the metadata database when present, otherwise reads the
+/// manifest object from the store.
+async fn load_dataset_manifest(db: &MetadataDb, storage: &LocalStorage, name: &str, version: &str) -> Result<Manifest> {
+    if let Some(manifest) = db.get_manifest(name, version).await? {
+        return Ok(manifest);
+    }
+
+    let record = db
+        .get_dataset(name, version)
+        .await?
+        .with_context(|| format!("Dataset not found: {}/{}", name, version))?;
+    let hash = Blake3Hash::from_str(&record.manifest_hash)?;
+    let path = storage.get(&hash).await?;
+    read_manifest(&path.to_string_lossy()).await
+}
+
+/// Verify dataset command implementation
+///
+/// Fails if any referenced object is missing or, with `deep`, corrupted.
+async fn verify_dataset_command(
+    config: &StorageConfig,
+    name: &str,
+    version: &str,
+    deep: bool,
+    format: Format,
+) -> Result<()> {
+    let db = open_db(config).await?;
+    let storage = LocalStorage::new(config.clone());
+    let manifest = load_dataset_manifest(&db, &storage, name, version).await?;
+
+    let report = manifest.verify_against(&storage, deep).await?;
+    output::emit(format, &report, |report| {
+        for hash in &report.missing {
+            println!("missing    {}", hash);
+        }
+        for hash in &report.corrupted {
+            println!("corrupted  {}", hash);
+        }
+        if report.is_complete() {
+            println!("{}/{}: all {} objects present", name, version, report.checked);
+        }
+    })?;
+
+    if !report.is_complete() {
+        anyhow::bail!(
+            "{}/{} is incomplete: {} missing, {} corrupted",
+            name,
+            version,
+            report.missing.len(),
+            report.corrupted.len()
+        );
+    }
+    Ok(())
+}
+
+/// Result of `cast verify` for one object
+#[derive(Debug, serde::Serialize)]
+struct VerifiedObject {
+    hash: Blake3Hash,
+}
+
+/// Verify command implementation
+///
+/// Fails if the object is missing or its contents no longer match its hash.
+async fn verify_command(config: &StorageConfig, hash: &str, format: Format) -> Result<()> {
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_hash(&storage, hash).await?;
+    let _lock = storage.lock_shared_for_read().await;
+    if !storage.exists(&hash).await {
+        anyhow::bail!("Object {} is not present in the store at {}", hash, config.root.display());
+    }
+
+    let reader = storage.get_reader(&hash)?;
+    let actual = tokio::task::spawn_blocking(move || Blake3Hash::from_reader(reader))
+        .await
+        .context("Verify task panicked")?
+        .with_context(|| format!("Failed to read object {}", hash))?;
+    if actual != hash {
+        anyhow::bail!("Object {} is corrupt (contents hash to {})", hash, actual);
+    }
+
+    output::emit(format, &VerifiedObject { hash }, |verified| {
+        println!("verified   {}", verified.hash);
+    })
+}
+
+/// Verify-store command implementation
+///
+/// Checks every object, then fails if any object's contents no longer
+/// match its hash or it could not be read.
+async fn verify_store_command(config: &StorageConfig, jobs: usize, format: Format) -> Result<()> {
+    let storage = LocalStorage::new(config.clone());
+    let report = storage.verify_all(jobs).await?;
+
+    output::emit(format, &report, |report| {
+        for hash in &report.corrupted {
+            println!("corrupted  {}", hash);
+        }
+        for object in &report.unreadable {
+            println!("unreadable {}  {}", object.hash, object.error);
+        }
+        if report.corrupted.is_empty() && report.unreadable.is_empty() {
+            println!("All {} objects verified", report.checked);
+        }
+    })?;
+
+    if !report.corrupted.is_empty() || !report.unreadable.is_empty() {
+        anyhow::bail!(
+            "{} of {} objects are corrupted, {} unreadable",
+            report.corrupted.len(),
+            report.checked,
+            report.unreadable.len()
+        );
+    }
+    Ok(())
+}
+
+/// Result of `cast tree`
+#[derive(Debug, serde::Serialize)]
+struct TreeOutput {
+    name: String,
+    version: String,
+    contents: Vec<tree::TreeNode>,
+}
+
+/// Tree command implementation
+async fn tree_command(config: &StorageConfig, name: &str, version: &str, format: Format) -> Result<()> {
+    let db = open_db(config).await?;
+    let storage = LocalStorage::new(config.clone());
+    let manifest = load_dataset_manifest(&db, &storage, name, version).await?;
+
+    let result = TreeOutput {
+        name: name.to_string(),
+        version: version.to_string(),
+        contents: tree::build(&manifest.contents)?,
+    };
+    output::emit(format, &result, |result| {
+        println!("{}/{}", result.name, result.version);
+        if result.contents.is_empty() {
+            println!("(empty)");
+        }
+        for line in tree::render(&result.contents) {
+            println!("{}", line);
+        }
+    })
+}
+
+/// Download a response into the store, checking its SHA-256 and size first
+///
+/// The body is written to the store's `tmp/` directory (keyed by `url`) and
+/// only stored once it matches; the temporary file is removed either way.
+async fn store_sha256_verified(
+    storage: &LocalStorage,
+    url: &str,
+    response: reqwest::Response,
+    expected: &[u8; 32],
+    expected_size: u64,
+    bar: &indicatif::ProgressBar,
+) -> Result<PutResult> {
+    use futures::StreamExt;
+    use sha2::Digest;
+
+    let part = storage.partial_download_path(url);
+    let download = async {
+        if let Some(parent) = part.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let mut file = tokio::fs::File::create(&part)
+            .await
+            .with_context(|| format!("Failed to create download: {}", part.display()))?;
+        let mut stream = response.bytes_stream();
+        let mut hasher = sha2::Sha256::new();
+        let mut size = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.with_context(|| format!("Failed to download {}", url))?;
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+            bar.inc(chunk.len() as u64);
+        }
+        file.flush().await?;
+
+        let actual: [u8; 32] = hasher.finalize().into();
+        if actual != *expected || size != expected_size {
+            anyhow::bail!(
+                "expected {} ({} bytes), got {} ({} bytes)",
+                hash::ContentHash::Sha256(*expected),
+                expected_size,
+                hash::ContentHash::Sha256(actual),
+                size
+            );
+        }
+        storage.put_file(&part).await
+    };
+    let result = download.await;
+    remove_partial_download(&part).await;
+    result
+}
+
+/// Import-oci command implementation
+///
+/// Downloads each layer to the store's `tmp/` directory, checks it against
+/// the digest and size the image manifest declares, and only then stores
+/// it. The image manifest itself is stored as the dataset's source archive,
+/// and the dataset (named after the repository, versioned by tag or digest)
+/// lists each layer under its digest.
+async fn import_oci_command(
+    config: &StorageConfig,
+    image: &str,
+    token: Option<String>,
+    insecure: bool,
+    format: Format,
+    quiet: bool,
+) -> Result<Manifest> {
+    let image = oci::ImageRef::parse(image)?;
+    manifest::Dataset::split_name(&image.repository)?;
+    let mut registry = oci::Registry::new(&image, token, insecure);
+    let (raw_manifest, image_manifest) = registry.manifest(&image.reference).await?;
+    if image_manifest.layers.is_empty() {
+        anyhow::bail!("Image {} has no layers", image);
+    }
+
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
+    let db = open_db(config).await?;
+    let manifest_object = storage.put(&raw_manifest).await?;
+
+    // Importing the same image again changes nothing, refcounts included
+    if let Some(existing) = db.get_manifest(&image.repository, &image.reference).await? {
+        let archive = manifest_object.to_string_prefixed();
+        if existing.source.archive_hash.as_deref() == Some(archive.as_str()) {
+            tracing::info!("{} is already imported", image);
+            let layers = existing.contents.len();
+            emit_oci_import(&existing, layers, format)?;
+            return Ok(existing);
+        }
+    }
+    db.register_object(&manifest_object.to_string_prefixed(), raw_manifest.len() as i64, None)
+        .await?;
+
+    let bar = progress_bar(Some(image_manifest.layers.iter().map(|l| l.size).sum()), quiet);
+    let mut contents = Vec::with_capacity(image_manifest.layers.len());
+    let mut deduplicated = 0;
+    for layer in &image_manifest.layers {
+        let expected = oci::sha256_digest(&layer.digest)?;
+        let url = registry.blob_url(&layer.digest);
+        let response = registry.blob(&layer.digest).await?;
+        let result = store_sha256_verified(&storage, &url, response, &expected, layer.size, &bar)
+            .await
+            .with_context(|| format!("Layer {} failed verification", layer.digest))?;
+        if result.deduplicated {
+            deduplicated += 1;
+        }
+
+        contents.push(Content {
+            path: layer.digest.clone(),
+            hash: result.hash.to_string_prefixed(),
+            size: result.size,
+            executable: false,
+            mime_type: layer.media_type.clone(),
+            kind: ContentKind::File,
+            derived_from: Vec::new(),
+        });
+    }
+    bar.finish_and_clear();
+
+    let manifest = Manifest {
+        schema_version: manifest::CURRENT_SCHEMA_VERSION.to_string(),
+        dataset: manifest::Dataset {
+            name: image.repository.clone(),
+            version: image.reference.clone(),
+            description: None,
+        },
+        source: manifest::Source {
+            url: Some(format!("oci://{}", image)),
+            download_date: Some(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            server_mtime: None,
+            archive_hash: Some(manifest_object.to_string_prefixed()),
+            archive_type: None,
+        },
+        contents,
+        transformations: Vec::new(),
+        signature: None,
+    };
+    manifest.validate()?;
+
+    let manifest_hash = manifest.content_hash();
+    storage.register_dataset(&manifest).await?;
+    db.register_manifest(&manifest, &manifest_hash.to_string_prefixed())
+        .await?;
+
+    emit_oci_import(&manifest, deduplicated, format)?;
+    Ok(manifest)
+}
+
+/// Report an imported image's dataset, as `put --recursive` reports a tree
+fn emit_oci_import(manifest: &Manifest, deduplicated: usize, format: Format) -> Result<()> {
+    let summary = PutTreeOutput {
+        manifest_hash: manifest.content_hash(),
+        name: manifest.dataset.name.clone(),
+        version: manifest.dataset.version.clone(),
+        files: manifest.contents.len(),
+        size: manifest.contents.iter().map(|c| c.size).sum(),
+        deduplicated,
+    };
+    output::emit(format, &summary, |summary| {
+        println!("{}", summary.manifest_hash);
+        eprintln!(
+            "Imported {} layer(s), {} bytes, as {}/{}",
+            summary.files, summary.size, summary.name, summary.version
+        );
+    })
+}
+
+/// Import-lfs command implementation
+///
+/// The object is checked against the pointer's SHA-256 and size before it
+/// is stored.
+async fn import_lfs_command(
+    config: &StorageConfig,
+    pointer_path: &str,
+    endpoint: &str,
+    token: Option<&str>,
+    format: Format,
+    quiet: bool,
+) -> Result<PutResult> {
+    let text = tokio::fs::read_to_string(pointer_path)
+        .await
+        .with_context(|| format!("Failed to read LFS pointer: {}", pointer_path))?;
+    let pointer = lfs::Pointer::parse(&text).with_context(|| format!("Invalid LFS pointer: {}", pointer_path))?;
+
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
+    let db = open_db(config).await?;
+
+    let (url, response) = lfs::download(endpoint, &pointer, token).await?;
+    let bar = progress_bar(Some(pointer.size), quiet);
+    let result = store_sha256_verified(&storage, &url, response, &pointer.oid, pointer.size, &bar)
+        .await
+        .with_context(|| format!("LFS object {} failed verification", pointer.oid_hex()))?;
+    bar.finish_and_clear();
+    db.register_object(&result.hash.to_string_prefixed(), result.size as i64, None)
+        .await?;
+
+    output::emit(format, &result.hash, |hash| println!("{}", hash))?;
+    Ok(result)
+}
+
+/// Export-lfs command implementation
+async fn export_lfs_command(config: &StorageConfig, hash: &str, output: Option<&str>) -> Result<lfs::Pointer> {
+    use sha2::Digest;
+
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_hash(&storage, hash).await?;
+    let _lock = storage.lock_shared_for_read().await;
+    let size = storage.object_len(&hash)?;
+    let mut reader = storage.get_reader(&hash)?;
+    let oid = tokio::task::spawn_blocking(move || -> std::io::Result<[u8; 32]> {
+        let mut hasher = sha2::Sha256::new();
+        std::io::copy(&mut reader, &mut hasher)?;
+        Ok(hasher.finalize().into())
+    })
+    .await
+    .context("Hashing task panicked")?
+    .with_context(|| format!("Failed to read object {}", hash))?;
+
+    let pointer = lfs::Pointer { oid, size };
+    match output {
+        Some(path) => tokio::fs::write(path, pointer.to_string())
+            .await
+            .with_context(|| format!("Failed to write LFS pointer: {}", path))?,
+        None => print!("{}", pointer),
+    }
+    Ok(pointer)
+}
+
+/// Result of `cast export-nix`
+#[derive(Debug, serde::Serialize)]
+struct NixExport {
+    hash: Blake3Hash,
+    store_path: String,
+    /// SHA-256 of the NAR, in Nix's `sha256:<base32>` notation
+    nar_hash: String,
+    nar_size: u64,
+}
+
+/// Export-nix command implementation
+///
+/// Streams the object through the NAR serialization to hash it, copying
+/// the NAR to `nar_path` if given. See the `nix` module for the scheme.
+async fn export_nix_command(
+    config: &StorageConfig,
+    hash: &str,
+    name: &str,
+    executable: bool,
+    nar_path: Option<&str>,
+    format: Format,
+) -> Result<NixExport> {
+    nix::validate_name(name)?;
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_hash(&storage, hash).await?;
+    let _lock = storage.lock_shared_for_read().await;
+    let size = storage.object_len(&hash)?;
+    let mut reader = storage.get_reader(&hash)?;
+
+    let nar_file = match nar_path {
+        Some(path) => Some(std::io::BufWriter::new(
+            std::fs::File::create(path).with_context(|| format!("Failed to create NAR: {}", path))?,
+        )),
+        None => None,
+    };
+    let (nar_hash, nar_size) = tokio::task::spawn_blocking(move || {
+        let mut hasher = nix::NarHasher::new(nar_file);
+        nix::write_nar(&mut hasher, &mut reader, size, executable)?;
+        hasher.finish()
+    })
+    .await
+    .context("NAR task panicked")?
+    .context("Failed to serialize object as NAR")?;
+
+    let export = NixExport {
+        hash,
+        store_path: nix::store_path(&nar_hash, name),
+        nar_hash: format!("sha256:{}", nix::base32(&nar_hash)),
+        nar_size,
+    };
+    output::emit(format, &export, |export| println!("{}", export.store_path))?;
+    Ok(export)
+}
+
+/// Result of `cast export` and `cast import`
+#[derive(Debug, serde::Serialize)]
+struct BundleOutput {
+    manifest_hash: Blake3Hash,
+    name: String,
+    version: String,
+    files: usize,
+    bundle: String,
+}
+
+impl BundleOutput {
+    fn new(manifest: &Manifest, bundle: &str) -> Self {
+        Self {
+            manifest_hash: manifest.content_hash(),
+            name: manifest.dataset.name.clone(),
+            version: manifest.dataset.version.clone(),
+            files: manifest.contents.len(),
+            bundle: bundle.to_string(),
+        }
+    }
+}
+
+/// Export command implementation
+async fn export_command(
+    config: &StorageConfig,
+    name: &str,
+    version: &str,
+    bundle: &str,
+    format: Format,
+) -> Result<()> {
+    let db = open_db(config).await?;
+    let storage = LocalStorage::new(config.clone());
+    let manifest = load_dataset_manifest(&db, &storage, name, version).await?;
+
+    let file = std::fs::File::create(bundle)
+        .with_context(|| format!("Failed to create bundle: {}", bundle))?;
+    storage
+        .export_bundle(&manifest, std::io::BufWriter::new(file))?
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .with_context(|| format!("Failed to write bundle: {}", bundle))?;
+
+    output::emit(format, &BundleOutput::new(&manifest, bundle), |export| {
+        println!("Exported {}/{} to {}", export.name, export.version, export.bundle)
+    })
+}
+
+/// Import command implementation
+///
+/// Nothing is stored unless every object in the bundle verifies.
+async fn import_command(config: &StorageConfig, bundle: &str, format: Format) -> Result<Manifest> {
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
+
+    let manifest = storage
+        .import_bundle(Path::new(bundle))
+        .await
+        .with_context(|| format!("Failed to import bundle: {}", bundle))?;
+
+    let db = open_db(config).await?;
+    db.register_manifest(&manifest, &manifest.content_hash().to_string_prefixed())
+        .await?;
+
+    output::emit(format, &BundleOutput::new(&manifest, bundle), |import| {
+        println!("Imported {}/{} ({} files)", import.name, import.version, import.files)
+    })?;
+    Ok(manifest)
+}
+
+/// Store summary reported by `cast stats`
+#[derive(Debug, serde::Serialize)]
+struct StoreStats {
+    #[serde(flatten)]
+    db: DatabaseStats,
+    /// Bytes occupied by objects on disk
+    disk_size: u64,
+    /// Logical size divided by on-disk size
+    dedup_ratio: f64,
+    largest_objects: Vec<ObjectRecord>,
+    /// Transformation counts by type, with `--transforms`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transform_types: Option<Vec<TransformTypeCount>>,
+}
+
+/// Number of recorded transformations of one type
+#[derive(Debug, serde::Serialize)]
+struct TransformTypeCount {
+    transform_type: String,
+    count: i64,
+}
+
+/// Gather database and on-disk statistics for a store
+async fn collect_stats(storage: &LocalStorage, db: &MetadataDb, top: i64) -> Result<StoreStats> {
+    let db_stats = db.get_stats().await?;
+    let disk_size = storage.disk_usage().await?;
+
+    let dedup_ratio = if disk_size == 0 {
+        1.0
+    } else {
+        db_stats.logical_size as f64 / disk_size as f64
+    };
+
+    Ok(StoreStats {
+        db: db_stats,
+        disk_size,
+        dedup_ratio,
+        largest_objects: db.objects_by_size(top).await?,
+        transform_types: None,
+    })
+}
+
+/// Stats command implementation
+async fn stats_command(config: &StorageConfig, top: i64, transforms: bool, format: Format) -> Result<()> {
+    let db = open_db(config).await?;
+    let storage = LocalStorage::new(config.clone());
+    let mut stats = collect_stats(&storage, &db, top).await?;
+    if transforms {
+        let counts = db.transform_type_counts().await?;
+        stats.transform_types = Some(
+            counts
+                .into_iter()
+                .map(|(transform_type, count)| TransformTypeCount { transform_type, count })
+                .collect(),
+        );
+    }
+    output::emit(format, &stats, print_stats)
+}
+
+/// Human-readable rendering of `cast stats`
+fn print_stats(stats: &StoreStats) {
+    println!("Objects:         {}", stats.db.objects_count);
+    println!("Datasets:        {}", stats.db.datasets_count);
+    println!("Transformations: {}", stats.db.transformations_count);
+    println!("Logical size:    {} bytes", stats.db.logical_size);
+    println!("Stored size:     {} bytes", stats.db.total_size);
+    println!("On-disk size:    {} bytes", stats.disk_size);
+    println!("Dedup ratio:     {:.2}", stats.dedup_ratio);
+
+    if !stats.largest_objects.is_empty() {
+        println!();
+        println!("Largest objects:");
+        for object in &stats.largest_objects {
+            println!("  {}  {:>12}", object.hash, object.size);
+        }
+    }
+
+    if let Some(types) = &stats.transform_types {
+        println!();
+        println!("Transformations by type:");
+        for entry in types {
+            println!("  {:<20} {:>8}", entry.transform_type, entry.count);
+        }
+    }
+}
+
+/// Dedup report command implementation
+async fn dedup_report_command(config: &StorageConfig, format: Format) -> Result<()> {
+    let db = open_db(config).await?;
+    let report = db.dedup_report().await?;
+
+    output::emit(format, &report, |report| {
+        println!("Objects:        {}", report.objects);
+        println!("Logical size:   {} bytes", report.logical_bytes);
+        println!("Physical size:  {} bytes", report.physical_bytes);
+        println!("Saved:          {} bytes", report.saved_bytes);
+        println!("Savings ratio:  {:.2}", report.ratio());
+    })
+}
+
+/// Lineage command implementation
+async fn lineage_command(config: &StorageConfig, hash: &str) -> Result<()> {
+    let hash = Blake3Hash::from_str(hash)?;
+    let db = open_db(config).await?;
+    print!("{}", db.lineage_dot(&hash.to_string_prefixed()).await?);
+    Ok(())
+}
+
+/// Objects removed (or, in a dry run, selected) by garbage collection
+#[derive(Debug, Default)]
+struct GcReport {
+    swept: Vec<Blake3Hash>,
+    swept_chunks: usize,
+    reclaimed_bytes: u64,
+    /// Collectable objects left for a later run because a limit was reached
+    remaining: usize,
+    /// Unused chunks left for a later run because a limit was reached
+    remaining_chunks: usize,
+}
+
+/// Bounds on the sweep phase of `cast gc`
+#[derive(Debug, Default, Clone, Copy)]
+struct GcLimits {
+    /// Stop after deleting this many objects and chunks in total
+    max_deletes: Option<usize>,
+    /// Stop deleting once this instant has passed
+    deadline: Option<std::time::Instant>,
+}
+
+impl GcLimits {
+    /// Whether the sweep must stop before deleting another object
+    fn reached(&self, deleted: usize) -> bool {
+        self.max_deletes.is_some_and(|max| deleted >= max) || self.timed_out()
+    }
+
+    fn timed_out(&self) -> bool {
+        self.deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline)
+    }
+}
+
+/// Sweep chunks left unreferenced, unless the time limit is already spent
+///
+/// Chunks count against `max_deletes` along with the objects already swept.
+async fn sweep_chunks_within(storage: &LocalStorage, report: &mut GcReport, limits: GcLimits, dry_run: bool) -> Result<()> {
+    if limits.timed_out() {
+        tracing::info!("Time limit reached; unused chunks are left for the next run");
+        return Ok(());
+    }
+    let budget = limits.max_deletes.map(|max| max.saturating_sub(report.swept.len()));
+    let chunks = storage.sweep_chunks(dry_run, budget).await?;
+    report.swept_chunks = chunks.swept;
+    report.reclaimed_bytes += chunks.bytes;
+    report.remaining_chunks = chunks.remaining;
+    Ok(())
+}
+
+/// Garbage collect objects not reachable from any registered dataset
+///
+/// Holds the store lock exclusively for the whole sweep so concurrent puts
+/// cannot race a deletion. Deleted objects are also removed from the
+/// metadata database. The mark phase always completes; `limits` only bound
+/// the sweep, and unreachable objects past them are counted in `remaining`.
+async fn collect_garbage(
+    storage: &LocalStorage,
+    db: &MetadataDb,
+    dry_run: bool,
+    limits: GcLimits,
+) -> Result<GcReport> {
+    let _lock = storage.lock_exclusive().await?;
+
+    // Mark: everything referenced by a dataset manifest
+    let reachable = db.reachable_objects().await?;
+
+    // Sweep: every stored object that wasn't marked
+    let mut report = GcReport::default();
+    for hash in storage.list().await? {
+        if reachable.contains(&hash.to_string_prefixed()) {
+            continue;
+        }
+        if limits.reached(report.swept.len()) {
+            report.remaining += 1;
+            continue;
+        }
+
+        let size = storage.reclaimable_size(&hash).await?;
+
+        if !dry_run {
+            // As in `collect_expired`, the row and the file go together
+            db.delete_object_with(&hash.to_string_prefixed(), || storage.delete(&hash))
+                .await?;
+        }
+        report.swept.push(hash);
+        report.reclaimed_bytes += size;
+    }
+
+    // Chunks only referenced by the swept objects' recipes go with them
+    sweep_chunks_within(storage, &mut report, limits, dry_run).await?;
+
+    Ok(report)
+}
+
+/// Delete objects whose `put --ttl` expiry has passed
+///
+/// Unlike `collect_garbage`, expired objects go regardless of refcount.
+/// Objects still referenced by a dataset or a recorded transformation are
+/// kept, with a warning naming what references them.
+async fn collect_expired(
+    storage: &LocalStorage,
+    db: &MetadataDb,
+    now: chrono::DateTime<chrono::Utc>,
+    dry_run: bool,
+    limits: GcLimits,
+) -> Result<GcReport> {
+    let _lock = storage.lock_exclusive().await?;
+
+    let mut report = GcReport::default();
+    for key in db.expired_objects(now).await? {
+        let hash = Blake3Hash::from_str(&key)?;
+
+        let datasets = db.datasets_referencing(&key).await?;
+        if !datasets.is_empty() {
+            let names: Vec<String> = datasets.iter().map(|d| format!("{}@{}", d.name, d.version)).collect();
+            tracing::warn!("Keeping expired object {}: still referenced by {}", hash, names.join(", "));
+            continue;
+        }
+        let transformations = db.transformations_referencing(&key).await?;
+        if !transformations.is_empty() {
+            let ids: Vec<String> = transformations.iter().map(|t| format!("#{} ({})", t.id, t.transform_type)).collect();
+            tracing::warn!("Keeping expired object {}: still referenced by transformation {}", hash, ids.join(", "));
+            continue;
+        }
+        if limits.reached(report.swept.len()) {
+            report.remaining += 1;
+            continue;
+        }
+
+        let size = if storage.exists(&hash).await {
+            storage.reclaimable_size(&hash).await?
+        } else {
+            0
+        };
+
+        if !dry_run {
+            // The row goes first, in a transaction that only commits once
+            // the file is gone, so a failure on either side leaves both
+            db.delete_object_with(&key, || async {
+                if storage.exists(&hash).await {
+                    storage.delete(&hash).await?;
+                }
+                Ok(())
+            })
+            .await?;
+        }
+        report.swept.push(hash);
+        report.reclaimed_bytes += size;
+    }
+
+    sweep_chunks_within(storage, &mut report, limits, dry_run).await?;
+
+    Ok(report)
+}
+
+/// Gc command implementation
+///
+/// `max_time` is measured from the start of the command, so a slow mark
+/// phase leaves less time for the sweep.
+async fn gc_command(
+    config: &StorageConfig,
+    dry_run: bool,
+    expired: bool,
+    max_deletes: Option<usize>,
+    max_time: Option<chrono::Duration>,
+) -> Result<()> {
+    let limits = GcLimits {
+        max_deletes,
+        deadline: max_time
+            .map(|d| d.to_std().context("--max-time must not be negative"))
+            .transpose()?
+            .map(|d| std::time::Instant::now() + d),
+    };
+    let db = open_db(config).await?;
+    let storage = LocalStorage::new(config.clone());
+
+    let (report, kind) = if expired {
+        (collect_expired(&storage, &db, chrono::Utc::now(), dry_run, limits).await?, "expired")
+    } else {
+        (collect_garbage(&storage, &db, dry_run, limits).await?, "unreachable")
+    };
+    let pruned = if dry_run { 0 } else { db.prune_orphan_transformations().await? };
+    if !dry_run && (!report.swept.is_empty() || pruned > 0) {
+        db.checkpoint_opportunistic().await;
+    }
+
+    for hash in &report.swept {
+        if dry_run {
+            println!("Would delete {}", hash);
+        } else {
+            println!("Deleted {}", hash);
+        }
+    }
+
+    let chunks = match report.swept_chunks {
+        0 => String::new(),
+        n => format!(" and {} unused chunk(s)", n),
+    };
+    if dry_run {
+        println!(
+            "{} {} object(s){}, {} bytes would be reclaimed",
+            report.swept.len(),
+            kind,
+            chunks,
+            report.reclaimed_bytes
+        );
+    } else {
+        println!(
+            "{} {} object(s){} deleted, {} bytes reclaimed",
+            report.swept.len(),
+            kind,
+            chunks,
+            report.reclaimed_bytes
+        );
+    }
+    if report.remaining > 0 || report.remaining_chunks > 0 {
+        let chunks = match report.remaining_chunks {
+            0 => String::new(),
+            n => format!(" and {} unused chunk(s)", n),
+        };
+        println!(
+            "Stopped at the limit; {} {} object(s){} remain, run `cast gc{}` again to continue",
+            report.remaining,
+            kind,
+            chunks,
+            if expired { " --expired" } else { "" }
+        );
+    }
+    if pruned > 0 {
+        println!("Pruned {} orphaned transformation record(s)", pruned);
+    }
+    Ok(())
+}
+
+/// Prune command implementation
+async fn prune_command(config: &StorageConfig, name: &str, keep: usize, dry_run: bool, format: Format) -> Result<()> {
+    let db = open_db(config).await?;
+    if db.get_dataset_versions(name).await?.is_empty() {
+        anyhow::bail!("Dataset not found: {}", name);
+    }
+
+    let versions = if dry_run {
+        db.stale_dataset_versions(name, keep).await?
+    } else {
+        db.prune_dataset_versions(name, keep).await?
+    };
+
+    output::emit(format, &versions, |versions| {
+        let verb = if dry_run { "Would remove" } else { "Removed" };
+        for version in versions {
+            println!("{} {}/{}", verb, name, version);
+        }
+        if !dry_run && !versions.is_empty() {
+            println!("Run `cast gc` to reclaim objects no longer referenced");
+        }
+    })
+}
+
+/// Sync command implementation
+async fn sync_command(src: &str, dst: &str, rate: Option<u64>, format: Format) -> Result<()> {
+    let source = LocalStorage::with_root(storage::config::expand_path(src)?);
+    if !source.store_path().is_dir() {
+        anyhow::bail!("No store found at {}", src);
+    }
+    let dest = LocalStorage::with_root(storage::config::expand_path(dst)?);
+
+    let report = source.sync_to(&dest, rate.map(Throttle::new).as_ref()).await?;
+    output::emit(format, &report, |report| {
+        println!(
+            "Transferred {} object(s), {} bytes; skipped {} already present",
+            report.transferred, report.bytes, report.skipped
+        )
+    })
+}
+
+/// Default size limit for objects moved into packfiles (1 MiB)
+const DEFAULT_PACK_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Pack command implementation
+async fn pack_command(config: &StorageConfig, max_size: u64, format: Format) -> Result<()> {
+    let storage = LocalStorage::new(config.clone());
+    let report = storage.pack_objects(max_size).await?;
+
+    output::emit(format, &report, |report| match &report.pack {
+        Some(pack) => println!(
+            "Packed {} object(s), {} bytes, into {}",
+            report.objects, report.bytes, pack
+        ),
+        None => println!("No loose objects to pack"),
+    })
+}
+
+/// What `rm` did to an object
+#[derive(Debug, PartialEq, Eq)]
+enum RmOutcome {
+    /// One reference was dropped; the object is still stored
+    Decremented { remaining: i32 },
+    /// The object was deleted from the store and the database
+    Deleted,
+}
+
+/// Drop one reference to an object, deleting it when none remain
+///
+/// Objects still listed by a registered dataset are refused unless `force`
+/// is set, which also deletes the object whatever its refcount.
+async fn remove_object(
+    storage: &LocalStorage,
+    db: &MetadataDb,
+    hash: &Blake3Hash,
+    force: bool,
+) -> Result<RmOutcome> {
+    let _lock = storage.lock_exclusive().await?;
+    let key = hash.to_string_prefixed();
+
+    if !force {
+        let datasets = db.datasets_referencing(&key).await?;
+        if !datasets.is_empty() {
+            let names: Vec<String> = datasets
+                .iter()
+                .map(|d| format!("{}@{}", d.name, d.version))
+                .collect();
+            anyhow::bail!(
+                "Object {} is referenced by {} (use --force to delete anyway)",
+                hash,
+                names.join(", ")
+            );
+        }
+
+        if let Some(record) = db.get_object(&key).await? {
+            if record.refs > 1 {
+                db.update_refs(&key, -1).await?;
+                return Ok(RmOutcome::Decremented {
+                    remaining: record.refs - 1,
+                });
+            }
+        }
+    }
+
+    if storage.exists(hash).await {
+        storage.delete(hash).await?;
+    } else if db.get_object(&key).await?.is_none() {
+        anyhow::bail!("Object {} is not present in the store", hash);
+    }
+    db.delete_object(&key).await?;
+
+    Ok(RmOutcome::Deleted)
+}
+
+/// Result of `cast rm`
+#[derive(Debug, serde::Serialize)]
+struct RmOutput {
+    hash: Blake3Hash,
+    /// Whether the object was deleted rather than just dereferenced
+    deleted: bool,
+    /// References left after the call
+    refs: i32,
+}
+
+/// Rm command implementation
+async fn rm_command(config: &StorageConfig, hash: &str, force: bool, format: Format) -> Result<()> {
+    let db = open_db(config).await?;
+    let storage = LocalStorage::new(config.clone());
+    let hash = resolve_hash(&storage, hash).await?;
+
+    let result = match remove_object(&storage, &db, &hash, force).await? {
+        RmOutcome::Decremented { remaining } => RmOutput {
+            hash,
+            deleted: false,
+            refs: remaining,
+        },
+        RmOutcome::Deleted => RmOutput {
+            hash,
+            deleted: true,
+            refs: 0,
+        },
+    };
+    output::emit(format, &result, |result| {
+        if result.deleted {
+            println!("Deleted {}", result.hash)
+        } else {
+            println!("Dropped a reference to {} ({} remaining)", result.hash, result.refs)
+        }
+    })
+}
+
+/// Verify-manifest command implementation
+async fn verify_manifest_command(file: &str, key: &str) -> Result<()> {
+    let manifest = read_manifest(file).await?;
+
+    let key_hex = if Path::new(key).is_file() {
+        tokio::fs::read_to_string(key)
+            .await
+            .with_context(|| format!("Failed to read public key: {}", key))?
+    } else {
+        key.to_string()
+    };
+    let verifying_key = manifest::parse_verifying_key(&key_hex)?;
+
+    manifest::verify_signature(&manifest, &verifying_key)
+        .with_context(|| format!("Signature verification failed: {}", file))?;
+
+    println!(
+        "Valid signature for {}/{}",
+        manifest.dataset.name, manifest.dataset.version
+    );
+    Ok(())
+}
+
+/// Read and parse a manifest file, in JSON, YAML, or TOML by extension
+async fn read_manifest(path: &str) -> Result<Manifest> {
+    let path = std::path::PathBuf::from(path);
+    tokio::task::spawn_blocking(move || Manifest::from_path(&path))
+        .await
+        .context("Manifest read task panicked")?
+}
+
+/// Diff command implementation
+async fn diff_command(old: &str, new: &str, format: Format) -> Result<()> {
+    let diff = Manifest::diff(&read_manifest(old).await?, &read_manifest(new).await?);
+    output::emit(format, &diff, print_diff)
+}
+
+/// Human-readable rendering of `cast diff`
+fn print_diff(diff: &manifest::ManifestDiff) {
+    for content in &diff.added {
+        println!("+ {} ({})", content.path, content.hash);
+    }
+    for content in &diff.removed {
+        println!("- {} ({})", content.path, content.hash);
+    }
+    for change in &diff.modified {
+        println!("~ {} ({} -> {})", change.new.path, change.old.hash, change.new.hash);
+    }
+
+    println!(
+        "{} added, {} removed, {} modified",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len()
+    );
+}
+
+/// Write a completion script for `shell` to `out`
+fn write_completions(shell: clap_complete::Shell, out: &mut dyn std::io::Write) {
+    clap_complete::generate(shell, &mut Cli::command(), "cast", out);
+}
+
+/// Resolve the storage configuration, honoring `--store` and `--profile`
+///
+/// `--store` builds a default configuration rooted at the given path, so
+/// nothing from the config file or environment applies.
+async fn load_config(profile: Option<&str>, store: Option<&str>) -> Result<StorageConfig> {
+    if let Some(root) = store {
+        let config = StorageConfig {
+            root: storage::config::expand_path(root)?,
+            ..Default::default()
+        };
+        config.validate()?;
+        return Ok(config);
+    }
+
+    match profile {
+        Some(name) => StorageConfig::load_profile(name).await,
+        None => StorageConfig::load().await,
+    }
+}
+
+/// Copy freshly stored objects to the configured `mirror_root` store, if any
+///
+/// A failure to reach the mirror is logged, not returned.
+async fn mirror_objects(config: &StorageConfig, hashes: impl IntoIterator<Item = Blake3Hash>) -> Result<()> {
+    let Some(mirror) = config.open_mirror() else {
+        return Ok(());
+    };
+    for hash in hashes {
+        mirror.replicate_existing(&hash).await?;
+    }
+    Ok(())
+}
+
+/// Copy an object missing from the store back from the configured
+/// `mirror_root` store; returns whether the object is now present
+async fn repair_from_mirror(config: &StorageConfig, hash: &Blake3Hash) -> bool {
+    let Some(mirror) = config.open_mirror() else {
+        return false;
+    };
+    match mirror.get(hash).await {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::debug!("Mirror has no usable copy of {}: {:#}", hash, e);
+            false
+        }
+    }
+}
+
+/// Note in the metadata database that an object was read
+///
+/// A failure only warns; it never fails the read itself.
+async fn record_access(config: &StorageConfig, hash: &Blake3Hash) {
+    let result = match open_db(config).await {
+        Ok(db) => db.record_access(&hash.to_string_prefixed()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record access to {}: {:#}", hash, e);
+    }
+}
+
+/// Open the metadata database of the configured store
+async fn open_db(config: &StorageConfig) -> Result<MetadataDb> {
+    MetadataDb::with_options(config.db_path(), &config.db_options()).await
+}
+
+/// Database export command implementation
+async fn db_export_command(config: &StorageConfig, output: Option<&str>) -> Result<()> {
+    let db = open_db(config).await?;
+    let doc = db.export_json().await?;
+
+    let json = serde_json::to_string_pretty(&doc)
+        .context("Failed to serialize metadata export")?;
+
+    match output {
+        Some(path) => {
+            tokio::fs::write(path, json)
+                .await
+                .with_context(|| format!("Failed to write metadata export: {}", path))?;
+            tracing::info!("Wrote metadata export to {}", path);
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
+
+/// Database import command implementation
+async fn db_import_command(config: &StorageConfig, file: &str, replace: bool) -> Result<()> {
+    let content = tokio::fs::read_to_string(file)
+        .await
+        .with_context(|| format!("Failed to read metadata dump: {}", file))?;
+
+    let doc: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse metadata dump: {}", file))?;
+
+    let mode = if replace { ImportMode::Replace } else { ImportMode::Merge };
+
+    let db = open_db(config).await?;
+    let report = db.import_json(&doc, mode).await?;
+
+    println!(
+        "Objects: {} inserted, {} skipped",
+        report.objects_inserted, report.objects_skipped
+    );
+    println!(
+        "Datasets: {} inserted, {} skipped",
+        report.datasets_inserted, report.datasets_skipped
+    );
+    println!(
+        "Transformations: {} inserted, {} skipped",
+        report.transformations_inserted, report.transformations_skipped
+    );
+
+    Ok(())
+}
+
+/// Database backup command implementation
+async fn db_backup_command(config: &StorageConfig, path: &str) -> Result<()> {
+    let db = open_db(config).await?;
+    db.backup_to(Path::new(path)).await?;
+    println!("Backed up metadata database to {}", path);
+    Ok(())
+}
+
+/// Db repair-refs command implementation
+async fn db_repair_refs_command(config: &StorageConfig) -> Result<()> {
+    let db = open_db(config).await?;
+    db.recompute_refs().await?;
+    println!("Recomputed object reference counts");
+    Ok(())
+}
+
+/// Db checkpoint command implementation
+async fn db_checkpoint_command(config: &StorageConfig, mode: &str) -> Result<()> {
+    let mode = CheckpointMode::from_str(mode)?;
+    let db = open_db(config).await?;
+    db.checkpoint(mode).await?;
+    println!("Checkpointed metadata database ({})", mode);
+    Ok(())
+}
+
+/// Config set command implementation
+///
+/// Edits the store defined at the top level of `config_file`, so settings
+/// coming only from the environment are not written back.
+async fn config_set_command(config_file: &Path, key: &str, value: &str) -> Result<()> {
+    let mut config = StorageConfig::load_for_edit(config_file).await?;
+    config.set_key(key, value)?;
+    config.save_to(config_file).await?;
+    println!("Set {} = {} in {}", key, config.get_key(key)?, config_file.display());
+    Ok(())
+}
+
+/// Doctor command implementation
+///
+/// Prints one line per check and fails if any check failed. A config that
+/// cannot be loaded is itself reported as a failed check.
+async fn doctor_command(profile: Option<&str>, store: Option<&str>, format: Format) -> Result<()> {
+    let checks = match load_config(profile, store).await {
+        Ok(config) => doctor::run(&config).await,
+        Err(e) => vec![doctor::Check {
+            name: "config",
+            status: doctor::Status::Fail,
+            detail: format!("{:#}", e),
+        }],
+    };
+
+    output::emit(format, &checks, |checks| {
+        for check in checks {
+            println!("{}  {:<11} {}", check.status, check.name, check.detail);
+        }
+    })?;
+
+    let failed = checks.iter().filter(|c| c.status == doctor::Status::Fail).count();
+    if failed > 0 {
+        anyhow::bail!("{} check(s) failed", failed);
+    }
+    Ok(())
+}
+
+/// Guess a file's MIME type
+///
+/// The extension is tried first; only when it is missing or unknown are the
+/// file's leading bytes sniffed. `None` if neither gives a type.
+fn guess_mime_type(path: &Path) -> Option<String> {
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return Some(mime.essence_str().to_string());
+    }
+    let sniffed = infer::get_from_path(path).ok().flatten()?;
+    Some(sniffed.mime_type().to_string())
+}
+
+/// Default number of files `transform` hashes concurrently
+const DEFAULT_SCAN_JOBS: usize = 8;
+
+/// Run `f` over `items` with at most `limit` futures in flight
+///
+/// Results come back in completion order; the first error aborts the rest.
+async fn map_bounded<T, U, F, Fut>(items: Vec<T>, limit: usize, f: F) -> Result<Vec<U>>
+where
+    F: FnMut(T) -> Fut,
+    Fut: std::future::Future<Output = Result<U>>,
+{
+    use futures::{StreamExt, TryStreamExt};
+
+    futures::stream::iter(items)
+        .map(f)
+        .buffer_unordered(limit.max(1))
+        .try_collect()
+        .await
+}
+
+/// Hash every file under a transform output directory into manifest contents
+///
+/// Subdirectories are walked recursively and files are recorded with their
+/// path relative to `output_path`. Symlinks are recorded with their target
+/// rather than followed. Up to `jobs` files are hashed at once, each read
+/// `buf_size` bytes at a time; the result is sorted by path, so it does not
+/// depend on `jobs`.
+async fn scan_output_dir(output_path: &Path, jobs: usize, buf_size: usize) -> Result<Vec<Content>> {
+    let entries = walk_tree(output_path).await?;
+    let mut contents = map_bounded(entries, jobs, |(rel_path, path, is_symlink)| {
+        scan_entry(rel_path, path, is_symlink, buf_size)
+    })
+    .await?;
+
+    contents.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(contents)
+}
+
+/// Describe one file or symlink found by `walk_tree`
+async fn scan_entry(
+    rel_path: String,
+    path: std::path::PathBuf,
+    is_symlink: bool,
+    buf_size: usize,
+) -> Result<Content> {
+    if is_symlink {
+        let target = tokio::fs::read_link(&path)
+            .await
+            .with_context(|| format!("Failed to read symlink: {}", path.display()))?
+            .to_string_lossy()
+            .to_string();
+
+        tracing::debug!("Recorded symlink: {}", path.display());
+        return Ok(Content {
+            path: rel_path,
+            hash: Blake3Hash::from_bytes(target.as_bytes()).to_hex(),
+            size: target.len() as u64,
+            executable: false,
+            mime_type: None,
+            kind: ContentKind::Symlink { target },
+            derived_from: Vec::new(),
+        });
+    }
+
+    // Hash the file off the async runtime
+    let hash_path = path.clone();
+    let hash = tokio::task::spawn_blocking(move || Blake3Hash::from_file_with_capacity(&hash_path, buf_size))
+        .await
+        .context("Hashing task panicked")?
+        .with_context(|| format!("Failed to hash file: {}", path.display()))?;
+
+    // Get file metadata
+    let metadata = tokio::fs::metadata(&path).await?;
+    let size = metadata.len();
+
+    #[cfg(unix)]
+    let executable = metadata.permissions().mode() & 0o111 != 0;
+    #[cfg(not(unix))]
+    let executable = false;
+
+    tracing::debug!("Processed file: {} (hash: {})", path.display(), hash);
+    Ok(Content {
+        path: rel_path,
+        hash: hash.to_hex(),
+        size,
+        executable,
+        mime_type: guess_mime_type(&path),
+        kind: ContentKind::File,
+        derived_from: Vec::new(),
+    })
+}
+
+/// Result of `transform_command`
+struct TransformOutcome {
+    manifest: Manifest,
+    /// Whether the manifest came from the transformation cache
+    cached: bool,
+}
+
+/// Transform command implementation
+///
+/// Unless `no_cache` is set, a transformation already recorded for the same
+/// input manifest, type, and parameters is answered from the metadata
+/// database without scanning the output directory. `derived_from` names a
+/// mapping file for `Manifest::record_derivation`; it also bypasses the
+/// cache, since a cached manifest would not reflect the mapping.
+#[allow(clippy::too_many_arguments)]
+async fn transform_command(
+    config: &StorageConfig,
+    input_manifest: &str,
+    output_dir: &str,
+    transform_type: &str,
+    params: &[String],
+    no_cache: bool,
+    jobs: usize,
+    derived_from: Option<&str>,
+) -> Result<TransformOutcome> {
+    let params = parse_params(params)?;
+    // serde_json maps are sorted by key, so equal parameter sets serialize identically
+    let params_key = params
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .context("Failed to serialize transformation parameters")?;
+
+    tracing::info!("Processing transformation: {}", transform_type);
+    tracing::info!("Input manifest: {}", input_manifest);
+    tracing::info!("Output directory: {}", output_dir);
+
+    // Read and parse input manifest (JSON, YAML, or TOML by extension)
+    let input_manifest_data = read_manifest(input_manifest).await?;
+
+    input_manifest_data
+        .validate()
+        .with_context(|| format!("Invalid input manifest: {}", input_manifest))?;
+
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
+    let db = open_db(config).await?;
+    let input_hash = input_manifest_data.content_hash().to_string_prefixed();
+
+    let derivation = match derived_from {
+        Some(path) => Some(read_derivation(path).await?),
+        None => None,
+    };
+
+    // A cached result would not carry this run's mapping
+    if !no_cache && derivation.is_none() {
+        if let Some(manifest) =
+            cached_transformation(&storage, &db, &input_hash, transform_type, params_key.as_deref()).await?
+        {
+            tracing::info!("Cache hit: {}", manifest.content_hash());
+            return Ok(TransformOutcome {
+                manifest,
+                cached: true,
+            });
+        }
+    }
+
+    // Registered transforms produce their own contents; any other type is
+    // external, and its output directory is scanned as it stands
+    let output_path = Path::new(output_dir);
+    let ctx = transform::TransformCtx {
+        storage: &storage,
+        output_dir: output_path,
+        jobs,
+        buf_size: config.hash_buffer_size(),
+    };
+    let contents = transform::Registry::with_builtins()
+        .contents(transform_type, &input_manifest_data, &ctx)
+        .await?;
+
+    if contents.is_empty() {
+        anyhow::bail!(
+            "No files found in output directory: {} ({})",
+            output_dir,
+            describe_empty_dir(output_path).await?
+        );
+    }
+
+    tracing::info!("Processed {} output files", contents.len());
+
+    // Provenance points at the source archive, or at the input manifest
+    // when there is none (e.g. the input is itself a transform output)
+    let source_hash = input_manifest_data.source.archive_hash.clone().unwrap_or_else(|| input_hash.clone());
+
+    // Create transformation record
+    let new_transformation = Transformation {
+        transform_type: transform_type.to_string(),
+        from: source_hash.clone(),
+        params: params.map(serde_json::Value::Object),
+    };
+
+    // Build transformations array (preserve existing + add new)
+    let mut transformations = input_manifest_data.transformations.clone();
+    transformations.push(new_transformation);
+
+    // Generate output manifest
+    let mut output_manifest = Manifest {
+        schema_version: manifest::CURRENT_SCHEMA_VERSION.to_string(),
+        dataset: input_manifest_data.dataset.clone(),
+        source: input_manifest_data.source.clone(),
+        contents,
+        transformations,
+        signature: None,
+    };
+    if let Some(mapping) = &derivation {
+        output_manifest
+            .record_derivation(&input_manifest_data, mapping)
+            .context("Failed to apply --derived-from mapping")?;
+    }
+    output_manifest.sort_contents();
+
+    record_transformation(
+        &storage,
+        &db,
+        &input_manifest_data,
+        &output_manifest,
+        transform_type,
+        params_key,
+    )
+    .await?;
+
+    Ok(TransformOutcome {
+        manifest: output_manifest,
+        cached: false,
+    })
+}
+
+/// Read a `--derived-from` file: a JSON object from output paths to arrays
+/// of input paths
+async fn read_derivation(path: &str) -> Result<std::collections::BTreeMap<String, Vec<String>>> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read derivation mapping: {}", path))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse derivation mapping: {}", path))
+}
+
+/// Pipeline transform implementation
+///
+/// Runs every step of the spec in its own subdirectory of `output_dir`
+/// (see `transform::run_pipeline`), recording each transformation.
+async fn pipeline_command(
+    config: &StorageConfig,
+    input_manifest: &str,
+    pipeline: &str,
+    output_dir: &str,
+    jobs: usize,
+) -> Result<Manifest> {
+    let input = read_manifest(input_manifest).await?;
+    input
+        .validate()
+        .with_context(|| format!("Invalid input manifest: {}", input_manifest))?;
+
+    let spec = transform::PipelineSpec::load(Path::new(pipeline)).await?;
+    let storage = LocalStorage::new(config.clone());
+    storage.initialize().await?;
+    let db = open_db(config).await?;
+    let ctx = transform::TransformCtx {
+        storage: &storage,
+        output_dir: Path::new(output_dir),
+        jobs,
+        buf_size: config.hash_buffer_size(),
+    };
+
+    transform::run_pipeline(&transform::Registry::with_builtins(), &spec.steps, &input, &ctx, &db).await
+}
+
+/// Write a manifest as pretty JSON (or YAML for `format` `"yaml"`) to `path`,
+/// or to stdout for `None` or `-`
+///
+/// Missing parent directories of `path` are created.
+async fn write_manifest(manifest: &Manifest, path: Option<&str>, format: &str) -> Result<()> {
+    let serialized = match format {
+        "yaml" => serde_yaml::to_string(manifest)
+            .map(|yaml| yaml.trim_end().to_string())
+            .context("Failed to serialize output manifest")?,
+        _ => serde_json::to_string_pretty(manifest).context("Failed to serialize output manifest")?,
+    };
+
+    match path {
+        None | Some("-") => println!("{}", serialized),
+        Some(path) => {
+            let path = Path::new(path);
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+            }
+            tokio::fs::write(path, serialized + "\n")
+                .await
+                .with_context(|| format!("Failed to write manifest: {}", path.display()))?;
+            tracing::info!("Wrote output manifest to {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Load the output manifest of a previously recorded transformation
+///
+/// Returns `None` on a cache miss, or if the cached manifest object is no
+/// longer in the store.
+async fn cached_transformation(
+    storage: &LocalStorage,
+    db: &MetadataDb,
+    input_hash: &str,
+    transform_type: &str,
+    params_key: Option<&str>,
+) -> Result<Option<Manifest>> {
+    let Some(output_hash) = db
+        .find_cached_transformation(input_hash, transform_type, params_key)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let hash = Blake3Hash::from_str(&output_hash)?;
+    if !storage.exists(&hash).await {
+        tracing::warn!("Cached manifest {} is missing from the store", output_hash);
+        return Ok(None);
+    }
+
+    let path = storage.get(&hash).await?;
+    read_manifest(&path.to_string_lossy()).await.map(Some)
+}
+
+/// Parse a human duration such as `30m`, `12h`, `7d`, or `2w`
+///
+/// A bare number is taken as seconds.
+fn parse_duration(raw: &str) -> Result<chrono::Duration> {
+    let raw = raw.trim();
+    let split = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+
+    let value: i64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{}' (expected e.g. 12h or 7d)", raw))?;
+    let seconds_per_unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        other => anyhow::bail!("Unknown duration unit '{}' in '{}' (use s, m, h, d, or w)", other, raw),
+    };
+
+    value
+        .checked_mul(seconds_per_unit)
+        .and_then(chrono::Duration::try_seconds)
+        .with_context(|| format!("Duration is too large: {}", raw))
+}
+
+/// Parse repeated `--param key=value` flags into a JSON object
+///
+/// Returns `None` when no parameters were given.
+fn parse_params(params: &[String]) -> Result<Option<serde_json::Map<String, serde_json::Value>>> {
+    if params.is_empty() {
+        return Ok(None);
+    }
+
+    let mut map = serde_json::Map::new();
+    for param in params {
+        let (key, value) = param
+            .split_once('=')
+            .filter(|(key, _)| !key.is_empty())
+            .with_context(|| format!("Invalid --param '{}': expected key=value", param))?;
+        map.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+    }
+    Ok(Some(map))
+}
+
+/// Store both manifests and record the transformation between them
+///
+/// The input and output manifests (by content hash) together with the
+/// transform type and parameters form the cache key, so the same transform
+/// with different parameters is recorded separately.
+async fn record_transformation(
+    storage: &LocalStorage,
+    db: &MetadataDb,
+    input: &Manifest,
+    output: &Manifest,
+    transform_type: &str,
+    params_key: Option<String>,
+) -> Result<()> {
+    let input_hash = input.content_hash().to_string_prefixed();
+    let output_hash = output.content_hash().to_string_prefixed();
+
+    let cached = db
+        .find_cached_transformation(&input_hash, transform_type, params_key.as_deref())
+        .await?;
+    if cached.as_deref() == Some(output_hash.as_str()) {
+        tracing::info!("Transformation already recorded: {}", output_hash);
+        return Ok(());
+    }
+
+    for manifest in [input, output] {
+        storage.register_dataset(manifest).await?;
+        db.register_object(
+            &manifest.content_hash().to_string_prefixed(),
+            manifest::canonical_bytes(manifest).len() as i64,
+            None,
+        )
+        .await?;
+    }
+
+    db.register_transformation(&input_hash, &output_hash, transform_type, params_key)
+        .await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Initialize tracing subscriber for logging (stderr keeps stdout clean for output)
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+
+    let cli = Cli::parse();
+    let profile = cli.profile.as_deref();
+    let store = cli.store.as_deref();
+    let format = Format::from_json_flag(cli.json);
+    let quiet = cli.quiet;
+
+    match cli.command {
+        Commands::Init { path, force } => init_command(path.as_deref(), force, profile, store, format).await,
+        Commands::Put {
+            file,
+            recursive: true,
+            name,
+            version,
+            dry_run,
+            ..
+        } => {
+            if dry_run {
+                return put_tree_preview_command(&load_config(profile, store).await?, &file, format, quiet)
+                    .await
+                    .map(|_| ());
+            }
+            tracing::info!("Storing directory: {}", file);
+            let name = name.context("--recursive requires --name")?;
+            let version = version.context("--recursive requires --version")?;
+            put_tree_command(&load_config(profile, store).await?, &file, &name, &version, format, quiet)
+                .await
+                .map(|_| ())
+        }
+        Commands::Put {
+            file,
+            preserve,
+            chunked,
+            ttl,
+            ..
+        } => {
+            tracing::info!("Storing file: {}", file);
+            put_command(&load_config(profile, store).await?, &file, preserve, chunked, ttl, format, quiet)
+                .await
+                .map(|_| ())
+        }
+        Commands::Get {
+            hash,
+            stdin,
+            output,
+            stdout,
+            restore,
+            materialize,
+        } => {
+            let config = load_config(profile, store).await?;
+            let hashes = hash_args(hash, stdin)?;
+            if hashes.len() > 1 && (output.is_some() || stdout || restore.is_some() || materialize.is_some()) {
+                anyhow::bail!("--output, --stdout, --restore, and --materialize take a single hash");
+            }
+            let (config, restore, output, materialize) =
+                (&config, restore.as_deref(), output.as_deref(), materialize.as_deref());
+            for_each_hash(&hashes, |hash| async move {
+                tracing::info!("Retrieving file with hash: {}", hash);
+                match (restore, materialize) {
+                    (Some(dir), _) => restore_command(config, &hash, Path::new(dir), format).await.map(|_| ()),
+                    (_, Some(dir)) => materialize_command(config, &hash, Path::new(dir), format).await,
+                    _ => get_command(config, &hash, output, stdout, format).await,
+                }
+            })
+            .await
+        }
+        Commands::Info {
+            hash,
+            stdin,
+            referenced_by,
+        } => {
+            let config = load_config(profile, store).await?;
+            let hashes = hash_args(hash, stdin)?;
+            let config = &config;
+            for_each_hash(&hashes, |hash| async move { info_command(config, &hash, referenced_by, format).await }).await
+        }
+        Commands::Cat { hash } => cat_command(&load_config(profile, store).await?, &hash).await,
+        Commands::Fetch {
+            url,
+            mirrors,
+            hash,
+            checksums,
+            rate,
+        } => {
+            tracing::info!("Fetching from URL: {}", url);
+            fetch_command(
+                &load_config(profile, store).await?,
+                &url,
+                &mirrors,
+                hash.as_deref(),
+                checksums.as_deref(),
+                rate,
+                format,
+                quiet,
+            )
+            .await
+            .map(|_| ())
+        }
+        Commands::Transform {
+            input_manifest,
+            output_dir,
+            transform_type,
+            params,
+            pipeline,
+            no_cache,
+            jobs,
+            output_manifest,
+            derived_from,
+            output_format,
+        } => {
+            let manifest = match (pipeline, transform_type) {
+                (Some(pipeline), _) => {
+                    let config = load_config(profile, store).await?;
+                    pipeline_command(&config, &input_manifest, &pipeline, &output_dir, jobs).await?
+                }
+                (None, Some(transform_type)) => {
+                    let config = load_config(profile, store).await?;
+                    let outcome = transform_command(
+                        &config,
+                        &input_manifest,
+                        &output_dir,
+                        &transform_type,
+                        &params,
+                        no_cache,
+                        jobs,
+                        derived_from.as_deref(),
+                    )
+                    .await?;
+                    if outcome.cached {
+                        eprintln!("Cached result reused (pass --no-cache to rerun)");
+                    }
+                    outcome.manifest
+                }
+                (None, None) => anyhow::bail!("--transform-type is required without --pipeline"),
+            };
+            write_manifest(&manifest, output_manifest.as_deref(), &output_format).await
+        }
+        Commands::List {
+            datasets: _,
+            objects,
+            name,
+            limit,
+            offset,
+        } => {
+            let config = load_config(profile, store).await?;
+            list_command(&config, objects, name.as_deref(), limit, offset, format).await
+        }
+        Commands::Lineage { hash } => lineage_command(&load_config(profile, store).await?, &hash).await,
+        Commands::Stats { top, transforms } => {
+            stats_command(&load_config(profile, store).await?, top, transforms, format).await
+        }
+        Commands::DedupReport => dedup_report_command(&load_config(profile, store).await?, format).await,
+        Commands::Gc {
+            dry_run,
+            expired,
+            max_deletes,
+            max_time,
+        } => {
+            tracing::info!("Running garbage collection (dry_run: {}, expired: {})", dry_run, expired);
+            gc_command(&load_config(profile, store).await?, dry_run, expired, max_deletes, max_time).await
+        }
+        Commands::Prune { name, keep, dry_run } => {
+            prune_command(&load_config(profile, store).await?, &name, keep, dry_run, format).await
+        }
+        Commands::Sync { src, dst, rate } => sync_command(&src, &dst, rate, format).await,
+        Commands::Pack { max_size } => pack_command(&load_config(profile, store).await?, max_size, format).await,
+        Commands::Rm { hash, stdin, force } => {
+            let config = load_config(profile, store).await?;
+            let hashes = hash_args(hash, stdin)?;
+            let config = &config;
+            for_each_hash(&hashes, |hash| async move { rm_command(config, &hash, force, format).await }).await
+        }
+        Commands::VerifyManifest { file, key } => verify_manifest_command(&file, &key).await,
+        Commands::Verify { hash, stdin } => {
+            let config = load_config(profile, store).await?;
+            let hashes = hash_args(hash, stdin)?;
+            let config = &config;
+            for_each_hash(&hashes, |hash| async move { verify_command(config, &hash, format).await }).await
+        }
+        Commands::VerifyDataset { name, version, deep } => {
+            verify_dataset_command(&load_config(profile, store).await?, &name, &version, deep, format).await
+        }
+        Commands::VerifyStore { jobs } => {
+            verify_store_command(&load_config(profile, store).await?, jobs, format).await
+        }
+        Commands::Tree { name, version } => {
+            tree_command(&load_config(profile, store).await?, &name, &version, format).await
+        }
+        Commands::Diff { old, new } => diff_command(&old, &new, format).await,
+        Commands::Cp {
+            name,
+            version,
+            pattern,
+            dest,
+        } => cp_command(
+            &load_config(profile, store).await?,
+            &name,
+            &version,
+            &pattern,
+            Path::new(&dest),
+            format,
+        )
+        .await
+        .map(|_| ()),
+        Commands::Export {
+            name,
+            version,
+            bundle,
+        } => export_command(&load_config(profile, store).await?, &name, &version, &bundle, format).await,
+        Commands::ExportNix {
+            hash,
+            name,
+            executable,
+            nar,
+        } => export_nix_command(&load_config(profile, store).await?, &hash, &name, executable, nar.as_deref(), format)
+            .await
+            .map(|_| ()),
+        Commands::ImportOci { image, token, insecure } => {
+            tracing::info!("Importing OCI image: {}", image);
+            import_oci_command(&load_config(profile, store).await?, &image, token, insecure, format, quiet)
+                .await
+                .map(|_| ())
+        }
+        Commands::ImportLfs {
+            pointer,
+            endpoint,
+            token,
+        } => import_lfs_command(
+            &load_config(profile, store).await?,
+            &pointer,
+            &endpoint,
+            token.as_deref(),
+            format,
+            quiet,
+        )
+        .await
+        .map(|_| ()),
+        Commands::ExportLfs { hash, output } => {
+            export_lfs_command(&load_config(profile, store).await?, &hash, output.as_deref())
+                .await
+                .map(|_| ())
+        }
+        Commands::Import { bundle } => import_command(&load_config(profile, store).await?, &bundle, format)
+            .await
+            .map(|_| ()),
+        Commands::Config { command } => match command {
+            ConfigCommands::Get { key } => {
+                println!("{}", load_config(profile, store).await?.get_key(&key)?);
+                Ok(())
+            }
+            ConfigCommands::Set { key, value } => {
+                let config_file =
+                    StorageConfig::config_file_path().context("Failed to determine config file location")?;
+                config_set_command(&config_file, &key, &value).await
+            }
+        },
+        Commands::Doctor => doctor_command(profile, store, format).await,
+        Commands::Completions { shell } => {
+            write_completions(shell, &mut std::io::stdout());
+            Ok(())
+        }
+        Commands::Db { command } => {
+            let config = load_config(profile, store).await?;
+            match command {
+                DbCommands::Export { output } => db_export_command(&config, output.as_deref()).await,
+                DbCommands::Import { file, replace } => db_import_command(&config, &file, replace).await,
+                DbCommands::Backup { path } => db_backup_command(&config, &path).await,
+                DbCommands::RepairRefs => db_repair_refs_command(&config).await,
+                DbCommands::Checkpoint { mode } => db_checkpoint_command(&config, &mode).await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cli_parsing() {
+        // Test that CLI parsing works
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_completions() {
+        use clap_complete::Shell;
+
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let mut script = Vec::new();
+            write_completions(shell, &mut script);
+            assert!(!script.is_empty(), "empty completions for {}", shell);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_init_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("cast");
+        let config_file = temp_dir.path().join("config").join("config.toml");
+
+        let output = init_store(&root, false, Some(&config_file)).await.unwrap();
+        assert!(root.join("store").is_dir());
+        assert_eq!(output.database, root.join("meta.db"));
+        assert_eq!(output.config_file.as_deref(), Some(config_file.as_path()));
+
+        let config = StorageConfig {
+            root: root.clone(),
+            ..Default::default()
+        };
+        let db = open_db(&config).await.unwrap();
+        assert_eq!(db.get_stats().await.unwrap().objects_count, 0);
+
+        let written: StorageConfig = toml::from_str(&std::fs::read_to_string(&config_file).unwrap()).unwrap();
+        assert_eq!(written.root, root);
+
+        let err = init_store(&root, false, Some(&config_file)).await.unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+        assert!(init_store(&root, true, None).await.is_ok());
+
+        // --force re-initializes the store but leaves an existing config alone
+        let other = temp_dir.path().join("other");
+        let output = init_store(&other, true, Some(&config_file)).await.unwrap();
+        assert!(output.config_file.is_none());
+        assert!(other.join("store").is_dir());
+        let kept: StorageConfig = toml::from_str(&std::fs::read_to_string(&config_file).unwrap()).unwrap();
+        assert_eq!(kept.root, root);
+    }
+
+    #[tokio::test]
+    async fn test_put_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let file = temp_dir.path().join("input.txt");
+        tokio::fs::write(&file, b"put me in the store").await.unwrap();
+
+        let result = put_command(&config, file.to_str().unwrap(), false, false, None, Format::Human, true).await.unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_file(&file).unwrap());
+        assert!(!result.deduplicated);
+
+        let db = open_db(&config).await.unwrap();
+        let object = db.get_object(&result.hash.to_string_prefixed()).await.unwrap().unwrap();
+        assert_eq!(object.size, 19);
+
+        let again = put_command(&config, file.to_str().unwrap(), false, false, None, Format::Human, true).await.unwrap();
+        assert!(again.deduplicated);
+
+        let missing = temp_dir.path().join("missing.txt");
+        let err = put_command(&config, missing.to_str().unwrap(), false, false, None, Format::Human, true).await.unwrap_err();
+        assert!(err.to_string().contains("File not found"));
+    }
+
+    #[tokio::test]
+    async fn test_put_command_chunked() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let mut data = vec![0u8; 2 * 1024 * 1024];
+        blake3::Hasher::new().update(b"chunked put").finalize_xof().fill(&mut data);
+        let file = temp_dir.path().join("big.bin");
+        tokio::fs::write(&file, &data).await.unwrap();
+
+        let result = put_command(&config, file.to_str().unwrap(), false, true, None, Format::Human, true).await.unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_bytes(&data));
+        assert_eq!(result.size, data.len() as u64);
+        assert!(!result.deduplicated);
+
+        let storage = LocalStorage::new(config.clone());
+        assert!(storage.is_chunked(&result.hash));
+        let object = open_db(&config).await.unwrap().get_object(&result.hash.to_string_prefixed()).await.unwrap().unwrap();
+        assert_eq!(object.size, data.len() as i64);
+
+        let again = put_command(&config, file.to_str().unwrap(), false, true, None, Format::Human, true).await.unwrap();
+        assert!(again.deduplicated);
+    }
+
+    #[tokio::test]
+    async fn test_put_command_mirrors_and_get_repairs() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            mirror_root: Some(temp_dir.path().join("mirror")),
+            ..Default::default()
+        };
+        let file = temp_dir.path().join("input.txt");
+        tokio::fs::write(&file, b"mirrored").await.unwrap();
+
+        let result = put_command(&config, file.to_str().unwrap(), false, false, None, Format::Human, true).await.unwrap();
+        let mirror = LocalStorage::new(config.mirror_config().unwrap());
+        assert!(mirror.exists(&result.hash).await);
+
+        let storage = LocalStorage::new(config.clone());
+        storage.delete(&result.hash).await.unwrap();
+        assert!(!storage.exists(&result.hash).await);
+
+        let output = temp_dir.path().join("output.txt");
+        get_command(&config, &result.hash.to_string_prefixed(), Some(output.to_str().unwrap()), false, Format::Human)
+            .await
+            .unwrap();
+        assert_eq!(tokio::fs::read(&output).await.unwrap(), b"mirrored");
+        assert!(storage.exists(&result.hash).await);
+    }
+
+    #[tokio::test]
+    async fn test_verify_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+        storage.initialize().await.unwrap();
+        let hash = storage.put(b"intact").await.unwrap();
+        verify_command(&config, &hash.to_string_prefixed(), Format::Human).await.unwrap();
+
+        let path = storage.get(&hash).await.unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::fs::write(&path, b"tampered").unwrap();
+
+        let err = verify_command(&config, &hash.to_string_prefixed(), Format::Human).await.unwrap_err();
+        assert!(err.to_string().contains("corrupt"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_put_tree_preview_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+        storage.initialize().await.unwrap();
+        storage.put(b"stored").await.unwrap();
+
+        let tree = temp_dir.path().join("tree");
+        tokio::fs::create_dir_all(tree.join("sub")).await.unwrap();
+        tokio::fs::write(tree.join("a.txt"), b"same").await.unwrap();
+        tokio::fs::write(tree.join("sub/copy.txt"), b"same").await.unwrap();
+        tokio::fs::write(tree.join("new.txt"), b"brand new").await.unwrap();
+        tokio::fs::write(tree.join("old.txt"), b"stored").await.unwrap();
+
+        let preview = put_tree_preview_command(&config, tree.to_str().unwrap(), Format::Human, true)
+            .await
+            .unwrap();
+
+        assert_eq!(preview.files, 4);
+        assert_eq!(preview.new_files, 2);
+        assert_eq!(preview.deduplicated, 2);
+        assert_eq!(preview.new_bytes, 4 + 9);
+        assert!(!storage.exists(&Blake3Hash::from_bytes(b"same")).await);
+        assert!(!storage.exists(&Blake3Hash::from_bytes(b"brand new")).await);
+    }
+
+    #[tokio::test]
+    async fn test_put_tree_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let tree = temp_dir.path().join("tree");
+        tokio::fs::create_dir_all(tree.join("sub/deeper")).await.unwrap();
+        tokio::fs::write(tree.join("top.txt"), b"top").await.unwrap();
+        tokio::fs::write(tree.join("sub/a.fasta"), b">a\nACGT\n").await.unwrap();
+        tokio::fs::write(tree.join("sub/deeper/b.txt"), b"top").await.unwrap();
+        tokio::fs::write(tree.join("sub/deeper/run.sh"), b"#!/bin/sh\n").await.unwrap();
+        #[cfg(unix)]
+        std::fs::set_permissions(
+            tree.join("sub/deeper/run.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+
+        let (manifest, _) = put_tree_command(&config, tree.to_str().unwrap(), "tree", "1", Format::Human, true)
+            .await
+            .unwrap();
+
+        assert_eq!(manifest.contents.len(), 4);
+        let paths: Vec<&str> = manifest.contents.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["sub/a.fasta", "sub/deeper/b.txt", "sub/deeper/run.sh", "top.txt"]);
+        #[cfg(unix)]
+        assert!(manifest.contents[2].executable);
+        assert!(!manifest.contents[3].executable);
+
+        // Identical files share one object; the manifest is stored too
+        let storage = LocalStorage::new(config.clone());
+        assert_eq!(storage.list().await.unwrap().len(), 4);
+        assert!(storage.exists(&manifest.content_hash()).await);
+
+        let db = open_db(&config).await.unwrap();
+        let registered = db.get_manifest("tree", "1").await.unwrap().unwrap();
+        assert_eq!(registered.content_hash(), manifest.content_hash());
+
+        // New names must follow the namespace rules
+        assert!(put_tree_command(&config, tree.to_str().unwrap(), "bad name", "1", Format::Human, true)
+            .await
+            .is_err());
+        assert!(db.get_dataset("bad name", "1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_put_tree_dedups_identical_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let tree = temp_dir.path().join("tree");
+        tokio::fs::create_dir_all(tree.join("copies")).await.unwrap();
+        for name in ["a.bin", "copies/b.bin", "copies/c.bin"] {
+            tokio::fs::write(tree.join(name), b"same payload").await.unwrap();
+        }
+        // Same size, different content: hashed, then stored on its own
+        tokio::fs::write(tree.join("other.bin"), b"diff payload").await.unwrap();
+
+        let (manifest, summary) = put_tree_command(&config, tree.to_str().unwrap(), "dups", "1", Format::Json, true)
+            .await
+            .unwrap();
+
+        assert_eq!(manifest.contents.len(), 4);
+        let same = Blake3Hash::from_bytes(b"same payload").to_string_prefixed();
+        let copies: Vec<&str> = manifest
+            .contents
+            .iter()
+            .filter(|c| c.hash == same)
+            .map(|c| c.path.as_str())
+            .collect();
+        assert_eq!(copies, ["a.bin", "copies/b.bin", "copies/c.bin"]);
+        assert_eq!(summary.files, 4);
+        assert_eq!(summary.deduplicated, 2);
+
+        // Two file objects plus the manifest
+        let storage = LocalStorage::new(config);
+        assert_eq!(storage.list().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cp_command_copies_matching_subtree() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let tree = temp_dir.path().join("tree");
+        tokio::fs::create_dir_all(tree.join("a/sub")).await.unwrap();
+        tokio::fs::create_dir_all(tree.join("b")).await.unwrap();
+        tokio::fs::write(tree.join("a/x.txt"), b"x").await.unwrap();
+        tokio::fs::write(tree.join("a/sub/y.txt"), b"y").await.unwrap();
+        tokio::fs::write(tree.join("b/z.txt"), b"z").await.unwrap();
+        tokio::fs::write(tree.join("top.txt"), b"top").await.unwrap();
+        put_tree_command(&config, tree.to_str().unwrap(), "ds", "1.0.0", Format::Json, true)
+            .await
+            .unwrap();
+
+        let out = temp_dir.path().join("out");
+        let copied = cp_command(&config, "ds", "1.0.0", "a/**", &out, Format::Json).await.unwrap();
+        assert_eq!(copied.files, 2);
+        assert_eq!(std::fs::read(out.join("a/x.txt")).unwrap(), b"x");
+        assert_eq!(std::fs::read(out.join("a/sub/y.txt")).unwrap(), b"y");
+        assert!(std::fs::symlink_metadata(out.join("a/x.txt")).unwrap().is_file());
+        assert!(!out.join("b").exists());
+        assert!(!out.join("top.txt").exists());
+
+        // `*` does not cross directories
+        let shallow = temp_dir.path().join("shallow");
+        assert_eq!(cp_command(&config, "ds", "1.0.0", "a/*", &shallow, Format::Json).await.unwrap().files, 1);
+
+        let err = cp_command(&config, "ds", "1.0.0", "c/**", &temp_dir.path().join("none"), Format::Json)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("No paths"), "{}", err);
+        assert!(!temp_dir.path().join("none").exists());
+    }
+
+    #[tokio::test]
+    async fn test_put_preserve_then_restore() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let file = temp_dir.path().join("analyze.sh");
+        tokio::fs::write(&file, b"#!/bin/sh\necho hi\n").await.unwrap();
+        #[cfg(unix)]
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+        let result = put_command(&config, file.to_str().unwrap(), true, false, None, Format::Human, true)
+            .await
+            .unwrap();
+
+        let restore_dir = temp_dir.path().join("restored");
+        tokio::fs::create_dir(&restore_dir).await.unwrap();
+        let restored = restore_command(&config, &result.hash.to_string_prefixed(), &restore_dir, Format::Human)
+            .await
+            .unwrap();
+
+        assert_eq!(restored, restore_dir.join("analyze.sh"));
+        assert_eq!(tokio::fs::read(&restored).await.unwrap(), b"#!/bin/sh\necho hi\n");
+        #[cfg(unix)]
+        assert_eq!(std::fs::metadata(&restored).unwrap().permissions().mode() & 0o777, 0o750);
+        let mtime_secs = |path: &Path| {
+            let modified = std::fs::metadata(path).unwrap().modified().unwrap();
+            modified.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+        };
+        assert_eq!(mtime_secs(&restored), mtime_secs(&file));
+
+        // Without --preserve there is nothing to restore from
+        let plain = temp_dir.path().join("plain.txt");
+        tokio::fs::write(&plain, b"anonymous").await.unwrap();
+        let result = put_command(&config, plain.to_str().unwrap(), false, false, None, Format::Human, true)
+            .await
+            .unwrap();
+        assert!(restore_command(&config, &result.hash.to_string_prefixed(), &restore_dir, Format::Human)
+            .await
+            .is_err());
+
+        // A recorded setuid bit is dropped
+        #[cfg(unix)]
+        {
+            let setuid = FileMetadata {
+                name: "suid.sh".to_string(),
+                mode: 0o4755,
+                mtime: None,
+            };
+            let db = open_db(&config).await.unwrap();
+            db.set_object_metadata(&result.hash.to_string_prefixed(), &setuid.merge_into(None).unwrap())
+                .await
+                .unwrap();
+            let restored = restore_command(&config, &result.hash.to_string_prefixed(), &restore_dir, Format::Human)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::metadata(&restored).unwrap().permissions().mode() & 0o7777, 0o755);
+        }
+    }
+
+    /// Serve `body` to a single HTTP request on a local port
+    async fn serve_once(body: &'static [u8]) -> String {
+        serve_status("200 OK", body).await
+    }
+
+    /// Answer every HTTP request on a local port with `status` and `body`
+    async fn serve_status(status: &'static str, body: &'static [u8]) -> String {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let header = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = socket.write_all(header.as_bytes()).await;
+                let _ = socket.write_all(body).await;
+            }
+        });
+
+        format!("http://{}/data.bin", addr)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_command_fails_over_to_mirror() {
+        const BODY: &[u8] = b"mirrored database contents";
+        let broken = serve_status("500 Internal Server Error", b"oops").await;
+        let corrupt = serve_status("200 OK", b"corrupted contents").await;
+        let mirror = serve_once(BODY).await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let expected = Blake3Hash::from_bytes(BODY).to_string_prefixed();
+
+        let result = fetch_command(
+            &config,
+            &broken,
+            std::slice::from_ref(&mirror),
+            Some(&expected),
+            None,
+            None,
+            Format::Human,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_bytes(BODY));
+        let db = open_db(&config).await.unwrap();
+        let object = db.get_object(&expected).await.unwrap().unwrap();
+        let source: manifest::Source = serde_json::from_str(&object.metadata.unwrap()).unwrap();
+        assert_eq!(source.url.as_deref(), Some(mirror.as_str()));
+
+        // A mirror serving the wrong bytes is skipped too
+        let result = fetch_command(
+            &config,
+            &corrupt,
+            std::slice::from_ref(&mirror),
+            Some(&expected),
+            None,
+            None,
+            Format::Human,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_bytes(BODY));
+
+        let err = fetch_command(
+            &config,
+            &broken,
+            std::slice::from_ref(&corrupt),
+            Some(&expected),
+            None,
+            None,
+            Format::Human,
+            true,
+        )
+        .await
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("Every mirror failed"));
+        assert!(!LocalStorage::new(config.clone())
+            .exists(&Blake3Hash::from_bytes(b"corrupted contents"))
+            .await);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_command_verifies_hash() {
+        const BODY: &[u8] = b"remote database contents";
+        let url = serve_once(BODY).await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+
+        let wrong = Blake3Hash::from_bytes(b"something else").to_string_prefixed();
+        assert!(fetch_command(&config, &url, &[], Some(&wrong), None, None, Format::Human, true).await.is_err());
+        assert!(storage.list().await.unwrap().is_empty());
+
+        let expected = Blake3Hash::from_bytes(BODY);
+        let result = fetch_command(&config, &url, &[], Some(&expected.to_string_prefixed()), None, None, Format::Human, true)
+            .await
+            .unwrap();
+        assert_eq!(result.hash, expected);
+        assert!(storage.exists(&expected).await);
+
+        let db = open_db(&config).await.unwrap();
+        let object = db.get_object(&expected.to_string_prefixed()).await.unwrap().unwrap();
+        let source: manifest::Source = serde_json::from_str(&object.metadata.unwrap()).unwrap();
+        assert_eq!(source.url.as_deref(), Some(url.as_str()));
+        assert!(source.download_date.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_export_nix_command() {
+        use sha2::Digest;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+        storage.initialize().await.unwrap();
+        let hash = storage.put(b"hello\n").await.unwrap();
+
+        let nar_path = temp_dir.path().join("hello.nar");
+        let export = export_nix_command(
+            &config,
+            &hash.to_string(),
+            "hello.txt",
+            false,
+            nar_path.to_str(),
+            Format::Human,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(export.store_path, "/nix/store/i9pmrzmpshapij2kin22pff6fc2adavx-hello.txt");
+        let nar = std::fs::read(&nar_path).unwrap();
+        assert_eq!(nar.len() as u64, export.nar_size);
+        let nar_hash: [u8; 32] = sha2::Sha256::digest(&nar).into();
+        assert_eq!(export.nar_hash, format!("sha256:{}", nix::base32(&nar_hash)));
+
+        assert!(export_nix_command(&config, &hash.to_string(), "bad name", false, None, Format::Human)
+            .await
+            .is_err());
+    }
+
+    /// Serve a mock OCI registry for repository `test/data` on a local port
+    ///
+    /// Every `/v2/` request needs the bearer token handed out anonymously by
+    /// `/token`. Tag `v1` is a two-layer image; tag `bad` declares a digest
+    /// its layer does not match. Returns the registry's `host:port`.
+    async fn serve_registry() -> String {
+        use sha2::Digest;
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let digest = |data: &[u8]| format!("sha256:{:x}", sha2::Sha256::digest(data));
+        let layers: [&[u8]; 2] = [b"first layer", b"second layer"];
+        let layer_json = |data: &[u8], digest: &str| {
+            format!(
+                r#"{{"mediaType":"application/vnd.oci.image.layer.v1.tar","digest":"{}","size":{}}}"#,
+                digest,
+                data.len()
+            )
+        };
+        let good = format!(
+            r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","layers":[{},{}]}}"#,
+            layer_json(layers[0], &digest(layers[0])),
+            layer_json(layers[1], &digest(layers[1]))
+        );
+        let bad = format!(
+            r#"{{"schemaVersion":2,"layers":[{}]}}"#,
+            layer_json(layers[1], &digest(b"something else"))
+        );
+        let mut routes = std::collections::HashMap::new();
+        routes.insert("/v2/test/data/manifests/v1".to_string(), good.into_bytes());
+        routes.insert("/v2/test/data/manifests/bad".to_string(), bad.into_bytes());
+        for layer in layers {
+            routes.insert(format!("/v2/test/data/blobs/{}", digest(layer)), layer.to_vec());
+        }
+        routes.insert(format!("/v2/test/data/blobs/{}", digest(b"something else")), layers[1].to_vec());
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let n = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+                let authorized = request.lines().any(|line| line == "authorization: Bearer anon-token");
+
+                let (status, extra, body) = if path.starts_with("/token?") {
+                    ("200 OK", String::new(), br#"{"token":"anon-token"}"#.to_vec())
+                } else if !authorized {
+                    let challenge = format!(
+                        "WWW-Authenticate: Bearer realm=\"http://{}/token\",service=\"mock\",scope=\"repository:test/data:pull\"\r\n",
+                        addr
+                    );
+                    ("401 Unauthorized", challenge, Vec::new())
+                } else if let Some(body) = routes.get(&path) {
+                    ("200 OK", String::new(), body.clone())
+                } else {
+                    ("404 Not Found", String::new(), Vec::new())
+                };
+                let header = format!(
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    extra,
+                    body.len()
+                );
+                let _ = socket.write_all(header.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            }
+        });
+
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_import_oci_command() {
+        let registry = serve_registry().await;
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+
+        let manifest = import_oci_command(&config, &format!("{}/test/data:v1", registry), None, true, Format::Human, true)
+            .await
+            .unwrap();
+        assert_eq!(manifest.dataset.name, "test/data");
+        assert_eq!(manifest.dataset.version, "v1");
+        assert_eq!(manifest.contents.len(), 2);
+        for (content, data) in manifest.contents.iter().zip([&b"first layer"[..], b"second layer"]) {
+            assert!(content.path.starts_with("sha256:"));
+            assert_eq!(content.hash, Blake3Hash::from_bytes(data).to_string_prefixed());
+            assert!(storage.exists(&Blake3Hash::from_bytes(data)).await);
+        }
+        let db = open_db(&config).await.unwrap();
+        assert!(db.get_dataset("test/data", "v1").await.unwrap().is_some());
+
+        // The raw image manifest is only referenced as the source archive
+        let raw_manifest = Blake3Hash::from_str(manifest.source.archive_hash.as_deref().unwrap()).unwrap();
+        let report = collect_garbage(&storage, &db, false, GcLimits::default()).await.unwrap();
+        assert!(report.swept.is_empty(), "{:?}", report.swept);
+        assert!(storage.exists(&raw_manifest).await);
+
+        // A repeated import returns the same dataset without bumping refcounts
+        let refs = |hash: Blake3Hash| {
+            let db = &db;
+            async move { db.get_object(&hash.to_string_prefixed()).await.unwrap().unwrap().refs }
+        };
+        assert_eq!(refs(raw_manifest).await, 1);
+        let layer_refs = refs(Blake3Hash::from_bytes(b"first layer")).await;
+        let reference = format!("{}/test/data:v1", registry);
+        let again = import_oci_command(&config, &reference, None, true, Format::Human, true)
+            .await
+            .unwrap();
+        assert_eq!(again.content_hash(), manifest.content_hash());
+        assert_eq!(refs(raw_manifest).await, 1);
+        assert_eq!(refs(Blake3Hash::from_bytes(b"first layer")).await, layer_refs);
+
+        let err = import_oci_command(&config, &format!("{}/test/data:bad", registry), None, true, Format::Human, true)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("failed verification"));
+        assert!(db.get_dataset("test/data", "bad").await.unwrap().is_none());
+    }
+
+    /// Serve a mock LFS server holding `data` on a local port
+    ///
+    /// The batch endpoint answers any request with a download action whose
+    /// `Authorization` header the object endpoint requires. Returns the
+    /// server's base URL.
+    async fn serve_lfs(data: &'static [u8]) -> String {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let href = format!("{}/objects/data", base);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let n = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+
+                let (status, body) = if path == "/objects/batch" {
+                    let batch = format!(
+                        r#"{{"transfer":"basic","objects":[{{"actions":{{"download":{{"href":"{}","header":{{"Authorization":"RemoteAuth secret"}}}}}}}}]}}"#,
+                        href
+                    );
+                    ("200 OK", batch.into_bytes())
+                } else if path == "/objects/data" && request.lines().any(|l| l == "authorization: RemoteAuth secret") {
+                    ("200 OK", data.to_vec())
+                } else {
+                    ("404 Not Found", Vec::new())
+                };
+                let header = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = socket.write_all(header.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn test_lfs_export_then_import() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = StorageConfig {
+            root: temp_dir.path().join("source"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(source.clone());
+        storage.initialize().await.unwrap();
+        let hash = storage.put(b"large file contents").await.unwrap();
+
+        let pointer_path = temp_dir.path().join("data.bin");
+        let pointer = export_lfs_command(&source, &hash.to_string(), pointer_path.to_str())
+            .await
+            .unwrap();
+        assert_eq!(pointer.size, 19);
+        let text = std::fs::read_to_string(&pointer_path).unwrap();
+        assert_eq!(lfs::Pointer::parse(&text).unwrap(), pointer);
+
+        let endpoint = serve_lfs(b"large file contents").await;
+        let target = StorageConfig {
+            root: temp_dir.path().join("target"),
+            ..Default::default()
+        };
+        let pointer_path = pointer_path.to_str().unwrap();
+        let result = import_lfs_command(&target, pointer_path, &endpoint, None, Format::Human, true)
+            .await
+            .unwrap();
+        assert_eq!(result.hash, hash);
+        assert!(LocalStorage::new(target.clone()).exists(&hash).await);
+        let db = open_db(&target).await.unwrap();
+        assert!(db.get_object(&hash.to_string_prefixed()).await.unwrap().is_some());
+
+        let tampered = lfs::Pointer { size: 20, ..pointer };
+        std::fs::write(pointer_path, tampered.to_string()).unwrap();
+        let err = import_lfs_command(&target, pointer_path, &endpoint, None, Format::Human, true)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("failed verification"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_command_verifies_checksums_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+
+        let downloads = temp_dir.path().join("downloads");
+        tokio::fs::create_dir(&downloads).await.unwrap();
+        tokio::fs::write(downloads.join("good.txt"), b"abc").await.unwrap();
+        tokio::fs::write(downloads.join("bad.txt"), b"tampered").await.unwrap();
+        tokio::fs::write(downloads.join("b3.txt"), b"blake3 listed").await.unwrap();
+        let sums = temp_dir.path().join("SHA256SUMS");
+        let abc_sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        tokio::fs::write(
+            &sums,
+            format!(
+                "{abc_sha256}  good.txt\n{abc_sha256}  bad.txt\nblake3:{}  b3.txt\n",
+                Blake3Hash::from_bytes(b"blake3 listed").to_hex()
+            ),
+        )
+        .await
+        .unwrap();
+        let sums = sums.to_str().unwrap();
+        let url = |name: &str| reqwest::Url::from_file_path(downloads.join(name)).unwrap().to_string();
+
+        let result = fetch_command(&config, &url("good.txt"), &[], None, Some(sums), None, Format::Human, true).await.unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_bytes(b"abc"));
+        let result = fetch_command(&config, &url("b3.txt"), &[], None, Some(sums), None, Format::Human, true).await.unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_bytes(b"blake3 listed"));
+
+        let err = fetch_command(&config, &url("bad.txt"), &[], None, Some(sums), None, Format::Human, true).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Checksum mismatch"));
+        assert!(!storage.exists(&Blake3Hash::from_bytes(b"tampered")).await);
+
+        tokio::fs::write(downloads.join("unlisted.txt"), b"x").await.unwrap();
+        assert!(fetch_command(&config, &url("unlisted.txt"), &[], None, Some(sums), None, Format::Human, true).await.is_err());
+    }
+
+    /// Serve `body` with byte-range support, cutting the first full response short
+    ///
+    /// Returns the URL and the `Range` header of every request received.
+    async fn serve_resumable(
+        body: &'static [u8],
+        etag: &'static str,
+    ) -> (String, std::sync::Arc<std::sync::Mutex<Vec<Option<String>>>>) {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ranges = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+
+        tokio::spawn(async move {
+            let mut interrupted = false;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let n = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_string();
+                let header = |name: &str| {
+                    request.lines().find_map(|line| {
+                        let (key, value) = line.split_once(':')?;
+                        key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+                    })
+                };
+
+                let range = header("Range");
+                seen.lock().unwrap().push(range.clone());
+
+                let start = match (range, header("If-Range")) {
+                    (Some(range), if_range) if if_range.as_deref().is_none_or(|e| e == etag) => range
+                        .trim_start_matches("bytes=")
+                        .trim_end_matches('-')
+                        .parse::<usize>()
+                        .unwrap(),
+                    _ => 0,
+                };
+
+                let response = if start > 0 {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n",
+                        start,
+                        body.len() - 1,
+                        body.len()
+                    )
+                } else {
+                    "HTTP/1.1 200 OK\r\n".to_string()
+                };
+                let response = format!(
+                    "{}Accept-Ranges: bytes\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response,
+                    etag,
+                    body.len() - start
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+
+                // Drop the connection halfway through the first full download
+                if start == 0 && !interrupted {
+                    interrupted = true;
+                    let _ = socket.write_all(&body[..body.len() / 2]).await;
+                    continue;
+                }
+                let _ = socket.write_all(&body[start..]).await;
+            }
+        });
+
+        (format!("http://{}/big.bin", addr), ranges)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_command_resumes_interrupted_download() {
+        const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz0123456789abcdefghijklmnopqrstuvwxyz";
+        let (url, ranges) = serve_resumable(BODY, "\"v1\"").await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+
+        let expected = Blake3Hash::from_bytes(BODY);
+        let result = fetch_command(&config, &url, &[], Some(&expected.to_string_prefixed()), None, None, Format::Human, true)
+            .await
+            .unwrap();
+        assert_eq!(result.hash, expected);
+        assert_eq!(tokio::fs::read(storage.get(&expected).await.unwrap()).await.unwrap(), BODY);
+
+        // The retry asked only for the bytes it was missing
+        let ranges = ranges.lock().unwrap().clone();
+        assert_eq!(ranges, vec![None, Some(format!("bytes={}-", BODY.len() / 2))]);
+        assert!(!storage.partial_download_path(&url).exists());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_command_restarts_when_resource_changed() {
+        const BODY: &[u8] = b"the current version of the remote database";
+        let (url, ranges) = serve_resumable(BODY, "\"v2\"").await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+
+        // A partial download left behind by an older version of the resource
+        let part = storage.partial_download_path(&url);
+        tokio::fs::create_dir_all(part.parent().unwrap()).await.unwrap();
+        tokio::fs::write(&part, b"stale bytes").await.unwrap();
+        tokio::fs::write(part.with_extension("json"), br#"{"accept_ranges":true,"etag":"\"v1\""}"#)
+            .await
+            .unwrap();
+
+        let result = fetch_command(&config, &url, &[], None, None, None, Format::Human, true).await.unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_bytes(BODY));
+
+        // The stale range request got a full (interrupted) response, then the
+        // retry resumed from the fresh partial download
+        let ranges = ranges.lock().unwrap().clone();
+        assert_eq!(ranges[0], Some("bytes=11-".to_string()));
+        assert_eq!(ranges.last().unwrap(), &Some(format!("bytes={}-", BODY.len() / 2)));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_command_file_url() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let file = temp_dir.path().join("local.bin");
+        tokio::fs::write(&file, b"local bytes").await.unwrap();
+        let url = reqwest::Url::from_file_path(&file).unwrap().to_string();
+
+        let result = fetch_command(&config, &url, &[], None, None, None, Format::Human, true).await.unwrap();
+        assert_eq!(result.hash, Blake3Hash::from_bytes(b"local bytes"));
+
+        let archive = temp_dir.path().join("local.gz");
+        tokio::fs::write(&archive, [0x1f, 0x8b, 0x08, 0x00]).await.unwrap();
+        let url = reqwest::Url::from_file_path(&archive).unwrap().to_string();
+        let result = fetch_command(&config, &url, &[], None, None, None, Format::Human, true).await.unwrap();
+
+        let db = open_db(&config).await.unwrap();
+        let record = db.get_object(&result.hash.to_string_prefixed()).await.unwrap().unwrap();
+        let source: manifest::Source = serde_json::from_str(&record.metadata.unwrap()).unwrap();
+        assert_eq!(source.archive_type, Some(manifest::ArchiveType::Gzip));
+    }
+
+    #[tokio::test]
+    async fn test_collect_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        for (name, data) in [("a.txt", &b"first"[..]), ("b.txt", b"second!"), ("c.txt", b"first")] {
+            let file = temp_dir.path().join(name);
+            tokio::fs::write(&file, data).await.unwrap();
+            put_command(&config, file.to_str().unwrap(), false, false, None, Format::Human, true).await.unwrap();
+        }
+
+        let storage = LocalStorage::new(config.clone());
+        let db = open_db(&config).await.unwrap();
+        let stats = collect_stats(&storage, &db, 1).await.unwrap();
+
+        assert_eq!(stats.db.objects_count, 2);
+        assert_eq!(stats.db.total_size, 12);
+        assert_eq!(stats.disk_size, 12);
+        assert_eq!(stats.db.logical_size, 17);
+        assert!(stats.dedup_ratio > 1.0);
+        assert_eq!(stats.largest_objects.len(), 1);
+        assert_eq!(stats.largest_objects[0].size, 7);
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = StorageConfig {
+            root: temp_dir.path().join("source"),
+            ..Default::default()
+        };
+        let dest = StorageConfig {
+            root: temp_dir.path().join("dest"),
+            ..Default::default()
+        };
+
+        let storage = LocalStorage::new(source.clone());
+        storage.initialize().await.unwrap();
+        let a = storage.put(b"alpha").await.unwrap();
+        let b = storage.put(b"beta").await.unwrap();
+        let manifest = Manifest::from_json(&format!(
+            r#"{{
+                "schema_version": "1.0",
+                "dataset": {{"name": "moved", "version": "2.0"}},
+                "source": {{}},
+                "contents": [
+                    {{"path": "a", "hash": "{}", "size": 5}},
+                    {{"path": "b", "hash": "{}", "size": 4}}
+                ]
+            }}"#,
+            a.to_string_prefixed(),
+            b.to_string_prefixed()
+        ))
+        .unwrap();
+        storage.register_dataset(&manifest).await.unwrap();
+        open_db(&source)
+            .await
+            .unwrap()
+            .register_manifest(&manifest, &manifest.content_hash().to_string_prefixed())
+            .await
+            .unwrap();
+
+        let bundle = temp_dir.path().join("moved.tar");
+        export_command(&source, "moved", "2.0", bundle.to_str().unwrap(), Format::Human)
+            .await
+            .unwrap();
+        import_command(&dest, bundle.to_str().unwrap(), Format::Human).await.unwrap();
+
+        let dest_db = open_db(&dest).await.unwrap();
+        let record = dest_db.get_dataset("moved", "2.0").await.unwrap().unwrap();
+        assert_eq!(record.manifest_hash, manifest.content_hash().to_string_prefixed());
+        let imported = dest_db.get_manifest("moved", "2.0").await.unwrap().unwrap();
+        assert_eq!(imported.contents, manifest.contents);
+
+        let dest_storage = LocalStorage::new(dest.clone());
+        assert!(dest_storage.exists(&a).await);
+        assert!(dest_storage.exists(&b).await);
+        assert!(dest_storage.exists(&manifest.content_hash()).await);
+    }
+
+    #[tokio::test]
+    async fn test_import_keeps_legacy_dataset_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = StorageConfig {
+            root: temp_dir.path().join("source"),
+            ..Default::default()
+        };
+        let dest = StorageConfig {
+            root: temp_dir.path().join("dest"),
+            ..Default::default()
+        };
+
+        let storage = LocalStorage::new(source.clone());
+        storage.initialize().await.unwrap();
+        let a = storage.put(b"alpha").await.unwrap();
+        let manifest = Manifest::from_json(&format!(
+            r#"{{
+                "schema_version": "1.0",
+                "dataset": {{"name": "old data", "version": "1"}},
+                "source": {{}},
+                "contents": [{{"path": "a", "hash": "{}", "size": 5}}]
+            }}"#,
+            a.to_string_prefixed()
+        ))
+        .unwrap();
+        storage.register_dataset(&manifest).await.unwrap();
+        open_db(&source)
+            .await
+            .unwrap()
+            .register_manifest(&manifest, &manifest.content_hash().to_string_prefixed())
+            .await
+            .unwrap();
+
+        let bundle = temp_dir.path().join("old.tar");
+        export_command(&source, "old data", "1", bundle.to_str().unwrap(), Format::Human)
+            .await
+            .unwrap();
+        import_command(&dest, bundle.to_str().unwrap(), Format::Human).await.unwrap();
+
+        let dest_db = open_db(&dest).await.unwrap();
+        assert!(dest_db.get_dataset("old data", "1").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_progress_reader_reports_running_total() {
+        use tokio::io::AsyncReadExt;
+
+        let data = vec![1u8; 50_000];
+        let mut totals = Vec::new();
+        {
+            let mut reader = ProgressReader::new(&data[..], |total| totals.push(total));
+            let mut buf = [0u8; 4096];
+            while reader.read(&mut buf).await.unwrap() > 0 {}
+        }
+
+        assert!(totals.len() > 1);
+        assert!(totals.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(*totals.last().unwrap(), data.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_object_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        storage.initialize().await.unwrap();
+        let db = MetadataDb::new(temp_dir.path().join("meta.db")).await.unwrap();
+
+        let hash = storage.put(b"described").await.unwrap();
+        let manifest = Manifest::from_json(&format!(
+            r#"{{
+                "schema_version": "1.0",
+                "dataset": {{"name": "info-test", "version": "1.0"}},
+                "source": {{}},
+                "contents": [{{"path": "d.txt", "hash": "{}", "size": 9}}]
+            }}"#,
+            hash.to_string_prefixed()
+        ))
+        .unwrap();
+        db.register_manifest(&manifest, &manifest.content_hash().to_string_prefixed())
+            .await
+            .unwrap();
+
+        let info = object_info(&storage, &db, hash).await.unwrap();
+        assert!(info.registered);
+        assert_eq!(info.size, Some(9));
+        // Uncompressed objects take exactly their content length on disk
+        assert_eq!(info.disk_size, Some(9));
+        assert_eq!(info.datasets, vec!["info-test/1.0".to_string()]);
+        assert!(info.path.is_some());
+        assert!(info.last_accessed.is_none());
+
+        db.record_access(&hash.to_string_prefixed()).await.unwrap();
+        let info = object_info(&storage, &db, hash).await.unwrap();
+        assert!(info.last_accessed.is_some());
+
+        // Present on disk but unknown to the database
+        let stray = storage.put(b"stray").await.unwrap();
+        let info = object_info(&storage, &db, stray).await.unwrap();
+        assert!(!info.registered);
+        assert_eq!(info.size, Some(5));
+        assert!(info.datasets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_transform_command() {
+        // Create temp directory for output
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+
+        // Create a test file in output directory
+        let test_file = output_dir.join("test.txt");
+        tokio::fs::write(&test_file, b"transformed data").await.unwrap();
+
+        // Create input manifest
+        let manifest_dir = TempDir::new().unwrap();
+        let input_manifest_path = manifest_dir.path().join("input-manifest.json");
 
         let input_manifest = Manifest {
             schema_version: "1.0".to_string(),
             dataset: manifest::Dataset {
-                name: "test-dataset".to_string(),
+                name: "test-dataset".to_string(),
+                version: "1.0.0".to_string(),
+                description: Some("Test dataset".to_string()),
+            },
+            source: manifest::Source {
+                url: Some("test://input".to_string()),
+                download_date: Some("2024-01-01T00:00:00Z".to_string()),
+                server_mtime: None,
+                archive_hash: Some("blake3:input123".to_string()),
+                archive_type: None,
+            },
+            contents: vec![],
+            transformations: vec![],
+            signature: None,
+        };
+
+        let manifest_json = serde_json::to_string_pretty(&input_manifest).unwrap();
+        tokio::fs::write(&input_manifest_path, manifest_json).await.unwrap();
+
+        // Run transform command
+        let config = StorageConfig {
+            root: manifest_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let result = transform_command(
+            &config,
+            input_manifest_path.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+            "test-transform",
+            &[],
+            false,
+            DEFAULT_SCAN_JOBS,
+            None,
+        ).await;
+
+        assert!(result.is_ok(), "Transform command failed: {:?}", result.err());
+    }
+
+    #[tokio::test]
+    async fn test_transform_params_are_part_of_cache_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("out");
+        tokio::fs::create_dir(&output_dir).await.unwrap();
+        tokio::fs::write(output_dir.join("result.txt"), b"result").await.unwrap();
+
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            r#"{"schema_version": "1.0", "dataset": {"name": "p", "version": "1"}, "source": {}, "contents": []}"#,
+        )
+        .await
+        .unwrap();
+        let input = read_manifest(input_path.to_str().unwrap()).await.unwrap();
+
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let run = |params: Vec<String>| {
+            let config = config.clone();
+            let input_path = input_path.clone();
+            let output_dir = output_dir.clone();
+            async move {
+                transform_command(
+                    &config,
+                    input_path.to_str().unwrap(),
+                    output_dir.to_str().unwrap(),
+                    "filter",
+                    &params,
+                    false,
+                    DEFAULT_SCAN_JOBS,
+                    None,
+                )
+                .await
+                .map(|outcome| outcome.manifest)
+            }
+        };
+
+        let low = run(vec!["threshold=0.1".to_string()]).await.unwrap();
+        let high = run(vec!["threshold=0.9".to_string(), "mode=strict".to_string()]).await.unwrap();
+        assert_ne!(low.content_hash(), high.content_hash());
+        assert_eq!(
+            high.transformations[0].params,
+            Some(serde_json::json!({"mode": "strict", "threshold": "0.9"}))
+        );
+
+        let db = open_db(&config).await.unwrap();
+        let input_hash = input.content_hash().to_string_prefixed();
+        let cached_low = db
+            .find_cached_transformation(&input_hash, "filter", Some(r#"{"threshold":"0.1"}"#))
+            .await
+            .unwrap();
+        let cached_high = db
+            .find_cached_transformation(&input_hash, "filter", Some(r#"{"mode":"strict","threshold":"0.9"}"#))
+            .await
+            .unwrap();
+        assert_eq!(cached_low, Some(low.content_hash().to_string_prefixed()));
+        assert_eq!(cached_high, Some(high.content_hash().to_string_prefixed()));
+
+        // Re-running with identical params doesn't record a duplicate
+        run(vec!["threshold=0.1".to_string()]).await.unwrap();
+        assert_eq!(db.get_stats().await.unwrap().transformations_count, 2);
+
+        assert!(parse_params(&["novalue".to_string()]).is_err());
+        assert!(parse_params(&["=value".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            r#"{"schema_version": "1.0", "dataset": {"name": "p", "version": "1"}, "source": {}, "contents": []}"#,
+        )
+        .await
+        .unwrap();
+
+        let spec_path = temp_dir.path().join("pipeline.json");
+        tokio::fs::write(
+            &spec_path,
+            r#"{"steps": [{"type": "decompress"}, {"type": "index", "params": {"k": "31"}}]}"#,
+        )
+        .await
+        .unwrap();
+
+        // Both steps are external, so their files are already in place
+        let output_dir = temp_dir.path().join("out");
+        for (step, file, data) in [("step-1", "data.txt", "raw"), ("step-2", "data.idx", "index")] {
+            tokio::fs::create_dir_all(output_dir.join(step)).await.unwrap();
+            tokio::fs::write(output_dir.join(step).join(file), data).await.unwrap();
+        }
+
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let output = pipeline_command(
+            &config,
+            input_path.to_str().unwrap(),
+            spec_path.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+            1,
+        )
+        .await
+        .unwrap();
+        let types: Vec<_> = output.transformations.iter().map(|t| t.transform_type.as_str()).collect();
+        assert_eq!(types, vec!["decompress", "index"]);
+        let paths: Vec<_> = output.contents.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, vec!["data.idx"]);
+
+        let db = open_db(&config).await.unwrap();
+        assert_eq!(db.get_stats().await.unwrap().transformations_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_transform_records_derived_from() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("out");
+        tokio::fs::create_dir(&output_dir).await.unwrap();
+        tokio::fs::write(output_dir.join("merged.txt"), b"ab").await.unwrap();
+        tokio::fs::write(output_dir.join("notes.txt"), b"untracked").await.unwrap();
+
+        let (a, b) = (Blake3Hash::from_bytes(b"a"), Blake3Hash::from_bytes(b"b"));
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            format!(
+                r#"{{"schema_version": "1.0", "dataset": {{"name": "d", "version": "1"}}, "source": {{}}, "contents": [
+                    {{"path": "a.txt", "hash": "{}", "size": 1}},
+                    {{"path": "b.txt", "hash": "{}", "size": 1}}
+                ]}}"#,
+                a, b
+            ),
+        )
+        .await
+        .unwrap();
+        let mapping_path = temp_dir.path().join("derived.json");
+        tokio::fs::write(&mapping_path, r#"{"merged.txt": ["a.txt", "b.txt"]}"#)
+            .await
+            .unwrap();
+
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let outcome = transform_command(
+            &config,
+            input_path.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+            "merge",
+            &[],
+            false,
+            DEFAULT_SCAN_JOBS,
+            mapping_path.to_str(),
+        )
+        .await
+        .unwrap();
+
+        let contents = &outcome.manifest.contents;
+        let mut expected = vec![a.to_string_prefixed(), b.to_string_prefixed()];
+        expected.sort();
+        assert_eq!(contents[0].path, "merged.txt");
+        assert_eq!(contents[0].derived_from, expected);
+        assert!(contents[1].derived_from.is_empty());
+
+        tokio::fs::write(&mapping_path, r#"{"merged.txt": ["c.txt"]}"#).await.unwrap();
+        assert!(transform_command(
+            &config,
+            input_path.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+            "merge",
+            &[],
+            false,
+            DEFAULT_SCAN_JOBS,
+            mapping_path.to_str(),
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transform_cache_hit_skips_rescan() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("out");
+        tokio::fs::create_dir(&output_dir).await.unwrap();
+        tokio::fs::write(output_dir.join("result.txt"), b"result").await.unwrap();
+
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            r#"{"schema_version": "1.0", "dataset": {"name": "c", "version": "1"}, "source": {}, "contents": []}"#,
+        )
+        .await
+        .unwrap();
+
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let input = input_path.to_str().unwrap();
+        let output = output_dir.to_str().unwrap();
+
+        let first = transform_command(&config, input, output, "index", &[], false, DEFAULT_SCAN_JOBS, None)
+            .await
+            .unwrap();
+        assert!(!first.cached);
+
+        // With the output directory gone, only a cache hit can succeed
+        tokio::fs::remove_dir_all(&output_dir).await.unwrap();
+
+        let second = transform_command(&config, input, output, "index", &[], false, DEFAULT_SCAN_JOBS, None)
+            .await
+            .unwrap();
+        assert!(second.cached);
+        assert_eq!(second.manifest.content_hash(), first.manifest.content_hash());
+
+        assert!(transform_command(&config, input, output, "index", &[], true, DEFAULT_SCAN_JOBS, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transform_output_chains_into_transform() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let output_dir = temp_dir.path().join("out");
+        tokio::fs::create_dir(&output_dir).await.unwrap();
+        tokio::fs::write(output_dir.join("result.txt"), b"result").await.unwrap();
+        let output = output_dir.to_str().unwrap();
+
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            r#"{"schema_version": "1.0", "dataset": {"name": "chain", "version": "1"}, "source": {}, "contents": []}"#,
+        )
+        .await
+        .unwrap();
+        let input = read_manifest(input_path.to_str().unwrap()).await.unwrap();
+
+        let first = transform_command(&config, input_path.to_str().unwrap(), output, "index", &[], true, 1, None)
+            .await
+            .unwrap();
+        assert_eq!(first.manifest.transformations[0].from, input.content_hash().to_string_prefixed());
+        first.manifest.validate().unwrap();
+
+        let first_path = temp_dir.path().join("first.json");
+        write_manifest(&first.manifest, first_path.to_str(), "json").await.unwrap();
+        let second = transform_command(&config, first_path.to_str().unwrap(), output, "filter", &[], true, 1, None)
+            .await
+            .unwrap();
+        assert_eq!(second.manifest.transformations.len(), 2);
+        assert_eq!(
+            second.manifest.transformations[1].from,
+            first.manifest.content_hash().to_string_prefixed()
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transform_exec_runs_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            r#"{"schema_version": "1.0", "dataset": {"name": "x", "version": "1"}, "source": {}, "contents": []}"#,
+        )
+        .await
+        .unwrap();
+
+        // The script copies the dataset name it reads from stdin into one file
+        let script = temp_dir.path().join("name.sh");
+        tokio::fs::write(
+            &script,
+            "grep -o '\"name\":\"[a-z]*\"' > \"$1/name.txt\"\necho 'wrote name.txt' >&2\n",
+        )
+        .await
+        .unwrap();
+        let failing = temp_dir.path().join("failing.sh");
+        tokio::fs::write(&failing, "echo 'bad input' >&2\nexit 3\n").await.unwrap();
+
+        let output_dir = temp_dir.path().join("out");
+        let run = |script: &std::path::Path| {
+            let transform_type = format!("exec:sh {}", script.display());
+            let input = input_path.to_str().unwrap().to_string();
+            let output = output_dir.to_str().unwrap().to_string();
+            let config = config.clone();
+            async move { transform_command(&config, &input, &output, &transform_type, &[], true, 1, None).await }
+        };
+
+        let outcome = run(&script).await.unwrap();
+        let contents = &outcome.manifest.contents;
+        assert_eq!(contents.len(), 1);
+        assert_eq!(contents[0].path, "name.txt");
+        let written = std::fs::read_to_string(output_dir.join("name.txt")).unwrap();
+        assert_eq!(written.trim(), r#""name":"x""#);
+
+        let err = run(&failing).await.err().unwrap();
+        assert!(format!("{:#}", err).contains("exit status: 3"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn test_transform_extract_unpacks_source_archive() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let storage = LocalStorage::new(config.clone());
+        storage.initialize().await.unwrap();
+
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (path, data) in [("seqs/a.fasta", &b">a\nACGT\n"[..]), ("README", b"fixture")] {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, data).unwrap();
+        }
+        let archive = storage
+            .put(&builder.into_inner().unwrap().finish().unwrap())
+            .await
+            .unwrap();
+
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            format!(
+                r#"{{"schema_version": "1.0", "dataset": {{"name": "e", "version": "1"}}, "source": {{"archive_hash": "{}"}}, "contents": []}}"#,
+                archive.to_string_prefixed()
+            ),
+        )
+        .await
+        .unwrap();
+
+        // The output directory does not exist yet; extract creates it
+        let output_dir = temp_dir.path().join("out");
+        let outcome = transform_command(
+            &config,
+            input_path.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+            "extract",
+            &[],
+            true,
+            DEFAULT_SCAN_JOBS,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let contents = &outcome.manifest.contents;
+        let paths: Vec<&str> = contents.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["README", "seqs/a.fasta"]);
+        assert_eq!(contents[1].hash, Blake3Hash::from_bytes(b">a\nACGT\n").to_hex());
+        assert_eq!(outcome.manifest.transformations[0].from, archive.to_string_prefixed());
+    }
+
+    #[tokio::test]
+    async fn test_transform_output_is_independent_of_creation_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.path().join("input.json");
+        tokio::fs::write(
+            &input_path,
+            r#"{"schema_version": "1.0", "dataset": {"name": "o", "version": "1"}, "source": {}, "contents": []}"#,
+        )
+        .await
+        .unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let files: [(&str, &[u8]); 4] = [("z.txt", b"z"), ("a/b.txt", b"b"), ("m.txt", b"m"), ("a/a.txt", b"a")];
+        let mut outputs = Vec::new();
+        for (dir, order) in [("forward", [0, 1, 2, 3]), ("reverse", [3, 2, 1, 0])] {
+            let output_dir = temp_dir.path().join(dir);
+            tokio::fs::create_dir_all(output_dir.join("a")).await.unwrap();
+            for i in order {
+                tokio::fs::write(output_dir.join(files[i].0), files[i].1).await.unwrap();
+            }
+
+            let outcome = transform_command(
+                &config,
+                input_path.to_str().unwrap(),
+                output_dir.to_str().unwrap(),
+                "index",
+                &[],
+                true,
+                DEFAULT_SCAN_JOBS,
+                None,
+            )
+            .await
+            .unwrap();
+            outputs.push(serde_json::to_string_pretty(&outcome.manifest).unwrap());
+        }
+
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_scan_output_dir_records_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        tokio::fs::write(temp_dir.path().join("data.txt"), b"data").await.unwrap();
+        std::os::unix::fs::symlink("data.txt", temp_dir.path().join("link.txt")).unwrap();
+
+        let contents = scan_output_dir(temp_dir.path(), DEFAULT_SCAN_JOBS, hash::DEFAULT_FILE_BUFFER_SIZE).await.unwrap();
+        assert_eq!(contents.len(), 2);
+
+        let link = contents.iter().find(|c| c.path == "link.txt").unwrap();
+        assert_eq!(
+            link.kind,
+            ContentKind::Symlink {
+                target: "data.txt".to_string()
+            }
+        );
+
+        let file = contents.iter().find(|c| c.path == "data.txt").unwrap();
+        assert_eq!(file.kind, ContentKind::File);
+    }
+
+    #[tokio::test]
+    async fn test_scan_output_dir_recurses() {
+        let temp_dir = TempDir::new().unwrap();
+        tokio::fs::create_dir_all(temp_dir.path().join("nested/inner")).await.unwrap();
+        tokio::fs::write(temp_dir.path().join("top.txt"), b"top").await.unwrap();
+        tokio::fs::write(temp_dir.path().join("nested/inner/deep.txt"), b"deep").await.unwrap();
+
+        let contents = scan_output_dir(temp_dir.path(), DEFAULT_SCAN_JOBS, hash::DEFAULT_FILE_BUFFER_SIZE).await.unwrap();
+        let paths: Vec<&str> = contents.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["nested/inner/deep.txt", "top.txt"]);
+        assert_eq!(contents[0].hash, Blake3Hash::from_bytes(b"deep").to_hex());
+        assert_eq!(contents[0].size, 4);
+    }
+
+    #[tokio::test]
+    async fn test_scan_output_dir_parallel_matches_serial() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..64 {
+            let dir = temp_dir.path().join(format!("group{}", i % 4));
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            tokio::fs::write(dir.join(format!("file{}.txt", i)), vec![b'a' + (i % 26) as u8; i * 100])
+                .await
+                .unwrap();
+        }
+
+        let serial = scan_output_dir(temp_dir.path(), 1, 4096).await.unwrap();
+        let parallel = scan_output_dir(temp_dir.path(), 16, 4096).await.unwrap();
+        assert_eq!(serial.len(), 64);
+        assert_eq!(serial, parallel);
+    }
+
+    #[tokio::test]
+    async fn test_map_bounded_respects_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let results = map_bounded((0..32).collect(), 4, |i: u32| {
+            let (in_flight, peak) = (&in_flight, &peak);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(i * 2)
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(results.len(), 32);
+        assert_eq!(peak.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_scan_output_dir_mime_types() {
+        let temp_dir = TempDir::new().unwrap();
+        tokio::fs::write(temp_dir.path().join("data.json"), b"{}").await.unwrap();
+        tokio::fs::write(temp_dir.path().join("image.png"), b"\x89PNG").await.unwrap();
+        tokio::fs::write(temp_dir.path().join("unknown.zzz-no-such-ext"), b"?").await.unwrap();
+        tokio::fs::write(temp_dir.path().join("picture"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").await.unwrap();
+
+        let contents = scan_output_dir(temp_dir.path(), DEFAULT_SCAN_JOBS, hash::DEFAULT_FILE_BUFFER_SIZE).await.unwrap();
+        let mime_of = |name: &str| {
+            contents
+                .iter()
+                .find(|c| c.path == name)
+                .unwrap()
+                .mime_type
+                .clone()
+        };
+
+        assert_eq!(mime_of("data.json").as_deref(), Some("application/json"));
+        assert_eq!(mime_of("image.png").as_deref(), Some("image/png"));
+        assert_eq!(mime_of("picture").as_deref(), Some("image/png"));
+        assert_eq!(mime_of("unknown.zzz-no-such-ext"), None);
+    }
+
+    #[tokio::test]
+    async fn test_collect_garbage() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        storage.initialize().await.unwrap();
+        let db = MetadataDb::new(temp_dir.path().join("meta.db")).await.unwrap();
+
+        let referenced = storage.put(b"referenced").await.unwrap();
+        let orphan = storage.put(b"orphan").await.unwrap();
+        db.register_object(&orphan.to_string_prefixed(), 6, None)
+            .await
+            .unwrap();
+
+        let manifest = Manifest {
+            schema_version: "1.0".to_string(),
+            dataset: manifest::Dataset {
+                name: "gc-test".to_string(),
                 version: "1.0.0".to_string(),
-                description: Some("Test dataset".to_string()),
+                description: None,
             },
             source: manifest::Source {
-                url: Some("test://input".to_string()),
-                download_date: Some("2024-01-01T00:00:00Z".to_string()),
+                url: None,
+                download_date: None,
                 server_mtime: None,
-                archive_hash: Some("blake3:input123".to_string()),
+                archive_hash: None,
+                archive_type: None,
             },
-            contents: vec![],
+            contents: vec![Content {
+                path: "referenced.txt".to_string(),
+                hash: referenced.to_string_prefixed(),
+                size: 10,
+                executable: false,
+                mime_type: None,
+                kind: ContentKind::File,
+                derived_from: Vec::new(),
+            }],
             transformations: vec![],
+            signature: None,
         };
+        let manifest_json = serde_json::to_vec(&manifest).unwrap();
+        let manifest_hash = storage.put(&manifest_json).await.unwrap();
+        db.register_manifest(&manifest, &manifest_hash.to_string_prefixed())
+            .await
+            .unwrap();
 
-        let manifest_json = serde_json::to_string_pretty(&input_manifest).unwrap();
-        tokio::fs::write(&input_manifest_path, manifest_json).await.unwrap();
+        // Dry run reports the orphan but deletes nothing
+        let report = collect_garbage(&storage, &db, true, GcLimits::default()).await.unwrap();
+        assert_eq!(report.swept, vec![orphan]);
+        assert_eq!(report.reclaimed_bytes, 6);
+        assert!(storage.exists(&orphan).await);
+        assert!(db.get_object(&orphan.to_string_prefixed()).await.unwrap().is_some());
 
-        // Run transform command
-        let result = transform_command(
-            input_manifest_path.to_str().unwrap(),
-            output_dir.to_str().unwrap(),
-            "test-transform",
-        ).await;
+        let report = collect_garbage(&storage, &db, false, GcLimits::default()).await.unwrap();
+        assert_eq!(report.swept, vec![orphan]);
+        assert_eq!(report.reclaimed_bytes, 6);
+        assert!(!storage.exists(&orphan).await);
+        assert!(db.get_object(&orphan.to_string_prefixed()).await.unwrap().is_none());
+        assert!(storage.exists(&referenced).await);
+        assert!(storage.exists(&manifest_hash).await);
+    }
 
-        assert!(result.is_ok(), "Transform command failed: {:?}", result.err());
+    #[tokio::test]
+    async fn test_collect_garbage_after_db_import() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let tree = temp_dir.path().join("tree");
+        std::fs::create_dir(&tree).unwrap();
+        std::fs::write(tree.join("a.txt"), b"alpha").unwrap();
+        std::fs::write(tree.join("b.txt"), b"beta").unwrap();
+        let (manifest, _) = put_tree_command(&config, tree.to_str().unwrap(), "kept", "1", Format::Human, true)
+            .await
+            .unwrap();
+
+        let dump = temp_dir.path().join("dump.json");
+        db_export_command(&config, dump.to_str()).await.unwrap();
+        db_import_command(&config, dump.to_str().unwrap(), true).await.unwrap();
+
+        let storage = LocalStorage::new(config.clone());
+        let db = open_db(&config).await.unwrap();
+        let report = collect_garbage(&storage, &db, false, GcLimits::default()).await.unwrap();
+        assert!(report.swept.is_empty(), "{:?}", report.swept);
+        for content in &manifest.contents {
+            assert!(storage.exists(&Blake3Hash::from_str(&content.hash).unwrap()).await);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), chrono::Duration::seconds(90));
+        assert_eq!(parse_duration("30m").unwrap(), chrono::Duration::minutes(30));
+        assert_eq!(parse_duration("12h").unwrap(), chrono::Duration::hours(12));
+        assert_eq!(parse_duration("7d").unwrap(), chrono::Duration::days(7));
+        assert_eq!(parse_duration("2w").unwrap(), chrono::Duration::weeks(2));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("-1h").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_manifest_to_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = Manifest::from_json(
+            r#"{"schema_version": "1.0", "dataset": {"name": "out", "version": "1"}, "source": {},
+                "contents": [{"path": "a.txt", "hash": "blake3:aa", "size": 1}]}"#,
+        )
+        .unwrap();
+
+        let path = temp_dir.path().join("nested/dir/manifest.json");
+        write_manifest(&manifest, Some(path.to_str().unwrap()), "json").await.unwrap();
+
+        let written = read_manifest(path.to_str().unwrap()).await.unwrap();
+        assert_eq!(written.contents, manifest.contents);
+        assert_eq!(written.content_hash(), manifest.content_hash());
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let root = Path::new("/data/out");
+        assert_eq!(relative_path(root, Path::new("/data/out/a/b.txt")).unwrap(), "a/b.txt");
+        assert_eq!(relative_path(root, Path::new("/data/out/./c.txt")).unwrap(), "c.txt");
+
+        let err = relative_path(root, Path::new("/elsewhere/b.txt")).unwrap_err().to_string();
+        assert!(err.contains("not under"), "{}", err);
+        let err = relative_path(root, Path::new("/data/out/../secret")).unwrap_err().to_string();
+        assert!(err.contains("escapes"), "{}", err);
+        assert!(relative_path(root, root).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transform_empty_output_dir_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let input_path = temp_dir.path().join("input.json");
+        let input_json = format!(
+            r#"{{"schema_version": "1.0", "dataset": {{"name": "in", "version": "1"}}, "source": {{}},
+                "contents": [{{"path": "a.txt", "hash": "{}", "size": 1}}]}}"#,
+            Blake3Hash::from_bytes(b"a")
+        );
+        tokio::fs::write(&input_path, input_json).await.unwrap();
+        let input = input_path.to_str().unwrap();
+
+        let output_dir = temp_dir.path().join("out");
+        std::fs::create_dir(&output_dir).unwrap();
+        let output = output_dir.to_str().unwrap();
+
+        let err = transform_command(&config, input, output, "index", &[], true, 1, None).await.err().unwrap();
+        assert!(err.to_string().contains("(it is empty)"), "{}", err);
+
+        std::fs::create_dir_all(output_dir.join("a/b")).unwrap();
+        std::fs::create_dir(output_dir.join("c")).unwrap();
+        let err = transform_command(&config, input, output, "index", &[], true, 1, None).await.err().unwrap();
+        assert!(err.to_string().contains("only 3 empty subdirectories"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_object_ref() {
+        let hash = format!("blake3:{}", "ab".repeat(32));
+        assert_eq!(parse_object_ref(&hash).unwrap(), ObjectRef::Hash(&hash));
+        assert_eq!(parse_object_ref("abc123").unwrap(), ObjectRef::Hash("abc123"));
+        assert_eq!(
+            parse_object_ref("ds@1.0.0").unwrap(),
+            ObjectRef::Dataset {
+                name: "ds",
+                version: "1.0.0"
+            }
+        );
+        assert_eq!(
+            parse_object_ref("ds@latest").unwrap(),
+            ObjectRef::Dataset {
+                name: "ds",
+                version: "latest"
+            }
+        );
+        assert!(parse_object_ref("@1.0.0").is_err());
+        assert!(parse_object_ref("ds@").is_err());
+        assert!(parse_object_ref("ds@1@2").is_err());
+    }
+
+    #[test]
+    fn test_read_hash_list() {
+        let input = "blake3:aa\n\n  bb  \r\ncc";
+        assert_eq!(read_hash_list(input.as_bytes()).unwrap(), vec!["blake3:aa", "bb", "cc"]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_garbage_max_deletes() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        storage.initialize().await.unwrap();
+        let db = MetadataDb::new(temp_dir.path().join("meta.db")).await.unwrap();
+
+        let orphans = [storage.put(b"orphan one").await.unwrap(), storage.put(b"orphan two").await.unwrap()];
+        let limits = GcLimits {
+            max_deletes: Some(1),
+            deadline: None,
+        };
+
+        let report = collect_garbage(&storage, &db, false, limits).await.unwrap();
+        assert_eq!(report.swept.len(), 1);
+        assert_eq!(report.remaining, 1);
+        let survivors: Vec<_> = orphans.iter().filter(|h| !report.swept.contains(h)).collect();
+        assert_eq!(survivors.len(), 1);
+        assert!(storage.exists(survivors[0]).await);
+        assert!(!storage.exists(&report.swept[0]).await);
+
+        // An expired deadline still lets the mark phase run but deletes nothing
+        let limits = GcLimits {
+            max_deletes: None,
+            deadline: Some(std::time::Instant::now()),
+        };
+        let report = collect_garbage(&storage, &db, false, limits).await.unwrap();
+        assert!(report.swept.is_empty());
+        assert_eq!(report.remaining, 1);
+
+        let report = collect_garbage(&storage, &db, false, GcLimits::default()).await.unwrap();
+        assert_eq!(report.swept, [*survivors[0]]);
+        assert_eq!(report.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_collect_garbage_max_deletes_bounds_chunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        storage.initialize().await.unwrap();
+        let db = MetadataDb::new(temp_dir.path().join("meta.db")).await.unwrap();
+
+        let mut data = vec![0u8; 2 * 1024 * 1024];
+        blake3::Hasher::new().update(b"chunked orphan").finalize_xof().fill(&mut data);
+        storage.put_chunked(std::io::Cursor::new(data)).await.unwrap();
+
+        // The object uses up the single delete; its chunks wait
+        let limits = GcLimits {
+            max_deletes: Some(1),
+            deadline: None,
+        };
+        let report = collect_garbage(&storage, &db, false, limits).await.unwrap();
+        assert_eq!(report.swept.len(), 1);
+        assert_eq!(report.swept_chunks, 0);
+        assert!(report.remaining_chunks > 0);
+
+        let chunks = report.remaining_chunks;
+        let report = collect_garbage(&storage, &db, false, GcLimits::default()).await.unwrap();
+        assert_eq!(report.swept_chunks, chunks);
+        assert_eq!(report.remaining_chunks, 0);
+    }
+
+    #[tokio::test]
+    async fn test_collect_expired() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let stale = temp_dir.path().join("stale.txt");
+        let fresh = temp_dir.path().join("fresh.txt");
+        tokio::fs::write(&stale, b"past its ttl").await.unwrap();
+        tokio::fs::write(&fresh, b"still fresh").await.unwrap();
+
+        let ttl = Some(chrono::Duration::days(7));
+        let stale = put_command(&config, stale.to_str().unwrap(), false, false, ttl, Format::Human, true)
+            .await
+            .unwrap()
+            .hash;
+        let fresh = put_command(&config, fresh.to_str().unwrap(), false, false, ttl, Format::Human, true)
+            .await
+            .unwrap()
+            .hash;
+
+        let storage = LocalStorage::new(config.clone());
+        let db = open_db(&config).await.unwrap();
+        db.set_object_expiry(&stale.to_string_prefixed(), chrono::Utc::now() - chrono::Duration::hours(1))
+            .await
+            .unwrap();
+
+        let report = collect_expired(&storage, &db, chrono::Utc::now(), false, GcLimits::default()).await.unwrap();
+        assert_eq!(report.swept, vec![stale]);
+        assert!(!storage.exists(&stale).await);
+        assert!(db.get_object(&stale.to_string_prefixed()).await.unwrap().is_none());
+        assert!(storage.exists(&fresh).await);
+        assert!(db.get_object(&fresh.to_string_prefixed()).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_collect_expired_keeps_referenced_objects() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+
+        let tree = temp_dir.path().join("tree");
+        tokio::fs::create_dir_all(&tree).await.unwrap();
+        tokio::fs::write(tree.join("listed.txt"), b"listed").await.unwrap();
+        let input = temp_dir.path().join("input.txt");
+        let output = temp_dir.path().join("output.txt");
+        tokio::fs::write(&input, b"transform input").await.unwrap();
+        tokio::fs::write(&output, b"transform output").await.unwrap();
+
+        let ttl = Some(chrono::Duration::days(7));
+        let listed = put_command(&config, tree.join("listed.txt").to_str().unwrap(), false, false, ttl, Format::Human, true)
+            .await
+            .unwrap()
+            .hash;
+        let input = put_command(&config, input.to_str().unwrap(), false, false, ttl, Format::Human, true)
+            .await
+            .unwrap()
+            .hash;
+        let output = put_command(&config, output.to_str().unwrap(), false, false, None, Format::Human, true)
+            .await
+            .unwrap()
+            .hash;
+        put_tree_command(&config, tree.to_str().unwrap(), "listed", "1", Format::Human, true)
+            .await
+            .unwrap();
+
+        let storage = LocalStorage::new(config.clone());
+        let db = open_db(&config).await.unwrap();
+        db.register_transformation(&input.to_string_prefixed(), &output.to_string_prefixed(), "copy", None)
+            .await
+            .unwrap();
+        let past = chrono::Utc::now() - chrono::Duration::hours(1);
+        for hash in [listed, input] {
+            db.set_object_expiry(&hash.to_string_prefixed(), past).await.unwrap();
+        }
+
+        // Kept objects do not count as left over by a limit
+        let limits = GcLimits {
+            max_deletes: Some(0),
+            deadline: None,
+        };
+        let report = collect_expired(&storage, &db, chrono::Utc::now(), false, limits).await.unwrap();
+        assert_eq!(report.remaining, 0);
+
+        let report = collect_expired(&storage, &db, chrono::Utc::now(), false, GcLimits::default()).await.unwrap();
+        assert!(report.swept.is_empty());
+        for hash in [listed, input] {
+            assert!(storage.exists(&hash).await);
+            assert!(db.get_object(&hash.to_string_prefixed()).await.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_ttl_keeps_existing_expiry() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().join("cast"),
+            ..Default::default()
+        };
+        let file = temp_dir.path().join("kept.txt");
+        tokio::fs::write(&file, b"stored without a ttl").await.unwrap();
+
+        let hash = put_command(&config, file.to_str().unwrap(), false, false, None, Format::Human, true)
+            .await
+            .unwrap()
+            .hash;
+        let again = put_command(&config, file.to_str().unwrap(), false, false, Some(chrono::Duration::days(1)), Format::Human, true)
+            .await
+            .unwrap();
+        assert!(again.deduplicated);
+
+        let db = open_db(&config).await.unwrap();
+        assert!(db.expired_objects(chrono::Utc::now() + chrono::Duration::days(2)).await.unwrap().is_empty());
+        assert!(db.get_object(&hash.to_string_prefixed()).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_remove_object_respects_refcount() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        storage.initialize().await.unwrap();
+        let db = MetadataDb::new(temp_dir.path().join("meta.db")).await.unwrap();
+
+        let shared = storage.put(b"shared").await.unwrap();
+        db.register_object(&shared.to_string_prefixed(), 6, None).await.unwrap();
+        db.register_object(&shared.to_string_prefixed(), 6, None).await.unwrap();
+
+        let outcome = remove_object(&storage, &db, &shared, false).await.unwrap();
+        assert_eq!(outcome, RmOutcome::Decremented { remaining: 1 });
+        assert!(storage.exists(&shared).await);
+        let record = db.get_object(&shared.to_string_prefixed()).await.unwrap().unwrap();
+        assert_eq!(record.refs, 1);
+
+        let orphan = storage.put(b"orphan").await.unwrap();
+        db.register_object(&orphan.to_string_prefixed(), 6, None).await.unwrap();
+
+        let outcome = remove_object(&storage, &db, &orphan, false).await.unwrap();
+        assert_eq!(outcome, RmOutcome::Deleted);
+        assert!(!storage.exists(&orphan).await);
+        assert!(db.get_object(&orphan.to_string_prefixed()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_remove_object_referenced_by_dataset() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        storage.initialize().await.unwrap();
+        let db = MetadataDb::new(temp_dir.path().join("meta.db")).await.unwrap();
+
+        let object = storage.put(b"in a dataset").await.unwrap();
+        let manifest = Manifest::from_json(&format!(
+            r#"{{
+                "schema_version": "1.0",
+                "dataset": {{"name": "held", "version": "1"}},
+                "source": {{}},
+                "contents": [{{"path": "a.txt", "hash": "{}", "size": 12}}]
+            }}"#,
+            object.to_string_prefixed()
+        ))
+        .unwrap();
+        db.register_manifest(&manifest, &manifest.content_hash().to_string_prefixed())
+            .await
+            .unwrap();
+
+        let err = remove_object(&storage, &db, &object, false).await.unwrap_err();
+        assert!(err.to_string().contains("held@1"));
+        assert!(storage.exists(&object).await);
+
+        let outcome = remove_object(&storage, &db, &object, true).await.unwrap();
+        assert_eq!(outcome, RmOutcome::Deleted);
+        assert!(!storage.exists(&object).await);
     }
 }
@@ -0,0 +1,206 @@
+// Nix store paths for `cast export-nix`
+//
+// An object is treated as a single regular file added to the Nix store by
+// content, as `nix-store --add` or `builtins.path` would:
+//
+// 1. The file is serialized as a NAR: the strings `nix-archive-1`, `(`,
+//    `type`, `regular`, optionally `executable` and an empty string,
+//    `contents`, the file's bytes, and `)`. Each string is written as its
+//    length (u64, little endian), its bytes, and zero padding to a multiple
+//    of 8 bytes.
+// 2. The NAR hash is the SHA-256 of that serialization.
+// 3. The fingerprint is `source:sha256:<NAR hash as hex>:/nix/store:<name>`.
+// 4. Its SHA-256 is folded to 20 bytes (byte `i` is XORed into `i % 20`) and
+//    written in Nix's base32 alphabet, giving 32 characters.
+// 5. The store path is `/nix/store/<base32>-<name>`.
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+
+pub const STORE_DIR: &str = "/nix/store";
+
+/// Nix's base32 alphabet (no `e`, `o`, `u`, or `t`)
+const BASE32_CHARS: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Encode bytes in Nix's base32, which reads the bits in reverse order
+pub fn base32(bytes: &[u8]) -> String {
+    let len = (bytes.len() * 8).div_ceil(5);
+    (0..len)
+        .rev()
+        .map(|n| {
+            let (i, j) = (n * 5 / 8, n * 5 % 8);
+            let low = bytes[i] as u16 >> j;
+            let high = bytes.get(i + 1).map_or(0, |&b| (b as u16) << (8 - j));
+            BASE32_CHARS[((low | high) & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
+/// Check that `name` is a valid store path name
+///
+/// Nix allows letters, digits, and `+-._?=`, at most 211 characters, not
+/// starting with a dot.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > 211 {
+        anyhow::bail!("Nix store path names must be 1 to 211 characters: {}", name);
+    }
+    if name.starts_with('.') {
+        anyhow::bail!("Nix store path names cannot start with a dot: {}", name);
+    }
+    if let Some(c) = name.chars().find(|c| !c.is_ascii_alphanumeric() && !"+-._?=".contains(*c)) {
+        anyhow::bail!("Invalid character {:?} in Nix store path name: {}", c, name);
+    }
+    Ok(())
+}
+
+fn write_str(out: &mut impl Write, s: &[u8]) -> io::Result<()> {
+    out.write_all(&(s.len() as u64).to_le_bytes())?;
+    out.write_all(s)?;
+    out.write_all(&[0; 8][..padding(s.len() as u64)])
+}
+
+fn padding(len: u64) -> usize {
+    ((8 - len % 8) % 8) as usize
+}
+
+/// Write the NAR serialization of a single regular file
+///
+/// `contents` must yield exactly `size` bytes.
+pub fn write_nar(out: &mut impl Write, contents: &mut impl Read, size: u64, executable: bool) -> io::Result<()> {
+    for s in ["nix-archive-1", "(", "type", "regular"] {
+        write_str(out, s.as_bytes())?;
+    }
+    if executable {
+        write_str(out, b"executable")?;
+        write_str(out, b"")?;
+    }
+    write_str(out, b"contents")?;
+
+    out.write_all(&size.to_le_bytes())?;
+    let copied = io::copy(&mut contents.take(size), out)?;
+    if copied != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("expected {} bytes of contents, got {}", size, copied),
+        ));
+    }
+    out.write_all(&[0; 8][..padding(size)])?;
+
+    write_str(out, b")")
+}
+
+/// Writer that hashes everything written through it, optionally copying it on
+pub struct NarHasher<W> {
+    inner: Option<W>,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<W: Write> NarHasher<W> {
+    pub fn new(inner: Option<W>) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            len: 0,
+        }
+    }
+
+    /// NAR hash and size
+    pub fn finish(self) -> io::Result<([u8; 32], u64)> {
+        if let Some(mut inner) = self.inner {
+            inner.flush()?;
+        }
+        Ok((self.hasher.finalize().into(), self.len))
+    }
+}
+
+impl<W: Write> Write for NarHasher<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = match &mut self.inner {
+            Some(inner) => inner.write(buf)?,
+            None => buf.len(),
+        };
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.as_mut().map_or(Ok(()), |inner| inner.flush())
+    }
+}
+
+/// Store path of content with the given NAR hash, added under `name`
+pub fn store_path(nar_hash: &[u8; 32], name: &str) -> String {
+    let fingerprint = format!("source:sha256:{}:{}:{}", hex_digest(nar_hash), STORE_DIR, name);
+    let digest: [u8; 32] = Sha256::digest(fingerprint.as_bytes()).into();
+
+    let mut folded = [0u8; 20];
+    for (i, byte) in digest.iter().enumerate() {
+        folded[i % 20] ^= byte;
+    }
+    format!("{}/{}-{}", STORE_DIR, base32(&folded), name)
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nar_of(contents: &[u8], executable: bool) -> Vec<u8> {
+        let mut nar = Vec::new();
+        write_nar(&mut nar, &mut &contents[..], contents.len() as u64, executable).unwrap();
+        nar
+    }
+
+    #[test]
+    fn test_nar_layout() {
+        let nar = nar_of(b"hi", false);
+        // Every string is an 8-byte length and its bytes padded to 8: the
+        // magic takes 24 bytes and each of the other six strings 16
+        assert_eq!(nar.len(), 24 + 6 * 16);
+        assert_eq!(&nar[..8], &13u64.to_le_bytes());
+        assert_eq!(&nar[8..21], b"nix-archive-1");
+        assert!(!nar.windows(10).any(|w| w == b"executable"));
+        assert_eq!(nar_of(b"hi", true).len(), nar.len() + 24 + 8);
+
+        assert!(write_nar(&mut Vec::new(), &mut &b"short"[..], 10, false).is_err());
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32(&[]), "");
+        assert_eq!(base32(&[0x1f]), "0z");
+        assert_eq!(base32(&[0; 20]).len(), 32);
+    }
+
+    #[test]
+    fn test_store_path_shape_is_stable() {
+        let mut hasher = NarHasher::new(None::<Vec<u8>>);
+        write_nar(&mut hasher, &mut &b"hello\n"[..], 6, false).unwrap();
+        let (nar_hash, nar_size) = hasher.finish().unwrap();
+        assert_eq!(nar_size, nar_of(b"hello\n", false).len() as u64);
+
+        let path = store_path(&nar_hash, "hello.txt");
+        assert_eq!(base32(&nar_hash), "04zwf782yjwnh3q6hz5izfd6jyip8kgw6g6yj43fiqhbyhdd0dqw");
+        assert_eq!(path, "/nix/store/i9pmrzmpshapij2kin22pff6fc2adavx-hello.txt");
+        let (hash, name) = path.strip_prefix("/nix/store/").unwrap().split_once('-').unwrap();
+        assert_eq!(name, "hello.txt");
+        assert_eq!(hash.len(), 32);
+        assert!(hash.bytes().all(|b| BASE32_CHARS.contains(&b)));
+        assert_ne!(store_path(&nar_hash, "other.txt").split_once('-').unwrap().0, path.split_once('-').unwrap().0);
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("uniprot-2024.01").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name(".hidden").is_err());
+        assert!(validate_name("has space").is_err());
+        assert!(validate_name(&"a".repeat(212)).is_err());
+    }
+}
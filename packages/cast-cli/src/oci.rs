@@ -0,0 +1,313 @@
+// OCI registry client for `cast import-oci`
+//
+// Speaks the pull side of the OCI distribution API: image manifests and
+// blobs under `/v2/<repository>/`, with bearer tokens obtained from the
+// realm named in a 401 challenge when no token is given up front.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use cast_core::hash::ContentHash;
+
+/// Registry used for references without a registry host, as in `docker pull`
+pub const DEFAULT_REGISTRY: &str = "registry-1.docker.io";
+
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+
+/// Image reference: `[registry/]repository[:tag][@digest]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    pub registry: String,
+    pub repository: String,
+    /// Tag or `sha256:` digest
+    pub reference: String,
+}
+
+impl ImageRef {
+    /// Parse a reference the way `docker pull` does
+    ///
+    /// The first path component is a registry host if it contains `.` or
+    /// `:` or is `localhost`; otherwise the default registry is used, where
+    /// single-component repositories live under `library/`. The tag
+    /// defaults to `latest`.
+    pub fn parse(s: &str) -> Result<Self> {
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (s, None),
+        };
+        let (registry, path) = match name.split_once('/') {
+            Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => (host.to_string(), rest),
+            _ => (DEFAULT_REGISTRY.to_string(), name),
+        };
+
+        let tag_start = path.rfind(':').filter(|&i| !path[i..].contains('/'));
+        let (repository, tag) = match tag_start {
+            Some(i) => (&path[..i], Some(&path[i + 1..])),
+            None => (path, None),
+        };
+        if repository.is_empty() || tag == Some("") || digest == Some("") {
+            anyhow::bail!("Invalid image reference: {}", s);
+        }
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository.to_string()
+        };
+
+        Ok(Self {
+            registry,
+            repository,
+            reference: digest.or(tag).unwrap_or("latest").to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ImageRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.reference.contains(':') { '@' } else { ':' };
+        write!(f, "{}/{}{}{}", self.registry, self.repository, separator, self.reference)
+    }
+}
+
+/// Content descriptor in a manifest or index
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    #[serde(default)]
+    pub media_type: Option<String>,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default)]
+    pub platform: Option<Platform>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+}
+
+/// Image manifest or index; only the fields needed for pulling layers
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageManifest {
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    /// Platform manifests, for an index
+    #[serde(default)]
+    pub manifests: Vec<Descriptor>,
+}
+
+/// Registry architecture name of the running platform
+fn current_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        other => other,
+    }
+}
+
+/// Check `data` against a `sha256:` digest
+pub fn verify_digest(digest: &str, data: &[u8]) -> Result<()> {
+    let expected = sha256_digest(digest)?;
+    let actual: [u8; 32] = Sha256::digest(data).into();
+    if actual != expected {
+        anyhow::bail!("Digest mismatch: expected {}, got {}", digest, ContentHash::Sha256(actual));
+    }
+    Ok(())
+}
+
+/// Bytes of a `sha256:` digest; other algorithms are rejected
+pub fn sha256_digest(digest: &str) -> Result<[u8; 32]> {
+    match digest.parse::<ContentHash>() {
+        Ok(ContentHash::Sha256(bytes)) => Ok(bytes),
+        Ok(_) => anyhow::bail!("Unsupported digest algorithm: {}", digest),
+        Err(e) => Err(e).with_context(|| format!("Invalid digest: {}", digest)),
+    }
+}
+
+/// Pull access to one repository
+pub struct Registry {
+    client: reqwest::Client,
+    base: String,
+    repository: String,
+    token: Option<String>,
+    /// Whether `token` was given by the user, rather than obtained anonymously
+    explicit_token: bool,
+}
+
+impl Registry {
+    /// Connect over HTTPS, or plain HTTP when `insecure`
+    pub fn new(image: &ImageRef, token: Option<String>, insecure: bool) -> Self {
+        let scheme = if insecure { "http" } else { "https" };
+        Self {
+            client: reqwest::Client::new(),
+            base: format!("{}://{}", scheme, image.registry),
+            repository: image.repository.clone(),
+            explicit_token: token.is_some(),
+            token,
+        }
+    }
+
+    /// URL of a blob, as used for log messages and temporary file names
+    pub fn blob_url(&self, digest: &str) -> String {
+        format!("{}/v2/{}/blobs/{}", self.base, self.repository, digest)
+    }
+
+    /// GET `/v2/<repository>/<path>`, authenticating on a 401 challenge
+    async fn get(&mut self, path: &str, accept: Option<&str>) -> Result<reqwest::Response> {
+        let url = format!("{}/v2/{}/{}", self.base, self.repository, path);
+        let mut retried = false;
+        loop {
+            let mut request = self.client.get(&url);
+            if let Some(accept) = accept {
+                request = request.header(reqwest::header::ACCEPT, accept);
+            }
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await.with_context(|| format!("Failed to request {}", url))?;
+
+            if response.status() != reqwest::StatusCode::UNAUTHORIZED || retried || self.explicit_token {
+                return response
+                    .error_for_status()
+                    .with_context(|| format!("Registry request failed: {}", url));
+            }
+            let challenge = response
+                .headers()
+                .get(reqwest::header::WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+                .with_context(|| format!("Registry requires authentication but sent no challenge: {}", url))?
+                .to_string();
+            self.token = Some(self.fetch_token(&challenge).await?);
+            retried = true;
+        }
+    }
+
+    /// Obtain an anonymous bearer token from a `WWW-Authenticate` challenge
+    async fn fetch_token(&self, challenge: &str) -> Result<String> {
+        let params = challenge
+            .strip_prefix("Bearer ")
+            .with_context(|| format!("Unsupported registry authentication: {}", challenge))?;
+        let param = |name: &str| {
+            params.split(',').find_map(|part| {
+                let (key, value) = part.trim().split_once('=')?;
+                (key == name).then(|| value.trim_matches('"').to_string())
+            })
+        };
+        let realm = param("realm").with_context(|| format!("No realm in challenge: {}", challenge))?;
+
+        let query: Vec<(&str, String)> = ["service", "scope"]
+            .into_iter()
+            .filter_map(|name| param(name).map(|value| (name, value)))
+            .collect();
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let body = self
+            .client
+            .get(&realm)
+            .query(&query)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Failed to obtain registry token from {}", realm))?
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to read token response from {}", realm))?;
+        let response: TokenResponse = serde_json::from_slice(&body).context("Invalid token response")?;
+        response
+            .token
+            .or(response.access_token)
+            .with_context(|| format!("Token response from {} has no token", realm))
+    }
+
+    /// Fetch the image manifest for `reference`, and its raw bytes
+    ///
+    /// An index is resolved to the manifest for the running platform (or
+    /// its first manifest). Manifests requested by digest are verified.
+    pub async fn manifest(&mut self, reference: &str) -> Result<(Vec<u8>, ImageManifest)> {
+        let accept = [OCI_MANIFEST, DOCKER_MANIFEST, OCI_INDEX, DOCKER_MANIFEST_LIST].join(", ");
+        let mut reference = reference.to_string();
+        for _ in 0..2 {
+            let raw = self
+                .get(&format!("manifests/{}", reference), Some(&accept))
+                .await?
+                .bytes()
+                .await
+                .context("Failed to read image manifest")?
+                .to_vec();
+            if reference.contains(':') {
+                verify_digest(&reference, &raw).context("Image manifest failed verification")?;
+            }
+            let manifest: ImageManifest = serde_json::from_slice(&raw).context("Invalid image manifest")?;
+
+            let is_index = matches!(manifest.media_type.as_deref(), Some(OCI_INDEX | DOCKER_MANIFEST_LIST))
+                || (manifest.layers.is_empty() && !manifest.manifests.is_empty());
+            if !is_index {
+                return Ok((raw, manifest));
+            }
+            let chosen = manifest
+                .manifests
+                .iter()
+                .find(|m| {
+                    m.platform
+                        .as_ref()
+                        .is_some_and(|p| p.os == "linux" && p.architecture == current_architecture())
+                })
+                .or(manifest.manifests.first())
+                .context("Image index lists no manifests")?;
+            reference = chosen.digest.clone();
+        }
+        anyhow::bail!("Image index points to another index")
+    }
+
+    /// Start downloading a blob
+    pub async fn blob(&mut self, digest: &str) -> Result<reqwest::Response> {
+        self.get(&format!("blobs/{}", digest), None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_image_ref() {
+        let parse = |s: &str| ImageRef::parse(s).unwrap();
+
+        assert_eq!(
+            parse("ubuntu"),
+            ImageRef {
+                registry: DEFAULT_REGISTRY.to_string(),
+                repository: "library/ubuntu".to_string(),
+                reference: "latest".to_string(),
+            }
+        );
+        assert_eq!(parse("ghcr.io/org/data:2024.01").repository, "org/data");
+        assert_eq!(parse("ghcr.io/org/data:2024.01").reference, "2024.01");
+        let local = parse("localhost:5000/data@sha256:abcd");
+        assert_eq!(local.registry, "localhost:5000");
+        assert_eq!(local.reference, "sha256:abcd");
+        assert_eq!(local.to_string(), "localhost:5000/data@sha256:abcd");
+        assert_eq!(parse("org/data").to_string(), "registry-1.docker.io/org/data:latest");
+
+        assert!(ImageRef::parse("data:").is_err());
+    }
+
+    #[test]
+    fn test_verify_digest() {
+        let digest = "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert!(verify_digest(digest, b"abc").is_ok());
+        assert!(verify_digest(digest, b"abd").is_err());
+        assert!(sha256_digest(&format!("blake3:{}", "0".repeat(64))).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+// Command output formatting
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// How commands print their results to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Prose for people
+    Human,
+    /// A single JSON document for scripts
+    Json,
+}
+
+impl Format {
+    /// Select the format from the global `--json` flag
+    pub fn from_json_flag(json: bool) -> Self {
+        if json {
+            Format::Json
+        } else {
+            Format::Human
+        }
+    }
+}
+
+/// Print a command's result in the requested format
+///
+/// In JSON mode `value` is printed as pretty JSON; otherwise `human` renders
+/// it. Logging goes to stderr, so in JSON mode stdout carries only the
+/// document.
+pub fn emit<T: Serialize>(format: Format, value: &T, human: impl FnOnce(&T)) -> Result<()> {
+    match format {
+        Format::Json => {
+            let json = serde_json::to_string_pretty(value).context("Failed to serialize output")?;
+            println!("{}", json);
+        }
+        Format::Human => human(value),
+    }
+    Ok(())
+}
@@ -0,0 +1,413 @@
+// Transform implementations and multi-step transformation pipelines
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use cast_core::db::MetadataDb;
+use cast_core::manifest::{Content, Manifest, Transformation, CURRENT_SCHEMA_VERSION};
+use cast_core::storage::local::LocalStorage;
+
+pub mod builtin;
+pub mod exec;
+
+/// What a transform works with besides its input manifest
+pub struct TransformCtx<'a> {
+    pub storage: &'a LocalStorage,
+    /// Directory the transform's output files are in, or are written to
+    pub output_dir: &'a Path,
+    /// Maximum number of files to hash concurrently
+    pub jobs: usize,
+    /// Read buffer size for hashing files
+    pub buf_size: usize,
+}
+
+/// A transform cast runs itself, producing the output manifest's contents
+#[async_trait]
+pub trait Transform: Send + Sync {
+    /// The `transform_type` this transform is invoked by
+    fn name(&self) -> &str;
+
+    async fn apply(&self, input: &Manifest, ctx: &TransformCtx<'_>) -> Result<Vec<Content>>;
+}
+
+/// Transforms available by `transform_type`
+#[derive(Default)]
+pub struct Registry {
+    transforms: HashMap<String, Box<dyn Transform>>,
+}
+
+impl Registry {
+    /// Registry holding every built-in transform
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.register(Box::new(builtin::Extract));
+        registry
+    }
+
+    /// Add a transform, replacing any registered under the same name
+    pub fn register(&mut self, transform: Box<dyn Transform>) {
+        self.transforms.insert(transform.name().to_string(), transform);
+    }
+
+    pub fn get(&self, transform_type: &str) -> Option<&dyn Transform> {
+        self.transforms.get(transform_type).map(|t| t.as_ref())
+    }
+
+    /// Run the transform registered as `transform_type`, or the command an
+    /// `exec:` type names
+    ///
+    /// Returns `None` for other types, which are external: their output is
+    /// expected to be in `ctx.output_dir` already.
+    pub async fn apply(
+        &self,
+        transform_type: &str,
+        input: &Manifest,
+        ctx: &TransformCtx<'_>,
+    ) -> Result<Option<Vec<Content>>> {
+        let exec;
+        let transform = match (self.get(transform_type), exec::Exec::parse(transform_type)) {
+            (Some(transform), _) => transform,
+            (None, Some(parsed)) => {
+                exec = parsed?;
+                &exec as &dyn Transform
+            }
+            (None, None) => return Ok(None),
+        };
+        transform
+            .apply(input, ctx)
+            .await
+            .with_context(|| format!("Transform '{}' failed", transform_type))
+            .map(Some)
+    }
+
+    /// Contents a transform produces in `ctx.output_dir`
+    ///
+    /// Runs the transform as `apply` does; for an external type, scans the
+    /// output directory as it stands.
+    pub async fn contents(
+        &self,
+        transform_type: &str,
+        input: &Manifest,
+        ctx: &TransformCtx<'_>,
+    ) -> Result<Vec<Content>> {
+        if let Some(contents) = self.apply(transform_type, input, ctx).await? {
+            return Ok(contents);
+        }
+        if !ctx.output_dir.exists() {
+            anyhow::bail!(
+                "Output directory of external transform '{}' does not exist: {}",
+                transform_type,
+                ctx.output_dir.display()
+            );
+        }
+        crate::scan_output_dir(ctx.output_dir, ctx.jobs, ctx.buf_size).await
+    }
+}
+
+/// Pipeline specification read from `--pipeline <spec.json>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineSpec {
+    pub steps: Vec<PipelineStep>,
+}
+
+/// One step of a pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineStep {
+    #[serde(rename = "type")]
+    pub transform_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+}
+
+impl PipelineSpec {
+    /// Read and parse a pipeline spec file
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read pipeline spec: {}", path.display()))?;
+
+        let spec: PipelineSpec = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse pipeline spec: {}", path.display()))?;
+
+        if spec.steps.is_empty() {
+            anyhow::bail!("Pipeline spec has no steps: {}", path.display());
+        }
+        Ok(spec)
+    }
+}
+
+/// Run each pipeline step in order on the manifest the step before it produced
+///
+/// Step `n` works in `<ctx.output_dir>/step-<n>`: registered and `exec:`
+/// transforms are run through `registry` there, and any other type is
+/// external, so that directory must already hold its files. Each step's
+/// output manifest carries the files it produced and one more
+/// `Transformation`, and is recorded in `db` as a single `cast transform`
+/// would be. The first step's `from` is the input's source archive hash
+/// (or, without one, the input manifest's hash); each later step's is the
+/// content hash of the manifest produced by the step before it.
+pub async fn run_pipeline(
+    registry: &Registry,
+    steps: &[PipelineStep],
+    input: &Manifest,
+    ctx: &TransformCtx<'_>,
+    db: &MetadataDb,
+) -> Result<Manifest> {
+    if steps.is_empty() {
+        anyhow::bail!("Pipeline has no steps");
+    }
+
+    let mut current = input.clone();
+    for (i, step) in steps.iter().enumerate() {
+        let transform_type = step.transform_type.as_str();
+        if transform_type.trim().is_empty() {
+            anyhow::bail!("Pipeline step {} has an empty type", i + 1);
+        }
+        tracing::info!("Pipeline step {}: {}", i + 1, transform_type);
+
+        let step_dir = ctx.output_dir.join(format!("step-{}", i + 1));
+        let step_ctx = TransformCtx {
+            output_dir: &step_dir,
+            ..*ctx
+        };
+        let mut contents = registry
+            .contents(transform_type, &current, &step_ctx)
+            .await
+            .with_context(|| format!("Pipeline step {} failed", i + 1))?;
+        if contents.is_empty() {
+            anyhow::bail!(
+                "Pipeline step {} ({}) produced no files in {}",
+                i + 1,
+                transform_type,
+                step_dir.display()
+            );
+        }
+        contents.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let from = if i == 0 {
+            input
+                .source
+                .archive_hash
+                .clone()
+                .unwrap_or_else(|| input.content_hash().to_string_prefixed())
+        } else {
+            current.content_hash().to_string_prefixed()
+        };
+        let mut transformations = current.transformations.clone();
+        transformations.push(Transformation {
+            transform_type: transform_type.to_string(),
+            from,
+            params: step.params.clone(),
+        });
+
+        let output = Manifest {
+            schema_version: CURRENT_SCHEMA_VERSION.to_string(),
+            dataset: current.dataset.clone(),
+            source: current.source.clone(),
+            contents,
+            transformations,
+            signature: None,
+        };
+        let params_key = step
+            .params
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .context("Failed to serialize transformation parameters")?;
+        crate::record_transformation(ctx.storage, db, &current, &output, transform_type, params_key).await?;
+
+        current = output;
+    }
+
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_manifest() -> Manifest {
+        Manifest::from_json(
+            r#"{
+                "schema_version": "1.0",
+                "dataset": {"name": "pipe", "version": "1"},
+                "source": {"archive_hash": "blake3:archive"},
+                "contents": []
+            }"#,
+        )
+        .unwrap()
+    }
+
+    /// Writes `<name>.txt` holding how many contents its input had
+    struct Stamp(&'static str);
+
+    #[async_trait]
+    impl Transform for Stamp {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn apply(&self, input: &Manifest, ctx: &TransformCtx<'_>) -> Result<Vec<Content>> {
+            tokio::fs::create_dir_all(ctx.output_dir).await?;
+            let path = ctx.output_dir.join(format!("{}.txt", self.0));
+            tokio::fs::write(path, input.contents.len().to_string()).await?;
+            crate::scan_output_dir(ctx.output_dir, ctx.jobs, ctx.buf_size).await
+        }
+    }
+
+    async fn pipeline_env() -> (tempfile::TempDir, LocalStorage, MetadataDb) {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path().join("cast"));
+        storage.initialize().await.unwrap();
+        let db = MetadataDb::new(temp_dir.path().join("meta.db")).await.unwrap();
+        (temp_dir, storage, db)
+    }
+
+    #[tokio::test]
+    async fn test_two_step_pipeline() {
+        let (temp_dir, storage, db) = pipeline_env().await;
+        let output_dir = temp_dir.path().join("out");
+        let ctx = TransformCtx {
+            storage: &storage,
+            output_dir: &output_dir,
+            jobs: 1,
+            buf_size: 4096,
+        };
+        let mut registry = Registry::default();
+        registry.register(Box::new(Stamp("first")));
+        registry.register(Box::new(Stamp("second")));
+
+        let steps: PipelineSpec = serde_json::from_str(
+            r#"{"steps": [
+                {"type": "first"},
+                {"type": "second", "params": {"min_length": "50"}}
+            ]}"#,
+        )
+        .unwrap();
+
+        let input = input_manifest();
+        let output = run_pipeline(&registry, &steps.steps, &input, &ctx, &db).await.unwrap();
+
+        // The second step ran on the one file the first step produced
+        assert_eq!(output.contents.len(), 1);
+        assert_eq!(output.contents[0].path, "second.txt");
+        assert_eq!(output.contents[0].hash, cast_core::Blake3Hash::from_bytes(b"1").to_hex());
+        assert!(output_dir.join("step-1/first.txt").exists());
+
+        assert_eq!(output.transformations.len(), 2);
+        assert_eq!(output.transformations[0].transform_type, "first");
+        assert_eq!(output.transformations[0].from, "blake3:archive");
+        assert_eq!(output.transformations[1].transform_type, "second");
+        assert_eq!(
+            output.transformations[1].params,
+            Some(serde_json::json!({"min_length": "50"}))
+        );
+
+        // The second step points at the manifest produced by the first
+        let after_first = run_pipeline(&registry, &steps.steps[..1], &input, &ctx, &db).await.unwrap();
+        assert_eq!(after_first.contents[0].path, "first.txt");
+        assert_eq!(
+            output.transformations[1].from,
+            after_first.content_hash().to_string_prefixed()
+        );
+
+        // Each step is recorded like a single transform
+        let recorded = db
+            .find_cached_transformation(
+                &after_first.content_hash().to_string_prefixed(),
+                "second",
+                Some(r#"{"min_length":"50"}"#),
+            )
+            .await
+            .unwrap();
+        assert_eq!(recorded, Some(output.content_hash().to_string_prefixed()));
+        assert_eq!(db.get_stats().await.unwrap().transformations_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_external_step() {
+        let (temp_dir, storage, db) = pipeline_env().await;
+        let output_dir = temp_dir.path().join("out");
+        let ctx = TransformCtx {
+            storage: &storage,
+            output_dir: &output_dir,
+            jobs: 1,
+            buf_size: 4096,
+        };
+        let mut input = input_manifest();
+        input.source.archive_hash = None;
+        let steps = [PipelineStep {
+            transform_type: "index".to_string(),
+            params: None,
+        }];
+
+        // An external step's files must already be in its directory
+        let err = run_pipeline(&Registry::default(), &steps, &input, &ctx, &db).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("step-1"), "{:#}", err);
+
+        tokio::fs::create_dir_all(output_dir.join("step-1")).await.unwrap();
+        tokio::fs::write(output_dir.join("step-1/index.bin"), b"idx").await.unwrap();
+        let output = run_pipeline(&Registry::default(), &steps, &input, &ctx, &db).await.unwrap();
+        assert_eq!(output.contents.len(), 1);
+        assert_eq!(output.transformations[0].from, input.content_hash().to_string_prefixed());
+        output.validate().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_empty_pipeline_rejected() {
+        let (temp_dir, storage, db) = pipeline_env().await;
+        let ctx = TransformCtx {
+            storage: &storage,
+            output_dir: temp_dir.path(),
+            jobs: 1,
+            buf_size: 4096,
+        };
+        assert!(run_pipeline(&Registry::default(), &[], &input_manifest(), &ctx, &db)
+            .await
+            .is_err());
+    }
+
+    struct Dummy;
+
+    #[async_trait]
+    impl Transform for Dummy {
+        fn name(&self) -> &str {
+            "dummy"
+        }
+
+        async fn apply(&self, input: &Manifest, ctx: &TransformCtx<'_>) -> Result<Vec<Content>> {
+            Ok(vec![Content {
+                path: format!("{}-{}.txt", input.dataset.name, ctx.jobs),
+                hash: cast_core::Blake3Hash::from_bytes(b"dummy").to_string_prefixed(),
+                size: 5,
+                executable: false,
+                mime_type: None,
+                kind: Default::default(),
+                derived_from: Vec::new(),
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_dispatches_by_name() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        let ctx = TransformCtx {
+            storage: &storage,
+            output_dir: temp_dir.path(),
+            jobs: 3,
+            buf_size: 4096,
+        };
+
+        let mut registry = Registry::with_builtins();
+        assert!(registry.get("extract").is_some());
+        assert!(registry.get("dummy").is_none());
+        registry.register(Box::new(Dummy));
+
+        let contents = registry.apply("dummy", &input_manifest(), &ctx).await.unwrap().unwrap();
+        assert_eq!(contents[0].path, "pipe-3.txt");
+        assert!(registry.apply("external", &input_manifest(), &ctx).await.unwrap().is_none());
+    }
+}
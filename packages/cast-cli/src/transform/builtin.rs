@@ -0,0 +1,168 @@
+// Transforms cast implements itself, registered by `Registry::with_builtins`
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use cast_core::hash::Blake3Hash;
+use cast_core::manifest::{ArchiveType, Content, Manifest};
+use cast_core::storage::local::LocalStorage;
+use cast_core::storage::StorageBackend;
+
+use super::{Transform, TransformCtx};
+
+/// `extract`: unpack the source archive, then scan what it produced
+pub struct Extract;
+
+#[async_trait]
+impl Transform for Extract {
+    fn name(&self) -> &str {
+        "extract"
+    }
+
+    async fn apply(&self, input: &Manifest, ctx: &TransformCtx<'_>) -> Result<Vec<Content>> {
+        extract(ctx.storage, input, ctx.output_dir).await?;
+        crate::scan_output_dir(ctx.output_dir, ctx.jobs, ctx.buf_size).await
+    }
+}
+
+/// Unpack the input's source archive into `output_dir`
+///
+/// The archive is the stored object named by `source.archive_hash`. Tar,
+/// gzip-compressed tar, and zip archives are supported, recognized by their
+/// leading bytes. `output_dir` is created if needed; entries that would land
+/// outside it are not written.
+pub async fn extract(storage: &LocalStorage, input: &Manifest, output_dir: &Path) -> Result<()> {
+    let archive_hash = input
+        .source
+        .archive_hash
+        .as_deref()
+        .context("The extract transform needs an input manifest with a source archive_hash")?;
+    let hash = Blake3Hash::from_str(archive_hash)
+        .with_context(|| format!("Invalid source archive hash: {}", archive_hash))?;
+
+    let _lock = storage.lock_shared_for_read().await;
+    if !storage.exists(&hash).await {
+        anyhow::bail!("Source archive {} is not in the store", hash);
+    }
+    tokio::fs::create_dir_all(output_dir)
+        .await
+        .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+
+    let mut header = Vec::new();
+    storage
+        .get_reader(&hash)?
+        .take(512)
+        .read_to_end(&mut header)
+        .with_context(|| format!("Failed to read source archive {}", hash))?;
+    let output_dir = output_dir.to_path_buf();
+
+    match ArchiveType::detect(&header) {
+        Some(ArchiveType::Tar) => {
+            let reader = storage.get_reader(&hash)?;
+            run_blocking(move || unpack_tar(reader, &output_dir)).await
+        }
+        Some(ArchiveType::Gzip) => {
+            let reader = flate2::read::GzDecoder::new(storage.get_reader(&hash)?);
+            run_blocking(move || unpack_tar(reader, &output_dir)).await
+        }
+        Some(ArchiveType::Zip) => {
+            // Zip archives are read from their central directory at the end,
+            // so objects without a single file of their own are copied out
+            let (path, temporary) = match storage.get(&hash).await {
+                Ok(path) => (path, None),
+                Err(_) => {
+                    let path = storage.tmp_file("extract");
+                    if let Some(parent) = path.parent() {
+                        tokio::fs::create_dir_all(parent)
+                            .await
+                            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+                    }
+                    storage.copy_object(&hash, &path, false).await?;
+                    (path.clone(), Some(path))
+                }
+            };
+            let result = run_blocking(move || unpack_zip(&path, &output_dir)).await;
+            if let Some(temporary) = temporary {
+                let _ = tokio::fs::remove_file(temporary).await;
+            }
+            result
+        }
+        Some(other) => anyhow::bail!("The extract transform does not support {:?} archives", other),
+        None => anyhow::bail!("Source archive {} is not a tar, tar.gz, or zip archive", hash),
+    }
+}
+
+async fn run_blocking(f: impl FnOnce() -> Result<()> + Send + 'static) -> Result<()> {
+    tokio::task::spawn_blocking(f).await.context("Extract task panicked")?
+}
+
+fn unpack_tar(reader: impl Read, output_dir: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive
+        .unpack(output_dir)
+        .with_context(|| format!("Failed to unpack tar archive into {}", output_dir.display()))
+}
+
+fn unpack_zip(path: &Path, output_dir: &Path) -> Result<()> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open archive: {}", path.display()))?;
+    zip::ZipArchive::new(file)
+        .and_then(|mut archive| archive.extract(output_dir))
+        .with_context(|| format!("Failed to unpack zip archive into {}", output_dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, data) in files {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn manifest_for(archive_hash: &Blake3Hash) -> Manifest {
+        Manifest::from_json(&format!(
+            r#"{{"schema_version": "1.0", "dataset": {{"name": "x", "version": "1"}},
+                "source": {{"archive_hash": "{}"}}, "contents": []}}"#,
+            archive_hash.to_string_prefixed()
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_extract_tar_gz_and_zip() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path().join("cast"));
+        storage.initialize().await.unwrap();
+
+        let archive = storage.put(&tar_gz(&[("data/a.txt", b"alpha"), ("b.txt", b"beta")])).await.unwrap();
+        let out = temp_dir.path().join("tar-out");
+        extract(&storage, &manifest_for(&archive), &out).await.unwrap();
+        assert_eq!(std::fs::read(out.join("data/a.txt")).unwrap(), b"alpha");
+        assert_eq!(std::fs::read(out.join("b.txt")).unwrap(), b"beta");
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("nested/c.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"gamma").unwrap();
+        let archive = storage.put(&zip.finish().unwrap().into_inner()).await.unwrap();
+        let out = temp_dir.path().join("zip-out");
+        extract(&storage, &manifest_for(&archive), &out).await.unwrap();
+        assert_eq!(std::fs::read(out.join("nested/c.txt")).unwrap(), b"gamma");
+
+        let not_archive = storage.put(b"plain text").await.unwrap();
+        assert!(extract(&storage, &manifest_for(&not_archive), &out).await.is_err());
+    }
+}
@@ -0,0 +1,119 @@
+// `exec:` transforms: user commands run as subprocesses
+//
+// `--transform-type 'exec:<program> [args...]'` runs `<program>` directly,
+// without a shell, with its arguments split on whitespace and the output
+// directory appended as the last one. The input manifest is written to its
+// stdin as JSON; its stderr goes to the log.
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use cast_core::manifest::{Content, Manifest};
+
+use super::{Transform, TransformCtx};
+
+/// Prefix of transform types that name a command
+pub const PREFIX: &str = "exec:";
+
+/// A command-line transform
+pub struct Exec {
+    name: String,
+    program: String,
+    args: Vec<String>,
+}
+
+impl Exec {
+    /// Parse an `exec:<program> [args...]` transform type
+    ///
+    /// Returns `None` if `transform_type` is not an `exec:` type.
+    pub fn parse(transform_type: &str) -> Option<Result<Self>> {
+        let command = transform_type.strip_prefix(PREFIX)?;
+        let mut words = command.split_whitespace().map(str::to_string);
+        Some(match words.next() {
+            Some(program) => Ok(Self {
+                name: transform_type.to_string(),
+                program,
+                args: words.collect(),
+            }),
+            None => Err(anyhow::anyhow!("No command given in transform type: {}", transform_type)),
+        })
+    }
+}
+
+#[async_trait]
+impl Transform for Exec {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn apply(&self, input: &Manifest, ctx: &TransformCtx<'_>) -> Result<Vec<Content>> {
+        tokio::fs::create_dir_all(ctx.output_dir)
+            .await
+            .with_context(|| format!("Failed to create output directory: {}", ctx.output_dir.display()))?;
+        let manifest_json = serde_json::to_vec(input).context("Failed to serialize input manifest")?;
+
+        tracing::info!("Running {} {:?} {}", self.program, self.args, ctx.output_dir.display());
+        let mut child = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .arg(ctx.output_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program))?;
+
+        let mut stdin = child.stdin.take().context("Child has no stdin")?;
+        let write_input = async move {
+            // A command that does not read its input closes the pipe early
+            match stdin.write_all(&manifest_json).await {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+                _ => Ok(()),
+            }
+        };
+        let stderr = child.stderr.take().context("Child has no stderr")?;
+        let program = self.program.as_str();
+        let log_stderr = async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Some(line) = lines.next_line().await? {
+                tracing::info!("{}: {}", program, line);
+            }
+            Ok::<_, std::io::Error>(())
+        };
+        let stdout = child.stdout.take().context("Child has no stdout")?;
+        let log_stdout = async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Some(line) = lines.next_line().await? {
+                tracing::debug!("{}: {}", program, line);
+            }
+            Ok::<_, std::io::Error>(())
+        };
+
+        let (written, logged, _, status) = tokio::join!(write_input, log_stderr, log_stdout, child.wait());
+        let status = status.with_context(|| format!("Failed to wait for {}", self.program))?;
+        if !status.success() {
+            anyhow::bail!("{} exited with {}", self.program, status);
+        }
+        written.with_context(|| format!("Failed to write input manifest to {}", self.program))?;
+        logged.with_context(|| format!("Failed to read stderr of {}", self.program))?;
+
+        crate::scan_output_dir(ctx.output_dir, ctx.jobs, ctx.buf_size).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exec_type() {
+        let exec = Exec::parse("exec:python3 convert.py --fast").unwrap().unwrap();
+        assert_eq!(exec.program, "python3");
+        assert_eq!(exec.args, ["convert.py", "--fast"]);
+        assert_eq!(exec.name(), "exec:python3 convert.py --fast");
+
+        assert!(Exec::parse("extract").is_none());
+        assert!(Exec::parse("exec:  ").unwrap().is_err());
+    }
+}
@@ -0,0 +1,162 @@
+// Dataset file trees for `cast tree`
+use anyhow::Result;
+use serde::Serialize;
+
+use cast_core::manifest::{Content, ContentKind};
+
+/// One entry in a dataset's file tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+/// What a tree node is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Entry {
+    File { size: u64, executable: bool },
+    Symlink { target: String },
+    Directory { children: Vec<TreeNode> },
+}
+
+/// Rebuild the directory structure implied by a manifest's flat paths
+///
+/// Returns the top-level entries, with every directory's children sorted by
+/// name. Fails if a path is used both as a file and as a directory.
+pub fn build(contents: &[Content]) -> Result<Vec<TreeNode>> {
+    let mut root = Vec::new();
+    for content in contents {
+        let mut parts: Vec<&str> = content.path.split('/').filter(|p| !p.is_empty()).collect();
+        let Some(leaf) = parts.pop() else {
+            continue;
+        };
+
+        let mut siblings = &mut root;
+        for part in parts {
+            siblings = directory(siblings, part, &content.path)?;
+        }
+        match &content.kind {
+            ContentKind::Directory => {
+                directory(siblings, leaf, &content.path)?;
+            }
+            ContentKind::File => siblings.push(TreeNode {
+                name: leaf.to_string(),
+                entry: Entry::File {
+                    size: content.size,
+                    executable: content.executable,
+                },
+            }),
+            ContentKind::Symlink { target } => siblings.push(TreeNode {
+                name: leaf.to_string(),
+                entry: Entry::Symlink { target: target.clone() },
+            }),
+        }
+    }
+
+    sort(&mut root);
+    Ok(root)
+}
+
+/// Children of the directory `name` among `siblings`, created if missing
+fn directory<'a>(siblings: &'a mut Vec<TreeNode>, name: &str, path: &str) -> Result<&'a mut Vec<TreeNode>> {
+    let index = match siblings.iter().position(|node| node.name == name) {
+        Some(index) => index,
+        None => {
+            siblings.push(TreeNode {
+                name: name.to_string(),
+                entry: Entry::Directory { children: Vec::new() },
+            });
+            siblings.len() - 1
+        }
+    };
+    match &mut siblings[index].entry {
+        Entry::Directory { children } => Ok(children),
+        _ => anyhow::bail!("{} is used both as a file and as a directory in {}", name, path),
+    }
+}
+
+fn sort(nodes: &mut [TreeNode]) {
+    nodes.sort_by(|a, b| a.name.cmp(&b.name));
+    for node in nodes {
+        if let Entry::Directory { children } = &mut node.entry {
+            sort(children);
+        }
+    }
+}
+
+/// Render a tree with box-drawing guides, one line per entry
+///
+/// Directories end in `/`, executables in `*`, and symlinks show their
+/// target; files are followed by their size in bytes.
+pub fn render(nodes: &[TreeNode]) -> Vec<String> {
+    let mut lines = Vec::new();
+    render_level(nodes, "", &mut lines);
+    lines
+}
+
+fn render_level(nodes: &[TreeNode], prefix: &str, lines: &mut Vec<String>) {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        let branch = if last { "└── " } else { "├── " };
+        let label = match &node.entry {
+            Entry::File { size, executable } => {
+                format!("{}{} ({} bytes)", node.name, if *executable { "*" } else { "" }, size)
+            }
+            Entry::Symlink { target } => format!("{} -> {}", node.name, target),
+            Entry::Directory { .. } => format!("{}/", node.name),
+        };
+        lines.push(format!("{}{}{}", prefix, branch, label));
+
+        if let Entry::Directory { children } = &node.entry {
+            let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            render_level(children, &child_prefix, lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> Content {
+        Content {
+            path: path.to_string(),
+            hash: String::new(),
+            size,
+            executable: false,
+            mime_type: None,
+            kind: ContentKind::File,
+            derived_from: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_build_nests_paths_under_directories() {
+        let tree = build(&[file("a/c.txt", 5), file("top.txt", 1), file("a/b.txt", 3)]).unwrap();
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].name, "a");
+        let Entry::Directory { children } = &tree[0].entry else {
+            panic!("a is not a directory");
+        };
+        let names: Vec<&str> = children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["b.txt", "c.txt"]);
+
+        assert_eq!(
+            render(&tree),
+            ["├── a/", "│   ├── b.txt (3 bytes)", "│   └── c.txt (5 bytes)", "└── top.txt (1 bytes)"]
+        );
+    }
+
+    #[test]
+    fn test_build_edge_cases() {
+        assert!(build(&[]).unwrap().is_empty());
+
+        let deep = build(&[file("1/2/3/4/5/leaf", 0)]).unwrap();
+        assert_eq!(render(&deep).last().unwrap(), "                    └── leaf (0 bytes)");
+
+        assert!(build(&[file("a", 1), file("a/b", 1)]).is_err());
+    }
+}
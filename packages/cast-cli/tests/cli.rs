@@ -0,0 +1,409 @@
+// End-to-end tests that run the `cast` binary against a temporary store
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempfile::TempDir;
+
+/// Run `cast` with its store rooted in `root` and no user config file
+fn cast(root: &Path, args: &[&str], stdin: Option<&[u8]>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cast"))
+        .args(args)
+        .env("HOME", root)
+        .env("CAST_STORE", root.join("cast"))
+        .env("RUST_LOG", "warn")
+        .env_remove("CAST_CONFIG")
+        .env_remove("CAST_PROFILE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run cast");
+
+    if let Some(input) = stdin {
+        child.stdin.take().unwrap().write_all(input).unwrap();
+    }
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "cast failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap().trim().to_string()
+}
+
+#[test]
+fn test_put_then_get_stdout() {
+    let temp = TempDir::new().unwrap();
+    let data = b"round trip through the store";
+
+    let hash = stdout(&cast(temp.path(), &["put", "-"], Some(data)));
+    assert_eq!(hash, format!("blake3:{}", blake3::hash(data).to_hex()));
+
+    let output = cast(temp.path(), &["get", &hash, "--stdout"], None);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, data);
+
+    // A unique prefix resolves to the same object
+    let prefix = &hash["blake3:".len()..][..10];
+    let path = stdout(&cast(temp.path(), &["get", prefix], None));
+    assert_eq!(std::fs::read(path).unwrap(), data);
+}
+
+#[test]
+fn test_get_missing_object_fails() {
+    let temp = TempDir::new().unwrap();
+    let missing = format!("blake3:{}", blake3::hash(b"never stored").to_hex());
+
+    let output = cast(temp.path(), &["get", &missing], None);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not present"));
+}
+
+#[test]
+fn test_list_json_from_seeded_db() {
+    let temp = TempDir::new().unwrap();
+    let hash = format!("blake3:{}", blake3::hash(b"manifest").to_hex());
+
+    let dump = serde_json::json!({
+        "format_version": 1,
+        "schema_version": 3,
+        "objects": [
+            {"hash": hash, "size": 8, "refs": 1, "created_at": "2024-01-01 00:00:00", "metadata": null}
+        ],
+        "datasets": [
+            {"id": 1, "name": "uniprot", "version": "2024.01", "manifest_hash": hash,
+             "description": null, "created_at": "2024-01-01 00:00:00"},
+            {"id": 2, "name": "pdb", "version": "1", "manifest_hash": hash,
+             "description": null, "created_at": "2024-01-01 00:00:00"}
+        ],
+        "transformations": []
+    });
+    let dump_path = temp.path().join("dump.json");
+    std::fs::write(&dump_path, dump.to_string()).unwrap();
+    stdout(&cast(temp.path(), &["db", "import", dump_path.to_str().unwrap()], None));
+
+    let datasets: serde_json::Value =
+        serde_json::from_str(&stdout(&cast(temp.path(), &["list", "--json", "--name", "uni*"], None)))
+            .unwrap();
+    assert_eq!(datasets.as_array().unwrap().len(), 1);
+    assert_eq!(datasets[0]["name"], "uniprot");
+    assert_eq!(datasets[0]["manifest_hash"], hash.as_str());
+
+    let objects: serde_json::Value =
+        serde_json::from_str(&stdout(&cast(temp.path(), &["list", "--objects", "--json"], None)))
+            .unwrap();
+    assert_eq!(objects[0]["hash"], hash.as_str());
+    assert_eq!(objects[0]["size"], 8);
+}
+
+#[test]
+fn test_cat_streams_object() {
+    let temp = TempDir::new().unwrap();
+    let text = b"line one\nline two\n";
+    let file = temp.path().join("notes.txt");
+    std::fs::write(&file, text).unwrap();
+
+    let hash = stdout(&cast(temp.path(), &["put", file.to_str().unwrap()], None));
+
+    let output = cast(temp.path(), &["cat", &hash], None);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, text);
+
+    let missing = format!("blake3:{}", blake3::hash(b"absent").to_hex());
+    let output = cast(temp.path(), &["cat", &missing], None);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_cat_tolerates_closed_pipe() {
+    use std::io::Read;
+
+    let temp = TempDir::new().unwrap();
+    let data = vec![b'x'; 8 * 1024 * 1024];
+    let hash = stdout(&cast(temp.path(), &["put", "-"], Some(&data)));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cast"))
+        .args(["cat", &hash])
+        .env("HOME", temp.path())
+        .env("CAST_STORE", temp.path().join("cast"))
+        .env_remove("CAST_CONFIG")
+        .env_remove("CAST_PROFILE")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut first = [0u8; 16];
+    child.stdout.take().unwrap().read_exact(&mut first).unwrap();
+    // Dropping stdout closes the pipe while cat is still writing
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn test_put_json_output() {
+    let temp = TempDir::new().unwrap();
+    let data = b"structured output";
+
+    let output = stdout(&cast(temp.path(), &["--json", "put", "-"], Some(data)));
+    let result: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(result["hash"], format!("blake3:{}", blake3::hash(data).to_hex()));
+    assert_eq!(result["size"], data.len());
+    assert_eq!(result["deduplicated"], false);
+
+    // The flag is global, so it may also follow the subcommand
+    let output = stdout(&cast(temp.path(), &["put", "-", "--json"], Some(data)));
+    let result: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(result["deduplicated"], true);
+}
+
+#[test]
+fn test_packed_objects_stay_readable() {
+    let temp = TempDir::new().unwrap();
+    let data = b"small enough to pack";
+    let hash = stdout(&cast(temp.path(), &["put", "-"], Some(data)));
+
+    let report: serde_json::Value =
+        serde_json::from_str(&stdout(&cast(temp.path(), &["pack", "--json"], None))).unwrap();
+    assert_eq!(report["objects"], 1);
+
+    let output = cast(temp.path(), &["get", &hash, "--stdout"], None);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, data);
+
+    let output = cast(temp.path(), &["cat", &hash], None);
+    assert_eq!(output.stdout, data);
+}
+
+#[test]
+fn test_store_flag_overrides_config() {
+    let temp = TempDir::new().unwrap();
+    let explicit = temp.path().join("explicit");
+    let file = temp.path().join("data.txt");
+    std::fs::write(&file, b"stored via --store").unwrap();
+
+    // A config file naming yet another store must be ignored too
+    let config = temp.path().join("config.toml");
+    std::fs::write(&config, format!("root = {:?}\n", temp.path().join("configured"))).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_cast"))
+        .args(["--store", explicit.to_str().unwrap(), "put", file.to_str().unwrap()])
+        .env("HOME", temp.path())
+        .env("CAST_STORE", temp.path().join("ambient"))
+        .env("CAST_CONFIG", &config)
+        .env("RUST_LOG", "warn")
+        .output()
+        .unwrap();
+    let hash = stdout(&output);
+
+    let hex = hash.strip_prefix("blake3:").unwrap();
+    let object = explicit.join("store").join(&hex[..2]).join(&hex[2..4]).join(hex);
+    assert_eq!(std::fs::read(object).unwrap(), b"stored via --store");
+    assert!(!temp.path().join("ambient").exists());
+    assert!(!temp.path().join("configured").exists());
+
+    // Subcommands after the flag see the same store
+    let path = stdout(&cast(temp.path(), &["get", &hash, "--store", explicit.to_str().unwrap()], None));
+    assert!(Path::new(&path).starts_with(&explicit));
+}
+
+#[test]
+fn test_info_stdin_batch() {
+    let temp = TempDir::new().unwrap();
+    let first = stdout(&cast(temp.path(), &["put", "-"], Some(b"first object")));
+    let second = stdout(&cast(temp.path(), &["put", "-"], Some(b"second object")));
+    let missing = format!("blake3:{}", blake3::hash(b"never stored").to_hex());
+
+    let input = format!("{}\n{}\n\n{}\n", first, missing, second);
+    let output = cast(temp.path(), &["info", "--stdin"], Some(input.as_bytes()));
+    assert!(!output.status.success());
+
+    let out = String::from_utf8_lossy(&output.stdout);
+    assert!(out.contains(&first));
+    assert!(out.contains(&second));
+    let err = String::from_utf8_lossy(&output.stderr);
+    assert!(err.contains(&format!("{}: Object not found", missing)), "{}", err);
+    assert!(err.contains("1 of 3 hashes failed"), "{}", err);
+}
+
+/// `cast info --json` for `hash`, checking no missing-object warning is logged
+fn info_json(root: &Path, hash: &str) -> serde_json::Value {
+    let output = cast(root, &["info", hash, "--json"], None);
+    let err = String::from_utf8_lossy(&output.stderr);
+    assert!(!err.contains("missing from the store"), "{}", err);
+    serde_json::from_str(&stdout(&output)).unwrap()
+}
+
+#[test]
+fn test_info_chunked_object() {
+    let temp = TempDir::new().unwrap();
+    let data = vec![7u8; 64 * 1024];
+    let hash = stdout(&cast(temp.path(), &["put", "-", "--chunked"], Some(&data)));
+
+    let info = info_json(temp.path(), &hash);
+    assert_eq!(info["present"], true);
+    assert_eq!(info["path"], serde_json::Value::Null);
+    assert_eq!(info["size"], data.len());
+    assert_eq!(info["last_accessed"], serde_json::Value::Null);
+
+    // Reading the object records the access in the metadata database
+    assert_eq!(cast(temp.path(), &["cat", &hash], None).stdout, data);
+    assert!(info_json(temp.path(), &hash)["last_accessed"].is_string());
+}
+
+#[test]
+fn test_info_packed_object() {
+    let temp = TempDir::new().unwrap();
+    let data = b"small enough to pack";
+    let hash = stdout(&cast(temp.path(), &["put", "-"], Some(data)));
+    assert!(info_json(temp.path(), &hash)["path"].is_string());
+
+    stdout(&cast(temp.path(), &["pack"], None));
+
+    let info = info_json(temp.path(), &hash);
+    assert_eq!(info["present"], true);
+    assert_eq!(info["path"], serde_json::Value::Null);
+    assert_eq!(info["size"], data.len());
+}
+
+#[test]
+fn test_get_stdin_rejects_single_object_flags() {
+    let temp = TempDir::new().unwrap();
+    let hash = stdout(&cast(temp.path(), &["put", "-"], Some(b"batch")));
+    let input = format!("{}\n", hash);
+    let dir = temp.path().to_str().unwrap();
+
+    for flags in [&["--stdout"][..], &["--restore", dir], &["--materialize", dir]] {
+        let args = [&["get", "--stdin"][..], flags].concat();
+        let output = cast(temp.path(), &args, Some(input.as_bytes()));
+        assert!(!output.status.success(), "{:?} was accepted", flags);
+        let err = String::from_utf8_lossy(&output.stderr);
+        assert!(err.contains("cannot be used with"), "{}", err);
+    }
+}
+
+#[test]
+fn test_get_dataset_reference() {
+    let temp = TempDir::new().unwrap();
+    let put_version = |version: &str, data: &[u8]| {
+        let dir = temp.path().join(format!("src-{}", version));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("data.txt"), data).unwrap();
+        stdout(&cast(
+            temp.path(),
+            &["put", "--recursive", dir.to_str().unwrap(), "--name", "ds", "--version", version],
+            None,
+        ))
+    };
+    let get_hash = |reference: &str| {
+        let json: serde_json::Value =
+            serde_json::from_str(&stdout(&cast(temp.path(), &["--json", "get", reference], None))).unwrap();
+        json["hash"].as_str().unwrap().to_string()
+    };
+
+    let v1 = put_version("1.0.0", b"first");
+    let v2 = put_version("2.0.0", b"second");
+    assert_ne!(v1, v2);
+
+    assert_eq!(get_hash("ds@1.0.0"), v1);
+    assert_eq!(get_hash("ds@latest"), v2);
+    // A bare hash still resolves as a hash
+    assert_eq!(get_hash(&v1), v1);
+
+    let out = temp.path().join("checkout");
+    stdout(&cast(temp.path(), &["get", "ds@1.0.0", "--materialize", out.to_str().unwrap()], None));
+    assert_eq!(std::fs::read(out.join("data.txt")).unwrap(), b"first");
+
+    assert!(!cast(temp.path(), &["get", "ds@3.0.0"], None).status.success());
+}
+
+#[test]
+fn test_verify_store_reports_corruption() {
+    let temp = TempDir::new().unwrap();
+    stdout(&cast(temp.path(), &["put", "-"], Some(b"intact object")));
+    let damaged = stdout(&cast(temp.path(), &["put", "-"], Some(b"damaged object")));
+    let path = stdout(&cast(temp.path(), &["get", &damaged], None));
+    std::fs::write(path, b"flipped bits").unwrap();
+
+    let reports: Vec<serde_json::Value> = ["1", "4"]
+        .iter()
+        .map(|jobs| {
+            let output = cast(temp.path(), &["verify-store", "--json", "--jobs", jobs], None);
+            assert!(!output.status.success());
+            serde_json::from_slice(&output.stdout).unwrap()
+        })
+        .collect();
+    assert_eq!(reports[0]["checked"], 2);
+    assert_eq!(reports[0]["corrupted"], serde_json::json!([damaged]));
+    assert_eq!(reports[0], reports[1]);
+}
+
+#[test]
+fn test_verify_stdin_batch() {
+    let temp = TempDir::new().unwrap();
+    let first = stdout(&cast(temp.path(), &["put", "-"], Some(b"first object")));
+    let second = stdout(&cast(temp.path(), &["put", "-"], Some(b"second object")));
+    let missing = format!("blake3:{}", blake3::hash(b"never stored").to_hex());
+
+    let input = format!("{}\n{}\n{}\n", first, missing, second);
+    let output = cast(temp.path(), &["verify", "-"], Some(input.as_bytes()));
+    assert!(!output.status.success());
+
+    let out = String::from_utf8_lossy(&output.stdout);
+    assert!(out.contains(&format!("verified   {}", first)), "{}", out);
+    assert!(out.contains(&format!("verified   {}", second)), "{}", out);
+    let err = String::from_utf8_lossy(&output.stderr);
+    assert!(err.contains(&missing), "{}", err);
+    assert!(err.contains("1 of 3 hashes failed"), "{}", err);
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    serde_json::from_str(&stdout(output)).unwrap()
+}
+
+#[test]
+fn test_fetch_and_rm_json_output() {
+    let temp = TempDir::new().unwrap();
+    let data = b"fetched then removed";
+    let file = temp.path().join("download.bin");
+    std::fs::write(&file, data).unwrap();
+    let url = format!("file://{}", file.display());
+    let hash = format!("blake3:{}", blake3::hash(data).to_hex());
+
+    let fetched = json(&cast(temp.path(), &["--json", "fetch", &url], None));
+    assert_eq!(fetched["hash"], hash);
+    assert_eq!(fetched["size"], data.len());
+    stdout(&cast(temp.path(), &["put", "-"], Some(data)));
+
+    let removed = json(&cast(temp.path(), &["--json", "rm", &hash], None));
+    assert_eq!(removed, serde_json::json!({"hash": hash, "deleted": false, "refs": 1}));
+    let removed = json(&cast(temp.path(), &["--json", "rm", &hash], None));
+    assert_eq!(removed, serde_json::json!({"hash": hash, "deleted": true, "refs": 0}));
+}
+
+#[test]
+fn test_export_import_json_output() {
+    let source = TempDir::new().unwrap();
+    let dir = source.path().join("src");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("data.txt"), b"bundled").unwrap();
+    let manifest_hash = stdout(&cast(
+        source.path(),
+        &["put", "--recursive", dir.to_str().unwrap(), "--name", "ds", "--version", "1"],
+        None,
+    ));
+
+    let bundle = source.path().join("ds.tar");
+    let bundle = bundle.to_str().unwrap();
+    let exported = json(&cast(source.path(), &["--json", "export", "ds", "1", bundle], None));
+    assert_eq!(exported["manifest_hash"], manifest_hash);
+    assert_eq!(exported["files"], 1);
+    assert_eq!(exported["bundle"], bundle);
+
+    let dest = TempDir::new().unwrap();
+    let imported = json(&cast(dest.path(), &["--json", "import", bundle], None));
+    assert_eq!(imported, exported);
+}
@@ -0,0 +1,86 @@
+// Synchronous facade over the async storage API
+//
+// For callers without an async runtime, such as build scripts and sync CLIs.
+// Each call runs to completion on a private single-threaded tokio runtime, so
+// these methods must not be called from within an async context: tokio panics
+// when a runtime is started or blocked on from inside another one.
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+use crate::hash::Blake3Hash;
+use crate::storage::local::LocalStorage;
+use crate::storage::StorageBackend;
+
+/// Blocking wrapper around a `LocalStorage`
+///
+/// Must not be used from inside a tokio runtime; call the async
+/// `StorageBackend` methods there instead.
+pub struct BlockingStorage {
+    storage: LocalStorage,
+    runtime: Runtime,
+}
+
+impl BlockingStorage {
+    /// Wrap a storage backend, starting the runtime its calls run on
+    pub fn new(storage: LocalStorage) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start runtime for blocking storage")?;
+        Ok(Self { storage, runtime })
+    }
+
+    /// Create the store's directories if they do not exist
+    pub fn initialize(&self) -> Result<()> {
+        self.runtime.block_on(self.storage.initialize())
+    }
+
+    /// Store data and return its BLAKE3 hash
+    pub fn put(&self, data: &[u8]) -> Result<Blake3Hash> {
+        self.runtime.block_on(self.storage.put(data))
+    }
+
+    /// Path of a stored object
+    pub fn get(&self, hash: &Blake3Hash) -> Result<PathBuf> {
+        self.runtime.block_on(self.storage.get(hash))
+    }
+
+    /// Check if an object is stored
+    pub fn exists(&self, hash: &Blake3Hash) -> bool {
+        self.runtime.block_on(self.storage.exists(hash))
+    }
+
+    /// Delete an object from the store
+    pub fn delete(&self, hash: &Blake3Hash) -> Result<()> {
+        self.runtime.block_on(self.storage.delete(hash))
+    }
+
+    /// The wrapped async storage
+    pub fn inner(&self) -> &LocalStorage {
+        &self.storage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_put_get_round_trip_without_runtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = BlockingStorage::new(LocalStorage::with_root(temp_dir.path())).unwrap();
+        storage.initialize().unwrap();
+
+        let hash = storage.put(b"blocking data").unwrap();
+        assert_eq!(hash, Blake3Hash::from_bytes(b"blocking data"));
+        assert!(storage.exists(&hash));
+        assert_eq!(std::fs::read(storage.get(&hash).unwrap()).unwrap(), b"blocking data");
+
+        storage.delete(&hash).unwrap();
+        assert!(!storage.exists(&hash));
+        assert!(storage.get(&hash).is_err());
+    }
+}
@@ -0,0 +1,2933 @@
+// SQLite metadata database
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqliteConnection};
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::hash::Blake3Hash;
+use crate::manifest::{Dataset, Manifest};
+
+/// Version of the JSON document produced by `MetadataDb::export_json`
+pub const EXPORT_FORMAT_VERSION: i64 = 2;
+
+/// Format a time the way SQLite's `CURRENT_TIMESTAMP` does, so stored
+/// timestamps compare correctly as text
+fn format_timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Escape a string for use inside a quoted DOT identifier
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Schema version written by this build; see `initialize_schema`
+pub const CURRENT_SCHEMA_VERSION: i32 = 6;
+
+/// Default size of the database connection pool
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+
+/// Connection settings for `MetadataDb::with_options`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbOptions {
+    pub max_connections: u32,
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            journal_mode: JournalMode::default(),
+            synchronous: Synchronous::default(),
+        }
+    }
+}
+
+/// SQLite journal mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    #[default]
+    Wal,
+    Off,
+}
+
+impl From<JournalMode> for sqlx::sqlite::SqliteJournalMode {
+    fn from(mode: JournalMode) -> Self {
+        match mode {
+            JournalMode::Delete => Self::Delete,
+            JournalMode::Truncate => Self::Truncate,
+            JournalMode::Persist => Self::Persist,
+            JournalMode::Memory => Self::Memory,
+            JournalMode::Wal => Self::Wal,
+            JournalMode::Off => Self::Off,
+        }
+    }
+}
+
+/// SQLite `synchronous` level: how often writes are flushed to disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    #[default]
+    Normal,
+    Full,
+    Extra,
+}
+
+impl From<Synchronous> for sqlx::sqlite::SqliteSynchronous {
+    fn from(level: Synchronous) -> Self {
+        match level {
+            Synchronous::Off => Self::Off,
+            Synchronous::Normal => Self::Normal,
+            Synchronous::Full => Self::Full,
+            Synchronous::Extra => Self::Extra,
+        }
+    }
+}
+
+/// Metadata database for tracking CAS objects, datasets, and transformations
+pub struct MetadataDb {
+    pool: SqlitePool,
+}
+
+impl MetadataDb {
+    /// Create or open database at the specified path
+    ///
+    /// If the database doesn't exist, it will be created.
+    /// The schema will be initialized automatically.
+    pub async fn new(db_path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_options(db_path, &DbOptions::default()).await
+    }
+
+    /// Create or open a database with explicit connection settings
+    pub async fn with_options(db_path: impl AsRef<Path>, options: &DbOptions) -> Result<Self> {
+        let db_path = db_path.as_ref();
+
+        // Create parent directory if it doesn't exist
+        if let Some(parent) = db_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create database directory: {}", parent.display()))?;
+        }
+
+        // Configure SQLite connection
+        let connection_string = format!("sqlite:{}", db_path.display());
+        let connect_options = SqliteConnectOptions::from_str(&connection_string)?
+            .create_if_missing(true)
+            .journal_mode(options.journal_mode.into())
+            .synchronous(options.synchronous.into());
+
+        // Create connection pool
+        let pool = SqlitePoolOptions::new()
+            .max_connections(options.max_connections)
+            .connect_with(connect_options)
+            .await
+            .with_context(|| format!("Failed to connect to database: {}", db_path.display()))?;
+
+        let db = Self { pool };
+
+        // Initialize schema
+        db.initialize_schema().await?;
+
+        tracing::info!("Opened metadata database: {}", db_path.display());
+
+        Ok(db)
+    }
+
+    /// Initialize the database schema
+    async fn initialize_schema(&self) -> Result<()> {
+        // Create schema version table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Check current schema version
+        let current_version = self.get_schema_version().await?;
+
+        // A newer cast may have changed tables this build would write to
+        if current_version > CURRENT_SCHEMA_VERSION {
+            anyhow::bail!(
+                "Metadata database is at schema version {}, newer than this cast supports ({}); please upgrade cast",
+                current_version,
+                CURRENT_SCHEMA_VERSION
+            );
+        }
+
+        if current_version < 1 {
+            self.apply_migration_v1().await?;
+            self.set_schema_version(1).await?;
+        }
+
+        if current_version < 2 {
+            self.apply_migration_v2().await?;
+            self.set_schema_version(2).await?;
+        }
+
+        if current_version < 3 {
+            self.apply_migration_v3().await?;
+            self.set_schema_version(3).await?;
+        }
+
+        if current_version < 4 {
+            self.apply_migration_v4().await?;
+            self.set_schema_version(4).await?;
+        }
+
+        if current_version < 5 {
+            self.apply_migration_v5().await?;
+            self.set_schema_version(5).await?;
+        }
+
+        if current_version < 6 {
+            self.apply_migration_v6().await?;
+            self.set_schema_version(6).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get current schema version
+    async fn get_schema_version(&self) -> Result<i32> {
+        let row = sqlx::query("SELECT COALESCE(MAX(version), 0) as version FROM schema_version")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get("version"))
+    }
+
+    /// Set schema version
+    async fn set_schema_version(&self, version: i32) -> Result<()> {
+        sqlx::query("INSERT INTO schema_version (version) VALUES (?)")
+            .bind(version)
+            .execute(&self.pool)
+            .await?;
+
+        tracing::info!("Applied schema version {}", version);
+        Ok(())
+    }
+
+    /// Apply migration version 1 - initial schema
+    async fn apply_migration_v1(&self) -> Result<()> {
+        // Objects table - tracks all content-addressed objects
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS objects (
+                hash TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                refs INTEGER DEFAULT 1,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                metadata TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Datasets table - tracks registered datasets
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS datasets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                manifest_hash TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(name, version),
+                FOREIGN KEY (manifest_hash) REFERENCES objects(hash)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Transformations table - tracks transformation history
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS transformations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                input_hash TEXT NOT NULL,
+                output_hash TEXT NOT NULL,
+                transform_type TEXT NOT NULL,
+                params TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (input_hash) REFERENCES objects(hash),
+                FOREIGN KEY (output_hash) REFERENCES objects(hash)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create indexes for common queries
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_objects_refs ON objects(refs)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_datasets_name ON datasets(name)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_transformations_input ON transformations(input_hash)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_transformations_output ON transformations(output_hash)")
+            .execute(&self.pool)
+            .await?;
+
+        tracing::info!("Created database schema v1");
+        Ok(())
+    }
+
+    /// Apply migration version 2 - dataset descriptions and full-text search
+    async fn apply_migration_v2(&self) -> Result<()> {
+        sqlx::query("ALTER TABLE datasets ADD COLUMN description TEXT")
+            .execute(&self.pool)
+            .await?;
+
+        // FTS5 index mirroring datasets; the trigram tokenizer allows
+        // substring matches rather than whole-token matches only
+        sqlx::query(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS datasets_fts USING fts5(
+                name,
+                version,
+                description,
+                content='datasets',
+                content_rowid='id',
+                tokenize='trigram'
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Keep the index in sync with the datasets table
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS datasets_fts_insert AFTER INSERT ON datasets BEGIN
+                INSERT INTO datasets_fts(rowid, name, version, description)
+                VALUES (new.id, new.name, new.version, new.description);
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS datasets_fts_delete AFTER DELETE ON datasets BEGIN
+                INSERT INTO datasets_fts(datasets_fts, rowid, name, version, description)
+                VALUES ('delete', old.id, old.name, old.version, old.description);
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS datasets_fts_update AFTER UPDATE ON datasets BEGIN
+                INSERT INTO datasets_fts(datasets_fts, rowid, name, version, description)
+                VALUES ('delete', old.id, old.name, old.version, old.description);
+                INSERT INTO datasets_fts(rowid, name, version, description)
+                VALUES (new.id, new.name, new.version, new.description);
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Index any datasets registered before this migration
+        sqlx::query("INSERT INTO datasets_fts(datasets_fts) VALUES ('rebuild')")
+            .execute(&self.pool)
+            .await?;
+
+        tracing::info!("Created database schema v2");
+        Ok(())
+    }
+
+    /// Apply migration version 3 - store manifests alongside datasets
+    ///
+    /// Keeping the manifest JSON in the database lets garbage collection
+    /// determine reachability without reading objects from the store.
+    async fn apply_migration_v3(&self) -> Result<()> {
+        sqlx::query("ALTER TABLE datasets ADD COLUMN manifest TEXT")
+            .execute(&self.pool)
+            .await?;
+
+        tracing::info!("Created database schema v3");
+        Ok(())
+    }
+
+    /// Apply migration version 4 - optional object expiry
+    async fn apply_migration_v4(&self) -> Result<()> {
+        sqlx::query("ALTER TABLE objects ADD COLUMN expires_at TIMESTAMP")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_objects_expires_at ON objects(expires_at)")
+            .execute(&self.pool)
+            .await?;
+
+        tracing::info!("Created database schema v4");
+        Ok(())
+    }
+
+    /// Apply migration version 5 - dataset-to-object reference table
+    ///
+    /// Lets `datasets_referencing` answer from an index instead of parsing
+    /// every stored manifest.
+    async fn apply_migration_v5(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS object_refs (
+                dataset_id INTEGER NOT NULL,
+                object_hash TEXT NOT NULL,
+                PRIMARY KEY (dataset_id, object_hash),
+                FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_object_refs_hash ON object_refs(object_hash)")
+            .execute(&self.pool)
+            .await?;
+
+        // Index datasets registered before this migration
+        let rows: Vec<(i64, String, Option<String>)> =
+            sqlx::query_as("SELECT id, manifest_hash, manifest FROM datasets")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut tx = self.pool.begin().await?;
+        for (id, manifest_hash, manifest_json) in rows {
+            let manifest = match manifest_json {
+                Some(json) => Some(
+                    serde_json::from_str::<Manifest>(&json)
+                        .with_context(|| format!("Failed to parse stored manifest of dataset {}", id))?,
+                ),
+                None => None,
+            };
+            index_object_refs(&mut tx, id, &manifest_hash, manifest.as_ref()).await?;
+        }
+        tx.commit().await?;
+
+        tracing::info!("Created database schema v5");
+        Ok(())
+    }
+
+    /// Apply migration version 6 - object access times
+    async fn apply_migration_v6(&self) -> Result<()> {
+        sqlx::query("ALTER TABLE objects ADD COLUMN last_accessed TIMESTAMP")
+            .execute(&self.pool)
+            .await?;
+
+        tracing::info!("Created database schema v6");
+        Ok(())
+    }
+
+    // ========== Object Operations ==========
+
+    /// Register an object in the database
+    ///
+    /// If the object already exists, increment its reference count.
+    pub async fn register_object(
+        &self,
+        hash: &str,
+        size: i64,
+        metadata: Option<String>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO objects (hash, size, metadata)
+            VALUES (?, ?, ?)
+            ON CONFLICT(hash) DO UPDATE SET refs = refs + 1
+            "#,
+        )
+        .bind(hash)
+        .bind(size)
+        .bind(metadata)
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to register object: {}", hash))?;
+
+        tracing::debug!("Registered object: {}", hash);
+        Ok(())
+    }
+
+    /// Get object metadata
+    pub async fn get_object(&self, hash: &str) -> Result<Option<ObjectRecord>> {
+        let record = sqlx::query_as::<_, ObjectRecord>(
+            "SELECT hash, size, refs, created_at, last_accessed, metadata FROM objects WHERE hash = ?",
+        )
+        .bind(hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Replace an object's metadata JSON
+    pub async fn set_object_metadata(&self, hash: &str, metadata: &str) -> Result<()> {
+        sqlx::query("UPDATE objects SET metadata = ? WHERE hash = ?")
+            .bind(metadata)
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to update metadata for: {}", hash))?;
+
+        Ok(())
+    }
+
+    /// Record that an object was just read
+    pub async fn record_access(&self, hash: &str) -> Result<()> {
+        sqlx::query("UPDATE objects SET last_accessed = ? WHERE hash = ?")
+            .bind(format_timestamp(Utc::now()))
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to record access to: {}", hash))?;
+
+        Ok(())
+    }
+
+    /// Set the time after which `gc --expired` may delete an object
+    pub async fn set_object_expiry(&self, hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE objects SET expires_at = ? WHERE hash = ?")
+            .bind(format_timestamp(expires_at))
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to set expiry for: {}", hash))?;
+
+        Ok(())
+    }
+
+    /// Get all objects whose expiry is at or before `now`
+    pub async fn expired_objects(&self, now: DateTime<Utc>) -> Result<Vec<String>> {
+        let hashes = sqlx::query_scalar(
+            "SELECT hash FROM objects WHERE expires_at IS NOT NULL AND expires_at <= ? ORDER BY hash",
+        )
+        .bind(format_timestamp(now))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes)
+    }
+
+    /// Update object reference count
+    pub async fn update_refs(&self, hash: &str, delta: i32) -> Result<()> {
+        sqlx::query("UPDATE objects SET refs = refs + ? WHERE hash = ?")
+            .bind(delta)
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to update refs for: {}", hash))?;
+
+        Ok(())
+    }
+
+    /// Rebuild every object's refcount from the datasets that reference it
+    ///
+    /// The count is the number of datasets listing the object (or having it
+    /// as their manifest) in `object_refs`; objects no dataset references
+    /// drop to zero. Runs in one transaction, so a failure changes nothing.
+    pub async fn recompute_refs(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE objects SET refs = (
+                SELECT COUNT(*) FROM object_refs r
+                WHERE r.object_hash = objects.hash OR r.object_hash = 'blake3:' || objects.hash
+            )
+            "#,
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to recompute reference counts")?;
+
+        tx.commit().await?;
+
+        tracing::info!("Recomputed reference counts of {} object(s)", result.rows_affected());
+        Ok(())
+    }
+
+    /// Delete object from database
+    ///
+    /// This should only be called when refs reach 0
+    pub async fn delete_object(&self, hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM objects WHERE hash = ?")
+            .bind(hash)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to delete object: {}", hash))?;
+
+        tracing::debug!("Deleted object from database: {}", hash);
+        Ok(())
+    }
+
+    /// Delete an object's row, then run `remove` before committing
+    ///
+    /// If the delete fails (for example on a foreign key) `remove` is never
+    /// called, and if `remove` fails the row is kept.
+    pub async fn delete_object_with<F, Fut>(&self, hash: &str, remove: F) -> Result<()>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM objects WHERE hash = ?")
+            .bind(hash)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to delete object: {}", hash))?;
+        remove().await?;
+        tx.commit().await?;
+
+        tracing::debug!("Deleted object from database: {}", hash);
+        Ok(())
+    }
+
+    /// Get all objects with zero references (candidates for GC)
+    pub async fn get_unreferenced_objects(&self) -> Result<Vec<String>> {
+        let hashes = sqlx::query_scalar("SELECT hash FROM objects WHERE refs <= 0")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(hashes)
+    }
+
+    // ========== Dataset Operations ==========
+
+    /// Register a dataset
+    ///
+    /// The description is typically taken from `Manifest.dataset.description`
+    /// and is indexed for full-text search.
+    pub async fn register_dataset(
+        &self,
+        name: &str,
+        version: &str,
+        manifest_hash: &str,
+        description: Option<&str>,
+    ) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO datasets (name, version, manifest_hash, description)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(name, version) DO UPDATE SET
+                manifest_hash = excluded.manifest_hash,
+                description = excluded.description
+            RETURNING id
+            "#,
+        )
+        .bind(name)
+        .bind(version)
+        .bind(manifest_hash)
+        .bind(description)
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| format!("Failed to register dataset: {}/{}", name, version))?;
+
+        let id: i64 = result.get("id");
+
+        tracing::info!("Registered dataset: {}/{} (id: {})", name, version, id);
+        Ok(id)
+    }
+
+    /// Register a manifest, its content objects, and its dataset atomically
+    ///
+    /// In a single transaction this registers every distinct `Content`
+    /// object, the manifest object itself, and the dataset row (storing the
+    /// manifest JSON so `reachable_objects` can mark it). Any failure rolls
+    /// back everything.
+    ///
+    /// Re-registering the same manifest for a dataset version is a no-op;
+    /// registering a different manifest for an existing version is an error.
+    pub async fn register_manifest(&self, manifest: &Manifest, manifest_hash: &str) -> Result<i64> {
+        let name = &manifest.dataset.name;
+        let version = &manifest.dataset.version;
+        let manifest_json = serde_json::to_string(manifest).context("Failed to serialize manifest")?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let existing: Option<(i64, String)> =
+            sqlx::query_as("SELECT id, manifest_hash FROM datasets WHERE name = ? AND version = ?")
+                .bind(name)
+                .bind(version)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+        if let Some((id, existing_hash)) = &existing {
+            if existing_hash == manifest_hash {
+                tracing::debug!("Dataset {}/{} already registered", name, version);
+                return Ok(*id);
+            }
+        }
+
+        // A dataset holds one ref per distinct object, matching the
+        // `object_refs` rows `recompute_refs` counts
+        let mut seen = HashSet::new();
+        for content in manifest.contents.iter().filter(|c| seen.insert(c.hash.as_str())) {
+            sqlx::query(
+                r#"
+                INSERT INTO objects (hash, size)
+                VALUES (?, ?)
+                ON CONFLICT(hash) DO UPDATE SET refs = refs + 1
+                "#,
+            )
+            .bind(&content.hash)
+            .bind(content.size as i64)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to register object: {}", content.hash))?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO objects (hash, size)
+            VALUES (?, ?)
+            ON CONFLICT(hash) DO UPDATE SET refs = refs + 1
+            "#,
+        )
+        .bind(manifest_hash)
+        .bind(manifest_json.len() as i64)
+        .execute(&mut *tx)
+        .await
+        .with_context(|| format!("Failed to register manifest object: {}", manifest_hash))?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO datasets (name, version, manifest_hash, description, manifest)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+        .bind(name)
+        .bind(version)
+        .bind(manifest_hash)
+        .bind(&manifest.dataset.description)
+        .bind(&manifest_json)
+        .fetch_one(&mut *tx)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to register dataset {}/{}: already registered with a different manifest",
+                name, version
+            )
+        })?;
+
+        let id: i64 = result.get("id");
+        index_object_refs(&mut tx, id, manifest_hash, Some(manifest)).await?;
+
+        tx.commit().await?;
+
+        tracing::info!(
+            "Registered dataset: {}/{} with {} objects (id: {})",
+            name,
+            version,
+            manifest.contents.len(),
+            id
+        );
+        Ok(id)
+    }
+
+    /// Find datasets by name
+    pub async fn find_datasets_by_name(&self, name: &str) -> Result<Vec<DatasetRecord>> {
+        let records = sqlx::query_as::<_, DatasetRecord>(
+            "SELECT id, name, version, manifest_hash, description, created_at FROM datasets WHERE name = ? ORDER BY created_at DESC",
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Get dataset by name and version
+    pub async fn get_dataset(&self, name: &str, version: &str) -> Result<Option<DatasetRecord>> {
+        let record = sqlx::query_as::<_, DatasetRecord>(
+            "SELECT id, name, version, manifest_hash, description, created_at FROM datasets WHERE name = ? AND version = ?",
+        )
+        .bind(name)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Get the stored manifest of a dataset version
+    ///
+    /// Returns `None` if the dataset doesn't exist or was registered without
+    /// a manifest (see `register_dataset`).
+    pub async fn get_manifest(&self, name: &str, version: &str) -> Result<Option<Manifest>> {
+        let manifest_json: Option<Option<String>> =
+            sqlx::query_scalar("SELECT manifest FROM datasets WHERE name = ? AND version = ?")
+                .bind(name)
+                .bind(version)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        manifest_json
+            .flatten()
+            .map(|json| {
+                serde_json::from_str(&json)
+                    .with_context(|| format!("Failed to parse stored manifest for {}/{}", name, version))
+            })
+            .transpose()
+    }
+
+    /// Most recently registered version of a dataset, if any
+    ///
+    /// Recency is registration order, as for `stale_dataset_versions`.
+    pub async fn latest_version(&self, name: &str) -> Result<Option<DatasetRecord>> {
+        let record = sqlx::query_as::<_, DatasetRecord>(
+            r#"
+            SELECT id, name, version, manifest_hash, description, created_at FROM datasets
+            WHERE name = ?
+            ORDER BY created_at DESC, id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Whether any version of a dataset is registered
+    pub async fn dataset_exists(&self, name: &str) -> Result<bool> {
+        let exists = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM datasets WHERE name = ?)")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(exists)
+    }
+
+    /// Get all dataset versions
+    pub async fn get_dataset_versions(&self, name: &str) -> Result<Vec<String>> {
+        let versions = sqlx::query_scalar(
+            "SELECT version FROM datasets WHERE name = ? ORDER BY created_at DESC",
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+
+    /// Versions of a dataset beyond the `keep` most recent, newest first
+    ///
+    /// Recency is registration order (`created_at`, then id for datasets
+    /// registered within the same second).
+    pub async fn stale_dataset_versions(&self, name: &str, keep: usize) -> Result<Vec<String>> {
+        let versions = sqlx::query_scalar(
+            "SELECT version FROM datasets WHERE name = ? ORDER BY created_at DESC, id DESC LIMIT -1 OFFSET ?",
+        )
+        .bind(name)
+        .bind(keep as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+
+    /// Delete all but the `keep` most recent versions of a dataset
+    ///
+    /// The refs taken by `register_manifest` on each pruned version's
+    /// manifest object and contents are released, so `gc` can reclaim
+    /// objects no remaining dataset uses. Returns the pruned versions,
+    /// newest first.
+    pub async fn prune_dataset_versions(&self, name: &str, keep: usize) -> Result<Vec<String>> {
+        let mut tx = self.pool.begin().await?;
+
+        let stale: Vec<(i64, String, String, Option<String>)> = sqlx::query_as(
+            r#"
+            SELECT id, version, manifest_hash, manifest FROM datasets
+            WHERE name = ?
+            ORDER BY created_at DESC, id DESC
+            LIMIT -1 OFFSET ?
+            "#,
+        )
+        .bind(name)
+        .bind(keep as i64)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut pruned = Vec::with_capacity(stale.len());
+        for (id, version, manifest_hash, manifest_json) in stale {
+            let mut released = HashSet::from([manifest_hash]);
+            if let Some(json) = manifest_json {
+                let manifest: Manifest = serde_json::from_str(&json)
+                    .with_context(|| format!("Failed to parse stored manifest of {}/{}", name, version))?;
+                released.extend(manifest.contents.into_iter().map(|c| c.hash));
+            }
+
+            for hash in &released {
+                sqlx::query("UPDATE objects SET refs = refs - 1 WHERE hash = ?")
+                    .bind(hash)
+                    .execute(&mut *tx)
+                    .await
+                    .with_context(|| format!("Failed to update refs for: {}", hash))?;
+            }
+
+            sqlx::query("DELETE FROM object_refs WHERE dataset_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM datasets WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to delete dataset {}/{}", name, version))?;
+
+            pruned.push(version);
+        }
+
+        tx.commit().await?;
+
+        tracing::info!("Pruned {} version(s) of {}", pruned.len(), name);
+        Ok(pruned)
+    }
+
+    /// List datasets, optionally filtered by a glob pattern on the name
+    ///
+    /// Datasets are ordered by name and version. A `limit` of `None` returns
+    /// every row after `offset`.
+    pub async fn list_datasets(
+        &self,
+        name_pattern: Option<&str>,
+        limit: Option<i64>,
+        offset: i64,
+    ) -> Result<Vec<DatasetRecord>> {
+        let records = sqlx::query_as::<_, DatasetRecord>(
+            r#"
+            SELECT id, name, version, manifest_hash, description, created_at
+            FROM datasets
+            WHERE ?1 IS NULL OR name GLOB ?1
+            ORDER BY name, version
+            LIMIT ?2 OFFSET ?3
+            "#,
+        )
+        .bind(name_pattern)
+        .bind(limit.unwrap_or(-1))
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list datasets")?;
+
+        Ok(records)
+    }
+
+    /// Namespaces used by registered dataset names, in order
+    ///
+    /// A namespace is the part of a name before its first `/`; see
+    /// `Dataset::split_name`.
+    pub async fn list_namespaces(&self) -> Result<Vec<String>> {
+        let namespaces = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT substr(name, 1, instr(name, '/') - 1) AS namespace
+            FROM datasets
+            WHERE instr(name, '/') > 1
+            ORDER BY namespace
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list namespaces")?;
+
+        Ok(namespaces)
+    }
+
+    /// Datasets whose names are in `namespace`, ordered by name and version
+    pub async fn find_datasets_in_namespace(&self, namespace: &str) -> Result<Vec<DatasetRecord>> {
+        Dataset::check_namespace(namespace)?;
+        let records = sqlx::query_as::<_, DatasetRecord>(
+            r#"
+            SELECT id, name, version, manifest_hash, description, created_at
+            FROM datasets
+            WHERE substr(name, 1, length(?1) + 1) = ?1 || '/'
+            ORDER BY name, version
+            "#,
+        )
+        .bind(namespace)
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("Failed to list datasets in namespace {}", namespace))?;
+
+        Ok(records)
+    }
+
+    /// List objects ordered by hash
+    pub async fn list_objects(&self, limit: Option<i64>, offset: i64) -> Result<Vec<ObjectRecord>> {
+        let records = sqlx::query_as::<_, ObjectRecord>(
+            "SELECT hash, size, refs, created_at, last_accessed, metadata FROM objects ORDER BY hash LIMIT ? OFFSET ?",
+        )
+        .bind(limit.unwrap_or(-1))
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list objects")?;
+
+        Ok(records)
+    }
+
+    /// Full-text search over dataset names, versions, and descriptions
+    ///
+    /// The query is matched as a literal substring (minimum 3 characters,
+    /// a limitation of the trigram tokenizer). Results are ordered by relevance.
+    pub async fn search_datasets(&self, query: &str) -> Result<Vec<DatasetRecord>> {
+        // Quote the query so FTS5 operators in user input are treated literally
+        let fts_query = format!("\"{}\"", query.replace('"', "\"\""));
+
+        let records = sqlx::query_as::<_, DatasetRecord>(
+            r#"
+            SELECT d.id, d.name, d.version, d.manifest_hash, d.description, d.created_at
+            FROM datasets_fts
+            JOIN datasets d ON d.id = datasets_fts.rowid
+            WHERE datasets_fts MATCH ?
+            ORDER BY datasets_fts.rank
+            "#,
+        )
+        .bind(fts_query)
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("Failed to search datasets: {}", query))?;
+
+        Ok(records)
+    }
+
+    // ========== Garbage Collection ==========
+
+    /// Collect every object reachable from a registered dataset
+    ///
+    /// This is the mark phase of garbage collection: each dataset's manifest
+    /// object is reachable, as is every content object listed in the stored
+    /// manifest and its source's `archive_hash` (e.g. the raw image manifest
+    /// of an OCI import), when that is stored too. Inputs and outputs of
+    /// recorded transformations are kept too, since provenance rows
+    /// reference them. Hashes are normalized to the `blake3:` prefixed form
+    /// when they parse as BLAKE3 hashes.
+    pub async fn reachable_objects(&self) -> Result<HashSet<String>> {
+        let rows: Vec<(String, Option<String>)> =
+            sqlx::query_as("SELECT manifest_hash, manifest FROM datasets")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut reachable = HashSet::new();
+
+        for (manifest_hash, manifest_json) in rows {
+            reachable.insert(normalize_hash(&manifest_hash));
+
+            let Some(manifest_json) = manifest_json else {
+                tracing::warn!(
+                    "Dataset {} has no stored manifest; only the manifest object is marked",
+                    manifest_hash
+                );
+                continue;
+            };
+
+            let manifest: Manifest = serde_json::from_str(&manifest_json)
+                .with_context(|| format!("Failed to parse stored manifest: {}", manifest_hash))?;
+
+            for content in &manifest.contents {
+                reachable.insert(normalize_hash(&content.hash));
+            }
+            if let Some(archive_hash) = &manifest.source.archive_hash {
+                reachable.insert(normalize_hash(archive_hash));
+            }
+        }
+
+        let transformations: Vec<(String, String)> =
+            sqlx::query_as("SELECT input_hash, output_hash FROM transformations")
+                .fetch_all(&self.pool)
+                .await?;
+
+        for (input_hash, output_hash) in transformations {
+            reachable.insert(normalize_hash(&input_hash));
+            reachable.insert(normalize_hash(&output_hash));
+        }
+
+        Ok(reachable)
+    }
+
+    /// Find datasets whose manifest is, or lists, the given object
+    pub async fn datasets_referencing(&self, hash: &str) -> Result<Vec<DatasetRecord>> {
+        let records = sqlx::query_as::<_, DatasetRecord>(
+            r#"
+            SELECT d.id, d.name, d.version, d.manifest_hash, d.description, d.created_at
+            FROM datasets d
+            INNER JOIN object_refs r ON r.dataset_id = d.id
+            WHERE r.object_hash = ?
+            ORDER BY d.name, d.version
+            "#,
+        )
+        .bind(normalize_hash(hash))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    // ========== Transformation Operations ==========
+
+    /// Register a transformation
+    pub async fn register_transformation(
+        &self,
+        input_hash: &str,
+        output_hash: &str,
+        transform_type: &str,
+        params: Option<String>,
+    ) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO transformations (input_hash, output_hash, transform_type, params)
+            VALUES (?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+        .bind(input_hash)
+        .bind(output_hash)
+        .bind(transform_type)
+        .bind(params)
+        .fetch_one(&self.pool)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to register transformation: {} -> {}",
+                input_hash, output_hash
+            )
+        })?;
+
+        let id: i64 = result.get("id");
+
+        tracing::info!("Registered transformation: {} (id: {})", transform_type, id);
+        Ok(id)
+    }
+
+    /// Get every transformation with an object as its input or output
+    pub async fn transformations_referencing(&self, hash: &str) -> Result<Vec<TransformationRecord>> {
+        let records = sqlx::query_as::<_, TransformationRecord>(
+            r#"
+            SELECT id, input_hash, output_hash, transform_type, params, created_at
+            FROM transformations
+            WHERE input_hash = ? OR output_hash = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(hash)
+        .bind(hash)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Get transformation chain for an output hash
+    ///
+    /// Returns transformations ordered from original source to final output
+    pub async fn get_transformation_chain(&self, hash: &str) -> Result<Vec<TransformationRecord>> {
+        let records = sqlx::query_as::<_, TransformationRecord>(
+            r#"
+            WITH RECURSIVE chain(id, input_hash, output_hash, transform_type, params, created_at, depth) AS (
+                SELECT id, input_hash, output_hash, transform_type, params, created_at, 0
+                FROM transformations
+                WHERE output_hash = ?
+                UNION ALL
+                SELECT t.id, t.input_hash, t.output_hash, t.transform_type, t.params, t.created_at, c.depth + 1
+                FROM transformations t
+                INNER JOIN chain c ON t.output_hash = c.input_hash
+            )
+            SELECT id, input_hash, output_hash, transform_type, params, created_at
+            FROM chain
+            ORDER BY depth DESC
+            "#,
+        )
+        .bind(hash)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Render the lineage of an output hash as a Graphviz DOT graph
+    ///
+    /// Nodes are object hashes and edges are transformations, labeled by
+    /// type. Every ancestor is followed, so lineages where an output has
+    /// several inputs (or an input is reached along several paths) are drawn
+    /// with each node and edge once.
+    pub async fn lineage_dot(&self, output_hash: &str) -> Result<String> {
+        let edges = sqlx::query_as::<_, (String, String, String)>(
+            r#"
+            WITH RECURSIVE ancestry(hash) AS (
+                SELECT ?
+                UNION
+                SELECT t.input_hash
+                FROM transformations t
+                INNER JOIN ancestry a ON t.output_hash = a.hash
+            )
+            SELECT DISTINCT t.input_hash, t.output_hash, t.transform_type
+            FROM transformations t
+            WHERE t.output_hash IN (SELECT hash FROM ancestry)
+            ORDER BY t.input_hash, t.output_hash, t.transform_type
+            "#,
+        )
+        .bind(output_hash)
+        .fetch_all(&self.pool)
+        .await
+        .with_context(|| format!("Failed to query lineage of: {}", output_hash))?;
+
+        let mut nodes = std::collections::BTreeSet::new();
+        nodes.insert(output_hash.to_string());
+        for (input, output, _) in &edges {
+            nodes.insert(input.clone());
+            nodes.insert(output.clone());
+        }
+
+        let mut dot = String::from("digraph lineage {\n    rankdir=LR;\n    node [shape=box, fontname=monospace];\n");
+        for node in &nodes {
+            let label: String = node.strip_prefix("blake3:").unwrap_or(node).chars().take(12).collect();
+            dot.push_str(&format!("    \"{}\" [label=\"{}\"];\n", dot_escape(node), dot_escape(&label)));
+        }
+        for (input, output, transform_type) in &edges {
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                dot_escape(input),
+                dot_escape(output),
+                dot_escape(transform_type)
+            ));
+        }
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    /// Delete transformation records whose input or output object is gone
+    ///
+    /// Such rows are left behind by databases written without foreign key
+    /// enforcement, or by imports. Returns the number of rows removed.
+    pub async fn prune_orphan_transformations(&self) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM transformations
+            WHERE input_hash NOT IN (SELECT hash FROM objects)
+               OR output_hash NOT IN (SELECT hash FROM objects)
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to prune orphaned transformations")?;
+
+        let pruned = result.rows_affected();
+        if pruned > 0 {
+            tracing::info!("Pruned {} orphaned transformation record(s)", pruned);
+        }
+        Ok(pruned)
+    }
+
+    /// Get every transformation of one type, oldest first
+    pub async fn transformations_by_type(&self, transform_type: &str) -> Result<Vec<TransformationRecord>> {
+        let records = sqlx::query_as::<_, TransformationRecord>(
+            r#"
+            SELECT id, input_hash, output_hash, transform_type, params, created_at
+            FROM transformations
+            WHERE transform_type = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(transform_type)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Count transformations per type, most frequent first
+    pub async fn transform_type_counts(&self) -> Result<Vec<(String, i64)>> {
+        let counts = sqlx::query_as::<_, (String, i64)>(
+            r#"
+            SELECT transform_type, COUNT(*) AS count
+            FROM transformations
+            GROUP BY transform_type
+            ORDER BY count DESC, transform_type
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// Find cached transformation result
+    ///
+    /// The newest matching record wins; records created within the same
+    /// second are ordered by id.
+    pub async fn find_cached_transformation(
+        &self,
+        input_hash: &str,
+        transform_type: &str,
+        params: Option<&str>,
+    ) -> Result<Option<String>> {
+        let output_hash = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT output_hash FROM transformations
+            WHERE input_hash = ? AND transform_type = ? AND params IS ?
+            ORDER BY created_at DESC, id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(input_hash)
+        .bind(transform_type)
+        .bind(params)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(output_hash)
+    }
+
+    // ========== Transaction Support ==========
+
+    /// Begin a transaction
+    pub async fn begin_transaction(&self) -> Result<sqlx::Transaction<'_, sqlx::Sqlite>> {
+        let tx = self.pool.begin().await?;
+        Ok(tx)
+    }
+
+    /// Execute multiple operations in a transaction
+    pub async fn with_transaction<F, T>(&self, f: F) -> Result<T>
+    where
+        F: for<'c> FnOnce(&'c mut SqliteConnection) -> futures::future::BoxFuture<'c, Result<T>> + Send,
+        T: Send,
+    {
+        let mut tx = self.pool.begin().await?;
+        let result = f(&mut tx).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    // ========== Import/Export ==========
+
+    /// Export all objects, datasets, and transformations as a JSON document
+    ///
+    /// Timestamps are kept as the strings stored by SQLite. Datasets carry
+    /// their stored manifest and `object_refs` entries, so an imported
+    /// database marks the same objects reachable. The document is tagged
+    /// with `EXPORT_FORMAT_VERSION` so importers can reject dumps they
+    /// don't understand.
+    pub async fn export_json(&self) -> Result<serde_json::Value> {
+        let objects = sqlx::query_as::<_, ExportedObject>(
+            "SELECT hash, size, refs, created_at, last_accessed, metadata, expires_at FROM objects ORDER BY hash",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut datasets = sqlx::query_as::<_, ExportedDataset>(
+            "SELECT id, name, version, manifest_hash, description, created_at, manifest FROM datasets ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for dataset in &mut datasets {
+            dataset.object_refs =
+                sqlx::query_scalar("SELECT object_hash FROM object_refs WHERE dataset_id = ? ORDER BY object_hash")
+                    .bind(dataset.record.id)
+                    .fetch_all(&self.pool)
+                    .await?;
+        }
+
+        let transformations = sqlx::query_as::<_, TransformationRecord>(
+            "SELECT id, input_hash, output_hash, transform_type, params, created_at FROM transformations ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let schema_version = self.get_schema_version().await?;
+
+        tracing::info!(
+            "Exported {} objects, {} datasets, {} transformations",
+            objects.len(),
+            datasets.len(),
+            transformations.len()
+        );
+
+        Ok(serde_json::json!({
+            "format_version": EXPORT_FORMAT_VERSION,
+            "schema_version": schema_version,
+            "objects": objects,
+            "datasets": datasets,
+            "transformations": transformations,
+        }))
+    }
+
+    /// Import a JSON document produced by `export_json`
+    ///
+    /// All rows are inserted inside a single transaction. With
+    /// `ImportMode::Merge`, existing objects keep the higher of the two
+    /// refcounts and existing datasets/transformations are skipped. With
+    /// `ImportMode::Replace`, all tables are cleared first. Dumps taken from
+    /// a database with a newer schema than `CURRENT_SCHEMA_VERSION` are
+    /// rejected.
+    pub async fn import_json(&self, doc: &serde_json::Value, mode: ImportMode) -> Result<ImportReport> {
+        let format_version = doc
+            .get("format_version")
+            .and_then(|v| v.as_i64())
+            .context("Metadata dump is missing format_version")?;
+
+        if !(1..=EXPORT_FORMAT_VERSION).contains(&format_version) {
+            anyhow::bail!(
+                "Unsupported metadata dump format version: {} (expected 1 to {})",
+                format_version,
+                EXPORT_FORMAT_VERSION
+            );
+        }
+        if format_version < 2 {
+            tracing::warn!(
+                "Metadata dump format {} has no stored manifests; gc will only keep the manifest objects of imported datasets",
+                format_version
+            );
+        }
+
+        // Schema version of the database the dump was taken from; columns
+        // added by later migrations are only read from dumps that have them
+        let schema_version = doc
+            .get("schema_version")
+            .and_then(|v| v.as_i64())
+            .context("Metadata dump is missing schema_version")?;
+
+        if schema_version > CURRENT_SCHEMA_VERSION as i64 {
+            anyhow::bail!(
+                "Metadata dump has schema version {}, newer than this build supports ({})",
+                schema_version,
+                CURRENT_SCHEMA_VERSION
+            );
+        }
+
+        let objects: Vec<ExportedObject> = serde_json::from_value(doc["objects"].clone())
+            .context("Failed to parse objects from metadata dump")?;
+        let datasets: Vec<ExportedDataset> = serde_json::from_value(doc["datasets"].clone())
+            .context("Failed to parse datasets from metadata dump")?;
+        let transformations: Vec<TransformationRecord> =
+            serde_json::from_value(doc["transformations"].clone())
+                .context("Failed to parse transformations from metadata dump")?;
+
+        let mut report = ImportReport::default();
+        let mut tx = self.pool.begin().await?;
+
+        if mode == ImportMode::Replace {
+            sqlx::query("DELETE FROM transformations").execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM object_refs").execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM datasets").execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM objects").execute(&mut *tx).await?;
+        }
+
+        for ExportedObject { record: object, expires_at } in &objects {
+            // Expiry arrived with schema v4, access times with v6
+            let expires_at = if schema_version >= 4 { expires_at.as_deref() } else { None };
+            let last_accessed = if schema_version >= 6 { object.last_accessed.as_deref() } else { None };
+            let existing: Option<i32> = sqlx::query_scalar("SELECT refs FROM objects WHERE hash = ?")
+                .bind(&object.hash)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            match existing {
+                Some(refs) => {
+                    if object.refs > refs {
+                        sqlx::query("UPDATE objects SET refs = ? WHERE hash = ?")
+                            .bind(object.refs)
+                            .bind(&object.hash)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                    report.objects_skipped += 1;
+                }
+                None => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO objects (hash, size, refs, created_at, last_accessed, metadata, expires_at)
+                        VALUES (?, ?, ?, ?, ?, ?, ?)
+                        "#,
+                    )
+                    .bind(&object.hash)
+                    .bind(object.size)
+                    .bind(object.refs)
+                    .bind(&object.created_at)
+                    .bind(last_accessed)
+                    .bind(&object.metadata)
+                    .bind(expires_at)
+                    .execute(&mut *tx)
+                    .await
+                    .with_context(|| format!("Failed to import object: {}", object.hash))?;
+                    report.objects_inserted += 1;
+                }
+            }
+        }
+
+        for ExportedDataset { record: dataset, manifest, object_refs } in &datasets {
+            // Descriptions arrived with schema v2, stored manifests with v3
+            let description = if schema_version >= 2 { dataset.description.as_deref() } else { None };
+            let manifest_json = if schema_version >= 3 { manifest.as_deref() } else { None };
+            let inserted: Option<i64> = sqlx::query_scalar(
+                r#"
+                INSERT INTO datasets (name, version, manifest_hash, description, created_at, manifest)
+                VALUES (?, ?, ?, ?, ?, ?)
+                ON CONFLICT(name, version) DO NOTHING
+                RETURNING id
+                "#,
+            )
+            .bind(&dataset.name)
+            .bind(&dataset.version)
+            .bind(&dataset.manifest_hash)
+            .bind(description)
+            .bind(&dataset.created_at)
+            .bind(manifest_json)
+            .fetch_optional(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to import dataset: {}/{}", dataset.name, dataset.version))?;
+
+            let Some(id) = inserted else {
+                report.datasets_skipped += 1;
+                continue;
+            };
+
+            // object_refs arrived with schema v5; older dumps are indexed
+            // from the manifest the same way migration v5 does
+            if schema_version >= 5 {
+                for hash in object_refs {
+                    sqlx::query("INSERT OR IGNORE INTO object_refs (dataset_id, object_hash) VALUES (?, ?)")
+                        .bind(id)
+                        .bind(normalize_hash(hash))
+                        .execute(&mut *tx)
+                        .await
+                        .with_context(|| format!("Failed to import reference from dataset {} to {}", id, hash))?;
+                }
+            }
+            let manifest = manifest_json
+                .map(serde_json::from_str::<Manifest>)
+                .transpose()
+                .with_context(|| format!("Failed to parse manifest of {}/{}", dataset.name, dataset.version))?;
+            index_object_refs(&mut tx, id, &dataset.manifest_hash, manifest.as_ref()).await?;
+            report.datasets_inserted += 1;
+        }
+
+        for transformation in &transformations {
+            let exists: bool = sqlx::query_scalar(
+                r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM transformations
+                    WHERE input_hash = ? AND output_hash = ? AND transform_type = ? AND params IS ?
+                )
+                "#,
+            )
+            .bind(&transformation.input_hash)
+            .bind(&transformation.output_hash)
+            .bind(&transformation.transform_type)
+            .bind(&transformation.params)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if exists {
+                report.transformations_skipped += 1;
+                continue;
+            }
+
+            sqlx::query(
+                r#"
+                INSERT INTO transformations (input_hash, output_hash, transform_type, params, created_at)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&transformation.input_hash)
+            .bind(&transformation.output_hash)
+            .bind(&transformation.transform_type)
+            .bind(&transformation.params)
+            .bind(&transformation.created_at)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to import transformation: {} -> {}",
+                    transformation.input_hash, transformation.output_hash
+                )
+            })?;
+            report.transformations_inserted += 1;
+        }
+
+        tx.commit().await?;
+        self.checkpoint_opportunistic().await;
+
+        tracing::info!(
+            "Imported {} objects, {} datasets, {} transformations ({} skipped)",
+            report.objects_inserted,
+            report.datasets_inserted,
+            report.transformations_inserted,
+            report.objects_skipped + report.datasets_skipped + report.transformations_skipped
+        );
+
+        Ok(report)
+    }
+
+    /// Write a consistent snapshot of the database to `dest`
+    ///
+    /// Uses `VACUUM INTO`, which reads from a single transaction and is
+    /// therefore safe while other connections are writing (unlike copying
+    /// `meta.db` alongside an active WAL). The destination must not exist.
+    pub async fn backup_to(&self, dest: &Path) -> Result<()> {
+        if dest.exists() {
+            anyhow::bail!("Backup destination already exists: {}", dest.display());
+        }
+
+        if let Some(parent) = dest.parent() {
+            if !parent.as_os_str().is_empty() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .with_context(|| format!("Failed to create backup directory: {}", parent.display()))?;
+            }
+        }
+
+        let dest_str = dest
+            .to_str()
+            .with_context(|| format!("Backup path is not valid UTF-8: {}", dest.display()))?;
+
+        sqlx::query("VACUUM INTO ?")
+            .bind(dest_str)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to back up database to: {}", dest.display()))?;
+
+        tracing::info!("Backed up metadata database to {}", dest.display());
+        Ok(())
+    }
+
+    /// Checkpoint the write-ahead log into the main database file
+    ///
+    /// `Truncate` also empties the `-wal` file, which otherwise keeps its
+    /// high-water size in long-lived processes.
+    pub async fn checkpoint(&self, mode: CheckpointMode) -> Result<()> {
+        let row = sqlx::query(&format!("PRAGMA wal_checkpoint({})", mode.as_sql()))
+            .fetch_one(&self.pool)
+            .await
+            .with_context(|| format!("Failed to checkpoint database ({})", mode))?;
+
+        let busy: i64 = row.get(0);
+        let log_frames: i64 = row.get(1);
+        let checkpointed: i64 = row.get(2);
+        if busy != 0 && mode != CheckpointMode::Passive {
+            tracing::warn!("Database checkpoint ({}) was blocked by another connection", mode);
+        }
+        tracing::debug!(
+            "Checkpointed {} of {} WAL frames ({})",
+            checkpointed,
+            log_frames,
+            mode
+        );
+        Ok(())
+    }
+
+    /// Checkpoint after a large batch of writes, logging rather than
+    /// returning any failure
+    pub async fn checkpoint_opportunistic(&self) {
+        if let Err(e) = self.checkpoint(CheckpointMode::Passive).await {
+            tracing::warn!("{:#}", e);
+        }
+    }
+
+    /// Run SQLite's `integrity_check`, returning the problems it reports
+    ///
+    /// An empty list means the database is intact.
+    pub async fn integrity_check(&self) -> Result<Vec<String>> {
+        let rows: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to run database integrity check")?;
+
+        Ok(rows.into_iter().filter(|row| row != "ok").collect())
+    }
+
+    /// Get database statistics
+    pub async fn get_stats(&self) -> Result<DatabaseStats> {
+        let objects_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM objects")
+                .fetch_one(&self.pool)
+                .await?;
+
+        let datasets_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM datasets")
+                .fetch_one(&self.pool)
+                .await?;
+
+        let transformations_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM transformations")
+                .fetch_one(&self.pool)
+                .await?;
+
+        let total_size: i64 =
+            sqlx::query_scalar("SELECT COALESCE(SUM(size), 0) FROM objects")
+                .fetch_one(&self.pool)
+                .await?;
+
+        let logical_size: i64 =
+            sqlx::query_scalar("SELECT COALESCE(SUM(size * MAX(refs, 1)), 0) FROM objects")
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(DatabaseStats {
+            objects_count,
+            datasets_count,
+            transformations_count,
+            total_size,
+            logical_size,
+        })
+    }
+
+    /// Measure how much space content addressing saves
+    ///
+    /// Logical bytes are what storing each reference independently would take
+    /// (size × refcount); physical bytes store each referenced object once.
+    /// Unreferenced objects are left out of both, as `gc` would reclaim them.
+    pub async fn dedup_report(&self) -> Result<DedupReport> {
+        let (objects, logical_bytes, physical_bytes): (i64, i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COALESCE(SUM(size * refs), 0), COALESCE(SUM(size), 0) FROM objects WHERE refs > 0",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(DedupReport {
+            objects,
+            logical_bytes,
+            physical_bytes,
+            saved_bytes: logical_bytes - physical_bytes,
+        })
+    }
+
+    /// Get the largest objects, biggest first
+    pub async fn objects_by_size(&self, limit: i64) -> Result<Vec<ObjectRecord>> {
+        let records = sqlx::query_as::<_, ObjectRecord>(
+            "SELECT hash, size, refs, created_at, last_accessed, metadata FROM objects ORDER BY size DESC, hash LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to query largest objects")?;
+
+        Ok(records)
+    }
+}
+
+/// Normalize a hash string to its `blake3:` prefixed form if it parses
+fn normalize_hash(hash: &str) -> String {
+    Blake3Hash::from_str(hash)
+        .map(|h| h.to_string_prefixed())
+        .unwrap_or_else(|_| hash.to_string())
+}
+
+/// Record which objects a dataset references in `object_refs`
+///
+/// Covers the manifest object itself and, when given, every content entry.
+/// Hashes are stored in canonical `blake3:` form.
+async fn index_object_refs(
+    conn: &mut SqliteConnection,
+    dataset_id: i64,
+    manifest_hash: &str,
+    manifest: Option<&Manifest>,
+) -> Result<()> {
+    let contents = manifest.into_iter().flat_map(|m| m.contents.iter().map(|c| c.hash.as_str()));
+    for hash in std::iter::once(manifest_hash).chain(contents) {
+        sqlx::query("INSERT OR IGNORE INTO object_refs (dataset_id, object_hash) VALUES (?, ?)")
+            .bind(dataset_id)
+            .bind(normalize_hash(hash))
+            .execute(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to index reference from dataset {} to {}", dataset_id, hash))?;
+    }
+    Ok(())
+}
+
+// ========== Record Types ==========
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ObjectRecord {
+    pub hash: String,
+    pub size: i64,
+    pub refs: i32,
+    pub created_at: String,
+    /// When the object was last read, if ever
+    #[serde(default)]
+    pub last_accessed: Option<String>,
+    pub metadata: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DatasetRecord {
+    pub id: i64,
+    pub name: String,
+    pub version: String,
+    pub manifest_hash: String,
+    pub description: Option<String>,
+    pub created_at: String,
+}
+
+/// Object row as written by `export_json`
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct ExportedObject {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    record: ObjectRecord,
+    #[serde(default)]
+    expires_at: Option<String>,
+}
+
+/// Dataset row as written by `export_json`, with its stored manifest and
+/// the objects it references
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct ExportedDataset {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    record: DatasetRecord,
+    #[serde(default)]
+    manifest: Option<String>,
+    #[serde(default)]
+    #[sqlx(skip)]
+    object_refs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TransformationRecord {
+    pub id: i64,
+    pub input_hash: String,
+    pub output_hash: String,
+    pub transform_type: String,
+    pub params: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DatabaseStats {
+    pub objects_count: i64,
+    pub datasets_count: i64,
+    pub transformations_count: i64,
+    /// Sum of object sizes, counting each object once
+    pub total_size: i64,
+    /// Sum of object sizes weighted by refcount (size before deduplication)
+    pub logical_size: i64,
+}
+
+/// Space saved by deduplication, as computed by `MetadataDb::dedup_report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct DedupReport {
+    /// Referenced objects
+    pub objects: i64,
+    /// Bytes needed if every reference were stored separately
+    pub logical_bytes: i64,
+    /// Bytes needed to store each referenced object once
+    pub physical_bytes: i64,
+    /// `logical_bytes - physical_bytes`
+    pub saved_bytes: i64,
+}
+
+impl DedupReport {
+    /// Logical bytes per physical byte (1.0 for an empty store)
+    pub fn ratio(&self) -> f64 {
+        if self.physical_bytes == 0 {
+            1.0
+        } else {
+            self.logical_bytes as f64 / self.physical_bytes as f64
+        }
+    }
+}
+
+/// How `MetadataDb::import_json` treats existing rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Keep existing rows and add missing ones
+    Merge,
+    /// Clear all tables before importing
+    Replace,
+}
+
+/// How `MetadataDb::checkpoint` treats readers and writers of the WAL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointMode {
+    /// Checkpoint as much as possible without waiting on other connections
+    Passive,
+    /// Wait for writers, then checkpoint the whole log
+    Full,
+    /// Like `Full`, then truncate the log file to zero bytes
+    Truncate,
+}
+
+impl CheckpointMode {
+    fn as_sql(self) -> &'static str {
+        match self {
+            CheckpointMode::Passive => "PASSIVE",
+            CheckpointMode::Full => "FULL",
+            CheckpointMode::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+impl FromStr for CheckpointMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "passive" => Ok(CheckpointMode::Passive),
+            "full" => Ok(CheckpointMode::Full),
+            "truncate" => Ok(CheckpointMode::Truncate),
+            other => anyhow::bail!("Unknown checkpoint mode '{}' (supported: passive, full, truncate)", other),
+        }
+    }
+}
+
+impl std::fmt::Display for CheckpointMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.as_sql().to_ascii_lowercase())
+    }
+}
+
+/// Counts of rows inserted and skipped by `MetadataDb::import_json`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub objects_inserted: u64,
+    pub objects_skipped: u64,
+    pub datasets_inserted: u64,
+    pub datasets_skipped: u64,
+    pub transformations_inserted: u64,
+    pub transformations_skipped: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Every test runs against a single-connection pool, so an operation
+    /// that holds one connection while waiting on another fails here rather
+    /// than only under load
+    async fn create_test_db() -> (MetadataDb, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let options = DbOptions {
+            max_connections: 1,
+            ..Default::default()
+        };
+        let db = MetadataDb::with_options(&db_path, &options).await.unwrap();
+        (db, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_journal_and_synchronous_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = DbOptions {
+            max_connections: 2,
+            journal_mode: JournalMode::Delete,
+            synchronous: Synchronous::Full,
+        };
+        let db = MetadataDb::with_options(temp_dir.path().join("test.db"), &options)
+            .await
+            .unwrap();
+
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(journal_mode, "delete");
+        let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(synchronous, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_newer_schema_version() {
+        let (db, temp) = create_test_db().await;
+        assert_eq!(db.get_schema_version().await.unwrap(), CURRENT_SCHEMA_VERSION);
+
+        db.set_schema_version(CURRENT_SCHEMA_VERSION + 1).await.unwrap();
+        db.pool.close().await;
+
+        let err = match MetadataDb::new(temp.path().join("test.db")).await {
+            Ok(_) => panic!("opened a database from a newer cast"),
+            Err(e) => e.to_string(),
+        };
+        assert!(err.contains("please upgrade"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_db_creation() {
+        let (db, _temp) = create_test_db().await;
+        let stats = db.get_stats().await.unwrap();
+        assert_eq!(stats.objects_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_register_object() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, Some("test metadata".to_string()))
+            .await
+            .unwrap();
+
+        let obj = db.get_object("hash1").await.unwrap().unwrap();
+        assert_eq!(obj.hash, "hash1");
+        assert_eq!(obj.size, 1000);
+        assert_eq!(obj.refs, 1);
+        assert!(obj.last_accessed.is_none());
+
+        db.record_access("hash1").await.unwrap();
+        let obj = db.get_object("hash1").await.unwrap().unwrap();
+        assert!(obj.last_accessed.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_object_ref_counting() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, None).await.unwrap();
+        db.register_object("hash1", 1000, None).await.unwrap(); // Duplicate
+
+        let obj = db.get_object("hash1").await.unwrap().unwrap();
+        assert_eq!(obj.refs, 2);
+
+        db.update_refs("hash1", -1).await.unwrap();
+        let obj = db.get_object("hash1").await.unwrap().unwrap();
+        assert_eq!(obj.refs, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_object() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, None).await.unwrap();
+        assert!(db.get_object("hash1").await.unwrap().is_some());
+
+        db.delete_object("hash1").await.unwrap();
+        assert!(db.get_object("hash1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_object_with() {
+        let (db, _temp) = create_test_db().await;
+        db.register_object("input_hash", 100, None).await.unwrap();
+        db.register_object("output_hash", 200, None).await.unwrap();
+        db.register_object("loose_hash", 300, None).await.unwrap();
+        db.register_transformation("input_hash", "output_hash", "extract", None)
+            .await
+            .unwrap();
+
+        // A failing removal keeps the row
+        let result = db.delete_object_with("loose_hash", || async { anyhow::bail!("disk error") }).await;
+        assert!(result.is_err());
+        assert!(db.get_object("loose_hash").await.unwrap().is_some());
+
+        // A row the database refuses to delete never reaches the removal
+        let mut called = false;
+        let result = db
+            .delete_object_with("input_hash", || {
+                called = true;
+                async { Ok(()) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(!called);
+        assert_eq!(db.transformations_referencing("input_hash").await.unwrap().len(), 1);
+
+        db.delete_object_with("loose_hash", || async { Ok(()) }).await.unwrap();
+        assert!(db.get_object("loose_hash").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unreferenced_objects() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, None).await.unwrap();
+        db.register_object("hash2", 2000, None).await.unwrap();
+
+        db.update_refs("hash1", -1).await.unwrap(); // refs = 0
+
+        let unreferenced = db.get_unreferenced_objects().await.unwrap();
+        assert_eq!(unreferenced.len(), 1);
+        assert_eq!(unreferenced[0], "hash1");
+    }
+
+    #[tokio::test]
+    async fn test_expired_objects() {
+        let (db, _temp) = create_test_db().await;
+        let now = Utc::now();
+
+        db.register_object("past", 1, None).await.unwrap();
+        db.register_object("future", 1, None).await.unwrap();
+        db.register_object("forever", 1, None).await.unwrap();
+        db.set_object_expiry("past", now - chrono::Duration::hours(1)).await.unwrap();
+        db.set_object_expiry("future", now + chrono::Duration::hours(1)).await.unwrap();
+
+        assert_eq!(db.expired_objects(now).await.unwrap(), vec!["past".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_register_dataset() {
+        let (db, _temp) = create_test_db().await;
+
+        // Register object first (foreign key constraint)
+        db.register_object("manifest_hash", 100, None)
+            .await
+            .unwrap();
+
+        let id = db
+            .register_dataset("test-dataset", "1.0.0", "manifest_hash", None)
+            .await
+            .unwrap();
+        assert!(id > 0);
+
+        let dataset = db
+            .get_dataset("test-dataset", "1.0.0")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dataset.name, "test-dataset");
+        assert_eq!(dataset.version, "1.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_find_datasets_by_name() {
+        let (db, _temp) = create_test_db().await;
+
+        // Register objects first
+        db.register_object("hash1", 100, None).await.unwrap();
+        db.register_object("hash2", 200, None).await.unwrap();
+
+        db.register_dataset("test", "1.0.0", "hash1", None)
+            .await
+            .unwrap();
+        db.register_dataset("test", "2.0.0", "hash2", None)
+            .await
+            .unwrap();
+
+        let datasets = db.find_datasets_by_name("test").await.unwrap();
+        assert_eq!(datasets.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_dataset_versions() {
+        let (db, _temp) = create_test_db().await;
+
+        // Register objects first
+        db.register_object("hash1", 100, None).await.unwrap();
+        db.register_object("hash2", 200, None).await.unwrap();
+
+        db.register_dataset("test", "1.0.0", "hash1", None)
+            .await
+            .unwrap();
+        db.register_dataset("test", "2.0.0", "hash2", None)
+            .await
+            .unwrap();
+
+        let versions = db.get_dataset_versions("test").await.unwrap();
+        assert_eq!(versions.len(), 2);
+        assert!(versions.contains(&"1.0.0".to_string()));
+        assert!(versions.contains(&"2.0.0".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_prune_dataset_versions() {
+        let (db, _temp) = create_test_db().await;
+
+        let mut manifests = Vec::new();
+        for i in 1..=5 {
+            let version = format!("{}.0.0", i);
+            let data = format!("version {}", i);
+            let manifest = test_manifest("pruned", &version, &[("data.txt", data.as_bytes())]);
+            db.register_manifest(&manifest, &format!("manifest{}", i)).await.unwrap();
+            manifests.push(manifest);
+        }
+
+        assert_eq!(
+            db.stale_dataset_versions("pruned", 2).await.unwrap(),
+            vec!["3.0.0", "2.0.0", "1.0.0"]
+        );
+
+        let pruned = db.prune_dataset_versions("pruned", 2).await.unwrap();
+        assert_eq!(pruned, vec!["3.0.0", "2.0.0", "1.0.0"]);
+        assert_eq!(db.get_dataset_versions("pruned").await.unwrap().len(), 2);
+        assert!(db.get_dataset("pruned", "5.0.0").await.unwrap().is_some());
+        assert!(db.get_dataset("pruned", "4.0.0").await.unwrap().is_some());
+
+        // The pruned versions' objects are released for gc
+        assert_eq!(db.get_object("manifest1").await.unwrap().unwrap().refs, 0);
+        let content = &manifests[0].contents[0].hash;
+        assert_eq!(db.get_object(content).await.unwrap().unwrap().refs, 0);
+        assert!(db.datasets_referencing(content).await.unwrap().is_empty());
+        assert_eq!(db.get_object("manifest5").await.unwrap().unwrap().refs, 1);
+
+        // Nothing left to prune
+        assert!(db.prune_dataset_versions("pruned", 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_hash_counts_once() {
+        let (db, _temp) = create_test_db().await;
+
+        let manifest = test_manifest("dups", "1", &[("a.txt", b"same"), ("b.txt", b"same")]);
+        db.register_manifest(&manifest, "manifest1").await.unwrap();
+        let content = &manifest.contents[0].hash;
+        assert_eq!(db.get_object(content).await.unwrap().unwrap().refs, 1);
+
+        db.register_manifest(&test_manifest("dups", "2", &[("c.txt", b"other")]), "manifest2")
+            .await
+            .unwrap();
+        db.prune_dataset_versions("dups", 1).await.unwrap();
+        assert_eq!(db.get_object(content).await.unwrap().unwrap().refs, 0);
+    }
+
+    #[tokio::test]
+    async fn test_search_datasets() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 100, None).await.unwrap();
+        db.register_object("hash2", 200, None).await.unwrap();
+
+        db.register_dataset("uniprot", "2024.01", "hash1", Some("UniProt protein sequences"))
+            .await
+            .unwrap();
+        db.register_dataset("ncbi-nr", "2024.01", "hash2", Some("NCBI non-redundant database"))
+            .await
+            .unwrap();
+
+        let results = db.search_datasets("redundant").await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "ncbi-nr");
+
+        // Substring of a name also matches
+        let results = db.search_datasets("nipro").await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "uniprot");
+
+        let results = db.search_datasets("no such dataset").await.unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_datasets_filter_and_paginate() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 100, None).await.unwrap();
+        for (name, version) in [("uniprot", "2024.01"), ("uniprot", "2024.02"), ("pdb", "1")] {
+            db.register_dataset(name, version, "hash1", None).await.unwrap();
+        }
+
+        let all = db.list_datasets(None, None, 0).await.unwrap();
+        let names: Vec<_> = all.iter().map(|d| format!("{}/{}", d.name, d.version)).collect();
+        assert_eq!(names, vec!["pdb/1", "uniprot/2024.01", "uniprot/2024.02"]);
+
+        let uniprot = db.list_datasets(Some("uni*"), None, 0).await.unwrap();
+        assert_eq!(uniprot.len(), 2);
+
+        let page = db.list_datasets(None, Some(1), 1).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].version, "2024.01");
+
+        let objects = db.list_objects(Some(10), 0).await.unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].size, 100);
+    }
+
+    #[tokio::test]
+    async fn test_namespaced_datasets() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 100, None).await.unwrap();
+        db.register_object("hash2", 100, None).await.unwrap();
+        db.register_dataset("team-a/ds", "1.0.0", "hash1", None).await.unwrap();
+        db.register_dataset("team-b/ds", "1.0.0", "hash2", None).await.unwrap();
+        db.register_dataset("plain", "1", "hash1", None).await.unwrap();
+
+        let a = db.get_dataset("team-a/ds", "1.0.0").await.unwrap().unwrap();
+        let b = db.get_dataset("team-b/ds", "1.0.0").await.unwrap().unwrap();
+        assert_ne!(a.id, b.id);
+        assert_eq!((a.manifest_hash.as_str(), b.manifest_hash.as_str()), ("hash1", "hash2"));
+
+        assert_eq!(db.list_namespaces().await.unwrap(), ["team-a", "team-b"]);
+        let in_a = db.find_datasets_in_namespace("team-a").await.unwrap();
+        assert_eq!(in_a.len(), 1);
+        assert_eq!(in_a[0].name, "team-a/ds");
+        assert!(db.find_datasets_in_namespace("team").await.unwrap().is_empty());
+        assert!(db.find_datasets_in_namespace("bad space").await.is_err());
+
+        db.register_dataset("legacy name", "1", "hash1", None).await.unwrap();
+        assert!(db.get_dataset("legacy name", "1").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_register_transformation() {
+        let (db, _temp) = create_test_db().await;
+
+        // Register objects first
+        db.register_object("input_hash", 100, None).await.unwrap();
+        db.register_object("output_hash", 200, None).await.unwrap();
+
+        let id = db
+            .register_transformation("input_hash", "output_hash", "extract", Some("{}".to_string()))
+            .await
+            .unwrap();
+        assert!(id > 0);
+    }
+
+    #[tokio::test]
+    async fn test_lineage_dot() {
+        let (db, _temp) = create_test_db().await;
+        for hash in ["blake3:raw", "blake3:extracted", "blake3:filtered"] {
+            db.register_object(hash, 1, None).await.unwrap();
+        }
+        db.register_transformation("blake3:raw", "blake3:extracted", "extract", None)
+            .await
+            .unwrap();
+        db.register_transformation("blake3:extracted", "blake3:filtered", "filter", None)
+            .await
+            .unwrap();
+
+        let dot = db.lineage_dot("blake3:filtered").await.unwrap();
+        assert!(dot.starts_with("digraph lineage {"));
+        assert_eq!(dot.matches(" -> ").count(), 2);
+        assert_eq!(dot.matches("[label=").count(), 5); // three nodes, two edges
+        assert!(dot.contains(r#""blake3:raw" -> "blake3:extracted" [label="extract"];"#));
+        assert!(dot.contains(r#""blake3:extracted" -> "blake3:filtered" [label="filter"];"#));
+    }
+
+    #[tokio::test]
+    async fn test_lineage_dot_diamond() {
+        let (db, _temp) = create_test_db().await;
+        for hash in ["root", "left", "right", "merged"] {
+            db.register_object(hash, 1, None).await.unwrap();
+        }
+        db.register_transformation("root", "left", "split", None).await.unwrap();
+        db.register_transformation("root", "right", "split", None).await.unwrap();
+        db.register_transformation("left", "merged", "merge", None).await.unwrap();
+        db.register_transformation("right", "merged", "merge", None).await.unwrap();
+        // Recording the same step twice must not draw a second edge
+        db.register_transformation("left", "merged", "merge", None).await.unwrap();
+
+        let dot = db.lineage_dot("merged").await.unwrap();
+        assert_eq!(dot.matches(" -> ").count(), 4);
+        assert_eq!(dot.matches("\"root\" [label").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prune_orphan_transformations() {
+        let (db, _temp) = create_test_db().await;
+        for hash in ["input", "output", "other"] {
+            db.register_object(hash, 1, None).await.unwrap();
+        }
+        db.register_transformation("input", "output", "extract", None).await.unwrap();
+        db.register_transformation("input", "other", "convert", None).await.unwrap();
+
+        // Nothing dangles yet
+        assert_eq!(db.prune_orphan_transformations().await.unwrap(), 0);
+
+        // Simulate a database written without foreign key enforcement; the
+        // test pool has a single connection, so the pragma applies to it
+        sqlx::query("PRAGMA foreign_keys = OFF").execute(&db.pool).await.unwrap();
+        db.delete_object("output").await.unwrap();
+        sqlx::query("PRAGMA foreign_keys = ON").execute(&db.pool).await.unwrap();
+
+        assert_eq!(db.prune_orphan_transformations().await.unwrap(), 1);
+        let remaining = db.transform_type_counts().await.unwrap();
+        assert_eq!(remaining, vec![("convert".to_string(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_transform_type_counts() {
+        let (db, _temp) = create_test_db().await;
+        for hash in ["a", "b", "c", "d", "e", "f"] {
+            db.register_object(hash, 1, None).await.unwrap();
+        }
+
+        db.register_transformation("a", "b", "extract", None).await.unwrap();
+        db.register_transformation("c", "d", "convert", None).await.unwrap();
+        db.register_transformation("e", "f", "extract", None).await.unwrap();
+
+        let counts = db.transform_type_counts().await.unwrap();
+        assert_eq!(
+            counts,
+            vec![("extract".to_string(), 2), ("convert".to_string(), 1)]
+        );
+
+        let extracts = db.transformations_by_type("extract").await.unwrap();
+        let outputs: Vec<&str> = extracts.iter().map(|t| t.output_hash.as_str()).collect();
+        assert_eq!(outputs, vec!["b", "f"]);
+        assert!(db.transformations_by_type("filter").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_find_cached_transformation() {
+        let (db, _temp) = create_test_db().await;
+
+        // Register objects first
+        db.register_object("input1", 100, None).await.unwrap();
+        db.register_object("output1", 200, None).await.unwrap();
+
+        db.register_transformation("input1", "output1", "extract", None)
+            .await
+            .unwrap();
+
+        let cached = db
+            .find_cached_transformation("input1", "extract", None)
+            .await
+            .unwrap();
+        assert_eq!(cached, Some("output1".to_string()));
+
+        // A rerun within the same second supersedes the earlier record
+        db.register_object("output2", 200, None).await.unwrap();
+        db.register_transformation("input1", "output2", "extract", None)
+            .await
+            .unwrap();
+        let cached = db
+            .find_cached_transformation("input1", "extract", None)
+            .await
+            .unwrap();
+        assert_eq!(cached, Some("output2".to_string()));
+
+        let not_cached = db
+            .find_cached_transformation("input2", "extract", None)
+            .await
+            .unwrap();
+        assert_eq!(not_cached, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_transformation_chain() {
+        let (db, _temp) = create_test_db().await;
+
+        // Register objects first
+        db.register_object("hash0", 100, None).await.unwrap();
+        db.register_object("hash1", 200, None).await.unwrap();
+        db.register_object("hash2", 300, None).await.unwrap();
+
+        db.register_transformation("hash0", "hash1", "extract", None)
+            .await
+            .unwrap();
+        db.register_transformation("hash1", "hash2", "convert", None)
+            .await
+            .unwrap();
+
+        let chain = db.get_transformation_chain("hash2").await.unwrap();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0].transform_type, "extract");
+        assert_eq!(chain[1].transform_type, "convert");
+    }
+
+    #[tokio::test]
+    async fn test_database_stats() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, None).await.unwrap();
+        db.register_object("hash2", 2000, None).await.unwrap();
+        // hash1 is registered as an object, so we can reference it
+        db.register_dataset("test", "1.0.0", "hash1", None)
+            .await
+            .unwrap();
+
+        let stats = db.get_stats().await.unwrap();
+        assert_eq!(stats.objects_count, 2);
+        assert_eq!(stats.datasets_count, 1);
+        assert_eq!(stats.total_size, 3000);
+        assert_eq!(stats.logical_size, 3000);
+
+        // A second registration of the same object counts towards logical size only
+        db.register_object("hash2", 2000, None).await.unwrap();
+        let stats = db.get_stats().await.unwrap();
+        assert_eq!(stats.total_size, 3000);
+        assert_eq!(stats.logical_size, 5000);
+    }
+
+    #[tokio::test]
+    async fn test_latest_version() {
+        let (db, _temp) = create_test_db().await;
+        assert!(!db.dataset_exists("ds").await.unwrap());
+        assert!(db.latest_version("ds").await.unwrap().is_none());
+
+        db.register_manifest(&test_manifest("ds", "1.0.0", &[("a.txt", b"a")]), "manifest1")
+            .await
+            .unwrap();
+        db.register_manifest(&test_manifest("ds", "2.0.0", &[("a.txt", b"b")]), "manifest2")
+            .await
+            .unwrap();
+
+        assert!(db.dataset_exists("ds").await.unwrap());
+        assert!(!db.dataset_exists("other").await.unwrap());
+        let latest = db.latest_version("ds").await.unwrap().unwrap();
+        assert_eq!(latest.version, "2.0.0");
+        assert_eq!(latest.manifest_hash, "manifest2");
+    }
+
+    #[tokio::test]
+    async fn test_dedup_report_counts_shared_object() {
+        let (db, _temp) = create_test_db().await;
+
+        let first = test_manifest("first", "1", &[("shared.txt", b"shared bytes"), ("a.txt", b"a")]);
+        let second = test_manifest("second", "1", &[("copy.txt", b"shared bytes"), ("b.txt", b"bb")]);
+        db.register_manifest(&first, "manifest1").await.unwrap();
+        db.register_manifest(&second, "manifest2").await.unwrap();
+        // Unreferenced objects count towards neither side
+        db.register_object("orphan", 100, None).await.unwrap();
+        db.update_refs("orphan", -1).await.unwrap();
+
+        let report = db.dedup_report().await.unwrap();
+        assert_eq!(report.objects, 5);
+        assert_eq!(report.saved_bytes, b"shared bytes".len() as i64);
+        assert_eq!(report.logical_bytes - report.physical_bytes, report.saved_bytes);
+        assert!(report.ratio() > 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_objects_by_size() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("small", 10, None).await.unwrap();
+        db.register_object("large", 1000, None).await.unwrap();
+        db.register_object("medium", 100, None).await.unwrap();
+
+        let largest = db.objects_by_size(2).await.unwrap();
+        let hashes: Vec<_> = largest.iter().map(|o| o.hash.as_str()).collect();
+        assert_eq!(hashes, vec!["large", "medium"]);
+    }
+
+    #[tokio::test]
+    async fn test_export_json() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, None).await.unwrap();
+        db.register_object("hash2", 2000, None).await.unwrap();
+        db.register_dataset("test", "1.0.0", "hash1", Some("Test dataset"))
+            .await
+            .unwrap();
+        db.register_transformation("hash1", "hash2", "extract", None)
+            .await
+            .unwrap();
+
+        let doc = db.export_json().await.unwrap();
+        assert_eq!(doc["format_version"], EXPORT_FORMAT_VERSION);
+        assert_eq!(doc["objects"].as_array().unwrap().len(), 2);
+        assert_eq!(doc["datasets"].as_array().unwrap().len(), 1);
+        assert_eq!(doc["transformations"].as_array().unwrap().len(), 1);
+        assert_eq!(doc["datasets"][0]["name"], "test");
+        assert_eq!(doc["datasets"][0]["description"], "Test dataset");
+        assert!(doc["objects"][0]["created_at"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_import_json_round_trip() {
+        let (db, _temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, None).await.unwrap();
+        db.register_object("hash2", 2000, None).await.unwrap();
+        db.register_dataset("test", "1.0.0", "hash1", Some("Test dataset"))
+            .await
+            .unwrap();
+        db.register_transformation("hash1", "hash2", "extract", None)
+            .await
+            .unwrap();
+
+        let doc = db.export_json().await.unwrap();
+
+        let (fresh, _temp2) = create_test_db().await;
+        let report = fresh.import_json(&doc, ImportMode::Merge).await.unwrap();
+        assert_eq!(report.objects_inserted, 2);
+        assert_eq!(report.datasets_inserted, 1);
+        assert_eq!(report.transformations_inserted, 1);
+
+        assert_eq!(fresh.get_stats().await.unwrap(), db.get_stats().await.unwrap());
+
+        // Importing again merges without duplicating anything
+        let report = fresh.import_json(&doc, ImportMode::Merge).await.unwrap();
+        assert_eq!(report.objects_inserted, 0);
+        assert_eq!(report.objects_skipped, 2);
+        assert_eq!(report.datasets_skipped, 1);
+        assert_eq!(report.transformations_skipped, 1);
+        assert_eq!(fresh.get_stats().await.unwrap(), db.get_stats().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_import_json_keeps_manifests_and_expiry() {
+        let (db, _temp) = create_test_db().await;
+        let manifest = test_manifest("dump", "1", &[("a.txt", b"alpha"), ("b.txt", b"beta")]);
+        let manifest_hash = manifest.content_hash().to_string_prefixed();
+        db.register_manifest(&manifest, &manifest_hash).await.unwrap();
+        let content = manifest.contents[0].hash.clone();
+        let expiry = Utc::now() - chrono::Duration::hours(1);
+        db.set_object_expiry(&content, expiry).await.unwrap();
+
+        let doc = db.export_json().await.unwrap();
+        assert!(doc["datasets"][0]["manifest"].is_string());
+        assert_eq!(doc["datasets"][0]["object_refs"].as_array().unwrap().len(), 3);
+
+        let (fresh, _temp2) = create_test_db().await;
+        fresh.import_json(&doc, ImportMode::Replace).await.unwrap();
+
+        assert_eq!(fresh.reachable_objects().await.unwrap(), db.reachable_objects().await.unwrap());
+        let imported = fresh.get_manifest("dump", "1").await.unwrap().unwrap();
+        assert_eq!(imported.contents, manifest.contents);
+        assert_eq!(fresh.datasets_referencing(&content).await.unwrap().len(), 1);
+        assert_eq!(fresh.expired_objects(Utc::now()).await.unwrap(), vec![content]);
+    }
+
+    #[tokio::test]
+    async fn test_import_json_merge_keeps_higher_refs() {
+        let (db, _temp) = create_test_db().await;
+        db.register_object("hash1", 1000, None).await.unwrap();
+        let doc = db.export_json().await.unwrap();
+
+        let (other, _temp2) = create_test_db().await;
+        other.register_object("hash1", 1000, None).await.unwrap();
+        other.register_object("hash1", 1000, None).await.unwrap(); // refs = 2
+
+        other.import_json(&doc, ImportMode::Merge).await.unwrap();
+        assert_eq!(other.get_object("hash1").await.unwrap().unwrap().refs, 2);
+    }
+
+    #[tokio::test]
+    async fn test_import_json_replace() {
+        let (db, _temp) = create_test_db().await;
+        db.register_object("hash1", 1000, None).await.unwrap();
+        let doc = db.export_json().await.unwrap();
+
+        let (other, _temp2) = create_test_db().await;
+        other.register_object("other", 5000, None).await.unwrap();
+
+        other.import_json(&doc, ImportMode::Replace).await.unwrap();
+        assert!(other.get_object("other").await.unwrap().is_none());
+        assert_eq!(other.get_stats().await.unwrap(), db.get_stats().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_import_json_rejects_unknown_version() {
+        let (db, _temp) = create_test_db().await;
+        let doc = serde_json::json!({
+            "format_version": EXPORT_FORMAT_VERSION + 1,
+            "objects": [],
+            "datasets": [],
+            "transformations": [],
+        });
+
+        let err = db.import_json(&doc, ImportMode::Merge).await.unwrap_err();
+        assert!(err.to_string().contains("format version"));
+    }
+
+    #[tokio::test]
+    async fn test_import_json_rejects_newer_schema_version() {
+        let (db, _temp) = create_test_db().await;
+        let mut doc = db.export_json().await.unwrap();
+        doc["schema_version"] = serde_json::json!(CURRENT_SCHEMA_VERSION + 1);
+
+        let err = db.import_json(&doc, ImportMode::Merge).await.unwrap_err();
+        assert!(err.to_string().contains("schema version"));
+
+        doc.as_object_mut().unwrap().remove("schema_version");
+        let err = db.import_json(&doc, ImportMode::Merge).await.unwrap_err();
+        assert!(err.to_string().contains("schema_version"));
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_keeps_data() {
+        let (db, temp) = create_test_db().await;
+
+        for i in 0..500 {
+            db.register_object(&format!("hash{:03}", i), i, None).await.unwrap();
+        }
+
+        db.checkpoint(CheckpointMode::Passive).await.unwrap();
+        db.checkpoint(CheckpointMode::Full).await.unwrap();
+        db.checkpoint(CheckpointMode::Truncate).await.unwrap();
+
+        let wal = temp.path().join("test.db-wal");
+        assert_eq!(std::fs::metadata(&wal).map(|m| m.len()).unwrap_or(0), 0);
+
+        let objects = db.list_objects(None, 0).await.unwrap();
+        assert_eq!(objects.len(), 500);
+        assert_eq!(db.get_object("hash499").await.unwrap().unwrap().size, 499);
+    }
+
+    #[test]
+    fn test_checkpoint_mode_from_str() {
+        assert_eq!("TRUNCATE".parse::<CheckpointMode>().unwrap(), CheckpointMode::Truncate);
+        assert_eq!("passive".parse::<CheckpointMode>().unwrap(), CheckpointMode::Passive);
+        assert!("restart".parse::<CheckpointMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backup_to() {
+        let (db, temp) = create_test_db().await;
+
+        db.register_object("hash1", 1000, None).await.unwrap();
+        db.register_object("hash2", 2000, None).await.unwrap();
+        db.register_dataset("test", "1.0.0", "hash1", Some("Backed up"))
+            .await
+            .unwrap();
+
+        let backup_path = temp.path().join("backups").join("meta-backup.db");
+        db.backup_to(&backup_path).await.unwrap();
+
+        let backup = MetadataDb::new(&backup_path).await.unwrap();
+        assert_eq!(backup.get_stats().await.unwrap(), db.get_stats().await.unwrap());
+
+        // Search index is carried over with the snapshot
+        let results = backup.search_datasets("Backed").await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        // Refuses to overwrite an existing backup
+        assert!(db.backup_to(&backup_path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reachable_objects() {
+        use crate::manifest::{Content, ContentKind, Dataset, Source};
+
+        let (db, _temp) = create_test_db().await;
+
+        let content_hash = Blake3Hash::from_bytes(b"content");
+        let orphan_hash = Blake3Hash::from_bytes(b"orphan");
+        let manifest_hash = Blake3Hash::from_bytes(b"manifest");
+
+        let manifest = Manifest {
+            schema_version: "1.0".to_string(),
+            dataset: Dataset {
+                name: "test".to_string(),
+                version: "1.0.0".to_string(),
+                description: None,
+            },
+            source: Source {
+                url: None,
+                download_date: None,
+                server_mtime: None,
+                archive_hash: None,
+                archive_type: None,
+            },
+            contents: vec![Content {
+                path: "data.txt".to_string(),
+                // Bare hex is normalized to the prefixed form
+                hash: content_hash.to_hex(),
+                size: 7,
+                executable: false,
+                mime_type: None,
+                kind: ContentKind::File,
+                derived_from: Vec::new(),
+            }],
+            transformations: vec![],
+            signature: None,
+        };
+
+        db.register_object(&orphan_hash.to_string_prefixed(), 6, None).await.unwrap();
+        db.register_manifest(&manifest, &manifest_hash.to_string_prefixed())
+            .await
+            .unwrap();
+
+        let reachable = db.reachable_objects().await.unwrap();
+        assert_eq!(reachable.len(), 2);
+        assert!(reachable.contains(&manifest_hash.to_string_prefixed()));
+        assert!(reachable.contains(&content_hash.to_string_prefixed()));
+        assert!(!reachable.contains(&orphan_hash.to_string_prefixed()));
+    }
+
+    fn test_manifest(name: &str, version: &str, files: &[(&str, &[u8])]) -> Manifest {
+        use crate::manifest::{Content, ContentKind, Dataset, Source};
+
+        Manifest {
+            schema_version: "1.0".to_string(),
+            dataset: Dataset {
+                name: name.to_string(),
+                version: version.to_string(),
+                description: None,
+            },
+            source: Source {
+                url: None,
+                download_date: None,
+                server_mtime: None,
+                archive_hash: None,
+                archive_type: None,
+            },
+            contents: files
+                .iter()
+                .map(|(path, data)| Content {
+                    path: path.to_string(),
+                    hash: Blake3Hash::from_bytes(data).to_string_prefixed(),
+                    size: data.len() as u64,
+                    executable: false,
+                    mime_type: None,
+                    kind: ContentKind::File,
+                    derived_from: Vec::new(),
+                })
+                .collect(),
+            transformations: vec![],
+            signature: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_manifest() {
+        let (db, _temp) = create_test_db().await;
+
+        let manifest = test_manifest("ds", "1.0.0", &[("a.txt", b"aaa"), ("b.txt", b"bbbb")]);
+        let id = db.register_manifest(&manifest, "manifest1").await.unwrap();
+        assert!(id > 0);
+
+        let stats = db.get_stats().await.unwrap();
+        assert_eq!(stats.objects_count, 3);
+        assert_eq!(stats.datasets_count, 1);
+
+        let a = db.get_object(&manifest.contents[0].hash).await.unwrap().unwrap();
+        assert_eq!(a.size, 3);
+        assert!(db.get_object("manifest1").await.unwrap().is_some());
+
+        // Registering the same manifest again is a no-op
+        let again = db.register_manifest(&manifest, "manifest1").await.unwrap();
+        assert_eq!(again, id);
+        let a = db.get_object(&manifest.contents[0].hash).await.unwrap().unwrap();
+        assert_eq!(a.refs, 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_manifest() {
+        let (db, _temp) = create_test_db().await;
+
+        let manifest = test_manifest("ds", "1.0.0", &[("a.txt", b"aaa")]);
+        db.register_manifest(&manifest, "manifest1").await.unwrap();
+
+        let stored = db.get_manifest("ds", "1.0.0").await.unwrap().unwrap();
+        assert_eq!(stored.contents, manifest.contents);
+        assert!(db.get_manifest("ds", "2.0.0").await.unwrap().is_none());
+
+        db.register_object("hash1", 1, None).await.unwrap();
+        db.register_dataset("bare", "1", "hash1", None).await.unwrap();
+        assert!(db.get_manifest("bare", "1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_datasets_referencing() {
+        let (db, _temp) = create_test_db().await;
+
+        let v1 = test_manifest("ds", "1.0.0", &[("a.txt", b"aaa"), ("b.txt", b"bbb")]);
+        let v2 = test_manifest("ds", "2.0.0", &[("a.txt", b"aaa")]);
+        db.register_manifest(&v1, "manifest1").await.unwrap();
+        db.register_manifest(&v2, "manifest2").await.unwrap();
+
+        let shared = db.datasets_referencing(&v1.contents[0].hash).await.unwrap();
+        assert_eq!(shared.len(), 2);
+
+        let only_v1 = db.datasets_referencing(&v1.contents[1].hash).await.unwrap();
+        assert_eq!(only_v1.len(), 1);
+        assert_eq!(only_v1[0].version, "1.0.0");
+
+        let manifest = db.datasets_referencing("manifest2").await.unwrap();
+        assert_eq!(manifest[0].version, "2.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_object_refs_indexed_on_registration() {
+        let (db, _temp) = create_test_db().await;
+
+        let manifest = test_manifest("refs", "1.0.0", &[("a.txt", b"aaa"), ("b.txt", b"bbb")]);
+        let id = db.register_manifest(&manifest, "manifest").await.unwrap();
+
+        let indexed: Vec<String> =
+            sqlx::query_scalar("SELECT object_hash FROM object_refs WHERE dataset_id = ? ORDER BY object_hash")
+                .bind(id)
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(indexed.len(), 3);
+
+        for content in &manifest.contents {
+            let datasets = db.datasets_referencing(&content.hash).await.unwrap();
+            assert_eq!(datasets.len(), 1);
+            assert_eq!(datasets[0].name, "refs");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recompute_refs() {
+        let (db, _temp) = create_test_db().await;
+
+        let v1 = test_manifest("ds", "1.0.0", &[("a.txt", b"aaa"), ("b.txt", b"bbb")]);
+        let v2 = test_manifest("ds", "2.0.0", &[("a.txt", b"aaa")]);
+        db.register_manifest(&v1, "manifest1").await.unwrap();
+        db.register_manifest(&v2, "manifest2").await.unwrap();
+        db.register_object("loose", 1, None).await.unwrap();
+
+        let shared = &v1.contents[0].hash;
+        sqlx::query("UPDATE objects SET refs = 42 WHERE hash = ?")
+            .bind(shared)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        db.recompute_refs().await.unwrap();
+
+        for (hash, expected) in [
+            (shared.as_str(), 2),
+            (v1.contents[1].hash.as_str(), 1),
+            ("manifest1", 1),
+            ("loose", 0),
+        ] {
+            assert_eq!(db.get_object(hash).await.unwrap().unwrap().refs, expected, "{}", hash);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recompute_refs_matches_registration() {
+        let (db, _temp) = create_test_db().await;
+
+        let manifest = test_manifest("dups", "1", &[("a.txt", b"same"), ("b.txt", b"same"), ("c.txt", b"c")]);
+        db.register_manifest(&manifest, "manifest1").await.unwrap();
+        let before = db.list_objects(None, 0).await.unwrap();
+
+        db.recompute_refs().await.unwrap();
+        let after = db.list_objects(None, 0).await.unwrap();
+        let refs = |objects: &[ObjectRecord]| objects.iter().map(|o| (o.hash.clone(), o.refs)).collect::<Vec<_>>();
+        assert_eq!(refs(&after), refs(&before));
+
+        // An imported database repairs to the same counts
+        let doc = db.export_json().await.unwrap();
+        let (fresh, _temp2) = create_test_db().await;
+        fresh.import_json(&doc, ImportMode::Replace).await.unwrap();
+        fresh.recompute_refs().await.unwrap();
+        assert_eq!(refs(&fresh.list_objects(None, 0).await.unwrap()), refs(&before));
+    }
+
+    #[tokio::test]
+    async fn test_register_manifest_rolls_back_on_conflict() {
+        let (db, _temp) = create_test_db().await;
+
+        let original = test_manifest("ds", "1.0.0", &[("a.txt", b"aaa")]);
+        db.register_manifest(&original, "manifest1").await.unwrap();
+        let before = db.get_stats().await.unwrap();
+
+        // Same name/version with a different manifest fails after its
+        // objects were inserted; none of them may survive
+        let conflicting = test_manifest("ds", "1.0.0", &[("a.txt", b"aaa"), ("c.txt", b"ccc")]);
+        let err = db.register_manifest(&conflicting, "manifest2").await.unwrap_err();
+        assert!(err.to_string().contains("ds/1.0.0"));
+
+        assert_eq!(db.get_stats().await.unwrap(), before);
+        assert!(db.get_object(&conflicting.contents[1].hash).await.unwrap().is_none());
+        assert!(db.get_object("manifest2").await.unwrap().is_none());
+        let a = db.get_object(&original.contents[0].hash).await.unwrap().unwrap();
+        assert_eq!(a.refs, 1);
+    }
+}
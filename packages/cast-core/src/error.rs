@@ -0,0 +1,33 @@
+// Error kinds callers can match on
+//
+// Storage and hash APIs return `anyhow::Result`; where a failure has a
+// well-defined kind, the error is a `CastError`, recoverable with
+// `anyhow::Error::downcast_ref::<CastError>()`.
+use crate::hash::Blake3Hash;
+use std::path::PathBuf;
+
+/// Failures with a specific meaning, as opposed to I/O or parse errors
+#[derive(Debug, thiserror::Error)]
+pub enum CastError {
+    /// The object is not in the store in any form
+    #[error("File not found in CAS: {0}")]
+    NotFound(Blake3Hash),
+
+    /// Data did not hash to the value it was expected to have
+    #[error("Hash mismatch: expected {expected}, got {actual}")]
+    IntegrityMismatch { expected: Blake3Hash, actual: Blake3Hash },
+
+    /// A stored object's bytes differ from new data with the same hash
+    ///
+    /// Points at a corrupted object on disk rather than a real collision.
+    #[error("Stored object {0} differs from new data with the same hash (corrupt object or hash collision)")]
+    Collision(Blake3Hash),
+
+    /// A write failed because the file system is full or over quota
+    #[error("No space or quota left to write {0}")]
+    QuotaExceeded(PathBuf),
+
+    /// A string is not a valid BLAKE3 hash
+    #[error("Invalid BLAKE3 hash: {0}")]
+    InvalidHash(String),
+}
@@ -0,0 +1,523 @@
+// BLAKE3 hashing implementation
+use anyhow::{Context, Result};
+use blake3::{Hash, Hasher};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::CastError;
+
+/// Read buffer size used by `Blake3Hash::from_file` (1 MiB)
+pub const DEFAULT_FILE_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// BLAKE3 hash wrapper with convenient methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Blake3Hash(Hash);
+
+impl Blake3Hash {
+    /// Compute BLAKE3 hash from a file using streaming I/O
+    ///
+    /// Reads `DEFAULT_FILE_BUFFER_SIZE` bytes at a time, so large files are
+    /// handled without loading them into memory.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_file_with_capacity(path, DEFAULT_FILE_BUFFER_SIZE)
+    }
+
+    /// Compute BLAKE3 hash from a file, reading `buf_size` bytes at a time
+    ///
+    /// Small buffers suit many tiny files; large ones suit long sequential
+    /// reads from spinning disks. The hash does not depend on `buf_size`.
+    pub fn from_file_with_capacity<P: AsRef<Path>>(path: P, buf_size: usize) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+
+        let mut hasher = Hasher::new();
+        let mut buffer = vec![0u8; buf_size.max(1)];
+        loop {
+            let bytes_read = file
+                .read(&mut buffer)
+                .with_context(|| format!("Failed to hash file: {}", path.display()))?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+        }
+
+        Ok(Blake3Hash(hasher.finalize()))
+    }
+
+    /// Compute BLAKE3 hash from any reader
+    ///
+    /// Reads data in chunks to support streaming hashing
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut hasher = Hasher::new();
+        let mut buffer = [0u8; 16384]; // 16KB chunks
+
+        loop {
+            let bytes_read = reader
+                .read(&mut buffer)
+                .context("Failed to read data for hashing")?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+        }
+
+        Ok(Blake3Hash(hasher.finalize()))
+    }
+
+    /// Compute BLAKE3 hash from bytes in memory
+    ///
+    /// This is optimized for small data that fits in memory
+    pub fn from_bytes(data: &[u8]) -> Self {
+        Blake3Hash(blake3::hash(data))
+    }
+
+    /// Get the underlying blake3::Hash
+    pub fn as_hash(&self) -> &Hash {
+        &self.0
+    }
+
+    /// Get hex string representation without prefix
+    pub fn to_hex(&self) -> String {
+        self.0.to_hex().to_string()
+    }
+
+    /// Get hex string with blake3: prefix
+    pub fn to_string_prefixed(&self) -> String {
+        format!("blake3:{}", self.to_hex())
+    }
+
+    /// Verify this hash matches the given string (with or without prefix)
+    pub fn verify(&self, other: &str) -> bool {
+        // Try with prefix first
+        if let Ok(parsed) = Self::from_str(other) {
+            return *self == parsed;
+        }
+
+        // Try as raw hex
+        if other.len() == 64 {
+            if let Ok(parsed) = Self::from_str(&format!("blake3:{}", other)) {
+                return *self == parsed;
+            }
+        }
+
+        false
+    }
+
+    /// Get the hash as bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Display for Blake3Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_prefixed())
+    }
+}
+
+impl From<Hash> for Blake3Hash {
+    fn from(hash: Hash) -> Self {
+        Blake3Hash(hash)
+    }
+}
+
+impl FromStr for Blake3Hash {
+    type Err = CastError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, CastError> {
+        let hex = s.strip_prefix("blake3:").unwrap_or(s);
+
+        if hex.len() != 64 {
+            return Err(CastError::InvalidHash(format!(
+                "expected 64 hex chars, got {}",
+                hex.len()
+            )));
+        }
+
+        let bytes = hex::decode(hex)
+            .map_err(|e| CastError::InvalidHash(format!("failed to decode hex {}: {}", hex, e)))?;
+
+        if bytes.len() != 32 {
+            return Err(CastError::InvalidHash(format!("expected 32 bytes, got {}", bytes.len())));
+        }
+
+        let mut hash_bytes = [0u8; 32];
+        hash_bytes.copy_from_slice(&bytes);
+
+        Ok(Blake3Hash(Hash::from(hash_bytes)))
+    }
+}
+
+/// Reader adapter that hashes data as it passes through
+///
+/// Once the inner reader reaches end of file, the digest is compared with
+/// the expected hash and a mismatch is reported as an `InvalidData` error,
+/// so a copy loop fails instead of silently accepting corrupted data.
+pub struct VerifyingReader<R> {
+    inner: R,
+    hasher: Hasher,
+    expected: Blake3Hash,
+}
+
+impl<R: Read> VerifyingReader<R> {
+    pub fn new(inner: R, expected: Blake3Hash) -> Self {
+        Self {
+            inner,
+            hasher: Hasher::new(),
+            expected,
+        }
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+
+        if bytes_read == 0 && !buf.is_empty() {
+            let actual = Blake3Hash(self.hasher.finalize());
+            if actual != self.expected {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    CastError::IntegrityMismatch {
+                        expected: self.expected,
+                        actual,
+                    },
+                ));
+            }
+        }
+
+        self.hasher.update(&buf[..bytes_read]);
+        Ok(bytes_read)
+    }
+}
+
+impl Serialize for Blake3Hash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string_prefixed())
+    }
+}
+
+impl<'de> Deserialize<'de> for Blake3Hash {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Blake3Hash::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Digest algorithm of a `ContentHash`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Prefix naming the algorithm in a hash string, without the colon
+    pub fn prefix(self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+/// Content digest tagged with the algorithm that produced it
+///
+/// The store addresses objects by BLAKE3; SHA-256 digests identify content
+/// pinned by other ecosystems, such as Docker images and Nix fixed-output
+/// derivations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentHash {
+    Blake3(Blake3Hash),
+    Sha256([u8; 32]),
+}
+
+impl ContentHash {
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            ContentHash::Blake3(_) => HashAlgorithm::Blake3,
+            ContentHash::Sha256(_) => HashAlgorithm::Sha256,
+        }
+    }
+
+    /// Get the digest as bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        match self {
+            ContentHash::Blake3(hash) => hash.as_bytes(),
+            ContentHash::Sha256(bytes) => bytes,
+        }
+    }
+
+    /// Get hex string representation without prefix
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_bytes())
+    }
+
+    /// Get hex string with the algorithm's prefix, e.g. `sha256:<hex>`
+    pub fn to_string_prefixed(&self) -> String {
+        format!("{}:{}", self.algorithm().prefix(), self.to_hex())
+    }
+
+    /// Sharded location under a store directory: `{hex[:2]}/{hex[2:4]}/{hex}`
+    pub fn shard_path(&self) -> PathBuf {
+        let hex = self.to_hex();
+        Path::new(&hex[..2]).join(&hex[2..4]).join(&hex)
+    }
+}
+
+impl From<Blake3Hash> for ContentHash {
+    fn from(hash: Blake3Hash) -> Self {
+        ContentHash::Blake3(hash)
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_prefixed())
+    }
+}
+
+impl FromStr for ContentHash {
+    type Err = CastError;
+
+    /// Parse `blake3:<hex>` or `sha256:<hex>`; unprefixed hex is BLAKE3
+    fn from_str(s: &str) -> std::result::Result<Self, CastError> {
+        match s.split_once(':') {
+            None | Some(("blake3", _)) => Ok(ContentHash::Blake3(Blake3Hash::from_str(s)?)),
+            Some(("sha256", hex)) => {
+                let bytes = hex::decode(hex)
+                    .map_err(|e| CastError::InvalidHash(format!("failed to decode hex {}: {}", hex, e)))?;
+                let bytes: [u8; 32] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+                    CastError::InvalidHash(format!("expected 32 bytes, got {}", bytes.len()))
+                })?;
+                Ok(ContentHash::Sha256(bytes))
+            }
+            Some((other, _)) => Err(CastError::InvalidHash(format!("unsupported hash algorithm: {}", other))),
+        }
+    }
+}
+
+impl Serialize for ContentHash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string_prefixed())
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentHash {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        ContentHash::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_hash_empty_bytes() {
+        let hash = Blake3Hash::from_bytes(b"");
+        assert_eq!(
+            hash.to_hex(),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+
+    #[test]
+    fn test_hash_hello_world() {
+        let hash = Blake3Hash::from_bytes(b"hello world");
+        // Known BLAKE3 hash for "hello world"
+        assert_eq!(
+            hash.to_hex(),
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+    }
+
+    #[test]
+    fn test_hash_with_prefix() {
+        let hash = Blake3Hash::from_bytes(b"test");
+        let prefixed = hash.to_string_prefixed();
+        assert!(prefixed.starts_with("blake3:"));
+        assert_eq!(prefixed.len(), 71); // "blake3:" (7) + 64 hex chars
+    }
+
+    #[test]
+    fn test_hash_display() {
+        let hash = Blake3Hash::from_bytes(b"test");
+        let display = format!("{}", hash);
+        assert!(display.starts_with("blake3:"));
+    }
+
+    #[test]
+    fn test_hash_from_str() {
+        let original = Blake3Hash::from_bytes(b"test data");
+        let hex_str = original.to_string_prefixed();
+
+        let parsed = Blake3Hash::from_str(&hex_str).unwrap();
+        assert_eq!(original, parsed);
+    }
+
+    #[test]
+    fn test_hash_from_str_without_prefix() {
+        let original = Blake3Hash::from_bytes(b"test data");
+        let hex_only = original.to_hex();
+
+        let parsed = Blake3Hash::from_str(&hex_only).unwrap();
+        assert_eq!(original, parsed);
+    }
+
+    #[test]
+    fn test_hash_from_str_invalid() {
+        assert!(Blake3Hash::from_str("invalid").is_err());
+        assert!(Blake3Hash::from_str("blake3:tooshort").is_err());
+        assert!(Blake3Hash::from_str("blake3:zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz").is_err());
+        assert!(matches!(Blake3Hash::from_str("abc"), Err(CastError::InvalidHash(_))));
+    }
+
+    #[test]
+    fn test_hash_verify() {
+        let hash = Blake3Hash::from_bytes(b"verify test");
+        let prefixed = hash.to_string_prefixed();
+        let hex_only = hash.to_hex();
+
+        assert!(hash.verify(&prefixed));
+        assert!(hash.verify(&hex_only));
+        assert!(!hash.verify("invalid"));
+        assert!(!hash.verify("blake3:0000000000000000000000000000000000000000000000000000000000000000"));
+    }
+
+    #[test]
+    fn test_hash_from_reader() {
+        let data = b"streaming test data";
+        let cursor = Cursor::new(data);
+
+        let hash = Blake3Hash::from_reader(cursor).unwrap();
+        let expected = Blake3Hash::from_bytes(data);
+
+        assert_eq!(hash, expected);
+    }
+
+    #[test]
+    fn test_hash_from_reader_large() {
+        // Test with data larger than buffer size
+        let data = vec![0xAB; 100_000]; // 100KB of 0xAB
+        let cursor = Cursor::new(&data);
+
+        let hash = Blake3Hash::from_reader(cursor).unwrap();
+        let expected = Blake3Hash::from_bytes(&data);
+
+        assert_eq!(hash, expected);
+    }
+
+    #[test]
+    fn test_hash_file_buffer_sizes_agree() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.bin");
+        let data: Vec<u8> = (0..5_000_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let small = Blake3Hash::from_file_with_capacity(&path, 4 * 1024).unwrap();
+        let large = Blake3Hash::from_file_with_capacity(&path, 4 * 1024 * 1024).unwrap();
+
+        assert_eq!(small, large);
+        assert_eq!(small, Blake3Hash::from_bytes(&data));
+        assert_eq!(small, Blake3Hash::from_file(&path).unwrap());
+    }
+
+    #[test]
+    fn test_hash_serialization() {
+        let hash = Blake3Hash::from_bytes(b"serialize me");
+        let json = serde_json::to_string(&hash).unwrap();
+
+        assert!(json.contains("blake3:"));
+
+        let deserialized: Blake3Hash = serde_json::from_str(&json).unwrap();
+        assert_eq!(hash, deserialized);
+    }
+
+    #[test]
+    fn test_hash_consistency() {
+        // Same input should always produce same hash
+        let data = b"consistency test";
+
+        let hash1 = Blake3Hash::from_bytes(data);
+        let hash2 = Blake3Hash::from_bytes(data);
+        let hash3 = Blake3Hash::from_reader(Cursor::new(data)).unwrap();
+
+        assert_eq!(hash1, hash2);
+        assert_eq!(hash2, hash3);
+    }
+
+    #[test]
+    fn test_hash_as_bytes() {
+        let hash = Blake3Hash::from_bytes(b"test");
+        let bytes = hash.as_bytes();
+
+        assert_eq!(bytes.len(), 32);
+
+        // Reconstruct from bytes
+        let reconstructed = Blake3Hash(Hash::from(*bytes));
+        assert_eq!(hash, reconstructed);
+    }
+
+    #[test]
+    fn test_verifying_reader() {
+        let data = b"verify me";
+        let expected = Blake3Hash::from_bytes(data);
+
+        let mut out = Vec::new();
+        std::io::copy(&mut VerifyingReader::new(&data[..], expected), &mut out).unwrap();
+        assert_eq!(out, data);
+
+        let wrong = Blake3Hash::from_bytes(b"something else");
+        let err = std::io::copy(&mut VerifyingReader::new(&data[..], wrong), &mut std::io::sink())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_content_hash_round_trip() {
+        let blake3 = format!("blake3:{}", Blake3Hash::from_bytes(b"abc").to_hex());
+        let sha256 = "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        let parsed = ContentHash::from_str(&blake3).unwrap();
+        assert_eq!(parsed, ContentHash::Blake3(Blake3Hash::from_bytes(b"abc")));
+        assert_eq!(parsed.to_string_prefixed(), blake3);
+
+        let parsed = ContentHash::from_str(sha256).unwrap();
+        assert_eq!(parsed.algorithm(), HashAlgorithm::Sha256);
+        assert_eq!(parsed.to_string(), sha256);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), format!("\"{}\"", sha256));
+        assert_eq!(serde_json::from_str::<ContentHash>(&format!("\"{}\"", sha256)).unwrap(), parsed);
+        assert_eq!(
+            parsed.shard_path(),
+            Path::new("ba/78/ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+
+        assert_eq!(
+            ContentHash::from_str(blake3.strip_prefix("blake3:").unwrap()).unwrap().algorithm(),
+            HashAlgorithm::Blake3
+        );
+        assert!(ContentHash::from_str("sha256:abcd").is_err());
+        assert!(ContentHash::from_str("md5:d41d8cd98f00b204e9800998ecf8427e").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+// Bandwidth throttling for transfers
+//
+// A `Throttle` is a token bucket shared by every reader and writer wrapped
+// with it, so a cap holds across a whole transfer (several objects, or a
+// download resumed after a dropped connection), not per stream. Bytes are
+// let through first and paid for afterwards: an operation that overdraws the
+// bucket delays the next one until the debt is refilled.
+
+use anyhow::{Context, Result};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// Shared bandwidth cap, in bytes per second
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    rate: u64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Available bytes; negative while paying off an overdraft
+    tokens: f64,
+    last: Instant,
+}
+
+impl Throttle {
+    /// Cap transfers at `rate` bytes per second
+    ///
+    /// The bucket starts empty and holds at most a tenth of a second's worth
+    /// of bytes, so idle time buys only a short burst.
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: 0.0,
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// Largest single read or write, so one operation never owes much
+    fn burst(&self) -> usize {
+        (self.rate / 10).clamp(1, 1024 * 1024) as usize
+    }
+
+    /// Take `bytes` from the bucket, returning how long to wait before the
+    /// next transfer
+    fn consume(&self, bytes: usize) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last).as_secs_f64() * self.rate as f64;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst() as f64) - bytes as f64;
+        bucket.last = now;
+        if bucket.tokens < 0.0 {
+            Duration::from_secs_f64(-bucket.tokens / self.rate as f64)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Wait out a pending delay, if any
+fn poll_delay(delay: &mut Option<Pin<Box<Sleep>>>, cx: &mut TaskContext<'_>) -> Poll<()> {
+    if let Some(sleep) = delay {
+        if sleep.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        *delay = None;
+    }
+    Poll::Ready(())
+}
+
+fn schedule(delay: &mut Option<Pin<Box<Sleep>>>, wait: Duration) {
+    if !wait.is_zero() {
+        *delay = Some(Box::pin(tokio::time::sleep(wait)));
+    }
+}
+
+/// Async reader limited by a `Throttle`
+pub struct ThrottledReader<R> {
+    inner: R,
+    throttle: Throttle,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> ThrottledReader<R> {
+    pub fn new(inner: R, throttle: Throttle) -> Self {
+        Self {
+            inner,
+            throttle,
+            delay: None,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if poll_delay(&mut this.delay, cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let limit = buf.remaining().min(this.throttle.burst());
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(limit));
+        let poll = Pin::new(&mut this.inner).poll_read(cx, &mut limited);
+        let read = limited.filled().len();
+        buf.advance(read);
+
+        if read > 0 {
+            schedule(&mut this.delay, this.throttle.consume(read));
+        }
+        poll
+    }
+}
+
+/// Async writer limited by a `Throttle`
+///
+/// Flushing and shutting down wait for the last write to be paid off, so a
+/// completed transfer never finishes faster than the cap allows.
+pub struct ThrottledWriter<W> {
+    inner: W,
+    throttle: Throttle,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<W> ThrottledWriter<W> {
+    pub fn new(inner: W, throttle: Throttle) -> Self {
+        Self {
+            inner,
+            throttle,
+            delay: None,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ThrottledWriter<W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if poll_delay(&mut this.delay, cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let limit = buf.len().min(this.throttle.burst());
+        let poll = Pin::new(&mut this.inner).poll_write(cx, &buf[..limit]);
+        if let Poll::Ready(Ok(written)) = poll {
+            if written > 0 {
+                schedule(&mut this.delay, this.throttle.consume(written));
+            }
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if poll_delay(&mut this.delay, cx).is_pending() {
+            return Poll::Pending;
+        }
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if poll_delay(&mut this.delay, cx).is_pending() {
+            return Poll::Pending;
+        }
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Parse a rate in bytes per second, e.g. `500000`, `512K`, `10MB`, or `1GiB/s`
+///
+/// `K`, `M`, and `G` (optionally followed by `B`) are decimal multiples;
+/// `KiB`, `MiB`, and `GiB` are binary. Case is ignored and a trailing `/s`
+/// is allowed.
+pub fn parse_rate(raw: &str) -> Result<u64> {
+    let trimmed = raw.trim();
+    let trimmed = trimmed.strip_suffix("/s").unwrap_or(trimmed);
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let value: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid rate '{}' (expected e.g. 500K or 10MB)", raw))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        other => anyhow::bail!("Unknown rate unit '{}' in '{}' (use K, M, G, KiB, MiB, or GiB)", other, raw),
+    };
+
+    let rate = (value * multiplier as f64).round();
+    if rate < 1.0 || rate > u64::MAX as f64 {
+        anyhow::bail!("Rate must be at least one byte per second: {}", raw);
+    }
+    Ok(rate as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("500").unwrap(), 500);
+        assert_eq!(parse_rate("10MB").unwrap(), 10_000_000);
+        assert_eq!(parse_rate("512k").unwrap(), 512_000);
+        assert_eq!(parse_rate("1.5M").unwrap(), 1_500_000);
+        assert_eq!(parse_rate("2MiB/s").unwrap(), 2 * 1024 * 1024);
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("10XB").is_err());
+        assert!(parse_rate("0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_throttled_reader_enforces_rate() {
+        // 3000 bytes at 10000 B/s take at least 0.3 s, less the 0.1 s burst
+        let payload = vec![7u8; 3000];
+        let start = Instant::now();
+        let mut reader = ThrottledReader::new(&payload[..], Throttle::new(10_000));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+
+        assert_eq!(out, payload);
+        assert!(start.elapsed() >= Duration::from_millis(200), "took {:?}", start.elapsed());
+    }
+
+    #[tokio::test]
+    async fn test_throttled_writer_shares_cap_across_streams() {
+        let throttle = Throttle::new(10_000);
+        let start = Instant::now();
+        for _ in 0..2 {
+            let mut writer = ThrottledWriter::new(Vec::new(), throttle.clone());
+            writer.write_all(&[1u8; 1500]).await.unwrap();
+            writer.shutdown().await.unwrap();
+            assert_eq!(writer.into_inner().len(), 1500);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200), "took {:?}", start.elapsed());
+    }
+}
@@ -0,0 +1,25 @@
+//! Core library for CAST (Content-Addressed Storage Tool): BLAKE3 hashing,
+//! dataset manifests, storage backends, and the SQLite metadata database.
+//!
+//! ```
+//! use cast_core::Blake3Hash;
+//!
+//! let hash = Blake3Hash::from_bytes(b"hello");
+//! assert_eq!(hash.to_string().parse::<Blake3Hash>().unwrap(), hash);
+//! ```
+
+pub mod blocking;
+pub mod db;
+pub mod error;
+pub mod hash;
+pub mod io;
+pub mod manifest;
+pub mod storage;
+
+pub use blocking::BlockingStorage;
+pub use db::MetadataDb;
+pub use error::CastError;
+pub use hash::{Blake3Hash, ContentHash};
+pub use manifest::Manifest;
+pub use storage::local::LocalStorage;
+pub use storage::{StorageBackend, StorageConfig};
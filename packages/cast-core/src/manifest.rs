@@ -0,0 +1,1315 @@
+// Manifest types and serialization
+// This will be expanded in later tasks
+
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::hash::Blake3Hash;
+use crate::storage::StorageBackend;
+
+/// Manifest schema version written by this version of cast
+pub const CURRENT_SCHEMA_VERSION: &str = "1.0";
+
+/// Manifest schema version 1.0
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub schema_version: String,
+    pub dataset: Dataset,
+    pub source: Source,
+    pub contents: Vec<Content>,
+    #[serde(default)]
+    pub transformations: Vec<Transformation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ManifestSignature>,
+}
+
+impl Manifest {
+    /// Parse a manifest from JSON, upgrading known legacy schema versions
+    ///
+    /// Current manifests parse exactly as with `serde_json::from_str`.
+    /// Older shapes are migrated to the current struct; versions newer than
+    /// `CURRENT_SCHEMA_VERSION` are rejected.
+    pub fn from_json(s: &str) -> Result<Manifest> {
+        let doc: serde_json::Value = serde_json::from_str(s).context("Invalid manifest JSON")?;
+        Self::from_document(doc)
+    }
+
+    /// Parse a manifest from YAML, with the same upgrades as `from_json`
+    pub fn from_yaml(s: &str) -> Result<Manifest> {
+        let doc: serde_json::Value = serde_yaml::from_str(s).context("Invalid manifest YAML")?;
+        Self::from_document(doc)
+    }
+
+    /// Parse a manifest from TOML, with the same upgrades as `from_json`
+    pub fn from_toml(s: &str) -> Result<Manifest> {
+        let doc: serde_json::Value = toml::from_str(s).context("Invalid manifest TOML")?;
+        Self::from_document(doc)
+    }
+
+    /// Read and parse a manifest file, choosing the format by extension
+    ///
+    /// `.yaml` and `.yml` files are read as YAML and `.toml` files as TOML;
+    /// anything else is read as JSON.
+    pub fn from_path(path: &Path) -> Result<Manifest> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let parsed = match extension.as_deref() {
+            Some("yaml" | "yml") => Self::from_yaml(&content),
+            Some("toml") => Self::from_toml(&content),
+            _ => Self::from_json(&content),
+        };
+        parsed.with_context(|| format!("Failed to parse manifest: {}", path.display()))
+    }
+
+    /// Migrate a parsed document to the current schema and deserialize it
+    fn from_document(mut doc: serde_json::Value) -> Result<Manifest> {
+        let version = doc
+            .get("schema_version")
+            .and_then(|v| v.as_str())
+            .context("Manifest is missing schema_version")?
+            .to_string();
+
+        match version.as_str() {
+            CURRENT_SCHEMA_VERSION => {}
+            "0.9" => upgrade_from_0_9(&mut doc)?,
+            other => {
+                if parse_schema_version(other) > parse_schema_version(CURRENT_SCHEMA_VERSION) {
+                    anyhow::bail!(
+                        "Manifest schema version {} is newer than supported ({}); please upgrade cast",
+                        other,
+                        CURRENT_SCHEMA_VERSION
+                    );
+                }
+                anyhow::bail!("Unsupported legacy manifest schema version: {}", other);
+            }
+        }
+
+        serde_json::from_value(doc)
+            .with_context(|| format!("Failed to parse manifest (schema version {})", version))
+    }
+
+    /// Check a parsed manifest for internal consistency
+    ///
+    /// Rejects empty dataset names, duplicate content paths, and content,
+    /// `derived_from`, or transformation `from` hashes that are not valid
+    /// BLAKE3 hashes. A `from` may name the source archive, a content
+    /// object, or the manifest a transform read; the last is not checked
+    /// here. Dataset name syntax (`Dataset::split_name`) is left to the
+    /// commands that create new names, so existing manifests stay readable.
+    pub fn validate(&self) -> Result<()> {
+        if self.dataset.name.trim().is_empty() {
+            anyhow::bail!("Manifest dataset name is empty");
+        }
+
+        let mut paths = HashSet::new();
+        for content in &self.contents {
+            if !paths.insert(content.path.as_str()) {
+                anyhow::bail!("Duplicate content path in manifest: {}", content.path);
+            }
+            Blake3Hash::from_str(&content.hash)
+                .with_context(|| format!("Malformed hash for '{}': {}", content.path, content.hash))?;
+            for input in &content.derived_from {
+                Blake3Hash::from_str(input)
+                    .with_context(|| format!("Malformed derived_from hash for '{}': {}", content.path, input))?;
+            }
+        }
+
+        for transformation in &self.transformations {
+            Blake3Hash::from_str(&transformation.from).with_context(|| {
+                format!(
+                    "Transformation '{}' has a malformed from hash: {}",
+                    transformation.transform_type, transformation.from
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Merge another manifest for the same dataset into a combined manifest
+    ///
+    /// Contents are concatenated: identical entries are deduplicated, while
+    /// the same path with differing hashes is an error. Transformations are
+    /// unioned, and the result uses the newer of the two schema versions.
+    /// `self`'s source is kept and any signature is dropped, since the merged
+    /// manifest has different content.
+    pub fn merge(&self, other: &Manifest) -> Result<Manifest> {
+        if self.dataset.name != other.dataset.name || self.dataset.version != other.dataset.version {
+            anyhow::bail!(
+                "Cannot merge manifests for different datasets: {}/{} and {}/{}",
+                self.dataset.name,
+                self.dataset.version,
+                other.dataset.name,
+                other.dataset.version
+            );
+        }
+
+        let mut contents = self.contents.clone();
+        for content in &other.contents {
+            match contents.iter().find(|c| c.path == content.path) {
+                Some(existing) if existing.hash == content.hash => continue,
+                Some(existing) => anyhow::bail!(
+                    "Path collision while merging '{}': {} vs {}",
+                    content.path,
+                    existing.hash,
+                    content.hash
+                ),
+                None => contents.push(content.clone()),
+            }
+        }
+
+        let mut transformations = self.transformations.clone();
+        for transformation in &other.transformations {
+            if !transformations.contains(transformation) {
+                transformations.push(transformation.clone());
+            }
+        }
+
+        let schema_version =
+            if parse_schema_version(&other.schema_version) > parse_schema_version(&self.schema_version) {
+                other.schema_version.clone()
+            } else {
+                self.schema_version.clone()
+            };
+
+        let mut dataset = self.dataset.clone();
+        if dataset.description.is_none() {
+            dataset.description = other.dataset.description.clone();
+        }
+
+        Ok(Manifest {
+            schema_version,
+            dataset,
+            source: self.source.clone(),
+            contents,
+            transformations,
+            signature: None,
+        })
+    }
+
+    /// Compare the contents of two manifests by path
+    ///
+    /// A path present in both with a different hash is reported as modified.
+    /// Each bucket is sorted by path.
+    pub fn diff(old: &Manifest, new: &Manifest) -> ManifestDiff {
+        let old_by_path: BTreeMap<&str, &Content> =
+            old.contents.iter().map(|c| (c.path.as_str(), c)).collect();
+        let new_by_path: BTreeMap<&str, &Content> =
+            new.contents.iter().map(|c| (c.path.as_str(), c)).collect();
+
+        let mut diff = ManifestDiff::default();
+
+        for (path, new_content) in &new_by_path {
+            match old_by_path.get(path) {
+                None => diff.added.push((*new_content).clone()),
+                Some(old_content) if old_content.hash != new_content.hash => {
+                    diff.modified.push(ModifiedContent {
+                        old: (*old_content).clone(),
+                        new: (*new_content).clone(),
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (path, old_content) in &old_by_path {
+            if !new_by_path.contains_key(path) {
+                diff.removed.push((*old_content).clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Order contents by path
+    ///
+    /// Directory scans return entries in file-system order; sorting before
+    /// serializing makes the same tree always produce the same JSON.
+    pub fn sort_contents(&mut self) {
+        self.contents.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    /// Record which `input` contents each of this manifest's files came from
+    ///
+    /// `mapping` maps an output path to the input paths that produced it;
+    /// each entry's `derived_from` becomes the sorted, deduplicated hashes of
+    /// those inputs. Paths missing from either manifest are an error.
+    /// Entries not named in `mapping` are left as they are.
+    pub fn record_derivation(&mut self, input: &Manifest, mapping: &BTreeMap<String, Vec<String>>) -> Result<()> {
+        let input_hashes: BTreeMap<&str, &str> = input
+            .contents
+            .iter()
+            .map(|c| (c.path.as_str(), c.hash.as_str()))
+            .collect();
+
+        for (output_path, input_paths) in mapping {
+            let mut derived_from = input_paths
+                .iter()
+                .map(|path| {
+                    let hash = input_hashes
+                        .get(path.as_str())
+                        .with_context(|| format!("Unknown input path for '{}': {}", output_path, path))?;
+                    Ok(Blake3Hash::from_str(hash)?.to_string_prefixed())
+                })
+                .collect::<Result<Vec<String>>>()?;
+            derived_from.sort();
+            derived_from.dedup();
+
+            let content = self
+                .contents
+                .iter_mut()
+                .find(|c| c.path == *output_path)
+                .with_context(|| format!("Unknown output path in derivation mapping: {}", output_path))?;
+            content.derived_from = derived_from;
+        }
+        Ok(())
+    }
+
+    /// Stable hash identifying this manifest
+    ///
+    /// Computed over `canonical_bytes`, so it is independent of JSON key
+    /// order, whitespace, content ordering, and any embedded signature.
+    /// This is the hash under which a dataset's manifest is stored and
+    /// registered.
+    pub fn content_hash(&self) -> Blake3Hash {
+        Blake3Hash::from_bytes(&canonical_bytes(self))
+    }
+
+    /// Check that every file this manifest references is in `storage`
+    ///
+    /// Symlinks and directories are not stored and are skipped. With `deep`,
+    /// each object is also re-hashed to detect corruption. A hash listed
+    /// under several paths is checked once.
+    pub async fn verify_against(&self, storage: &dyn StorageBackend, deep: bool) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        let mut seen = HashSet::new();
+
+        for content in self.contents.iter().filter(|c| c.kind.is_file()) {
+            let hash = Blake3Hash::from_str(&content.hash)
+                .with_context(|| format!("Invalid hash for {}: {}", content.path, content.hash))?;
+            if !seen.insert(hash) {
+                continue;
+            }
+            report.checked += 1;
+
+            if !storage.exists(&hash).await {
+                report.missing.push(hash.to_string_prefixed());
+                continue;
+            }
+            if !deep {
+                continue;
+            }
+
+            let reader = storage.open(&hash).await?;
+            let actual = tokio::task::spawn_blocking(move || Blake3Hash::from_reader(reader)).await??;
+            if actual != hash {
+                report.corrupted.push(hash.to_string_prefixed());
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Sign this manifest and embed the signature and public key
+    pub fn sign(&mut self, signing_key: &SigningKey) {
+        let signature = sign(self, signing_key);
+        self.signature = Some(ManifestSignature::new(&signature, &signing_key.verifying_key()));
+    }
+}
+
+/// Outcome of `Manifest::verify_against`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// Distinct objects checked
+    pub checked: usize,
+    /// Objects absent from the store
+    pub missing: Vec<String>,
+    /// Objects whose contents no longer match their hash (deep checks only)
+    pub corrupted: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Whether every referenced object is present and intact
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty()
+    }
+}
+
+/// Content changes between two manifests, as computed by `Manifest::diff`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ManifestDiff {
+    pub added: Vec<Content>,
+    pub removed: Vec<Content>,
+    pub modified: Vec<ModifiedContent>,
+}
+
+impl ManifestDiff {
+    /// Whether the two manifests have identical contents
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// A path whose hash differs between two manifests
+#[derive(Debug, Clone, Serialize)]
+pub struct ModifiedContent {
+    pub old: Content,
+    pub new: Content,
+}
+
+/// Detached ed25519 signature over a manifest's canonical bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestSignature {
+    /// Signature algorithm (always "ed25519")
+    pub algorithm: String,
+    /// Hex-encoded public key of the signer
+    pub public_key: String,
+    /// Hex-encoded signature
+    pub signature: String,
+}
+
+impl ManifestSignature {
+    pub fn new(signature: &Signature, public_key: &VerifyingKey) -> Self {
+        Self {
+            algorithm: "ed25519".to_string(),
+            public_key: hex::encode(public_key.as_bytes()),
+            signature: hex::encode(signature.to_bytes()),
+        }
+    }
+}
+
+/// Sign a manifest's canonical bytes with ed25519
+///
+/// Any embedded signature is excluded from the signed bytes, so the result
+/// can be stored in `Manifest.signature` without invalidating itself.
+pub fn sign(manifest: &Manifest, signing_key: &SigningKey) -> Signature {
+    signing_key.sign(&canonical_bytes(manifest))
+}
+
+/// Verify a manifest's embedded ed25519 signature against a trusted key
+///
+/// The embedded public key is never trusted on its own: if it differs from
+/// `verifying_key` the manifest is rejected even if its signature is valid
+/// for the embedded key.
+pub fn verify_signature(manifest: &Manifest, verifying_key: &VerifyingKey) -> Result<()> {
+    let embedded = manifest
+        .signature
+        .as_ref()
+        .context("Manifest is not signed")?;
+
+    if embedded.algorithm != "ed25519" {
+        anyhow::bail!("Unsupported signature algorithm: {}", embedded.algorithm);
+    }
+
+    let expected_key = hex::encode(verifying_key.as_bytes());
+    if !embedded.public_key.eq_ignore_ascii_case(&expected_key) {
+        anyhow::bail!(
+            "Manifest was signed by a different key (embedded: {}, expected: {})",
+            embedded.public_key,
+            expected_key
+        );
+    }
+
+    let signature_bytes: [u8; 64] = hex::decode(&embedded.signature)
+        .context("Manifest signature is not valid hex")?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| {
+            anyhow::anyhow!("Manifest signature has wrong length: {} bytes (expected 64)", bytes.len())
+        })?;
+    let signature = Signature::from_bytes(&signature_bytes);
+
+    verifying_key
+        .verify_strict(&canonical_bytes(manifest), &signature)
+        .context("Manifest signature does not match its contents")
+}
+
+/// Parse a hex-encoded ed25519 public key
+pub fn parse_verifying_key(hex_key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .context("Public key is not valid hex")?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| {
+            anyhow::anyhow!("Public key has wrong length: {} bytes (expected 32)", bytes.len())
+        })?;
+
+    VerifyingKey::from_bytes(&bytes).context("Invalid ed25519 public key")
+}
+
+/// Deterministic byte serialization of a manifest used for hashing and signing
+///
+/// The UTF-8 bytes of `to_canonical_json`.
+pub fn canonical_bytes(manifest: &Manifest) -> Vec<u8> {
+    to_canonical_json(manifest)
+        .expect("manifest serializes to JSON")
+        .into_bytes()
+}
+
+/// Canonical compact JSON for a manifest
+///
+/// Contents are sorted by path and the signature is omitted. Object keys,
+/// including those nested in transformation `params`, are sorted
+/// recursively regardless of how the map was built, and integral floats are
+/// written as integers, so equal manifests always produce the same string.
+pub fn to_canonical_json(manifest: &Manifest) -> Result<String> {
+    let mut canonical = manifest.clone();
+    canonical.signature = None;
+    canonical.contents.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let value = serde_json::to_value(&canonical).context("Failed to serialize manifest")?;
+    let mut out = String::new();
+    write_canonical(&value, &mut out)?;
+    Ok(out)
+}
+
+/// Append `value` to `out` with sorted keys and normalized numbers
+fn write_canonical(value: &serde_json::Value, out: &mut String) -> Result<()> {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key)?);
+                out.push(':');
+                write_canonical(value, out)?;
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out)?;
+            }
+            out.push(']');
+        }
+        Value::Number(number) => match number.as_f64() {
+            // 2^53 bounds the integers an f64 represents exactly
+            Some(f) if number.is_f64() && f.fract() == 0.0 && f.abs() < 9_007_199_254_740_992.0 => {
+                out.push_str(&(f as i64).to_string())
+            }
+            _ => out.push_str(&number.to_string()),
+        },
+        other => out.push_str(&serde_json::to_string(other)?),
+    }
+    Ok(())
+}
+
+/// Upgrade a 0.9 manifest, which predates transformation tracking
+fn upgrade_from_0_9(doc: &mut serde_json::Value) -> Result<()> {
+    let obj = doc.as_object_mut().context("Manifest must be a JSON object")?;
+
+    obj.entry("transformations")
+        .or_insert_with(|| serde_json::Value::Array(vec![]));
+    obj.insert(
+        "schema_version".to_string(),
+        serde_json::Value::String(CURRENT_SCHEMA_VERSION.to_string()),
+    );
+
+    tracing::debug!("Upgraded manifest from schema 0.9 to {}", CURRENT_SCHEMA_VERSION);
+    Ok(())
+}
+
+/// Parse a `major.minor` schema version for ordering (unparseable parts are 0)
+fn parse_schema_version(version: &str) -> (u64, u64) {
+    let mut parts = version.split('.').map(|p| p.parse().unwrap_or(0));
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dataset {
+    pub name: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl Dataset {
+    /// Split a dataset name into its optional namespace and the name within it
+    ///
+    /// `team/dataset` is `dataset` in namespace `team`; a name without `/`
+    /// has no namespace. Only the first `/` splits, so an OCI repository
+    /// such as `org/team/image` is `team/image` in namespace `org`.
+    /// Namespaces use ASCII letters, digits, `.`, `_`, and `-`, and do not
+    /// start with `.`. Names may not contain `@` (it separates the version
+    /// in `name@version` references), whitespace, or empty `/` segments.
+    pub fn split_name(name: &str) -> Result<(Option<&str>, &str)> {
+        let (namespace, local) = match name.split_once('/') {
+            Some((namespace, local)) => (Some(namespace), local),
+            None => (None, name),
+        };
+
+        if let Some(namespace) = namespace {
+            Self::check_namespace(namespace).with_context(|| format!("Invalid dataset name '{}'", name))?;
+        }
+        if local.trim().is_empty() {
+            anyhow::bail!("Dataset name is empty: '{}'", name);
+        }
+        if local.split('/').any(str::is_empty) {
+            anyhow::bail!("Dataset name has an empty path segment: '{}'", name);
+        }
+        if let Some(c) = local.chars().find(|&c| c == '@' || c.is_whitespace() || c.is_control()) {
+            anyhow::bail!("Dataset name '{}' contains invalid character {:?}", name, c);
+        }
+        Ok((namespace, local))
+    }
+
+    /// Check the characters of a namespace, as `split_name` does
+    pub fn check_namespace(namespace: &str) -> Result<()> {
+        let valid = !namespace.is_empty()
+            && !namespace.starts_with('.')
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        if !valid {
+            anyhow::bail!(
+                "Invalid namespace '{}' (use letters, digits, '.', '_', and '-', not starting with '.')",
+                namespace
+            );
+        }
+        Ok(())
+    }
+
+    /// Namespace of this dataset's name, if it has a valid one
+    pub fn namespace(&self) -> Option<&str> {
+        Self::split_name(&self.name).ok().and_then(|(namespace, _)| namespace)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_mtime: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_type: Option<ArchiveType>,
+}
+
+impl Source {
+    /// Describe a local archive file
+    ///
+    /// Records the archive's `blake3:` hash, its modification time (RFC 3339)
+    /// as `server_mtime`, and its type as detected from magic bytes.
+    pub fn from_archive(path: &Path) -> Result<Source> {
+        let hash = Blake3Hash::from_file(path)
+            .with_context(|| format!("Failed to hash archive: {}", path.display()))?;
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to stat archive: {}", path.display()))?;
+        let server_mtime = metadata
+            .modified()
+            .ok()
+            .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+
+        let archive_type = std::fs::File::open(path)
+            .and_then(ArchiveType::detect_reader)
+            .with_context(|| format!("Failed to read archive: {}", path.display()))?;
+
+        Ok(Source {
+            url: None,
+            download_date: None,
+            server_mtime,
+            archive_hash: Some(hash.to_string_prefixed()),
+            archive_type,
+        })
+    }
+}
+
+/// Size of a tar header block, enough to recognize every `ArchiveType`
+const TAR_HEADER_SIZE: usize = 512;
+
+/// Archive or compression format of a source, detected from magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveType {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Zip,
+    Tar,
+}
+
+impl ArchiveType {
+    /// Identify the format from the start of a file, if known
+    pub fn detect(header: &[u8]) -> Option<ArchiveType> {
+        const MAGIC: &[(&[u8], ArchiveType)] = &[
+            (&[0x1f, 0x8b], ArchiveType::Gzip),
+            (&[0x28, 0xb5, 0x2f, 0xfd], ArchiveType::Zstd),
+            (b"BZh", ArchiveType::Bzip2),
+            (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], ArchiveType::Xz),
+            (b"PK\x03\x04", ArchiveType::Zip),
+        ];
+
+        if let Some((_, kind)) = MAGIC.iter().find(|(magic, _)| header.starts_with(magic)) {
+            return Some(*kind);
+        }
+        // POSIX and GNU tar both put "ustar" at offset 257
+        match header.get(257..262) {
+            Some(b"ustar") => Some(ArchiveType::Tar),
+            _ => None,
+        }
+    }
+
+    /// Identify the format from the first bytes read from `reader`
+    pub fn detect_reader(reader: impl Read) -> std::io::Result<Option<ArchiveType>> {
+        let mut header = Vec::with_capacity(TAR_HEADER_SIZE);
+        reader.take(TAR_HEADER_SIZE as u64).read_to_end(&mut header)?;
+        Ok(ArchiveType::detect(&header))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Content {
+    pub path: String,
+    pub hash: String,
+    pub size: u64,
+    #[serde(default)]
+    pub executable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "ContentKind::is_file")]
+    pub kind: ContentKind,
+    /// Hashes of the input contents this entry was produced from, when the
+    /// transform that wrote it knows; empty (and omitted) otherwise
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub derived_from: Vec<String>,
+}
+
+/// What a manifest content entry represents
+///
+/// Regular files are the default and are omitted when serializing, so
+/// manifests without symlinks or directories look exactly as before.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ContentKind {
+    /// Regular file stored in CAS under `hash`
+    #[default]
+    File,
+    /// Symbolic link; `hash` and `size` describe the target string
+    Symlink { target: String },
+    /// Directory (used to preserve empty directories)
+    Directory,
+}
+
+impl ContentKind {
+    pub fn is_file(&self) -> bool {
+        matches!(self, ContentKind::File)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transformation {
+    #[serde(rename = "type")]
+    pub transform_type: String,
+    pub from: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_serialization() {
+        let manifest = Manifest {
+            schema_version: "1.0".to_string(),
+            dataset: Dataset {
+                name: "test".to_string(),
+                version: "1.0.0".to_string(),
+                description: None,
+            },
+            source: Source {
+                url: None,
+                download_date: None,
+                server_mtime: None,
+                archive_hash: None,
+                archive_type: None,
+            },
+            contents: vec![],
+            transformations: vec![],
+            signature: None,
+        };
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert!(json.contains("test"));
+    }
+
+    fn signed_test_manifest() -> (Manifest, SigningKey) {
+        let json = r#"{
+            "schema_version": "1.0",
+            "dataset": {"name": "signed", "version": "1.0.0"},
+            "source": {},
+            "contents": [
+                {"path": "b.txt", "hash": "blake3:bb", "size": 2},
+                {"path": "a.txt", "hash": "blake3:aa", "size": 1}
+            ]
+        }"#;
+        let manifest = Manifest::from_json(json).unwrap();
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        (manifest, signing_key)
+    }
+
+    #[test]
+    fn test_sign_manifest() {
+        use ed25519_dalek::Verifier;
+
+        let (mut manifest, signing_key) = signed_test_manifest();
+        manifest.sign(&signing_key);
+
+        let embedded = manifest.signature.clone().unwrap();
+        assert_eq!(embedded.algorithm, "ed25519");
+        assert_eq!(embedded.public_key, hex::encode(signing_key.verifying_key().as_bytes()));
+
+        let signature = sign(&manifest, &signing_key);
+        assert_eq!(embedded.signature, hex::encode(signature.to_bytes()));
+        assert!(signing_key
+            .verifying_key()
+            .verify(&canonical_bytes(&manifest), &signature)
+            .is_ok());
+
+        // Content ordering doesn't affect the signed bytes
+        let mut reordered = manifest.clone();
+        reordered.contents.reverse();
+        assert_eq!(canonical_bytes(&reordered), canonical_bytes(&manifest));
+
+        // Mutating contents invalidates the signature
+        let mut tampered = manifest.clone();
+        tampered.contents[0].size += 1;
+        assert!(signing_key
+            .verifying_key()
+            .verify(&canonical_bytes(&tampered), &signature)
+            .is_err());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let (mut manifest, signing_key) = signed_test_manifest();
+        manifest.sign(&signing_key);
+
+        assert!(verify_signature(&manifest, &signing_key.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn test_verify_signature_rejects_tamper() {
+        let (mut manifest, signing_key) = signed_test_manifest();
+        manifest.sign(&signing_key);
+
+        // Flip a single byte of the serialized manifest
+        let json = serde_json::to_string(&manifest).unwrap();
+        let tampered_json = json.replacen("blake3:aa", "blake3:ab", 1);
+        assert_eq!(json.len(), tampered_json.len());
+
+        let tampered = Manifest::from_json(&tampered_json).unwrap();
+        let err = verify_signature(&tampered, &signing_key.verifying_key()).unwrap_err();
+        assert!(format!("{:#}", err).contains("does not match"));
+    }
+
+    #[test]
+    fn test_verify_signature_rejects_unsigned() {
+        let (manifest, signing_key) = signed_test_manifest();
+        let err = verify_signature(&manifest, &signing_key.verifying_key()).unwrap_err();
+        assert!(err.to_string().contains("not signed"));
+    }
+
+    #[test]
+    fn test_verify_signature_rejects_other_embedded_key() {
+        let (mut manifest, _) = signed_test_manifest();
+        let attacker_key = SigningKey::from_bytes(&[9u8; 32]);
+        let trusted_key = SigningKey::from_bytes(&[7u8; 32]);
+
+        // Validly self-signed by another key; must not be trusted
+        manifest.sign(&attacker_key);
+        let err = verify_signature(&manifest, &trusted_key.verifying_key()).unwrap_err();
+        assert!(err.to_string().contains("different key"));
+    }
+
+    #[test]
+    fn test_parse_verifying_key() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let hex_key = hex::encode(signing_key.verifying_key().as_bytes());
+
+        assert_eq!(parse_verifying_key(&hex_key).unwrap(), signing_key.verifying_key());
+        assert!(parse_verifying_key("abcd").is_err());
+    }
+
+    #[test]
+    fn test_signature_round_trips_through_json() {
+        let (mut manifest, signing_key) = signed_test_manifest();
+        manifest.sign(&signing_key);
+
+        let json = serde_json::to_string_pretty(&manifest).unwrap();
+        let parsed = Manifest::from_json(&json).unwrap();
+        assert_eq!(parsed.signature, manifest.signature);
+    }
+
+    fn manifest_with(contents: &[(&str, &str)]) -> Manifest {
+        Manifest {
+            schema_version: "1.0".to_string(),
+            dataset: Dataset {
+                name: "merged".to_string(),
+                version: "1.0.0".to_string(),
+                description: None,
+            },
+            source: Source {
+                url: None,
+                download_date: None,
+                server_mtime: None,
+                archive_hash: None,
+                archive_type: None,
+            },
+            contents: contents
+                .iter()
+                .map(|(path, hash)| Content {
+                    path: path.to_string(),
+                    hash: hash.to_string(),
+                    size: 1,
+                    executable: false,
+                    mime_type: None,
+                    kind: ContentKind::File,
+                    derived_from: Vec::new(),
+                })
+                .collect(),
+            transformations: vec![],
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_record_derivation_serializes_per_content() {
+        let aa = Blake3Hash::from_bytes(b"a").to_string_prefixed();
+        let bb = Blake3Hash::from_bytes(b"b").to_string_prefixed();
+        let input = manifest_with(&[("a.fasta", aa.as_str()), ("b.fasta", bb.as_str())]);
+        let merged = Blake3Hash::from_bytes(b"merged").to_string_prefixed();
+        let untracked = Blake3Hash::from_bytes(b"log").to_string_prefixed();
+        let mut output = manifest_with(&[("merged.fasta", merged.as_str()), ("run.log", untracked.as_str())]);
+
+        let mapping = BTreeMap::from([(
+            "merged.fasta".to_string(),
+            vec!["b.fasta".to_string(), "a.fasta".to_string(), "b.fasta".to_string()],
+        )]);
+        output.record_derivation(&input, &mapping).unwrap();
+        output.validate().unwrap();
+
+        let json: serde_json::Value = serde_json::to_value(&output).unwrap();
+        let mut expected = vec![aa.clone(), bb.clone()];
+        expected.sort();
+        assert_eq!(json["contents"][0]["derived_from"], serde_json::json!(expected));
+        assert!(json["contents"][1].get("derived_from").is_none());
+        assert_eq!(Manifest::from_json(&json.to_string()).unwrap().contents, output.contents);
+
+        let unknown = BTreeMap::from([("merged.fasta".to_string(), vec!["c.fasta".to_string()])]);
+        assert!(output.clone().record_derivation(&input, &unknown).is_err());
+        let unknown = BTreeMap::from([("missing".to_string(), vec!["a.fasta".to_string()])]);
+        assert!(output.record_derivation(&input, &unknown).is_err());
+    }
+
+    #[test]
+    fn test_merge_disjoint() {
+        let mut left = manifest_with(&[("a.txt", "blake3:aa"), ("shared.txt", "blake3:ss")]);
+        left.transformations.push(Transformation {
+            transform_type: "extract".to_string(),
+            from: "blake3:00".to_string(),
+            params: None,
+        });
+        let mut right = manifest_with(&[("b.txt", "blake3:bb"), ("shared.txt", "blake3:ss")]);
+        right.schema_version = "0.9".to_string();
+        right.transformations = left.transformations.clone();
+
+        let merged = left.merge(&right).unwrap();
+        let paths: Vec<&str> = merged.contents.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "shared.txt", "b.txt"]);
+        assert_eq!(merged.transformations.len(), 1);
+        assert_eq!(merged.schema_version, "1.0");
+    }
+
+    #[test]
+    fn test_merge_path_collision() {
+        let left = manifest_with(&[("a.txt", "blake3:aa")]);
+        let right = manifest_with(&[("a.txt", "blake3:ff")]);
+
+        let err = left.merge(&right).unwrap_err().to_string();
+        assert!(err.contains("a.txt"));
+    }
+
+    #[test]
+    fn test_merge_different_datasets() {
+        let left = manifest_with(&[]);
+        let mut right = manifest_with(&[]);
+        right.dataset.version = "2.0.0".to_string();
+
+        assert!(left.merge(&right).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = manifest_with(&[
+            ("kept.txt", "blake3:kk"),
+            ("removed.txt", "blake3:rr"),
+            ("changed.txt", "blake3:c1"),
+        ]);
+        let new = manifest_with(&[
+            ("kept.txt", "blake3:kk"),
+            ("changed.txt", "blake3:c2"),
+            ("added.txt", "blake3:aa"),
+        ]);
+
+        let diff = Manifest::diff(&old, &new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].path, "added.txt");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].path, "removed.txt");
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].old.hash, "blake3:c1");
+        assert_eq!(diff.modified[0].new.hash, "blake3:c2");
+
+        assert!(Manifest::diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_content_hash_is_canonical() {
+        let compact = r#"{"schema_version":"1.0","dataset":{"name":"h","version":"1"},"source":{},"contents":[{"path":"a.txt","hash":"blake3:aa","size":1},{"path":"b.txt","hash":"blake3:bb","size":2}]}"#;
+        let reordered = r#"{
+            "contents": [
+                {"size": 2, "hash": "blake3:bb", "path": "b.txt"},
+                {"path": "a.txt", "hash": "blake3:aa", "size": 1}
+            ],
+            "source": {},
+            "dataset": {"version": "1", "name": "h"},
+            "schema_version": "1.0"
+        }"#;
+
+        let a = Manifest::from_json(compact).unwrap();
+        let b = Manifest::from_json(reordered).unwrap();
+        assert_eq!(a.content_hash(), b.content_hash());
+
+        // Signing doesn't change the content hash
+        let mut signed = a.clone();
+        signed.sign(&SigningKey::from_bytes(&[7u8; 32]));
+        assert_eq!(signed.content_hash(), a.content_hash());
+
+        // Changing content does
+        let mut changed = a.clone();
+        changed.contents[0].size = 42;
+        assert_ne!(changed.content_hash(), a.content_hash());
+    }
+
+    #[test]
+    fn test_content_mime_type_optional() {
+        let json = r#"{"path": "a.txt", "hash": "blake3:aa", "size": 1}"#;
+        let content: Content = serde_json::from_str(json).unwrap();
+        assert_eq!(content.mime_type, None);
+        assert!(!serde_json::to_string(&content).unwrap().contains("mime_type"));
+
+        let json = r#"{"path": "a.txt", "hash": "blake3:aa", "size": 1, "mime_type": "text/plain"}"#;
+        let content: Content = serde_json::from_str(json).unwrap();
+        assert_eq!(content.mime_type.as_deref(), Some("text/plain"));
+    }
+
+    #[test]
+    fn test_validate_rejects_duplicate_paths() {
+        let a = Blake3Hash::from_bytes(b"a").to_string_prefixed();
+        let b = Blake3Hash::from_bytes(b"b").to_string_prefixed();
+
+        assert!(manifest_with(&[("a.txt", &a), ("b.txt", &b)]).validate().is_ok());
+
+        let err = manifest_with(&[("a.txt", &a), ("a.txt", &b)]).validate().unwrap_err();
+        assert!(err.to_string().contains("a.txt"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_validate_rejects_malformed_manifests() {
+        let a = Blake3Hash::from_bytes(b"a").to_string_prefixed();
+
+        let mut unnamed = manifest_with(&[("a.txt", &a)]);
+        unnamed.dataset.name = String::new();
+        assert!(unnamed.validate().is_err());
+
+        // Names from before namespaces stay valid manifests
+        unnamed.dataset.name = "legacy name@2020".to_string();
+        assert!(unnamed.validate().is_ok());
+
+        assert!(manifest_with(&[("a.txt", "blake3:nothex")]).validate().is_err());
+
+        let mut dangling = manifest_with(&[("a.txt", &a)]);
+        dangling.transformations.push(Transformation {
+            transform_type: "extract".to_string(),
+            from: "blake3:unknown".to_string(),
+            params: None,
+        });
+        assert!(dangling.validate().is_err());
+
+        dangling.transformations[0].from = a.clone();
+        assert!(dangling.validate().is_ok());
+
+        // The hash of the manifest a transform read is not among the contents
+        dangling.transformations[0].from = Blake3Hash::from_bytes(b"upstream manifest").to_string_prefixed();
+        assert!(dangling.validate().is_ok());
+    }
+
+    #[test]
+    fn test_content_kind_serialization() {
+        let json = r#"{"path": "a.txt", "hash": "blake3:aa", "size": 1}"#;
+        let content: Content = serde_json::from_str(json).unwrap();
+        assert_eq!(content.kind, ContentKind::File);
+        assert!(!serde_json::to_string(&content).unwrap().contains("kind"));
+
+        let json = r#"{"path": "link", "hash": "blake3:aa", "size": 5, "kind": {"type": "symlink", "target": "a.txt"}}"#;
+        let content: Content = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            content.kind,
+            ContentKind::Symlink {
+                target: "a.txt".to_string()
+            }
+        );
+
+        let round_tripped: Content =
+            serde_json::from_str(&serde_json::to_string(&content).unwrap()).unwrap();
+        assert_eq!(round_tripped, content);
+    }
+
+    #[test]
+    fn test_split_dataset_name() {
+        assert_eq!(Dataset::split_name("uniprot").unwrap(), (None, "uniprot"));
+        assert_eq!(Dataset::split_name("team-a/ds").unwrap(), (Some("team-a"), "ds"));
+        assert_eq!(Dataset::split_name("ghcr/org/image").unwrap(), (Some("ghcr"), "org/image"));
+
+        for bad in ["/ds", "team a/ds", ".hidden/ds", "team/", "team//ds", "team/ds@1", "my ds"] {
+            assert!(Dataset::split_name(bad).is_err(), "{}", bad);
+        }
+
+        let dataset = Dataset {
+            name: "team-b/ds".to_string(),
+            version: "1".to_string(),
+            description: None,
+        };
+        assert_eq!(dataset.namespace(), Some("team-b"));
+    }
+
+    #[test]
+    fn test_from_json_current_version() {
+        let json = r#"{
+            "schema_version": "1.0",
+            "dataset": {"name": "test", "version": "1.0.0"},
+            "source": {},
+            "contents": [{"path": "a.txt", "hash": "blake3:00", "size": 1}],
+            "transformations": [{"type": "extract", "from": "blake3:00"}]
+        }"#;
+
+        let manifest = Manifest::from_json(json).unwrap();
+        assert_eq!(manifest.contents.len(), 1);
+        assert_eq!(manifest.transformations.len(), 1);
+    }
+
+    #[test]
+    fn test_from_json_upgrades_legacy() {
+        let json = r#"{
+            "schema_version": "0.9",
+            "dataset": {"name": "legacy", "version": "0.1.0"},
+            "source": {"url": "https://example.com/data.tar.gz"},
+            "contents": []
+        }"#;
+
+        let manifest = Manifest::from_json(json).unwrap();
+        assert_eq!(manifest.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(manifest.dataset.name, "legacy");
+        assert!(manifest.transformations.is_empty());
+    }
+
+    #[test]
+    fn test_from_json_rejects_future_version() {
+        let json = r#"{
+            "schema_version": "99.0",
+            "dataset": {"name": "future", "version": "1.0.0"},
+            "source": {},
+            "contents": []
+        }"#;
+
+        let err = Manifest::from_json(json).unwrap_err().to_string();
+        assert!(err.contains("99.0"));
+        assert!(err.contains("newer"));
+    }
+
+    #[test]
+    fn test_from_json_missing_version() {
+        let json = r#"{"dataset": {"name": "x", "version": "1"}, "source": {}, "contents": []}"#;
+        assert!(Manifest::from_json(json).is_err());
+    }
+
+    #[test]
+    fn test_from_path_yaml_and_toml_match_json() {
+        let json = r#"{
+            "schema_version": "1.0",
+            "dataset": {"name": "test", "version": "1.0.0", "description": "sample"},
+            "source": {"url": "https://example.com/data.tar.gz"},
+            "contents": [{"path": "a.txt", "hash": "blake3:00", "size": 1}],
+            "transformations": [{"type": "extract", "from": "blake3:00"}]
+        }"#;
+        let yaml = "schema_version: \"1.0\"
+dataset:
+  name: test
+  version: 1.0.0
+  description: sample
+source:
+  url: https://example.com/data.tar.gz
+contents:
+  - path: a.txt
+    hash: blake3:00
+    size: 1
+transformations:
+  - type: extract
+    from: blake3:00
+";
+        let toml = r#"schema_version = "1.0"
+
+[dataset]
+name = "test"
+version = "1.0.0"
+description = "sample"
+
+[source]
+url = "https://example.com/data.tar.gz"
+
+[[contents]]
+path = "a.txt"
+hash = "blake3:00"
+size = 1
+
+[[transformations]]
+type = "extract"
+from = "blake3:00"
+"#;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let parse = |name: &str, text: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            serde_json::to_value(Manifest::from_path(&path).unwrap()).unwrap()
+        };
+        let expected = parse("manifest.json", json);
+        assert_eq!(parse("manifest.yaml", yaml), expected);
+        assert_eq!(parse("manifest.yml", yaml), expected);
+        assert_eq!(parse("manifest.toml", toml), expected);
+        assert!(Manifest::from_path(&temp_dir.path().join("missing.yaml")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_against_reports_missing() {
+        use crate::storage::local::LocalStorage;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        let kept = storage.put(b"kept").await.unwrap();
+        let lost = storage.put(b"lost").await.unwrap();
+        let (kept_hex, lost_hex) = (kept.to_string_prefixed(), lost.to_string_prefixed());
+        let manifest = manifest_with(&[("kept", &kept_hex), ("lost", &lost_hex)]);
+
+        let report = manifest.verify_against(&storage, true).await.unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.checked, 2);
+
+        storage.delete(&lost).await.unwrap();
+        let report = manifest.verify_against(&storage, false).await.unwrap();
+        assert!(!report.is_complete());
+        assert_eq!(report.missing, vec![lost.to_string_prefixed()]);
+        assert!(report.corrupted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_verify_against_deep_detects_corruption() {
+        use crate::storage::local::LocalStorage;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = LocalStorage::with_root(temp_dir.path());
+        let hash = storage.put(b"original").await.unwrap();
+        std::fs::write(storage.get(&hash).await.unwrap(), b"tampered").unwrap();
+
+        let manifest = manifest_with(&[("file", &hash.to_string_prefixed())]);
+        assert!(manifest.verify_against(&storage, false).await.unwrap().is_complete());
+
+        let report = manifest.verify_against(&storage, true).await.unwrap();
+        assert_eq!(report.corrupted, vec![hash.to_string_prefixed()]);
+    }
+
+    #[test]
+    fn test_canonical_json_ignores_params_order() {
+        let with_params = |params: &str| {
+            Manifest::from_json(&format!(
+                r#"{{"schema_version": "1.0", "dataset": {{"name": "p", "version": "1"}}, "source": {{}},
+                    "contents": [], "transformations": [{{"type": "filter", "from": "blake3:aa", "params": {}}}]}}"#,
+                params
+            ))
+            .unwrap()
+        };
+
+        let first = with_params(r#"{"min_length": 50, "nested": {"z": 1, "a": [2.0, "x"]}}"#);
+        let second = with_params(r#"{"nested": {"a": [2, "x"], "z": 1.0}, "min_length": 50.0}"#);
+
+        let json = to_canonical_json(&first).unwrap();
+        assert_eq!(json, to_canonical_json(&second).unwrap());
+        assert!(json.contains(r#""params":{"min_length":50,"nested":{"a":[2,"x"],"z":1}}"#), "{}", json);
+        assert_eq!(first.content_hash(), second.content_hash());
+    }
+
+    #[test]
+    fn test_source_from_gzip_archive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.gz");
+        // gzip stream of an empty file
+        let gzip = [0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0x03, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        std::fs::write(&path, gzip).unwrap();
+
+        let source = Source::from_archive(&path).unwrap();
+        assert_eq!(source.archive_type, Some(ArchiveType::Gzip));
+        assert_eq!(source.archive_hash, Some(Blake3Hash::from_bytes(&gzip).to_string_prefixed()));
+        assert!(source.server_mtime.is_some());
+        assert!(source.url.is_none());
+    }
+
+    #[test]
+    fn test_source_from_tar_archive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.tar");
+
+        let mut builder = tar::Builder::new(std::fs::File::create(&path).unwrap());
+        let mut header = tar::Header::new_ustar();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "hello.txt", &b"hello"[..]).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let source = Source::from_archive(&path).unwrap();
+        assert_eq!(source.archive_type, Some(ArchiveType::Tar));
+        assert_eq!(
+            source.archive_hash,
+            Some(Blake3Hash::from_file(&path).unwrap().to_string_prefixed())
+        );
+
+        let plain = temp_dir.path().join("plain.txt");
+        std::fs::write(&plain, b"not an archive").unwrap();
+        assert_eq!(Source::from_archive(&plain).unwrap().archive_type, None);
+    }
+}
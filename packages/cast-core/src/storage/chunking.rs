@@ -0,0 +1,134 @@
+// Content-defined chunking for large-file deduplication
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::hash::Blake3Hash;
+
+/// Smallest chunk FastCDC will cut (except for the final chunk)
+pub const MIN_CHUNK_SIZE: u32 = 16 * 1024;
+
+/// Chunk size FastCDC aims for on average
+pub const AVG_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Largest chunk FastCDC will cut
+pub const MAX_CHUNK_SIZE: u32 = 256 * 1024;
+
+/// Split a stream into content-defined chunks
+///
+/// Boundaries depend only on nearby content, so an edit in one region of a
+/// file leaves the chunks of the other regions unchanged.
+pub fn split<R: Read>(reader: R) -> impl Iterator<Item = Result<Vec<u8>>> {
+    fastcdc::v2020::StreamCDC::new(reader, MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE)
+        .map(|chunk| chunk.map(|chunk| chunk.data).context("Failed to read data for chunking"))
+}
+
+/// One chunk of a chunked object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef {
+    pub hash: Blake3Hash,
+    pub size: u64,
+}
+
+/// Ordered chunk list from which a chunked object is reassembled
+///
+/// Stored in place of the object itself, addressed by the hash of the
+/// whole content.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recipe {
+    /// Total size of the reassembled object
+    pub size: u64,
+    pub chunks: Vec<ChunkRef>,
+}
+
+impl Recipe {
+    /// Parse a recipe read from the store
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let recipe: Recipe = serde_json::from_slice(bytes).context("Failed to parse chunk recipe")?;
+
+        let total: u64 = recipe.chunks.iter().map(|c| c.size).sum();
+        if total != recipe.size {
+            anyhow::bail!(
+                "Chunk recipe is inconsistent: chunks add up to {} bytes, expected {}",
+                total,
+                recipe.size
+            );
+        }
+        Ok(recipe)
+    }
+
+    /// Serialize the recipe for storage
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).context("Failed to serialize chunk recipe")
+    }
+}
+
+/// Reader that concatenates chunk files in order
+pub struct ChunkReader {
+    pending: VecDeque<PathBuf>,
+    current: Option<File>,
+}
+
+impl ChunkReader {
+    /// Read the chunk files at `paths`, in order, as one stream
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            pending: paths.into(),
+            current: None,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(file) = &mut self.current {
+                let n = file.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+                self.current = None;
+            }
+
+            match self.pending.pop_front() {
+                Some(path) => {
+                    let file = File::open(&path).map_err(|e| {
+                        std::io::Error::new(e.kind(), format!("Missing chunk {}: {}", path.display(), e))
+                    })?;
+                    self.current = Some(file);
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_reassembles() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+
+        let chunks: Vec<Vec<u8>> = split(&data[..]).collect::<Result<_>>().unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() <= MAX_CHUNK_SIZE as usize));
+        assert_eq!(chunks.concat(), data);
+    }
+
+    #[test]
+    fn test_recipe_rejects_wrong_size() {
+        let recipe = Recipe {
+            size: 10,
+            chunks: vec![ChunkRef {
+                hash: Blake3Hash::from_bytes(b"chunk"),
+                size: 5,
+            }],
+        };
+        assert!(Recipe::from_bytes(&recipe.to_bytes().unwrap()).is_err());
+    }
+}
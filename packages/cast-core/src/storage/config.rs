@@ -0,0 +1,1025 @@
+// Storage configuration management
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::fs;
+
+use super::local::{LocalStorage, DEFAULT_FS_RETRIES};
+use crate::db::{DbOptions, JournalMode, Synchronous, DEFAULT_MAX_CONNECTIONS};
+use crate::hash::DEFAULT_FILE_BUFFER_SIZE;
+use super::mirror::MirrorStorage;
+use super::StorageBackend;
+use std::sync::Arc;
+
+/// Storage backend types understood by `StorageConfig::open`
+pub const SUPPORTED_STORAGE_TYPES: &[&str] = &["local"];
+
+/// Keys accepted by `StorageConfig::get_key` and `StorageConfig::set_key`
+pub const CONFIG_KEYS: &[&str] = &[
+    "root",
+    "storage_type",
+    "compression",
+    "hash_buffer_size",
+    "db_max_connections",
+    "db_journal_mode",
+    "db_synchronous",
+    "fs_retries",
+    "verify_on_put",
+    "mirror_root",
+];
+
+/// Parse a lowercase enum value the way the config file would
+fn parse_enum<T: serde::de::DeserializeOwned>(key: &str, value: &str) -> Result<T> {
+    use serde::de::IntoDeserializer;
+
+    T::deserialize(IntoDeserializer::<serde::de::value::Error>::into_deserializer(value))
+        .with_context(|| format!("Invalid value '{}' for {}", value, key))
+}
+
+/// Storage configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Root directory for CAS storage
+    ///
+    /// A leading `~` and `$VAR`/`${VAR}` references are expanded on load.
+    #[serde(default = "default_root", deserialize_with = "deserialize_root")]
+    pub root: PathBuf,
+
+    /// Storage type (one of `SUPPORTED_STORAGE_TYPES`)
+    #[serde(default = "default_storage_type")]
+    pub storage_type: String,
+
+    /// Compression applied to stored objects
+    ///
+    /// Reserved for backends with compression support; the local backend
+    /// currently stores objects uncompressed.
+    #[serde(default)]
+    pub compression: Compression,
+
+    /// Read buffer size in bytes for hashing files
+    ///
+    /// Unset means `DEFAULT_FILE_BUFFER_SIZE` (1 MiB).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_buffer_size: Option<usize>,
+
+    /// Size of the metadata database connection pool
+    ///
+    /// Unset means `DEFAULT_MAX_CONNECTIONS` (5).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_max_connections: Option<u32>,
+
+    /// SQLite journal mode for the metadata database (default `wal`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_journal_mode: Option<JournalMode>,
+
+    /// SQLite `synchronous` level for the metadata database (default `normal`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_synchronous: Option<Synchronous>,
+
+    /// How many times a transiently failing filesystem operation is retried
+    ///
+    /// Unset means `DEFAULT_FS_RETRIES` (3); 0 disables retries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_retries: Option<u32>,
+
+    /// Compare new data byte for byte with an object already stored under
+    /// its hash instead of trusting deduplication
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify_on_put: bool,
+
+    /// Root of a second local store that receives a copy of every object
+    /// put, and from which missing objects are read back
+    ///
+    /// Expanded like `root`.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_mirror_root"
+    )]
+    pub mirror_root: Option<PathBuf>,
+}
+
+fn default_root() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("cast")
+}
+
+fn default_storage_type() -> String {
+    "local".to_string()
+}
+
+/// Object compression algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            other => anyhow::bail!("Unknown compression '{}' (supported: none, zstd)", other),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+fn deserialize_root<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    expand_path(&raw).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_mirror_root<'de, D>(deserializer: D) -> Result<Option<PathBuf>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_root(deserializer).map(Some)
+}
+
+/// Expand a leading `~` and `$VAR`/`${VAR}` environment references
+///
+/// Absolute paths are returned untouched. Referencing an unset variable
+/// is an error rather than silently expanding to an empty string.
+pub fn expand_path(raw: &str) -> Result<PathBuf> {
+    if Path::new(raw).is_absolute() {
+        return Ok(PathBuf::from(raw));
+    }
+
+    let tilde_expanded = if raw == "~" || raw.starts_with("~/") {
+        let home = dirs::home_dir().context("Failed to determine home directory for '~'")?;
+        format!("{}{}", home.display(), &raw[1..])
+    } else {
+        raw.to_string()
+    };
+
+    let mut expanded = String::with_capacity(tilde_expanded.len());
+    let mut chars = tilde_expanded.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+
+        let mut name = String::new();
+        while let Some(&next) = chars.peek() {
+            if next.is_ascii_alphanumeric() || next == '_' {
+                name.push(next);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if braced && chars.next() != Some('}') {
+            anyhow::bail!("Unterminated '${{' in path: {}", raw);
+        }
+
+        if name.is_empty() {
+            // A lone '$' is kept literally
+            expanded.push('$');
+            continue;
+        }
+
+        let value = std::env::var(&name)
+            .with_context(|| format!("Environment variable '{}' referenced in path '{}' is not set", name, raw))?;
+        expanded.push_str(&value);
+    }
+
+    Ok(PathBuf::from(expanded))
+}
+
+/// Whether a config file path should be treated as JSON
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// On-disk layout of `config.toml`
+///
+/// Either a single store configured at the top level (the original format)
+/// or several named stores under `[profiles.<name>]`, with `default_profile`
+/// selecting the one used when no profile is requested.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    default_profile: Option<String>,
+
+    #[serde(default)]
+    profiles: BTreeMap<String, StorageConfig>,
+
+    /// Top-level store keys (original single-store format)
+    #[serde(flatten)]
+    store: toml::Table,
+}
+
+impl ConfigFile {
+    /// Read and parse a config file
+    ///
+    /// Files with a `.json` extension are parsed as JSON, anything else as TOML.
+    async fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+
+        let parsed = if is_json(path) {
+            serde_json::from_str(&content).map_err(anyhow::Error::from)
+        } else {
+            toml::from_str(&content).map_err(anyhow::Error::from)
+        };
+
+        parsed.with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
+
+    /// Get a named profile
+    fn profile(&self, name: &str) -> Result<StorageConfig> {
+        self.profiles.get(name).cloned().with_context(|| {
+            let available: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            format!(
+                "Unknown profile '{}' (available: {})",
+                name,
+                if available.is_empty() { "none".to_string() } else { available.join(", ") }
+            )
+        })
+    }
+
+    /// Get the store used when no profile is requested
+    ///
+    /// Prefers `default_profile`, then a top-level store definition.
+    fn default_store(&self) -> Result<Option<StorageConfig>> {
+        if let Some(name) = &self.default_profile {
+            return self.profile(name).map(Some);
+        }
+
+        if self.store.is_empty() {
+            return Ok(None);
+        }
+
+        let config = StorageConfig::deserialize(toml::Value::Table(self.store.clone()))
+            .context("Invalid store configuration at top level of config file")?;
+        Ok(Some(config))
+    }
+}
+
+impl StorageConfig {
+    /// Load configuration, layering sources from lowest to highest precedence:
+    /// 1. Defaults (root: ~/.cache/cast)
+    /// 2. Config file: CAST_CONFIG if set, else ~/.config/cast/config.toml
+    ///    (its default profile or top-level store)
+    /// 3. Per-field environment overrides (see `apply_env_overrides`)
+    pub async fn load() -> Result<Self> {
+        let mut config = match Self::existing_config_file()? {
+            Some(config_path) => ConfigFile::read(&config_path).await?.default_store()?,
+            None => None,
+        }
+        .unwrap_or_default();
+
+        config.apply_env_overrides()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Load a named profile from `[profiles.<name>]` in the config file
+    pub async fn load_profile(name: &str) -> Result<Self> {
+        let config_path = Self::existing_config_file()?.with_context(|| {
+            format!("Profile '{}' requested but no config file exists", name)
+        })?;
+
+        let mut config = ConfigFile::read(&config_path).await?.profile(name)?;
+        config.apply_env_overrides()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Apply per-field environment variable overrides
+    ///
+    /// - `CAST_STORE`: root
+    /// - `CAST_STORAGE_TYPE`: storage_type
+    /// - `CAST_COMPRESSION`: compression (`none` or `zstd`)
+    pub fn apply_env_overrides(&mut self) -> Result<()> {
+        if let Ok(root) = std::env::var("CAST_STORE") {
+            self.root = PathBuf::from(root);
+        }
+
+        if let Ok(storage_type) = std::env::var("CAST_STORAGE_TYPE") {
+            self.storage_type = storage_type;
+        }
+
+        if let Ok(compression) = std::env::var("CAST_COMPRESSION") {
+            self.compression = compression
+                .parse()
+                .context("Invalid CAST_COMPRESSION")?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that the configuration names a supported backend
+    pub fn validate(&self) -> Result<()> {
+        if !SUPPORTED_STORAGE_TYPES.contains(&self.storage_type.as_str()) {
+            anyhow::bail!(
+                "Unknown storage_type '{}' (supported: {})",
+                self.storage_type,
+                SUPPORTED_STORAGE_TYPES.join(", ")
+            );
+        }
+
+        if self.hash_buffer_size == Some(0) {
+            anyhow::bail!("hash_buffer_size must be greater than zero");
+        }
+
+        if self.db_max_connections == Some(0) {
+            anyhow::bail!("db_max_connections must be greater than zero");
+        }
+
+        if self.mirror_root.as_ref() == Some(&self.root) {
+            anyhow::bail!("mirror_root must differ from root");
+        }
+
+        Ok(())
+    }
+
+    /// Read buffer size for hashing files, falling back to the default
+    pub fn hash_buffer_size(&self) -> usize {
+        self.hash_buffer_size.unwrap_or(DEFAULT_FILE_BUFFER_SIZE)
+    }
+
+    /// Retries for transient filesystem errors, falling back to the default
+    pub fn fs_retries(&self) -> u32 {
+        self.fs_retries.unwrap_or(DEFAULT_FS_RETRIES)
+    }
+
+    /// Connection settings for the metadata database
+    pub fn db_options(&self) -> DbOptions {
+        DbOptions {
+            max_connections: self.db_max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS),
+            journal_mode: self.db_journal_mode.unwrap_or_default(),
+            synchronous: self.db_synchronous.unwrap_or_default(),
+        }
+    }
+
+    /// Open the storage backend described by this configuration
+    ///
+    /// With `mirror_root` set, the backend mirrors onto that store (see
+    /// `open_mirror`).
+    pub fn open(&self) -> Result<Box<dyn StorageBackend>> {
+        self.validate()?;
+
+        if let Some(mirror) = self.open_mirror() {
+            return Ok(Box::new(mirror));
+        }
+        match self.storage_type.as_str() {
+            "local" => Ok(Box::new(LocalStorage::new(self.clone()))),
+            other => unreachable!("validated storage_type: {}", other),
+        }
+    }
+
+    /// Configuration of the store at `mirror_root`, sharing every other setting
+    pub fn mirror_config(&self) -> Option<StorageConfig> {
+        self.mirror_root.as_ref().map(|root| StorageConfig {
+            root: root.clone(),
+            mirror_root: None,
+            ..self.clone()
+        })
+    }
+
+    /// This store mirrored onto `mirror_root`, if one is configured
+    ///
+    /// Replication is synchronous, so a command that exits right after a
+    /// write leaves the mirror up to date.
+    pub fn open_mirror(&self) -> Option<MirrorStorage> {
+        let mirror = self.mirror_config()?;
+        let primary = Arc::new(LocalStorage::new(self.clone()));
+        Some(MirrorStorage::new(primary, vec![Arc::new(LocalStorage::new(mirror))]).synchronous(true))
+    }
+
+    /// Get the config file path
+    ///
+    /// `CAST_CONFIG` takes precedence over the XDG default
+    /// (~/.config/cast/config.toml).
+    pub fn config_file_path() -> Option<PathBuf> {
+        if let Ok(path) = std::env::var("CAST_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        dirs::config_dir().map(|dir| dir.join("cast").join("config.toml"))
+    }
+
+    /// Get the config file path if the file exists
+    ///
+    /// A missing default config file is not an error, but an explicit
+    /// `CAST_CONFIG` pointing at a nonexistent file is.
+    fn existing_config_file() -> Result<Option<PathBuf>> {
+        if let Ok(path) = std::env::var("CAST_CONFIG") {
+            let path = PathBuf::from(path);
+            if !path.exists() {
+                anyhow::bail!("CAST_CONFIG points at a nonexistent file: {}", path.display());
+            }
+            return Ok(Some(path));
+        }
+
+        Ok(Self::config_file_path().filter(|path| path.exists()))
+    }
+
+    /// Save configuration to config file
+    pub async fn save(&self) -> Result<()> {
+        let config_path = Self::config_file_path()
+            .context("Failed to determine config directory")?;
+        self.save_to(&config_path).await
+    }
+
+    /// Save configuration to a specific file, as JSON if it ends in `.json`
+    pub async fn save_to(&self, config_path: &Path) -> Result<()> {
+        // Create parent directory
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create config directory: {}", parent.display()))?;
+        }
+
+        let content = if is_json(config_path) {
+            serde_json::to_string_pretty(self).context("Failed to serialize config")?
+        } else {
+            toml::to_string_pretty(self).context("Failed to serialize config")?
+        };
+
+        fs::write(config_path, content)
+            .await
+            .with_context(|| format!("Failed to write config file: {}", config_path.display()))?;
+
+        Ok(())
+    }
+
+    /// Read the store defined at the top level of a config file, for editing
+    ///
+    /// Unlike `load`, no environment overrides are applied, so saving the
+    /// result does not persist them. A missing file yields the defaults.
+    /// Files using `[profiles]` are refused, since saving would drop them.
+    pub async fn load_for_edit(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = ConfigFile::read(path).await?;
+        if !file.profiles.is_empty() || file.default_profile.is_some() {
+            anyhow::bail!(
+                "{} defines profiles; edit it by hand to change a profile's settings",
+                path.display()
+            );
+        }
+        Ok(file.default_store()?.unwrap_or_default())
+    }
+
+    /// Get one setting by name, as the effective value
+    pub fn get_key(&self, key: &str) -> Result<String> {
+        let options = self.db_options();
+        let value = match key {
+            "root" => self.root.display().to_string(),
+            "storage_type" => self.storage_type.clone(),
+            "compression" => self.compression.to_string(),
+            "hash_buffer_size" => self.hash_buffer_size().to_string(),
+            "db_max_connections" => options.max_connections.to_string(),
+            "db_journal_mode" => serde_json::to_value(options.journal_mode)?.as_str().unwrap_or_default().to_string(),
+            "db_synchronous" => serde_json::to_value(options.synchronous)?.as_str().unwrap_or_default().to_string(),
+            "fs_retries" => self.fs_retries().to_string(),
+            "verify_on_put" => self.verify_on_put.to_string(),
+            "mirror_root" => self.mirror_root.as_ref().map(|p| p.display().to_string()).unwrap_or_default(),
+            other => anyhow::bail!("Unknown config key '{}' (valid keys: {})", other, CONFIG_KEYS.join(", ")),
+        };
+        Ok(value)
+    }
+
+    /// Set one setting by name, parsing the value as the config file would
+    ///
+    /// The result is validated, and left unchanged if the new value is invalid.
+    pub fn set_key(&mut self, key: &str, value: &str) -> Result<()> {
+        let mut updated = self.clone();
+        match key {
+            "root" => updated.root = expand_path(value)?,
+            "storage_type" => updated.storage_type = value.to_string(),
+            "compression" => updated.compression = value.parse()?,
+            "hash_buffer_size" => {
+                updated.hash_buffer_size = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid value '{}' for hash_buffer_size", value))?,
+                )
+            }
+            "db_max_connections" => {
+                updated.db_max_connections = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid value '{}' for db_max_connections", value))?,
+                )
+            }
+            "db_journal_mode" => updated.db_journal_mode = Some(parse_enum(key, value)?),
+            "db_synchronous" => updated.db_synchronous = Some(parse_enum(key, value)?),
+            "fs_retries" => {
+                updated.fs_retries = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid value '{}' for fs_retries", value))?,
+                )
+            }
+            "verify_on_put" => {
+                updated.verify_on_put = value
+                    .parse()
+                    .with_context(|| format!("Invalid value '{}' for verify_on_put (use true or false)", value))?
+            }
+            // An empty value turns mirroring off
+            "mirror_root" if value.is_empty() => updated.mirror_root = None,
+            "mirror_root" => updated.mirror_root = Some(expand_path(value)?),
+            other => anyhow::bail!("Unknown config key '{}' (valid keys: {})", other, CONFIG_KEYS.join(", ")),
+        }
+
+        updated.validate()?;
+        *self = updated;
+        Ok(())
+    }
+
+    /// Get the store directory path
+    pub fn store_path(&self) -> PathBuf {
+        self.root.join("store")
+    }
+
+    /// Get the chunk directory path (root/chunks/)
+    pub fn chunks_path(&self) -> PathBuf {
+        self.root.join("chunks")
+    }
+
+    /// Get the packfile directory path (root/packs/)
+    pub fn packs_path(&self) -> PathBuf {
+        self.root.join("packs")
+    }
+
+    /// Get the metadata database path
+    pub fn db_path(&self) -> PathBuf {
+        self.root.join("meta.db")
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            root: default_root(),
+            storage_type: default_storage_type(),
+            compression: Compression::default(),
+            hash_buffer_size: None,
+            db_max_connections: None,
+            db_journal_mode: None,
+            db_synchronous: None,
+            fs_retries: None,
+            verify_on_put: false,
+            mirror_root: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serializes tests that mutate process-wide environment variables
+    static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    #[test]
+    fn test_default_config() {
+        let config = StorageConfig::default();
+        assert_eq!(config.storage_type, "local");
+        assert!(config.root.ends_with("cast"));
+    }
+
+    #[test]
+    fn test_store_path() {
+        let config = StorageConfig {
+            root: PathBuf::from("/tmp/test-cast"),
+            storage_type: "local".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.store_path(), PathBuf::from("/tmp/test-cast/store"));
+    }
+
+    #[test]
+    fn test_db_path() {
+        let config = StorageConfig {
+            root: PathBuf::from("/tmp/test-cast"),
+            storage_type: "local".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.db_path(), PathBuf::from("/tmp/test-cast/meta.db"));
+    }
+
+    async fn write_config(content: &str) -> (tempfile::TempDir, PathBuf) {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, content).await.unwrap();
+        (temp_dir, path)
+    }
+
+    #[tokio::test]
+    async fn test_profiles() {
+        let (_temp, path) = write_config(
+            r#"
+            default_profile = "dev"
+
+            [profiles.dev]
+            root = "/tmp/cast-dev"
+
+            [profiles.ci]
+            root = "/srv/cast-ci"
+            storage_type = "local"
+            "#,
+        )
+        .await;
+
+        let file = ConfigFile::read(&path).await.unwrap();
+
+        assert_eq!(file.profile("dev").unwrap().root, PathBuf::from("/tmp/cast-dev"));
+        assert_eq!(file.profile("ci").unwrap().root, PathBuf::from("/srv/cast-ci"));
+
+        // default_profile selects dev
+        let default = file.default_store().unwrap().unwrap();
+        assert_eq!(default.root, PathBuf::from("/tmp/cast-dev"));
+
+        let err = file.profile("prod").unwrap_err().to_string();
+        assert!(err.contains("prod"));
+        assert!(err.contains("ci, dev"));
+    }
+
+    #[tokio::test]
+    async fn test_json_config_matches_toml() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let toml_path = temp_dir.path().join("config.toml");
+        fs::write(
+            &toml_path,
+            r#"
+            default_profile = "ci"
+
+            [profiles.ci]
+            root = "/srv/cast-ci"
+            compression = "zstd"
+            "#,
+        )
+        .await
+        .unwrap();
+
+        let json_path = temp_dir.path().join("config.json");
+        fs::write(
+            &json_path,
+            r#"{
+                "default_profile": "ci",
+                "profiles": {
+                    "ci": { "root": "/srv/cast-ci", "compression": "zstd" }
+                }
+            }"#,
+        )
+        .await
+        .unwrap();
+
+        let from_toml = ConfigFile::read(&toml_path).await.unwrap().default_store().unwrap();
+        let from_json = ConfigFile::read(&json_path).await.unwrap().default_store().unwrap();
+        assert!(from_toml.is_some());
+        assert_eq!(from_toml, from_json);
+    }
+
+    #[tokio::test]
+    async fn test_json_legacy_config() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.json");
+        fs::write(&path, r#"{ "root": "/data/cast", "storage_type": "local" }"#)
+            .await
+            .unwrap();
+
+        let config = ConfigFile::read(&path).await.unwrap().default_store().unwrap().unwrap();
+        assert_eq!(config.root, PathBuf::from("/data/cast"));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_single_store_config() {
+        let (_temp, path) = write_config(r#"root = "/data/cast""#).await;
+
+        let file = ConfigFile::read(&path).await.unwrap();
+        let config = file.default_store().unwrap().unwrap();
+        assert_eq!(config.root, PathBuf::from("/data/cast"));
+        assert_eq!(config.storage_type, "local");
+    }
+
+    #[tokio::test]
+    async fn test_profiles_without_default() {
+        let (_temp, path) = write_config(
+            r#"
+            root = "/data/cast"
+
+            [profiles.ci]
+            root = "/srv/cast-ci"
+            "#,
+        )
+        .await;
+
+        // Without default_profile, the top-level store is the fallback
+        let file = ConfigFile::read(&path).await.unwrap();
+        let config = file.default_store().unwrap().unwrap();
+        assert_eq!(config.root, PathBuf::from("/data/cast"));
+        assert_eq!(file.profile("ci").unwrap().root, PathBuf::from("/srv/cast-ci"));
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_path("~/foo").unwrap(), home.join("foo"));
+        assert_eq!(expand_path("~").unwrap(), home);
+
+        // Only a leading '~/' is special
+        assert_eq!(expand_path("data/~foo").unwrap(), PathBuf::from("data/~foo"));
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        std::env::set_var("CAST_TEST_EXPAND_ROOT", "/srv/expanded");
+
+        assert_eq!(
+            expand_path("$CAST_TEST_EXPAND_ROOT/cast").unwrap(),
+            PathBuf::from("/srv/expanded/cast")
+        );
+        assert_eq!(
+            expand_path("${CAST_TEST_EXPAND_ROOT}-store").unwrap(),
+            PathBuf::from("/srv/expanded-store")
+        );
+
+        std::env::remove_var("CAST_TEST_EXPAND_ROOT");
+
+        let err = expand_path("$CAST_TEST_UNSET_VAR/cast").unwrap_err().to_string();
+        assert!(err.contains("CAST_TEST_UNSET_VAR"));
+    }
+
+    #[test]
+    fn test_expand_leaves_absolute_paths() {
+        assert_eq!(expand_path("/data/$NOT_EXPANDED").unwrap(), PathBuf::from("/data/$NOT_EXPANDED"));
+    }
+
+    #[tokio::test]
+    async fn test_config_file_reports_expansion_errors() {
+        let (_temp, path) = write_config(r#"root = "$CAST_TEST_UNSET_ROOT/cast""#).await;
+
+        let file = ConfigFile::read(&path).await.unwrap();
+        let err = format!("{:#}", file.default_store().unwrap_err());
+        assert!(err.contains("CAST_TEST_UNSET_ROOT"));
+    }
+
+    #[test]
+    fn test_root_expanded_on_deserialize() {
+        let config: StorageConfig = toml::from_str(r#"root = "~/data/cast""#).unwrap();
+        assert_eq!(config.root, dirs::home_dir().unwrap().join("data/cast"));
+    }
+
+    #[test]
+    fn test_validate_unknown_storage_type() {
+        let config = StorageConfig {
+            root: PathBuf::from("/tmp/test-cast"),
+            storage_type: "locial".to_string(),
+            ..Default::default()
+        };
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("locial"));
+        assert!(err.contains("supported: local"));
+        assert!(config.open().is_err());
+    }
+
+    #[test]
+    fn test_hash_buffer_size() {
+        let config = StorageConfig::default();
+        assert_eq!(config.hash_buffer_size(), DEFAULT_FILE_BUFFER_SIZE);
+
+        let config: StorageConfig = toml::from_str("hash_buffer_size = 4096").unwrap();
+        assert_eq!(config.hash_buffer_size(), 4096);
+
+        let config: StorageConfig = toml::from_str("hash_buffer_size = 0").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_get_and_set_keys() {
+        let mut config = StorageConfig::default();
+        for key in CONFIG_KEYS {
+            assert!(config.get_key(key).is_ok(), "{}", key);
+        }
+
+        config.set_key("compression", "zstd").unwrap();
+        config.set_key("db_journal_mode", "delete").unwrap();
+        config.set_key("hash_buffer_size", "4096").unwrap();
+        config.set_key("verify_on_put", "true").unwrap();
+        config.set_key("mirror_root", "/backup/cast").unwrap();
+        assert_eq!(config.get_key("mirror_root").unwrap(), "/backup/cast");
+        config.set_key("mirror_root", "").unwrap();
+        assert_eq!(config.mirror_root, None);
+        assert_eq!(config.get_key("compression").unwrap(), "zstd");
+        assert_eq!(config.get_key("verify_on_put").unwrap(), "true");
+        assert_eq!(config.get_key("db_journal_mode").unwrap(), "delete");
+        assert_eq!(config.get_key("hash_buffer_size").unwrap(), "4096");
+
+        // Invalid values leave the config untouched
+        assert!(config.set_key("storage_type", "s3").is_err());
+        assert!(config.set_key("hash_buffer_size", "0").is_err());
+        assert!(config.set_key("db_synchronous", "sometimes").is_err());
+        assert!(config.set_key("verify_on_put", "yes").is_err());
+        assert_eq!(config.storage_type, "local");
+        assert_eq!(config.hash_buffer_size(), 4096);
+
+        let err = config.get_key("colour").unwrap_err().to_string();
+        assert!(err.contains("valid keys: root, storage_type"), "{}", err);
+        assert!(config.set_key("colour", "blue").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_set_root_persists() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "storage_type = \"local\"\nhash_buffer_size = 8192\n").await.unwrap();
+
+        let mut config = StorageConfig::load_for_edit(&path).await.unwrap();
+        config.set_key("root", "/data/new-store").unwrap();
+        config.save_to(&path).await.unwrap();
+
+        let reloaded = StorageConfig::load_for_edit(&path).await.unwrap();
+        assert_eq!(reloaded.get_key("root").unwrap(), "/data/new-store");
+        assert_eq!(reloaded.hash_buffer_size(), 8192);
+    }
+
+    #[tokio::test]
+    async fn test_load_for_edit_refuses_profiles() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "[profiles.dev]\nroot = \"/tmp/dev\"\n").await.unwrap();
+
+        assert!(StorageConfig::load_for_edit(&path).await.is_err());
+    }
+
+    #[test]
+    fn test_db_options() {
+        assert_eq!(StorageConfig::default().db_options(), DbOptions::default());
+
+        let config: StorageConfig = toml::from_str(
+            r#"
+            db_max_connections = 1
+            db_journal_mode = "delete"
+            db_synchronous = "full"
+            "#,
+        )
+        .unwrap();
+        let options = config.db_options();
+        assert_eq!(options.max_connections, 1);
+        assert_eq!(options.journal_mode, JournalMode::Delete);
+        assert_eq!(options.synchronous, Synchronous::Full);
+
+        let config: StorageConfig = toml::from_str("db_max_connections = 0").unwrap();
+        assert!(config.validate().is_err());
+        assert!(toml::from_str::<StorageConfig>(r#"db_journal_mode = "fast""#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_local() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = StorageConfig {
+            root: temp_dir.path().to_path_buf(),
+            storage_type: "local".to_string(),
+            ..Default::default()
+        };
+
+        let backend = config.open().unwrap();
+        let hash = backend.put(b"opened via factory").await.unwrap();
+        assert!(backend.exists(&hash).await);
+    }
+
+    #[tokio::test]
+    async fn test_load_rejects_unknown_storage_type() {
+        let _guard = ENV_LOCK.lock().await;
+        let (_temp, path) = write_config(
+            r#"
+            root = "/tmp/cast"
+            storage_type = "locial"
+            "#,
+        )
+        .await;
+
+        std::env::set_var("CAST_CONFIG", &path);
+        let result = StorageConfig::load().await;
+        std::env::remove_var("CAST_CONFIG");
+
+        assert!(result.unwrap_err().to_string().contains("Unknown storage_type"));
+    }
+
+    #[tokio::test]
+    async fn test_env_overrides_layer_over_file() {
+        let _guard = ENV_LOCK.lock().await;
+        let (_temp, path) = write_config(r#"root = "/tmp/from-file""#).await;
+
+        std::env::set_var("CAST_CONFIG", &path);
+        std::env::set_var("CAST_COMPRESSION", "zstd");
+        let config = StorageConfig::load().await;
+        std::env::remove_var("CAST_CONFIG");
+        std::env::remove_var("CAST_COMPRESSION");
+
+        let config = config.unwrap();
+        assert_eq!(config.root, PathBuf::from("/tmp/from-file"));
+        assert_eq!(config.compression, Compression::Zstd);
+    }
+
+    #[tokio::test]
+    async fn test_env_root_overrides_file_root() {
+        let _guard = ENV_LOCK.lock().await;
+        let (_temp, path) = write_config(
+            r#"
+            root = "/tmp/from-file"
+            compression = "zstd"
+            "#,
+        )
+        .await;
+
+        std::env::set_var("CAST_CONFIG", &path);
+        std::env::set_var("CAST_STORE", "/tmp/from-env");
+        let config = StorageConfig::load().await;
+        std::env::remove_var("CAST_CONFIG");
+        std::env::remove_var("CAST_STORE");
+
+        // Only root is overridden; compression still comes from the file
+        let config = config.unwrap();
+        assert_eq!(config.root, PathBuf::from("/tmp/from-env"));
+        assert_eq!(config.compression, Compression::Zstd);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_compression_env() {
+        let _guard = ENV_LOCK.lock().await;
+
+        std::env::set_var("CAST_COMPRESSION", "lz5");
+        let result = StorageConfig::load().await;
+        std::env::remove_var("CAST_COMPRESSION");
+
+        assert!(format!("{:#}", result.unwrap_err()).contains("lz5"));
+    }
+
+    #[tokio::test]
+    async fn test_load_from_cast_config() {
+        let _guard = ENV_LOCK.lock().await;
+        let (_temp, path) = write_config(r#"root = "/tmp/from-cast-config""#).await;
+
+        std::env::set_var("CAST_CONFIG", &path);
+        let config = StorageConfig::load().await;
+        std::env::remove_var("CAST_CONFIG");
+
+        assert_eq!(config.unwrap().root, PathBuf::from("/tmp/from-cast-config"));
+    }
+
+    #[tokio::test]
+    async fn test_cast_config_missing_file() {
+        let _guard = ENV_LOCK.lock().await;
+
+        std::env::set_var("CAST_CONFIG", "/nonexistent/cast/config.toml");
+        let result = StorageConfig::load().await;
+        std::env::remove_var("CAST_CONFIG");
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("CAST_CONFIG"));
+        assert!(err.contains("/nonexistent/cast/config.toml"));
+    }
+
+    #[tokio::test]
+    async fn test_load_from_env() {
+        let _guard = ENV_LOCK.lock().await;
+        std::env::set_var("CAST_STORE", "/tmp/env-test");
+
+        let config = StorageConfig::load().await.unwrap();
+        assert_eq!(config.root, PathBuf::from("/tmp/env-test"));
+
+        std::env::remove_var("CAST_STORE");
+    }
+}
@@ -0,0 +1,302 @@
+// Backend that replicates writes to secondary stores
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+use super::StorageBackend;
+use crate::error::CastError;
+use crate::hash::Blake3Hash;
+use crate::manifest::Manifest;
+
+/// Storage backend that mirrors every write to one or more secondaries
+///
+/// Writes go to the primary first, then to each secondary, either in
+/// background tasks (the default) or before the write returns when
+/// `synchronous` is set. A secondary failure is logged and otherwise
+/// ignored unless `strict` is set, in which case it fails the operation and
+/// replication is always synchronous. Reads come from the primary and fall
+/// back to the secondaries, copying the object back into the primary.
+pub struct MirrorStorage {
+    primary: Arc<dyn StorageBackend>,
+    secondaries: Vec<Arc<dyn StorageBackend>>,
+    synchronous: bool,
+    strict: bool,
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl MirrorStorage {
+    /// Mirror writes to `primary` onto each of `secondaries`
+    pub fn new(primary: Arc<dyn StorageBackend>, secondaries: Vec<Arc<dyn StorageBackend>>) -> Self {
+        Self {
+            primary,
+            secondaries,
+            synchronous: false,
+            strict: false,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Wait for secondaries to finish each write before returning
+    pub fn synchronous(mut self, synchronous: bool) -> Self {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// Fail writes that do not reach every secondary
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Wait for background replication started so far to finish
+    pub async fn flush(&self) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for task in pending {
+            if let Err(e) = task.await {
+                tracing::warn!("Mirror replication task failed: {}", e);
+            }
+        }
+    }
+
+    /// Apply `op` to every secondary, honoring `synchronous` and `strict`
+    async fn replicate<F>(&self, what: String, op: F) -> Result<()>
+    where
+        F: Fn(Arc<dyn StorageBackend>) -> futures::future::BoxFuture<'static, Result<()>>,
+    {
+        if self.synchronous || self.strict {
+            for (i, secondary) in self.secondaries.iter().enumerate() {
+                if let Err(e) = op(Arc::clone(secondary)).await {
+                    if self.strict {
+                        return Err(e).with_context(|| format!("Failed to mirror {} to secondary {}", what, i));
+                    }
+                    tracing::warn!("Failed to mirror {} to secondary {}: {:#}", what, i, e);
+                }
+            }
+            return Ok(());
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|task| !task.is_finished());
+        for (i, secondary) in self.secondaries.iter().enumerate() {
+            let task = op(Arc::clone(secondary));
+            let what = what.clone();
+            pending.push(tokio::spawn(async move {
+                if let Err(e) = task.await {
+                    tracing::warn!("Failed to mirror {} to secondary {}: {:#}", what, i, e);
+                }
+            }));
+        }
+        Ok(())
+    }
+
+    /// Copy an object already in the primary to every secondary lacking it
+    ///
+    /// For objects written to the primary directly rather than through `put`.
+    pub async fn replicate_existing(&self, hash: &Blake3Hash) -> Result<()> {
+        let mut reader = self.primary.open(hash).await?;
+        let data: Arc<[u8]> = tokio::task::spawn_blocking(move || {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).map(|_| data)
+        })
+        .await
+        .context("Mirror read task panicked")?
+        .with_context(|| format!("Failed to read {} for mirroring", hash))?
+        .into();
+
+        let hash = *hash;
+        self.replicate(format!("object {}", hash), move |secondary| {
+            let data = Arc::clone(&data);
+            Box::pin(async move {
+                if !secondary.exists(&hash).await {
+                    secondary.put(&data).await?;
+                }
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    /// Copy an object the primary lacks back from the first secondary holding it
+    async fn repair(&self, hash: &Blake3Hash) -> Result<PathBuf> {
+        for (i, secondary) in self.secondaries.iter().enumerate() {
+            let Ok(path) = secondary.get(hash).await else {
+                continue;
+            };
+
+            let data = tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("Failed to read mirrored object: {}", path.display()))?;
+            if Blake3Hash::from_bytes(&data) != *hash {
+                tracing::warn!("Secondary {} holds a corrupt copy of {}", i, hash);
+                continue;
+            }
+
+            self.primary.put(&data).await?;
+            tracing::info!("Repaired {} in primary from secondary {}", hash, i);
+            return self.primary.get(hash).await;
+        }
+
+        tracing::debug!("Object {} not found in primary or any mirror", hash);
+        Err(CastError::NotFound(*hash).into())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MirrorStorage {
+    async fn put(&self, data: &[u8]) -> Result<Blake3Hash> {
+        let hash = self.primary.put(data).await?;
+
+        let data: Arc<[u8]> = Arc::from(data);
+        self.replicate(format!("object {}", hash), move |secondary| {
+            let data = Arc::clone(&data);
+            Box::pin(async move { secondary.put(&data).await.map(|_| ()) })
+        })
+        .await?;
+        Ok(hash)
+    }
+
+    async fn get(&self, hash: &Blake3Hash) -> Result<PathBuf> {
+        match self.primary.get(hash).await {
+            Ok(path) => Ok(path),
+            Err(e) if self.secondaries.is_empty() => Err(e),
+            Err(_) => self.repair(hash).await,
+        }
+    }
+
+    async fn open(&self, hash: &Blake3Hash) -> Result<Box<dyn std::io::Read + Send>> {
+        match self.primary.open(hash).await {
+            Ok(reader) => Ok(reader),
+            Err(e) if self.secondaries.is_empty() => Err(e),
+            Err(_) => {
+                self.repair(hash).await?;
+                self.primary.open(hash).await
+            }
+        }
+    }
+
+    async fn exists(&self, hash: &Blake3Hash) -> bool {
+        if self.primary.exists(hash).await {
+            return true;
+        }
+        for secondary in &self.secondaries {
+            if secondary.exists(hash).await {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn delete(&self, hash: &Blake3Hash) -> Result<()> {
+        self.primary.delete(hash).await?;
+
+        let hash = *hash;
+        self.replicate(format!("deletion of {}", hash), move |secondary| {
+            Box::pin(async move { secondary.delete(&hash).await })
+        })
+        .await
+    }
+
+    async fn register_dataset(&self, manifest: &Manifest) -> Result<()> {
+        self.primary.register_dataset(manifest).await?;
+
+        let manifest = Arc::new(manifest.clone());
+        let what = format!("dataset {}@{}", manifest.dataset.name, manifest.dataset.version);
+        self.replicate(what, move |secondary| {
+            let manifest = Arc::clone(&manifest);
+            Box::pin(async move { secondary.register_dataset(&manifest).await })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::local::LocalStorage;
+    use tempfile::TempDir;
+
+    fn mirror(temp_dir: &TempDir) -> (Arc<LocalStorage>, Arc<LocalStorage>, MirrorStorage) {
+        let primary = Arc::new(LocalStorage::with_root(temp_dir.path().join("primary")));
+        let secondary = Arc::new(LocalStorage::with_root(temp_dir.path().join("secondary")));
+        let mirror = MirrorStorage::new(primary.clone(), vec![secondary.clone()]);
+        (primary, secondary, mirror)
+    }
+
+    #[tokio::test]
+    async fn test_put_reaches_both_backends() {
+        let temp_dir = TempDir::new().unwrap();
+        let (primary, secondary, mirror) = mirror(&temp_dir);
+
+        let hash = mirror.put(b"replicated").await.unwrap();
+        mirror.flush().await;
+
+        assert!(primary.exists(&hash).await);
+        assert!(secondary.exists(&hash).await);
+    }
+
+    #[tokio::test]
+    async fn test_get_repairs_primary_from_secondary() {
+        let temp_dir = TempDir::new().unwrap();
+        let (primary, secondary, mirror) = mirror(&temp_dir);
+        let mirror = mirror.synchronous(true);
+
+        let hash = mirror.put(b"lost from primary").await.unwrap();
+        primary.delete(&hash).await.unwrap();
+        assert!(!primary.exists(&hash).await);
+
+        let path = mirror.get(&hash).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"lost from primary");
+        assert!(primary.exists(&hash).await);
+        assert!(secondary.exists(&hash).await);
+
+        let missing = Blake3Hash::from_bytes(b"in neither store");
+        let err = mirror.get(&missing).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<CastError>(), Some(CastError::NotFound(h)) if *h == missing));
+    }
+
+    #[tokio::test]
+    async fn test_replicate_existing() {
+        let temp_dir = TempDir::new().unwrap();
+        let (primary, secondary, mirror) = mirror(&temp_dir);
+        let mirror = mirror.synchronous(true);
+
+        let hash = primary.put(b"written to the primary only").await.unwrap();
+        assert!(!secondary.exists(&hash).await);
+
+        mirror.replicate_existing(&hash).await.unwrap();
+        assert!(secondary.exists(&hash).await);
+    }
+
+    #[tokio::test]
+    async fn test_open_mirror_from_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = crate::storage::StorageConfig {
+            root: temp_dir.path().join("primary"),
+            mirror_root: Some(temp_dir.path().join("secondary")),
+            ..Default::default()
+        };
+
+        let hash = config.open().unwrap().put(b"through the config").await.unwrap();
+        let secondary = LocalStorage::new(config.mirror_config().unwrap());
+        assert!(secondary.exists(&hash).await);
+    }
+
+    #[tokio::test]
+    async fn test_strict_fails_on_secondary_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let primary = Arc::new(LocalStorage::with_root(temp_dir.path().join("primary")));
+        // A secondary rooted under a regular file cannot be written to
+        let blocker = temp_dir.path().join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+        let broken = Arc::new(LocalStorage::with_root(blocker.join("store")));
+
+        let lenient = MirrorStorage::new(primary.clone(), vec![broken.clone()]).synchronous(true);
+        assert!(lenient.put(b"data").await.is_ok());
+
+        let strict = MirrorStorage::new(primary, vec![broken]).strict(true);
+        assert!(strict.put(b"data").await.is_err());
+    }
+}
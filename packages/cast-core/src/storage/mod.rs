@@ -0,0 +1,134 @@
+// Storage backend trait and implementations
+pub mod chunking;
+pub mod config;
+pub mod local;
+pub mod mirror;
+pub mod pack;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+
+use crate::hash::Blake3Hash;
+use crate::manifest::Manifest;
+
+/// Storage backend trait for content-addressed storage operations
+///
+/// All methods are async to support various backend types (local, remote, etc.)
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Store data and return its BLAKE3 hash
+    ///
+    /// The data is read from the provided reader, hashed, and stored
+    /// in the content-addressed storage. Returns the hash for retrieval.
+    async fn put(&self, data: &[u8]) -> Result<Blake3Hash>;
+
+    /// Retrieve file path by hash
+    ///
+    /// Returns the path to the file in CAS. The file may be a symlink
+    /// to the actual storage location.
+    async fn get(&self, hash: &Blake3Hash) -> Result<PathBuf>;
+
+    /// Open an object for reading
+    ///
+    /// The default reads the file returned by `get`; backends that store
+    /// objects in other forms override it.
+    async fn open(&self, hash: &Blake3Hash) -> Result<Box<dyn std::io::Read + Send>> {
+        use anyhow::Context;
+
+        let path = self.get(hash).await?;
+        let file = std::fs::File::open(&path).with_context(|| format!("Failed to open object: {}", path.display()))?;
+        Ok(Box::new(std::io::BufReader::new(file)))
+    }
+
+    /// Check if hash exists in storage
+    async fn exists(&self, hash: &Blake3Hash) -> bool;
+
+    /// Delete data by hash
+    ///
+    /// Note: This should respect reference counting in production.
+    /// For now, it directly removes the file.
+    async fn delete(&self, hash: &Blake3Hash) -> Result<()>;
+
+    /// Register a dataset manifest
+    ///
+    /// Stores the manifest's canonical serialization, so the stored object
+    /// is addressed by `Manifest::content_hash`. Recording the dataset in the
+    /// metadata database is left to the caller.
+    async fn register_dataset(&self, manifest: &Manifest) -> Result<()>;
+}
+
+/// Outcome of storing an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct PutResult {
+    /// Hash the object is stored under
+    pub hash: Blake3Hash,
+    /// Object size in bytes
+    pub size: u64,
+    /// Whether the object was already present in the store
+    pub deduplicated: bool,
+}
+
+/// Original file attributes recorded with an object by `put --preserve`
+///
+/// Stored under the `file` key of the object's metadata JSON, so it can sit
+/// alongside other metadata such as a fetch `Source`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FileMetadata {
+    /// File name, without any directory
+    pub name: String,
+    /// Unix permission bits
+    pub mode: u32,
+    /// Modification time in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+}
+
+impl FileMetadata {
+    /// Capture the name, permissions, and mtime of a file
+    pub fn from_path(path: &std::path::Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let name = path
+            .file_name()
+            .with_context(|| format!("Path has no file name: {}", path.display()))?
+            .to_string_lossy()
+            .to_string();
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to stat file: {}", path.display()))?;
+
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777;
+        #[cfg(not(unix))]
+        let mode = if metadata.permissions().readonly() { 0o444 } else { 0o644 };
+
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64);
+
+        Ok(Self { name, mode, mtime })
+    }
+
+    /// Read the `file` entry from an object's metadata JSON, if present
+    pub fn from_object_metadata(metadata: Option<&str>) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(metadata?).ok()?;
+        serde_json::from_value(value.get("file")?.clone()).ok()
+    }
+
+    /// Merge this entry into an object's existing metadata JSON
+    pub fn merge_into(&self, metadata: Option<&str>) -> Result<String> {
+        let mut value = match metadata {
+            Some(json) => serde_json::from_str(json)?,
+            None => serde_json::Value::Object(Default::default()),
+        };
+        let Some(object) = value.as_object_mut() else {
+            anyhow::bail!("Object metadata is not a JSON object");
+        };
+        object.insert("file".to_string(), serde_json::to_value(self)?);
+        Ok(value.to_string())
+    }
+}
+
+pub use config::StorageConfig;
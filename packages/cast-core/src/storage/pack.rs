@@ -0,0 +1,185 @@
+// Packfiles consolidating many small objects
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::hash::Blake3Hash;
+
+/// File name of the index shared by all packs in a pack directory
+const INDEX_FILE: &str = "index.json";
+
+/// Where a packed object's bytes live
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackEntry {
+    /// Packfile name within the pack directory
+    pub pack: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Outcome of packing loose objects
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PackReport {
+    /// Name of the packfile written, if any object was packed
+    pub pack: Option<String>,
+    pub objects: usize,
+    pub bytes: u64,
+}
+
+/// Index mapping object hashes to their location in a packfile
+///
+/// Keys are bare hex hashes, so the JSON stays readable and ordered.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackIndex {
+    pub entries: BTreeMap<String, PackEntry>,
+}
+
+impl PackIndex {
+    /// Load the index of `dir`; a missing index is empty
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(INDEX_FILE);
+        match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Failed to parse pack index: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read pack index: {}", path.display())),
+        }
+    }
+
+    /// Replace the index of `dir` atomically
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(INDEX_FILE);
+        let tmp = dir.join(format!("{}.tmp-{}", INDEX_FILE, std::process::id()));
+
+        let json = serde_json::to_vec_pretty(self).context("Failed to serialize pack index")?;
+        let mut file = File::create(&tmp)
+            .with_context(|| format!("Failed to create pack index: {}", tmp.display()))?;
+        file.write_all(&json)
+            .and_then(|_| file.sync_all())
+            .with_context(|| format!("Failed to write pack index: {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to move pack index into place: {}", path.display()))
+    }
+
+    /// Location of a packed object
+    pub fn get(&self, hash: &Blake3Hash) -> Option<&PackEntry> {
+        self.entries.get(&hash.to_hex())
+    }
+
+    /// Forget a packed object; its bytes stay in the packfile
+    pub fn remove(&mut self, hash: &Blake3Hash) -> Option<PackEntry> {
+        self.entries.remove(&hash.to_hex())
+    }
+
+    /// Hashes of all packed objects
+    pub fn hashes(&self) -> impl Iterator<Item = Blake3Hash> + '_ {
+        self.entries.keys().filter_map(|hex| hex.parse().ok())
+    }
+}
+
+/// Concatenate `objects` into a new packfile in `dir`
+///
+/// Every object is checked against its hash while it is copied. The pack is
+/// written under a temporary name and renamed to `pack-<hash>.pack` (after
+/// the hash of its contents) once complete. Returns each object's entry;
+/// the index is left for the caller to update.
+pub fn write_pack(dir: &Path, objects: &[(Blake3Hash, PathBuf)]) -> Result<Vec<(Blake3Hash, PackEntry)>> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create pack directory: {}", dir.display()))?;
+
+    let tmp = dir.join(format!("pack.tmp-{}", std::process::id()));
+    let mut pack = File::create(&tmp)
+        .with_context(|| format!("Failed to create packfile: {}", tmp.display()))?;
+
+    let mut pack_hasher = blake3::Hasher::new();
+    let mut located = Vec::with_capacity(objects.len());
+    let mut offset = 0u64;
+    for (hash, path) in objects {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read object: {}", path.display()))?;
+        if Blake3Hash::from_bytes(&data) != *hash {
+            let _ = std::fs::remove_file(&tmp);
+            anyhow::bail!("Object {} is corrupt; refusing to pack it", hash);
+        }
+
+        pack.write_all(&data)
+            .with_context(|| format!("Failed to write packfile: {}", tmp.display()))?;
+        pack_hasher.update(&data);
+        located.push((*hash, offset, data.len() as u64));
+        offset += data.len() as u64;
+    }
+    pack.sync_all()
+        .with_context(|| format!("Failed to sync packfile: {}", tmp.display()))?;
+    drop(pack);
+
+    let name = format!("pack-{}.pack", &pack_hasher.finalize().to_hex()[..16]);
+    std::fs::rename(&tmp, dir.join(&name))
+        .with_context(|| format!("Failed to move packfile into place: {}", name))?;
+
+    Ok(located
+        .into_iter()
+        .map(|(hash, offset, length)| {
+            let entry = PackEntry {
+                pack: name.clone(),
+                offset,
+                length,
+            };
+            (hash, entry)
+        })
+        .collect())
+}
+
+/// Open a packed object for reading
+pub fn open_entry(dir: &Path, entry: &PackEntry) -> Result<std::io::Take<File>> {
+    let path = dir.join(&entry.pack);
+    let mut file = File::open(&path)
+        .with_context(|| format!("Failed to open packfile: {}", path.display()))?;
+    file.seek(SeekFrom::Start(entry.offset))
+        .with_context(|| format!("Failed to seek in packfile: {}", path.display()))?;
+    Ok(file.take(entry.length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_pack() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut objects = Vec::new();
+        for data in [&b"one"[..], b"two two", b"three three three"] {
+            let hash = Blake3Hash::from_bytes(data);
+            let path = temp_dir.path().join(hash.to_hex());
+            std::fs::write(&path, data).unwrap();
+            objects.push((hash, path));
+        }
+
+        let packs = temp_dir.path().join("packs");
+        let mut index = PackIndex::default();
+        for (hash, entry) in write_pack(&packs, &objects).unwrap() {
+            index.entries.insert(hash.to_hex(), entry);
+        }
+        index.save(&packs).unwrap();
+
+        let index = PackIndex::load(&packs).unwrap();
+        let entry = index.get(&Blake3Hash::from_bytes(b"two two")).unwrap();
+        assert_eq!((entry.offset, entry.length), (3, 7));
+
+        let mut data = Vec::new();
+        open_entry(&packs, entry).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"two two");
+    }
+
+    #[test]
+    fn test_write_pack_rejects_corrupt_object() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("object");
+        std::fs::write(&path, b"tampered").unwrap();
+
+        let objects = vec![(Blake3Hash::from_bytes(b"original"), path)];
+        assert!(write_pack(&temp_dir.path().join("packs"), &objects).is_err());
+    }
+}